@@ -0,0 +1,1524 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt,
+    io::{BufRead, Seek, SeekFrom},
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::Instant,
+};
+
+use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+use rayon::prelude::*;
+
+use crate::{
+    animation_mcmeta, decode_image_from, decoded_size, delta_e, emissive_name,
+    luma, read_color_chunks, transform::map_frames, ArchiveSet, Atlas,
+    AtlasKind, AtlasMap, AtlasPos, Cache, Crop, CropMap, CtmMap, EmissiveMask,
+    Failure, Hooks, Palettes, Stage, Tile, Trim, Upscale, Vision, Writer,
+    DECODE_BUDGET,
+};
+
+/// `AtlasPos` can only address a 256x256 grid
+pub const MAX_CELLS: u32 = 256;
+
+/// Grid of classic `terrain.png` and `items.png`, used for new sheets
+const CLASSIC_CELLS: u32 = 16;
+
+/// Dimensions of a decoded atlas, shared by all the validation helpers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AtlasInfo {
+    pub width: u32,
+    pub height: u32,
+    pub tile_size: u32,
+    /// Empty border around the whole grid
+    pub margin: u32,
+    /// Gutter between neighbouring cells
+    pub spacing: u32,
+    /// Border inside every cell around its tile
+    pub padding: u32,
+}
+
+impl AtlasInfo {
+    /// Sheet cut into a plain grid of `tile_size` cells, without gutters
+    pub fn new(width: u32, height: u32, tile_size: u32) -> Self {
+        Self {
+            width,
+            height,
+            tile_size,
+            margin: 0,
+            spacing: 0,
+            padding: 0,
+        }
+    }
+
+    /// Sheet laid out as `atlas` says, falling back to `tile_size`
+    pub fn of(atlas: &Atlas, width: u32, height: u32, tile_size: u32) -> Self {
+        Self {
+            width,
+            height,
+            tile_size: atlas.tile_size.unwrap_or(tile_size),
+            margin: atlas.margin,
+            spacing: atlas.spacing,
+            padding: atlas.padding,
+        }
+    }
+
+    /// Distance between the starts of neighbouring cells
+    pub fn pitch(&self) -> u32 {
+        self.tile_size + 2 * self.padding + self.spacing
+    }
+
+    fn cells(&self, length: u32) -> u32 {
+        (length + self.spacing).saturating_sub(2 * self.margin) / self.pitch()
+    }
+
+    /// Length a sheet needs to hold exactly `cells` cells
+    fn length(&self, cells: u32) -> u32 {
+        2 * self.margin + (cells * self.pitch()).saturating_sub(self.spacing)
+    }
+
+    pub fn columns(&self) -> u32 {
+        self.cells(self.width)
+    }
+
+    pub fn rows(&self) -> u32 {
+        self.cells(self.height)
+    }
+
+    /// Top left pixel of the tile in cell `(x, y)`, past margin and padding
+    pub fn origin(&self, x: u32, y: u32) -> (u32, u32) {
+        let start = self.margin + self.padding;
+        (start + x * self.pitch(), start + y * self.pitch())
+    }
+
+    /// Tiles of an area of `(columns, rows)` cells starting at `pos`, put
+    /// next to each other with the gutters between them left out
+    pub fn crop(
+        &self,
+        image: &RgbaImage,
+        pos: &AtlasPos,
+        (w, h): (u32, u32),
+    ) -> RgbaImage {
+        let size = self.tile_size;
+        let mut area = RgbaImage::new(size * w, size * h);
+        for dy in 0..h {
+            for dx in 0..w {
+                let (x, y) =
+                    self.origin(pos.x() as u32 + dx, pos.y() as u32 + dy);
+                let tile = image.view(x, y, size, size);
+                image::imageops::replace(
+                    &mut area,
+                    &tile,
+                    dx * size,
+                    dy * size,
+                );
+            }
+        }
+        area
+    }
+
+    /// Inverse of `crop`, spreading `area` back over its cells
+    pub fn paste(
+        &self,
+        image: &mut RgbaImage,
+        pos: &AtlasPos,
+        area: &RgbaImage,
+    ) {
+        let size = self.tile_size;
+        for dy in 0..area.height() / size {
+            for dx in 0..area.width() / size {
+                let (x, y) =
+                    self.origin(pos.x() as u32 + dx, pos.y() as u32 + dy);
+                let tile = area.view(dx * size, dy * size, size, size);
+                image::imageops::replace(image, &tile, x, y);
+            }
+        }
+    }
+
+    /// Whether cell `pos` is in the grid, never for indices it couldn't place
+    pub fn contains(&self, pos: &AtlasPos) -> bool {
+        pos.index().is_none()
+            && (pos.x() as u32) < self.columns()
+            && (pos.y() as u32) < self.rows()
+    }
+
+    /// Whether a vertical strip of `frames` cells starting at `pos` fits
+    pub fn contains_strip(&self, pos: &AtlasPos, frames: u32) -> bool {
+        self.contains_area(pos, (1, frames))
+    }
+
+    /// Whether an area of `(columns, rows)` cells starting at `pos` fits
+    pub fn contains_area(&self, pos: &AtlasPos, (w, h): (u32, u32)) -> bool {
+        self.contains(pos)
+            && pos.x() as u32 + w <= self.columns()
+            && pos.y() as u32 + h <= self.rows()
+    }
+}
+
+fn check_multiple(name: &str, info: AtlasInfo) -> anyhow::Result<()> {
+    let plain = info.margin == 0 && info.spacing == 0 && info.padding == 0;
+    if plain {
+        anyhow::ensure!(
+            info.width.is_multiple_of(info.tile_size)
+                && info.height.is_multiple_of(info.tile_size),
+            "atlas {}: size {}x{} is not a multiple of tile size {}",
+            name,
+            info.width,
+            info.height,
+            info.tile_size,
+        );
+    } else {
+        anyhow::ensure!(
+            info.length(info.columns()) == info.width
+                && info.length(info.rows()) == info.height,
+            "atlas {}: size {}x{} doesn't fit whole cells of {} pixels \
+             with margin {}, spacing {} and padding {}",
+            name,
+            info.width,
+            info.height,
+            info.tile_size,
+            info.margin,
+            info.spacing,
+            info.padding,
+        );
+    }
+    Ok(())
+}
+
+fn check_grid_bounds(
+    name: &str,
+    atlas: &Atlas,
+    info: AtlasInfo,
+) -> anyhow::Result<()> {
+    for (pos, tile) in &atlas.tiles {
+        let (w, h) = tile.footprint();
+        anyhow::ensure!(
+            w > 0 && h > 0,
+            "atlas {}: {:?} ({}) has empty span or no frames",
+            name,
+            pos,
+            tile.name
+        );
+    }
+    for (pos, tile) in &atlas.tiles {
+        anyhow::ensure!(
+            tile.rect_fits(info.tile_size),
+            "atlas {}: {:?} ({}) rect {:?} doesn't fit in its {}x{} frame",
+            name,
+            pos,
+            tile.name,
+            tile.rect.unwrap_or_default(),
+            tile.span.0 * info.tile_size,
+            tile.span.1 * info.tile_size,
+        );
+    }
+    let outside: Vec<_> = atlas
+        .tiles
+        .iter()
+        .flat_map(|(pos, tile)| {
+            std::iter::once(*pos)
+                .chain(tile.mask_cell())
+                .map(move |pos| (pos, tile))
+        })
+        .filter(|(pos, tile)| !info.contains_area(pos, tile.footprint()))
+        .map(|(pos, _)| pos)
+        .collect();
+    anyhow::ensure!(
+        outside.is_empty(),
+        "atlas {}: positions {:?} are outside of {}x{} grid",
+        name,
+        outside,
+        info.columns(),
+        info.rows(),
+    );
+    Ok(())
+}
+
+/// Tile names can point into subdirectories, but not out of the output one
+fn check_names(name: &str, atlas: &Atlas) -> anyhow::Result<()> {
+    for (pos, tile) in &atlas.tiles {
+        let nested = Path::new(&tile.name);
+        anyhow::ensure!(
+            !tile.name.is_empty()
+                && nested
+                    .components()
+                    .all(|part| matches!(part, Component::Normal(_))),
+            "atlas {}: {:?} has invalid name {:?}, expected a relative path \
+             like `machine/top`",
+            name,
+            pos,
+            tile.name
+        );
+    }
+    Ok(())
+}
+
+fn check_palettes(
+    name: &str,
+    atlas: &Atlas,
+    palettes: &Palettes,
+) -> anyhow::Result<()> {
+    for (pos, tile) in &atlas.tiles {
+        if let Some(palette) = &tile.palette {
+            anyhow::ensure!(
+                palettes.contains_key(palette),
+                "atlas {}: {:?} ({}) uses unknown palette {}",
+                name,
+                pos,
+                tile.name,
+                palette
+            );
+        }
+    }
+    Ok(())
+}
+
+fn check_fill(name: &str, info: AtlasInfo) {
+    if info.columns() > MAX_CELLS || info.rows() > MAX_CELLS {
+        log::warn!(
+            "atlas {}: only the top-left {}x{} cells of {}x{} grid \
+             are addressable",
+            name,
+            MAX_CELLS,
+            MAX_CELLS,
+            info.columns(),
+            info.rows(),
+        );
+    }
+}
+
+/// Runs all the validations that only need atlas dimensions, returning the
+/// layout, and `atlas` fitted to it with `Atlas::with_grid`
+fn check_atlas<'a>(
+    name: &str,
+    atlas: &'a Atlas,
+    width: u32,
+    height: u32,
+    options: &SliceOptions,
+) -> anyhow::Result<(AtlasInfo, Cow<'a, Atlas>)> {
+    let info = AtlasInfo::of(atlas, width, height, options.tile_size);
+    anyhow::ensure!(info.tile_size > 0, "atlas {}: tile size can't be 0", name);
+    check_multiple(name, info)?;
+    let atlas = atlas
+        .with_grid(info.columns(), info.rows())
+        .map_err(|e| anyhow::anyhow!("atlas {}: {}", name, e))?;
+    check_grid_bounds(name, &atlas, info)?;
+    check_names(name, &atlas)?;
+    check_palettes(name, &atlas, &options.palettes)?;
+    check_fill(name, info);
+    Ok((info, atlas))
+}
+
+/// Bounding box of non-transparent pixels, as `(x0, y0, x1, y1)` inclusive
+fn opaque_bbox(tile: &RgbaImage) -> Option<(u32, u32, u32, u32)> {
+    let mut bbox: Option<(u32, u32, u32, u32)> = None;
+    for (x, y, pixel) in tile.enumerate_pixels() {
+        if pixel.0[3] == 0 {
+            continue;
+        }
+        bbox = Some(match bbox {
+            None => (x, y, x, y),
+            Some((x0, y0, x1, y1)) => {
+                (x0.min(x), y0.min(y), x1.max(x), y1.max(y))
+            }
+        });
+    }
+    bbox
+}
+
+/// Fraction of opaque pixels that must hug a single edge for a cell to look
+/// like a grid line rather than a sprite
+const GRID_LINE_RATIO: f32 = 0.9;
+
+/// Fully transparent, or plain black, which a mapped tile rarely is on
+/// purpose
+fn looks_empty(tile: &RgbaImage) -> bool {
+    let transparent = tile.pixels().all(|pixel| pixel.0[3] == 0);
+    let black = tile.pixels().all(|pixel| pixel.0[..3] == [0, 0, 0]);
+    transparent || black
+}
+
+/// Heuristic for cells that only contain the separator between sprites:
+/// almost all opaque pixels lie within 1px of one edge
+fn looks_like_grid_line(tile: &RgbaImage) -> bool {
+    let (x0, y0, x1, y1) = match opaque_bbox(tile) {
+        Some(bbox) => bbox,
+        None => return false,
+    };
+    let (w, h) = tile.dimensions();
+    // Nothing near any edge
+    if x0 > 1 && y0 > 1 && x1 + 2 < w && y1 + 2 < h {
+        return false;
+    }
+    let mut total = 0;
+    let mut edges = [0; 4];
+    for (x, y, pixel) in tile.enumerate_pixels() {
+        if pixel.0[3] == 0 {
+            continue;
+        }
+        total += 1;
+        edges[0] += (x <= 1) as u32;
+        edges[1] += (y <= 1) as u32;
+        edges[2] += (x + 2 >= w) as u32;
+        edges[3] += (y + 2 >= h) as u32;
+    }
+    edges
+        .iter()
+        .any(|&count| count as f32 > total as f32 * GRID_LINE_RATIO)
+}
+
+/// Warns about tinted copies of `tile` whose average colors are closer than
+/// `min_tint_delta_e`, with normal vision or any simulated dichromacy
+fn check_tints(
+    atlas: &str,
+    tile: &Tile,
+    strip: &RgbaImage,
+    options: &SliceOptions,
+) -> anyhow::Result<()> {
+    if options.min_tint_delta_e <= 0.0 {
+        return Ok(());
+    }
+    // Weighted by alpha, so barely visible pixels barely count
+    let mut sum = [0.0; 3];
+    let mut weight = 0.0;
+    for Rgba([r, g, b, a]) in strip.pixels() {
+        let a = *a as f32;
+        for (sum, c) in sum.iter_mut().zip([r, g, b]) {
+            *sum += *c as f32 * a;
+        }
+        weight += a;
+    }
+    if weight == 0.0 {
+        return Ok(());
+    }
+    let tinted: Vec<_> = tile
+        .variants(&options.palettes)
+        .into_iter()
+        .filter_map(|(name, tint)| {
+            let tint = tint?.0;
+            let mut color = [0.0; 3];
+            for (i, channel) in color.iter_mut().enumerate() {
+                *channel = sum[i] / weight * tint[i] as f32 / 255.0;
+            }
+            Some((name, color))
+        })
+        .collect();
+    let mut close = vec![];
+    for (i, (a, a_color)) in tinted.iter().enumerate() {
+        for (b, b_color) in &tinted[i + 1..] {
+            let closest = Vision::ALL
+                .iter()
+                .map(|&vision| (delta_e(*a_color, *b_color, vision), vision))
+                .min_by(|x, y| x.0.total_cmp(&y.0));
+            if let Some((delta, vision)) = closest {
+                if delta < options.min_tint_delta_e {
+                    close.push(format!(
+                        "{} and {} ({}, deltaE {:.1})",
+                        a, b, vision, delta
+                    ));
+                }
+            }
+        }
+    }
+    if close.is_empty() {
+        return Ok(());
+    }
+    lint(
+        options.strict,
+        format_args!(
+            "atlas {}: tinted copies of {} are hard to tell apart: {}",
+            atlas,
+            tile.name,
+            close.join(", ")
+        ),
+    )
+}
+
+pub(crate) fn lint(
+    strict: bool,
+    message: fmt::Arguments,
+) -> anyhow::Result<()> {
+    if strict {
+        return Err(Failure::Validation.tag(anyhow::anyhow!("{}", message)));
+    }
+    log::warn!("{}", message);
+    Ok(())
+}
+
+/// Settings shared by every atlas in a run
+#[derive(Debug)]
+pub struct SliceOptions {
+    pub tile_size: u32,
+    pub strict: bool,
+    /// Palettes tiles can refer to, for writing tinted copies
+    pub palettes: Palettes,
+    /// Smallest difference allowed between tinted copies of a tile
+    pub min_tint_delta_e: f32,
+    /// Enlarge every tile written
+    pub upscale: Option<Upscale>,
+    /// Where to dump non-empty cells without mapping, if at all
+    pub unmapped_dir: Option<PathBuf>,
+    /// Look for tiles with identical content
+    pub dedupe: Option<Dedupe>,
+    /// Script with functions tiles name in `hook`
+    pub hooks: Option<Hooks>,
+}
+
+/// What to do about tiles identical to an earlier one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dedupe {
+    /// Only warn, writing every tile anyway
+    Warn,
+    /// Write the first tile only, listing the rest as its aliases
+    Alias,
+}
+
+impl FromStr for Dedupe {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warn" => Ok(Self::Warn),
+            "alias" => Ok(Self::Alias),
+            _ => Err(format!("unknown mode {:?}, expected warn or alias", s)),
+        }
+    }
+}
+
+/// Output name of a tile that isn't written, to name of the identical tile
+/// written instead
+pub type Aliases = BTreeMap<String, String>;
+
+/// Finds tiles identical to an earlier one, in config order. Tiles count as
+/// identical if their cells match and they're transformed the same way.
+fn find_duplicates(
+    atlas: &AtlasMap,
+    zips: &mut ArchiveSet,
+    options: &SliceOptions,
+    mode: Dedupe,
+) -> anyhow::Result<Aliases> {
+    let mut seen = HashMap::new();
+    let mut aliases = Aliases::new();
+    for (name, map) in atlas {
+        let sheet = zips.sheet(name);
+        let image = zips.image(&sheet)?;
+        let (width, height) = image.dimensions();
+        let info = AtlasInfo::of(map, width, height, options.tile_size);
+        // Bad layouts and positions are left for `slice_atlas` to report
+        if info.tile_size == 0 {
+            continue;
+        }
+        let map = match map.with_grid(info.columns(), info.rows()) {
+            Ok(map) => map,
+            Err(_) => continue,
+        };
+        let map = &*map;
+        for (pos, tile) in &map.tiles {
+            let (w, h) = tile.footprint();
+            if !info.contains_area(pos, (w, h))
+                || !tile.rect_fits(info.tile_size)
+            {
+                continue;
+            }
+            let strip = cut_rect(info.crop(&image, pos, (w, h)), tile);
+            for (output, tint) in tile.variants(&options.palettes) {
+                // Copies asked for in `[aliases]` are identical on purpose
+                if tile.aliases.contains(&output) {
+                    continue;
+                }
+                let applied = format!(
+                    "{:?} {:?} {} {:?} {:?} {:?} {:?} {:?} {} {} {}",
+                    tile.transform,
+                    tint,
+                    tile.frames,
+                    tile.frametime,
+                    map.grayscale,
+                    tile.emissive_mask,
+                    tile.scale,
+                    map.colorkey,
+                    map.fix_semitransparent_edges,
+                    tile.trim.unwrap_or(map.trim),
+                    tile.hook.as_deref().unwrap_or_default()
+                );
+                let key = (strip.dimensions(), strip.as_raw().clone(), applied);
+                let here = format!("{} {:?}", name, pos);
+                let (first, first_at) = match seen.get(&key) {
+                    Some(first) => first,
+                    None => {
+                        seen.insert(key, (output, here));
+                        continue;
+                    }
+                };
+                match mode {
+                    Dedupe::Warn => log::warn!(
+                        "`{}` ({}) is identical to `{}` ({})",
+                        output,
+                        here,
+                        first,
+                        first_at
+                    ),
+                    Dedupe::Alias => {
+                        log::info!("`{}` is an alias of `{}`", output, first);
+                        aliases.insert(output, first.clone());
+                    }
+                }
+            }
+        }
+    }
+    Ok(aliases)
+}
+
+/// Lists non-empty cells not covered by any mapping, optionally saving them
+/// as `<atlas>_<pos>.png`, with `pos` the key `keys` would give them
+fn report_unmapped(
+    name: &str,
+    atlas: &Atlas,
+    keys: &Atlas,
+    image: &RgbaImage,
+    info: AtlasInfo,
+    writer: &Writer,
+    options: &SliceOptions,
+) -> anyhow::Result<()> {
+    let covered = atlas.covered();
+    let mut unmapped = Vec::new();
+    for y in 0..info.rows().min(MAX_CELLS) {
+        for x in 0..info.columns().min(MAX_CELLS) {
+            let pos = AtlasPos::from_pos(x as u8, y as u8);
+            if covered.contains(&pos) {
+                continue;
+            }
+            let tile = info.crop(image, &pos, (1, 1));
+            let pos = keys.key(pos, (1, 1), info.columns(), info.rows());
+            if opaque_bbox(&tile).is_none() {
+                continue;
+            }
+            if let Some(dir) = &options.unmapped_dir {
+                writer.create_dir_all(dir)?;
+                let file = format!("{}_{:?}.png", name.replace('/', "_"), pos);
+                writer.write_image(
+                    &dir.join(file),
+                    &DynamicImage::ImageRgba8(tile),
+                )?;
+            }
+            unmapped.push(pos);
+        }
+    }
+    unmapped.sort();
+    if !unmapped.is_empty() {
+        log::info!("atlas {}: unmapped non-empty cells: {:?}", name, unmapped);
+    }
+    Ok(())
+}
+
+/// Cuts `tile.rect` out of every frame of `strip`
+fn cut_rect(strip: RgbaImage, tile: &Tile) -> RgbaImage {
+    match tile.rect {
+        Some([x, y, w, h]) => map_frames(&strip, tile.frames, |frame| {
+            frame.view(x, y, w, h).to_image()
+        }),
+        None => strip,
+    }
+}
+
+/// Smallest box in every frame of `strip` that holds all their visible
+/// pixels, unless it's the whole frame or there are none
+fn trim_rect(strip: &RgbaImage, frames: u32) -> Option<Trim> {
+    let (width, height) = (strip.width(), strip.height() / frames);
+    let (x0, y0, x1, y1) = (0..frames)
+        .filter_map(|i| {
+            opaque_bbox(&strip.view(0, i * height, width, height).to_image())
+        })
+        .reduce(|(ax0, ay0, ax1, ay1), (bx0, by0, bx1, by1)| {
+            (ax0.min(bx0), ay0.min(by0), ax1.max(bx1), ay1.max(by1))
+        })?;
+    let trim = Trim {
+        x: x0,
+        y: y0,
+        width: x1 + 1 - x0,
+        height: y1 + 1 - y0,
+        frame_width: width,
+        frame_height: height,
+    };
+    (trim.width != width || trim.height != height).then_some(trim)
+}
+
+/// Cuts `trim` out of every frame of `strip`
+fn crop_frames(strip: &RgbaImage, frames: u32, trim: Trim) -> RgbaImage {
+    map_frames(strip, frames, |frame| {
+        frame
+            .view(trim.x, trim.y, trim.width, trim.height)
+            .to_image()
+    })
+}
+
+/// Copy of `strip` with only pixels `glows` picks, the rest transparent
+fn emissive_overlay(
+    strip: &RgbaImage,
+    glows: impl Fn(u32, u32, [u8; 4]) -> bool,
+) -> RgbaImage {
+    RgbaImage::from_fn(strip.width(), strip.height(), |x, y| {
+        let pixel = *strip.get_pixel(x, y);
+        if pixel[3] > 0 && glows(x, y, pixel.0) {
+            pixel
+        } else {
+            Rgba([0; 4])
+        }
+    })
+}
+
+/// Inverse of `cut_rect`, putting every frame of `texture` back into `strip`
+fn fill_rect(strip: &mut RgbaImage, texture: &RgbaImage, tile: &Tile) {
+    let [x, y, w, h] = match tile.rect {
+        Some(rect) => rect,
+        None => {
+            *strip = texture.clone();
+            return;
+        }
+    };
+    let frame = strip.height() / tile.frames;
+    for i in 0..tile.frames {
+        let part = texture.view(0, i * h, w, h);
+        image::imageops::replace(strip, &part, x, i * frame + y);
+    }
+}
+
+/// Directories sliced tiles are written to
+#[derive(Debug, Clone, Default)]
+pub struct TileDirs {
+    pub dir: PathBuf,
+    /// Directories of tiles written elsewhere, by tile name
+    pub moved: BTreeMap<String, PathBuf>,
+}
+
+impl TileDirs {
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_owned(),
+            moved: BTreeMap::new(),
+        }
+    }
+
+    /// Output `name` of `tile`, one of its variants or its emissive overlay
+    pub fn tile_path(&self, tile: &Tile, name: &str) -> PathBuf {
+        let dir = self.moved.get(&tile.name).unwrap_or(&self.dir);
+        dir.join(name).with_extension("png")
+    }
+}
+
+pub(crate) fn mcmeta_path(path: &Path) -> PathBuf {
+    let mut mcmeta = path.to_owned().into_os_string();
+    mcmeta.push(".mcmeta");
+    mcmeta.into()
+}
+
+/// Files written when slicing `atlas`, not counting unmapped cells
+fn tile_outputs(
+    atlas: &Atlas,
+    dirs: &TileDirs,
+    options: &SliceOptions,
+) -> Vec<PathBuf> {
+    let mut outputs = vec![];
+    for tile in atlas.tiles.values() {
+        for name in tile.output_names(&options.palettes) {
+            let path = dirs.tile_path(tile, &name);
+            if tile.is_animated() {
+                outputs.push(mcmeta_path(&path));
+            }
+            outputs.push(path);
+        }
+    }
+    outputs
+}
+
+/// Tells `writer` which sheet cell each tile output comes from
+fn note_tile_sources(
+    atlas: &Atlas,
+    dirs: &TileDirs,
+    writer: &Writer,
+    options: &SliceOptions,
+    source: &str,
+) {
+    for (pos, tile) in &atlas.tiles {
+        let source = format!("{} [{:?}]", source, pos);
+        for name in tile.output_names(&options.palettes) {
+            let path = dirs.tile_path(tile, &name);
+            if tile.is_animated() {
+                writer.note_source(&mcmeta_path(&path), source.clone());
+            }
+            writer.note_source(&path, source.clone());
+        }
+    }
+}
+
+/// Tells `writer` the color chunks of the sheet tiles are cut from
+fn note_tile_chunks(
+    atlas: &Atlas,
+    dirs: &TileDirs,
+    writer: &Writer,
+    options: &SliceOptions,
+    chunks: Arc<[u8]>,
+) {
+    for path in tile_outputs(atlas, dirs, options) {
+        if path.extension().is_some_and(|ext| ext == "png") {
+            writer.note_chunks(&path, chunks.clone());
+        }
+    }
+}
+
+/// Number of files slicing `atlas` writes, counting every palette variant
+/// and animation mcmeta
+pub fn output_count(atlas: &AtlasMap, options: &SliceOptions) -> u64 {
+    atlas
+        .values()
+        .map(|map| {
+            tile_outputs(map, &TileDirs::default(), options).len() as u64
+        })
+        .sum()
+}
+
+pub fn process_atlas<R: BufRead + Seek>(
+    name: &str,
+    atlas: &Atlas,
+    mut input: R,
+    dirs: &TileDirs,
+    writer: &Writer,
+    options: &SliceOptions,
+) -> anyhow::Result<()> {
+    let start = input.stream_position()?;
+    let chunks: Arc<[u8]> = read_color_chunks(&mut input)?.into();
+    input.seek(SeekFrom::Start(start))?;
+    let image = decode_image_from(input, name)?.into_rgba8();
+    note_tile_chunks(atlas, dirs, writer, options, chunks);
+    let aliases = Aliases::new();
+    slice_atlas(name, atlas, &image, dirs, writer, options, &aliases)
+}
+
+/// Like `process_atlas`, for an already decoded sheet. Tiles listed in
+/// `aliases` are skipped.
+pub fn slice_atlas(
+    name: &str,
+    atlas: &Atlas,
+    image: &RgbaImage,
+    dirs: &TileDirs,
+    writer: &Writer,
+    options: &SliceOptions,
+    aliases: &Aliases,
+) -> anyhow::Result<()> {
+    let (width, height) = image.dimensions();
+    let keys = atlas;
+    let (info, atlas) = check_atlas(name, atlas, width, height, options)?;
+    let atlas = &*atlas;
+    atlas.tiles.par_iter().try_for_each(
+        |(pos, tile)| -> anyhow::Result<()> {
+            let started = writer.timings().map(|_| Instant::now());
+            let (w, h) = tile.footprint();
+            let strip = cut_rect(info.crop(image, pos, (w, h)), tile);
+            if looks_empty(&strip) {
+                lint(
+                    options.strict,
+                    format_args!(
+                        "atlas {}: {:?} ({}) is empty, is its position off?",
+                        name, pos, tile.name
+                    ),
+                )?;
+            }
+            if looks_like_grid_line(&strip) {
+                lint(
+                    options.strict,
+                    format_args!(
+                        "atlas {}: {:?} ({}) looks like a grid line",
+                        name, pos, tile.name
+                    ),
+                )?;
+            }
+            let finish = |strip: &RgbaImage| -> anyhow::Result<_> {
+                let mut strip = strip.clone();
+                atlas.clean_alpha(&mut strip);
+                let mut strip = tile.transform.apply(&strip, tile.frames);
+                if let Some(upscale) = options.upscale {
+                    strip = upscale.apply_frames(&strip, tile.frames);
+                }
+                if let Some(scale) = tile.scale {
+                    strip = scale.apply_frames(&strip, tile.frames).map_err(
+                        |e| {
+                            anyhow::anyhow!(
+                                "atlas {}: {:?} ({}): {}",
+                                name,
+                                pos,
+                                tile.name,
+                                e
+                            )
+                        },
+                    )?;
+                }
+                Ok(strip)
+            };
+            let mut strip = finish(&strip)?;
+            if let (Some(hook), Some(hooks)) = (&tile.hook, &options.hooks) {
+                strip = hooks.apply(hook, strip, name, pos, tile)?;
+            }
+            let emissive = match tile.emissive_mask {
+                Some(EmissiveMask::Threshold(threshold)) => {
+                    Some(emissive_overlay(&strip, |_, _, [r, g, b, _]| {
+                        luma(r, g, b) >= threshold
+                    }))
+                }
+                Some(EmissiveMask::Cell(mask)) => {
+                    let mask = cut_rect(info.crop(image, &mask, (w, h)), tile);
+                    let mask = finish(&mask)?;
+                    Some(emissive_overlay(&strip, |x, y, _| {
+                        let [r, g, b, a] = mask.get_pixel(x, y).0;
+                        a > 0 && [r, g, b] != [0, 0, 0]
+                    }))
+                }
+                None => None,
+            };
+            // Overlay keeps lining up with the tile, trimmed the same
+            let trim = match tile.trim.unwrap_or(atlas.trim) {
+                true => trim_rect(&strip, tile.frames),
+                false => None,
+            };
+            let (strip, emissive) = match trim {
+                Some(trim) => (
+                    crop_frames(&strip, tile.frames, trim),
+                    emissive.map(|e| crop_frames(&e, tile.frames, trim)),
+                ),
+                None => (strip, emissive),
+            };
+            if let (Some(timings), Some(started)) = (writer.timings(), started)
+            {
+                timings.add(name, Stage::Crop, started.elapsed());
+            }
+            let atlas_name = name;
+            check_tints(atlas_name, tile, &strip, options)?;
+            for (name, tint) in tile.variants(&options.palettes) {
+                if aliases.contains_key(&name) {
+                    continue;
+                }
+                let write = |name: &str,
+                             strip: &RgbaImage|
+                 -> anyhow::Result<()> {
+                    let mut strip = strip.clone();
+                    if let Some(tint) = tint {
+                        tint.tint(&mut strip);
+                    }
+                    let strip = match atlas.grayscale {
+                        Some(mode) => {
+                            DynamicImage::ImageLuma8(mode.convert(&strip))
+                        }
+                        None => DynamicImage::ImageRgba8(strip),
+                    };
+                    let path = dirs.tile_path(tile, name);
+                    if let Some(trim) = trim {
+                        writer.note_trim(&path, trim);
+                    }
+                    writer.timed(atlas_name, Stage::Encode, || {
+                        writer.write_image(&path, &strip)
+                    })?;
+                    if tile.is_animated() {
+                        let data = animation_mcmeta(tile.frametime);
+                        writer.write(&mcmeta_path(&path), data.as_bytes())?;
+                    }
+                    Ok(())
+                };
+                write(&name, &strip)?;
+                if let Some(emissive) = &emissive {
+                    write(&emissive_name(&name), emissive)?;
+                }
+            }
+            Ok(())
+        },
+    )?;
+    report_unmapped(name, atlas, keys, image, info, writer, options)?;
+    Ok(())
+}
+
+/// Slices every atlas, returning aliases found with `Dedupe::Alias`
+pub fn process_atlas_map(
+    atlas: &AtlasMap,
+    zips: &mut ArchiveSet,
+    dirs: &TileDirs,
+    writer: &Writer,
+    options: &SliceOptions,
+    mut cache: Option<&mut Cache>,
+) -> anyhow::Result<Aliases> {
+    let aliases = match options.dedupe {
+        Some(mode) => {
+            let sheets: Vec<_> = atlas.keys().map(|a| zips.sheet(a)).collect();
+            zips.prefetch_images(&sheets)?;
+            find_duplicates(atlas, zips, options, mode)?
+        }
+        None => Aliases::new(),
+    };
+    // Only sheets of atlases that changed are read, all at once, then
+    // sliced all at once
+    let mut stale = vec![];
+    for (atlas, map) in atlas {
+        let key = dirs.dir.join(atlas).display().to_string();
+        let path = &zips.sheet(atlas);
+        // Aliases depend on other atlases too, and priorities decide which
+        // source the sheet comes from
+        let located = zips.locate(path);
+        let input = cache.as_ref().map(|cache| {
+            cache.input_hash(&format!(
+                "{:?} {:?} {:?} {:?} {:?} {} {:?}",
+                map,
+                dirs.moved,
+                options,
+                aliases,
+                located,
+                writer.optimizes(),
+                writer.color_chunks()
+            ))
+        });
+        if let (Some(cache), Some(input)) = (&mut cache, &input) {
+            if cache.is_fresh(&key, input) {
+                log::info!("atlas {}: up to date", atlas);
+                continue;
+            }
+        }
+        stale.push((atlas, map, path.clone(), located, key, input));
+    }
+    // Only as many sheets as fit in `DECODE_BUDGET` decoded are in memory at
+    // a time, each released once sliced, so huge HD sheets don't add up
+    let mut batches: Vec<&[_]> = vec![];
+    let (mut start, mut total) = (0, 0);
+    for (i, (_, _, path, ..)) in stale.iter().enumerate() {
+        let size = match zips.image_dimensions(path)? {
+            Some((width, height)) => decoded_size(width, height),
+            None => DECODE_BUDGET,
+        };
+        if i > start && total + size > DECODE_BUDGET {
+            batches.push(&stale[start..i]);
+            start = i;
+            total = 0;
+        }
+        total += size;
+    }
+    batches.push(&stale[start..]);
+    for batch in batches {
+        let paths: Vec<_> =
+            batch.iter().map(|(_, _, path, ..)| path.clone()).collect();
+        zips.prefetch_images(&paths)?;
+        let mut sheets = vec![];
+        for (atlas, map, path, located, ..) in batch {
+            let image = zips.image(path)?;
+            let load_time = zips.load_time(path);
+            if let (Some(timings), Some((read, decode))) =
+                (writer.timings(), load_time)
+            {
+                timings.add(atlas, Stage::Read, read);
+                timings.add(atlas, Stage::Decode, decode);
+            }
+            if let Some(source) = located {
+                note_tile_sources(map, dirs, writer, options, source);
+            }
+            let chunks = zips.color_chunks(path);
+            note_tile_chunks(map, dirs, writer, options, chunks);
+            sheets.push((atlas, map, image));
+        }
+        sheets.par_iter().try_for_each(|(atlas, map, image)| {
+            slice_atlas(atlas, map, image, dirs, writer, options, &aliases)
+        })?;
+        drop(sheets);
+        for path in &paths {
+            zips.release_image(path);
+        }
+    }
+    if let Some(cache) = cache {
+        for (_, map, _, _, key, input) in stale {
+            let mut skipped = BTreeSet::new();
+            for tile in map.tiles.values() {
+                for (alias, _) in tile.variants(&options.palettes) {
+                    if !aliases.contains_key(&alias) {
+                        continue;
+                    }
+                    // Emissive overlays of aliases are skipped with them
+                    for name in [emissive_name(&alias), alias] {
+                        let path = dirs.tile_path(tile, &name);
+                        skipped.extend([mcmeta_path(&path), path]);
+                    }
+                }
+            }
+            let mut outputs = tile_outputs(map, dirs, options);
+            outputs.retain(|path| !skipped.contains(path));
+            let outputs: Vec<_> = outputs
+                .iter()
+                .map(|path| writer.output_path(path))
+                .collect();
+            cache.record(&key, input.unwrap(), &outputs, writer)?;
+        }
+    }
+    Ok(aliases)
+}
+
+/// Cuts pixel rects out of a single non-grid sheet, e.g. a GUI background
+pub fn process_crops(
+    name: &str,
+    crops: &[Crop],
+    image: &RgbaImage,
+    output_dir: &Path,
+    writer: &Writer,
+    upscale: Option<Upscale>,
+) -> anyhow::Result<()> {
+    let (width, height) = image.dimensions();
+    for crop in crops {
+        anyhow::ensure!(
+            crop.w > 0
+                && crop.h > 0
+                && crop.x.saturating_add(crop.w) <= width
+                && crop.y.saturating_add(crop.h) <= height,
+            "{}: crop {} ({}x{} at {},{}) doesn't fit in {}x{} sheet",
+            name,
+            crop.name,
+            crop.w,
+            crop.h,
+            crop.x,
+            crop.y,
+            width,
+            height
+        );
+        let border = crop.ninepatch.as_ref().map(|n| n.border.sides());
+        if let Some([left, top, right, bottom]) = border {
+            anyhow::ensure!(
+                left + right < crop.w && top + bottom < crop.h,
+                "{}: crop {}: nine-slice border {:?} leaves no center in {}x{}",
+                name,
+                crop.name,
+                [left, top, right, bottom],
+                crop.w,
+                crop.h
+            );
+        }
+        let mut part = image.view(crop.x, crop.y, crop.w, crop.h).to_image();
+        let mut factor = 1;
+        if let Some(upscale) = upscale {
+            part = upscale.apply(&part);
+            factor = upscale.factor();
+        }
+        let path = output_dir.join(&crop.name).with_extension("png");
+        match (&crop.ninepatch, border) {
+            (Some(ninepatch), Some(border)) if ninepatch.split => {
+                let [left, top, right, bottom] =
+                    border.map(|side| side * factor);
+                let (width, height) = part.dimensions();
+                let xs = [0, left, width - right, width];
+                let ys = [0, top, height - bottom, height];
+                // Outputs are in NINE_SLICE_PIECES order, row by row
+                for (i, file) in crop.outputs().into_iter().enumerate() {
+                    let (column, row) = (i % 3, i / 3);
+                    let (x, y) = (xs[column], ys[row]);
+                    let (w, h) = (xs[column + 1] - x, ys[row + 1] - y);
+                    let piece = part.view(x, y, w, h).to_image();
+                    let path = output_dir.join(file);
+                    writer
+                        .write_image(&path, &DynamicImage::ImageRgba8(piece))?;
+                }
+            }
+            (Some(_), Some([left, top, right, bottom])) => {
+                writer.write_image(&path, &DynamicImage::ImageRgba8(part))?;
+                // Sizes are in gui pixels, the same whatever the upscale
+                let mcmeta = serde_json::json!({
+                    "gui": {
+                        "scaling": {
+                            "type": "nine_slice",
+                            "width": crop.w,
+                            "height": crop.h,
+                            "border": {
+                                "left": left,
+                                "top": top,
+                                "right": right,
+                                "bottom": bottom,
+                            },
+                        },
+                    },
+                });
+                let mcmeta = serde_json::to_string_pretty(&mcmeta)?;
+                writer.write(&mcmeta_path(&path), mcmeta.as_bytes())?;
+            }
+            _ => writer.write_image(&path, &DynamicImage::ImageRgba8(part))?,
+        }
+    }
+    Ok(())
+}
+
+pub fn process_crop_map(
+    crops: &CropMap,
+    zips: &mut ArchiveSet,
+    output_dir: &Path,
+    writer: &Writer,
+    upscale: Option<Upscale>,
+) -> anyhow::Result<()> {
+    for (sheet, crops) in crops {
+        process_sheet_crops(sheet, crops, zips, output_dir, writer, upscale)?;
+    }
+    Ok(())
+}
+
+/// Cuts `crops` out of `sheet` from the archives, noting where each came
+/// from
+pub fn process_sheet_crops(
+    sheet: &str,
+    crops: &[Crop],
+    zips: &mut ArchiveSet,
+    output_dir: &Path,
+    writer: &Writer,
+    upscale: Option<Upscale>,
+) -> anyhow::Result<()> {
+    let image = zips.image(sheet)?;
+    let chunks = zips.color_chunks(sheet);
+    let is_png = |file: &PathBuf| file.extension().is_some_and(|e| e == "png");
+    for crop in crops {
+        for file in crop.outputs().iter().filter(|file| is_png(file)) {
+            writer.note_chunks(&output_dir.join(file), chunks.clone());
+        }
+    }
+    if let Some(source) = zips.locate(sheet) {
+        for crop in crops {
+            let source = format!(
+                "{} [{}x{} at {},{}]",
+                source, crop.w, crop.h, crop.x, crop.y
+            );
+            for file in crop.outputs().iter().filter(|file| is_png(file)) {
+                writer.note_source(&output_dir.join(file), source.clone());
+            }
+        }
+    }
+    process_crops(sheet, crops, &image, output_dir, writer, upscale)
+}
+
+/// Slices every connected texture set into `<name>/<i>.png`, numbered
+/// row by row from its start cell. Sheets are laid out like the first atlas
+/// of the same name in `atlases`, if any, with tile size, margin, spacing
+/// and padding.
+pub fn process_ctm_map(
+    ctm: &CtmMap,
+    atlases: &[&AtlasMap],
+    zips: &mut ArchiveSet,
+    output_dir: &Path,
+    writer: &Writer,
+    options: &SliceOptions,
+) -> anyhow::Result<()> {
+    for (name, set) in ctm {
+        let sheet = zips.sheet(&set.atlas);
+        let image = zips.image(&sheet)?;
+        let chunks = zips.color_chunks(&sheet);
+        let source = zips.locate(&sheet);
+        let (width, height) = image.dimensions();
+        let layout = atlases.iter().find_map(|map| map.get(&set.atlas));
+        let info = match layout {
+            Some(atlas) => {
+                AtlasInfo::of(atlas, width, height, options.tile_size)
+            }
+            None => AtlasInfo::new(width, height, options.tile_size),
+        };
+        anyhow::ensure!(
+            info.tile_size > 0,
+            "ctm {}: tile size of {} can't be 0",
+            name,
+            set.atlas
+        );
+        let (columns, rows) = set.method.region();
+        let start = set.start.place(info.columns());
+        anyhow::ensure!(
+            info.contains_area(&start, (columns, rows)),
+            "ctm {}: {}x{} cells from {:?} don't fit in {} ({}x{} cells)",
+            name,
+            columns,
+            rows,
+            set.start,
+            set.atlas,
+            info.columns(),
+            info.rows()
+        );
+        let (tiles, _) = set.method.layout();
+        for i in 0..tiles {
+            let pos = AtlasPos::from_pos(
+                start.x() + (i % columns) as u8,
+                start.y() + (i / columns) as u8,
+            );
+            let mut tile = info.crop(&image, &pos, (1, 1));
+            if let Some(upscale) = options.upscale {
+                tile = upscale.apply(&tile);
+            }
+            let path = output_dir.join(name).join(format!("{}.png", i));
+            if let Some(source) = &source {
+                writer.note_source(&path, format!("{} [{:?}]", source, pos));
+            }
+            writer.note_chunks(&path, chunks.clone());
+            writer.write_image(&path, &DynamicImage::ImageRgba8(tile))?;
+        }
+    }
+    Ok(())
+}
+
+/// Decodes every atlas and checks mappings against it, without slicing
+pub fn validate_atlas_map(
+    atlas: &AtlasMap,
+    zips: &mut ArchiveSet,
+    options: &SliceOptions,
+) -> anyhow::Result<()> {
+    for (atlas, map) in atlas {
+        let sheet = zips.sheet(atlas);
+        let image = zips.image(&sheet)?;
+        let (width, height) = image.dimensions();
+        check_atlas(atlas, map, width, height, options)?;
+    }
+    Ok(())
+}
+
+/// Inverse of `process_atlas`: pastes loose textures back into the sheet.
+/// Cells without a loose texture keep their original content, if any.
+pub fn stitch_atlas(
+    name: &str,
+    atlas: &Atlas,
+    base: Option<RgbaImage>,
+    input_dir: &Path,
+    options: &SliceOptions,
+) -> anyhow::Result<RgbaImage> {
+    let mut info = AtlasInfo::of(atlas, 0, 0, options.tile_size);
+    let size = info.tile_size;
+    anyhow::ensure!(size > 0, "atlas {}: tile size can't be 0", name);
+    let (columns, rows) = match &base {
+        Some(base) => {
+            let (width, height) = base.dimensions();
+            let info = AtlasInfo::of(atlas, width, height, size);
+            (info.columns(), info.rows())
+        }
+        None => {
+            // Without a sheet, a strip's animation is as long as its texture
+            let frames = atlas
+                .tiles
+                .values()
+                .filter(|tile| tile.frames == 0)
+                .find_map(|tile| {
+                    let path = input_dir.join(&tile.name).with_extension("png");
+                    let (_, height) = image::image_dimensions(path).ok()?;
+                    height.checked_div(tile.rect.map_or(size, |[_, _, _, h]| h))
+                })
+                .unwrap_or(0);
+            // and a grid is grown past the classic one if tiles need it,
+            // which doesn't depend on the corner they're counted from
+            let least = match atlas.kind {
+                AtlasKind::Grid => (CLASSIC_CELLS, CLASSIC_CELLS),
+                AtlasKind::Strip => (1, frames.max(1)),
+            };
+            let unflipped = atlas
+                .with_grid(0, 0)
+                .map_err(|e| anyhow::anyhow!("atlas {}: {}", name, e))?;
+            unflipped.tiles.iter().fold(
+                least,
+                |(columns, rows), (pos, tile)| {
+                    let (w, h) = tile.footprint();
+                    // Indices are counted over the classic width at least
+                    let y = pos.index().map_or(pos.y() as u32, |index| {
+                        index as u32 / CLASSIC_CELLS
+                    });
+                    (
+                        columns.max(pos.x() as u32 + w),
+                        rows.max(y + h),
+                    )
+                },
+            )
+        }
+    };
+    let atlas = &*atlas
+        .with_grid(columns, rows)
+        .map_err(|e| anyhow::anyhow!("atlas {}: {}", name, e))?;
+    let mut image = base.unwrap_or_else(|| {
+        RgbaImage::new(info.length(columns), info.length(rows))
+    });
+    let (width, height) = image.dimensions();
+    info.width = width;
+    info.height = height;
+    check_multiple(name, info)?;
+    check_grid_bounds(name, atlas, info)?;
+    for (pos, tile) in &atlas.tiles {
+        let path = input_dir.join(&tile.name).with_extension("png");
+        let texture = match image::open(&path) {
+            Ok(texture) => texture.to_rgba8(),
+            Err(image::ImageError::IoError(e))
+                if e.kind() == std::io::ErrorKind::NotFound =>
+            {
+                lint(
+                    options.strict,
+                    format_args!(
+                        "atlas {}: {:?} ({}) has no loose texture",
+                        name, pos, tile.name
+                    ),
+                )?;
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if tile.transform.recolor.is_some() {
+            lint(
+                options.strict,
+                format_args!(
+                    "atlas {}: {:?} ({}) was recolored, stitching it as is",
+                    name, pos, tile.name
+                ),
+            )?;
+        }
+        let texture = match tile.scale {
+            Some(scale) => scale
+                .inverse()
+                .apply_frames(&texture, tile.frames)
+                .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?,
+            None => texture,
+        };
+        let texture = tile.transform.undo(&texture, tile.frames);
+        let (w, h) = tile.footprint();
+        let expected = match tile.rect {
+            Some([_, _, rw, rh]) => (rw, rh * tile.frames),
+            None => (size * w, size * h),
+        };
+        anyhow::ensure!(
+            texture.dimensions() == expected,
+            "{}: expected {}x{} texture, found {}x{}",
+            path.display(),
+            expected.0,
+            expected.1,
+            texture.width(),
+            texture.height(),
+        );
+        // Pixels around `rect` are kept from the base sheet
+        let mut strip = info.crop(&image, pos, (w, h));
+        fill_rect(&mut strip, &texture, tile);
+        info.paste(&mut image, pos, &strip);
+    }
+    Ok(image)
+}
+
+pub fn stitch_atlas_map(
+    atlas: &AtlasMap,
+    zips: &mut ArchiveSet,
+    input_dir: &Path,
+    output_dir: &Path,
+    writer: &Writer,
+    options: &SliceOptions,
+) -> anyhow::Result<()> {
+    for (atlas, map) in atlas {
+        let path = Path::new(atlas).with_extension("png");
+        let sheet = zips.sheet(atlas);
+        let base = zips.find_image(&sheet)?.map(|image| (*image).clone());
+        let image = stitch_atlas(atlas, map, base, input_dir, options)?;
+        let output = output_dir.join(&path);
+        if let Some(parent) = output.parent() {
+            writer.create_dir_all(parent)?;
+        }
+        writer.write_image(&output, &DynamicImage::ImageRgba8(image))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{
+        assert_output, cell_color, encode_png, zip_of, Fixture,
+    };
+
+    #[test]
+    fn info_of_size_not_multiple_of_tile() {
+        let info = AtlasInfo::new(40, 36, 16);
+        assert_eq!((info.columns(), info.rows()), (2, 2));
+        assert!(check_multiple("odd", info).is_err());
+        // Leftover pixels don't make a partial cell
+        assert!(!info.contains(&AtlasPos::from_pos(2, 0)));
+        assert!(!info.contains(&AtlasPos::from_pos(0, 2)));
+    }
+
+    #[test]
+    fn info_of_non_square_sheet() {
+        let info = AtlasInfo::new(64, 32, 16);
+        assert_eq!((info.columns(), info.rows()), (4, 2));
+        assert!(check_multiple("wide", info).is_ok());
+        assert!(info.contains(&AtlasPos::from_pos(3, 1)));
+        assert!(!info.contains(&AtlasPos::from_pos(1, 2)));
+        assert!(info.contains_area(&AtlasPos::from_pos(2, 0), (2, 2)));
+        assert!(!info.contains_strip(&AtlasPos::from_pos(0, 1), 2));
+
+        let tall = AtlasInfo::new(16, 48, 16);
+        assert_eq!((tall.columns(), tall.rows()), (1, 3));
+        assert!(tall.contains_strip(&AtlasPos::from_pos(0, 0), 3));
+    }
+
+    #[test]
+    fn info_of_sheet_smaller_than_cell() {
+        let info = AtlasInfo::new(8, 12, 16);
+        assert_eq!((info.columns(), info.rows()), (0, 0));
+        assert!(check_multiple("tiny", info).is_err());
+        assert!(!info.contains(&AtlasPos::from_pos(0, 0)));
+
+        let gutters = AtlasInfo {
+            margin: 2,
+            ..AtlasInfo::new(3, 3, 16)
+        };
+        assert_eq!((gutters.columns(), gutters.rows()), (0, 0));
+        assert!(check_multiple("tiny", gutters).is_err());
+    }
+
+    #[test]
+    fn info_with_gutters_on_odd_sizes() {
+        // 1 + 18 + 2 + 18 + 1 wide, 1 + 18 + 1 high
+        let info = AtlasInfo {
+            margin: 1,
+            spacing: 2,
+            padding: 1,
+            ..AtlasInfo::new(40, 20, 16)
+        };
+        assert_eq!((info.columns(), info.rows()), (2, 1));
+        assert!(check_multiple("gutters", info).is_ok());
+        assert_eq!(info.origin(1, 0), (22, 2));
+
+        let off = AtlasInfo { width: 41, ..info };
+        assert_eq!(off.columns(), 2);
+        assert!(check_multiple("gutters", off).is_err());
+    }
+
+    #[test]
+    fn ctm_follows_sheet_layout() {
+        // 8 pixel tiles, 1 pixel margin and 2 pixel spacing, 4 by 1 cells
+        let info = AtlasInfo {
+            margin: 1,
+            spacing: 2,
+            ..AtlasInfo::new(4 * 10, 10, 8)
+        };
+        let mut sheet = RgbaImage::new(info.width, info.height);
+        for x in 0..4 {
+            let tile = RgbaImage::from_pixel(8, 8, cell_color(x, 0));
+            info.paste(&mut sheet, &AtlasPos::from_pos(x as u8, 0), &tile);
+        }
+        let jar = zip_of([("textures/glass.png", encode_png(&sheet))]).unwrap();
+        let extracted = Fixture::new(
+            r#"
+            modid = "demo"
+
+            [folders]
+            "demo.jar" = ["textures"]
+
+            [blocks.glass]
+            tile_size = 8
+            margin = 1
+            spacing = 2
+            00 = "glass"
+
+            [ctm.glass]
+            atlas = "glass"
+            start = "00"
+            method = "horizontal"
+            "#,
+        )
+        .with_archive("demo.jar", jar)
+        .run()
+        .unwrap();
+        for i in 0..4 {
+            let path = format!("assets/demo/optifine/ctm/glass/{}.png", i);
+            let tile = RgbaImage::from_pixel(8, 8, cell_color(i, 0));
+            assert_output(&extracted, &path, &tile);
+        }
+    }
+}
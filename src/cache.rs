@@ -0,0 +1,186 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    hash::Hasher,
+    io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Folders, Writer};
+
+/// Name of manifest file, stored in output root
+pub const CACHE_FILE: &str = ".tex-slasher-cache.json";
+
+/// 64-bit FNV-1a, used instead of `DefaultHasher` so hashes stay stable
+/// between builds
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+pub(crate) fn hash(data: &[u8]) -> String {
+    let mut hasher = Fnv::default();
+    hasher.write(data);
+    format!("{:016x}", hasher.finish())
+}
+
+/// Hash of an archive, or of every file in a directory source together with
+/// its relative path
+fn hash_source(path: &Path) -> anyhow::Result<String> {
+    if !path.is_dir() {
+        return Ok(hash(&fs::read(path)?));
+    }
+    let mut files = vec![];
+    let mut stack = vec![path.to_owned()];
+    while let Some(dir) = stack.pop() {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?.path();
+            if entry.is_dir() {
+                stack.push(entry);
+            } else {
+                files.push(entry);
+            }
+        }
+    }
+    files.sort();
+    let mut hasher = Fnv::default();
+    for file in files {
+        let relative = file.strip_prefix(path)?;
+        hasher.write(relative.to_string_lossy().as_bytes());
+        hasher.write(hash(&fs::read(&file)?).as_bytes());
+    }
+    Ok(format!("{:016x}", hasher.finish()))
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Manifest {
+    /// Hash of each input archive or directory
+    archives: BTreeMap<String, String>,
+    jobs: BTreeMap<String, Job>,
+}
+
+/// Record of a single piece of work, e.g. slicing one atlas
+#[derive(Serialize, Deserialize)]
+struct Job {
+    /// Hash of everything the outputs were made from
+    input: String,
+    /// Hash of each output file, as it was left on disk
+    outputs: BTreeMap<PathBuf, String>,
+}
+
+/// Manifest of input and output hashes from previous run, used to skip work
+/// whose inputs didn't change and whose outputs are still intact
+pub struct Cache {
+    path: PathBuf,
+    old: Manifest,
+    new: Manifest,
+}
+
+impl Cache {
+    /// Loads manifest from `path`, if there is one, and hashes archives
+    /// listed in `folders`. With `fresh`, previous manifest is ignored.
+    pub fn new(
+        path: PathBuf,
+        folders: &Folders,
+        input_dir: &Path,
+        fresh: bool,
+    ) -> anyhow::Result<Self> {
+        let old = match fs::read(&path) {
+            Ok(_) if fresh => Manifest::default(),
+            Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+                log::warn!("ignoring broken {}: {}", path.display(), e);
+                Manifest::default()
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                Manifest::default()
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let archives = folders
+            .keys()
+            .map(|file| {
+                // Nested archives change only together with the outer one
+                let outer = file.split('!').next().unwrap();
+                Ok((file.clone(), hash_source(&input_dir.join(outer))?))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            path,
+            old,
+            new: Manifest {
+                archives,
+                jobs: BTreeMap::new(),
+            },
+        })
+    }
+
+    /// Hash of job's `description`, combined with hashes of all archives
+    pub fn input_hash(&self, description: &str) -> String {
+        let mut hasher = Fnv::default();
+        for (file, hash) in &self.new.archives {
+            hasher.write(file.as_bytes());
+            hasher.write(hash.as_bytes());
+        }
+        hasher.write(description.as_bytes());
+        format!("{:016x}", hasher.finish())
+    }
+
+    /// Checks if job `key` was already done with the same `input`, and its
+    /// outputs weren't touched since. Fresh jobs are carried over to new
+    /// manifest.
+    pub fn is_fresh(&mut self, key: &str, input: &str) -> bool {
+        let job = match self.old.jobs.get(key) {
+            Some(job) if job.input == input => job,
+            _ => return false,
+        };
+        let intact = job.outputs.iter().all(|(path, expected)| {
+            fs::read(path).is_ok_and(|data| hash(&data) == *expected)
+        });
+        if intact {
+            let job = self.old.jobs.remove(key).unwrap();
+            self.new.jobs.insert(key.to_owned(), job);
+        }
+        intact
+    }
+
+    /// Records job `key` as done, hashing `outputs` as `writer` leaves them
+    pub fn record(
+        &mut self,
+        key: &str,
+        input: String,
+        outputs: &[PathBuf],
+        writer: &Writer,
+    ) -> anyhow::Result<()> {
+        let outputs = outputs
+            .iter()
+            .map(|path| Ok((path.clone(), hash(&writer.read(path)?))))
+            .collect::<anyhow::Result<_>>()?;
+        self.new.jobs.insert(key.to_owned(), Job { input, outputs });
+        Ok(())
+    }
+
+    /// Writes new manifest, replacing the old one
+    pub fn save(self) -> anyhow::Result<()> {
+        let data = serde_json::to_string_pretty(&self.new)?;
+        fs::write(&self.path, data)?;
+        Ok(())
+    }
+}
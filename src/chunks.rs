@@ -0,0 +1,269 @@
+use std::{
+    convert::{TryFrom, TryInto},
+    io::{self, Read, Seek, SeekFrom},
+    str::FromStr,
+};
+
+const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Ancillary chunks that change how colors are displayed
+const COLOR_CHUNKS: [&[u8]; 4] = [b"gAMA", b"cHRM", b"sRGB", b"iCCP"];
+
+/// What to do with gamma and color profile chunks of png outputs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorChunks {
+    /// Carry them over from the source, to sliced tiles too
+    Keep,
+    /// Drop them everywhere, copied textures included
+    #[default]
+    Strip,
+}
+
+impl FromStr for ColorChunks {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keep" => Ok(Self::Keep),
+            "strip" => Ok(Self::Strip),
+            _ => anyhow::bail!("expected one of: keep, strip"),
+        }
+    }
+}
+
+/// Byte ranges of chunks in `data` from `pos` on, with the type of each,
+/// or `None` if one of them is cut off
+fn parse(data: &[u8], mut pos: usize) -> Option<Vec<(usize, usize, &[u8])>> {
+    let mut chunks = vec![];
+    while pos < data.len() {
+        let header = data.get(pos..pos + 8)?;
+        let len = u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+        let end = pos + 12 + len;
+        if end > data.len() {
+            return None;
+        }
+        chunks.push((pos, end, &header[4..]));
+        pos = end;
+    }
+    Some(chunks)
+}
+
+/// Chunks of `png`, or `None` if it isn't one
+fn chunks(png: &[u8]) -> Option<Vec<(usize, usize, &[u8])>> {
+    if !png.starts_with(SIGNATURE) {
+        return None;
+    }
+    parse(png, SIGNATURE.len())
+}
+
+fn is_color_chunk(kind: &[u8]) -> bool {
+    COLOR_CHUNKS.contains(&kind)
+}
+
+/// Color chunks of `png`, whole and back to back
+pub fn color_chunks(png: &[u8]) -> Vec<u8> {
+    chunks(png)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(.., kind)| is_color_chunk(kind))
+        .flat_map(|(start, end, _)| &png[start..end])
+        .copied()
+        .collect()
+}
+
+/// Like `color_chunks`, but reads only chunk headers of a png from `input`,
+/// seeking over the rest, so image data is never loaded
+pub fn read_color_chunks<R: Read + Seek>(input: &mut R) -> io::Result<Vec<u8>> {
+    let mut signature = [0; 8];
+    if input.read_exact(&mut signature).is_err() || signature != SIGNATURE {
+        return Ok(vec![]);
+    }
+    let mut out = vec![];
+    let mut header = [0; 8];
+    loop {
+        // Cut off pngs have no color chunks, as with `color_chunks`
+        if input.read_exact(&mut header).is_err() {
+            return Ok(vec![]);
+        }
+        let len = u32::from_be_bytes(header[..4].try_into().unwrap());
+        let kind = &header[4..];
+        if is_color_chunk(kind) {
+            let mut rest = vec![0; len as usize + 4];
+            if input.read_exact(&mut rest).is_err() {
+                return Ok(vec![]);
+            }
+            out.extend_from_slice(&header);
+            out.extend(rest);
+        } else {
+            input.seek(SeekFrom::Current(i64::from(len) + 4))?;
+        }
+        if kind == b"IEND" {
+            return Ok(out);
+        }
+    }
+}
+
+/// Reads a png from `input` up to its image data, which color chunks have
+/// to come before, returning everything read, and its color chunks. Those
+/// are `None` if it isn't a png, and empty if it's cut off, as with
+/// `color_chunks`.
+pub fn read_png_head<R: Read>(
+    input: &mut R,
+) -> io::Result<(Vec<u8>, Option<Vec<u8>>)> {
+    let mut head = vec![];
+    input.by_ref().take(SIGNATURE.len() as u64).read_to_end(&mut head)?;
+    if head != SIGNATURE {
+        return Ok((head, None));
+    }
+    let mut out = vec![];
+    loop {
+        let start = head.len();
+        input.by_ref().take(8).read_to_end(&mut head)?;
+        if head.len() - start < 8 {
+            return Ok((head, Some(vec![])));
+        }
+        let header: [u8; 8] = head[start..].try_into().unwrap();
+        let kind = &header[4..];
+        if kind == b"IDAT" || kind == b"IEND" {
+            return Ok((head, Some(out)));
+        }
+        let len = u32::from_be_bytes(header[..4].try_into().unwrap());
+        let rest = u64::from(len) + 4;
+        if input.by_ref().take(rest).read_to_end(&mut head)? as u64 != rest {
+            return Ok((head, Some(vec![])));
+        }
+        if is_color_chunk(kind) {
+            out.extend_from_slice(&head[start..]);
+        }
+    }
+}
+
+/// `png` without any color chunks. Anything that doesn't parse as png is
+/// returned as is.
+pub fn strip_color_chunks(png: &[u8]) -> Vec<u8> {
+    let chunks = match chunks(png) {
+        Some(chunks) => chunks,
+        None => return png.to_owned(),
+    };
+    let mut out = SIGNATURE.to_owned();
+    for (start, end, kind) in chunks {
+        if !is_color_chunk(kind) {
+            out.extend_from_slice(&png[start..end]);
+        }
+    }
+    out
+}
+
+/// `png` with its color chunks replaced by `color`, right after `IHDR`.
+/// Color profiles are left out of grayscale images, which can't use an RGB
+/// one.
+pub fn insert_color_chunks(png: &[u8], color: &[u8]) -> Vec<u8> {
+    let chunks = match chunks(png) {
+        Some(chunks) if chunks.first().is_some_and(|c| c.2 == b"IHDR") => {
+            chunks
+        }
+        _ => return png.to_owned(),
+    };
+    // Color type is the 10th byte of IHDR data
+    let grayscale = matches!(png.get(SIGNATURE.len() + 17), Some(0 | 4));
+    let mut out = SIGNATURE.to_owned();
+    for (start, end, kind) in chunks {
+        if is_color_chunk(kind) {
+            continue;
+        }
+        out.extend_from_slice(&png[start..end]);
+        if kind == b"IHDR" {
+            for (start, end, kind) in parse(color, 0).unwrap_or_default() {
+                if !(grayscale && kind == b"iCCP") {
+                    out.extend_from_slice(&color[start..end]);
+                }
+            }
+        }
+    }
+    out
+}
+
+/// CRC-32 png chunks end with, over their type and data
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+/// `png` with a `tEXt` chunk of `keyword` and `text` before `IEND`, or
+/// replacing an earlier one of the same `keyword`. Characters outside
+/// Latin-1, which `tEXt` is limited to, are written as `?`.
+pub fn insert_text_chunk(png: &[u8], keyword: &str, text: &str) -> Vec<u8> {
+    let chunks = match chunks(png) {
+        Some(chunks) if chunks.last().is_some_and(|c| c.2 == b"IEND") => chunks,
+        _ => return png.to_owned(),
+    };
+    let latin1 = |s: &str| -> Vec<u8> {
+        s.chars()
+            .map(|c| u8::try_from(u32::from(c)).unwrap_or(b'?'))
+            .collect()
+    };
+    let mut data = latin1(keyword);
+    data.push(0);
+    let prefix = data.len();
+    data.extend(latin1(text));
+    let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+    chunk.extend_from_slice(b"tEXt");
+    chunk.extend(&data);
+    chunk.extend(crc32(&chunk[4..]).to_be_bytes());
+    let mut out = SIGNATURE.to_owned();
+    for (start, end, kind) in chunks {
+        let same = kind == b"tEXt"
+            && png[start + 8..end - 4].starts_with(&data[..prefix]);
+        if kind == b"IEND" {
+            out.extend(&chunk);
+        }
+        if !same {
+            out.extend_from_slice(&png[start..end]);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png_with_gamma() -> Vec<u8> {
+        let mut png = vec![];
+        let image = image::RgbaImage::new(4, 4);
+        image::codecs::png::PngEncoder::new(&mut png)
+            .encode(&image, 4, 4, image::ColorType::Rgba8)
+            .unwrap();
+        let mut gamma = vec![0, 0, 0, 4];
+        gamma.extend(b"gAMA");
+        gamma.extend(45455u32.to_be_bytes());
+        gamma.extend(crc32(&gamma[4..]).to_be_bytes());
+        insert_color_chunks(&png, &gamma)
+    }
+
+    #[test]
+    fn png_head_stops_at_image_data() {
+        let png = png_with_gamma();
+        let (head, chunks) = read_png_head(&mut &png[..]).unwrap();
+        assert_eq!(chunks, Some(color_chunks(&png)));
+        assert!(!color_chunks(&png).is_empty());
+        assert!(png.starts_with(&head));
+        assert!(head.ends_with(b"IDAT"));
+    }
+
+    #[test]
+    fn png_head_of_other_files() {
+        let (head, chunks) = read_png_head(&mut &b"BM not a png"[..]).unwrap();
+        assert_eq!((&head[..], chunks), (&b"BM not a"[..], None));
+        let png = png_with_gamma();
+        let cut = &png[..SIGNATURE.len() + 10];
+        let (head, chunks) = read_png_head(&mut &cut[..]).unwrap();
+        assert_eq!((&head[..], chunks), (cut, Some(vec![])));
+    }
+}
@@ -0,0 +1,382 @@
+use std::{collections::BTreeMap, path::Path, str::FromStr};
+
+use crate::{Atlas, AtlasKind, AtlasPos, Tile};
+
+/// Game version a classic sheet comes from, deciding which of its cells are
+/// known
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Era {
+    /// Beta 1.7.3 and earlier
+    Beta,
+    /// 1.0 up to 1.4, adding everything from the Adventure Update on
+    Release,
+}
+
+impl FromStr for Era {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "beta" => Ok(Self::Beta),
+            "release" => Ok(Self::Release),
+            _ => Err(format!("unknown era {:?}, expected beta or release", s)),
+        }
+    }
+}
+
+/// Cells of `terrain.png` present since Beta, by index in reading order,
+/// with modern texture names
+const TERRAIN_BETA: &[(u8, &str)] = &[
+    (0, "grass_block_top"),
+    (1, "stone"),
+    (2, "dirt"),
+    (3, "grass_block_side"),
+    (4, "oak_planks"),
+    (5, "smooth_stone_slab_side"),
+    (6, "smooth_stone"),
+    (7, "bricks"),
+    (8, "tnt_side"),
+    (9, "tnt_top"),
+    (10, "tnt_bottom"),
+    (11, "cobweb"),
+    (12, "poppy"),
+    (13, "dandelion"),
+    (15, "oak_sapling"),
+    (16, "cobblestone"),
+    (17, "bedrock"),
+    (18, "sand"),
+    (19, "gravel"),
+    (20, "oak_log"),
+    (21, "oak_log_top"),
+    (22, "iron_block"),
+    (23, "gold_block"),
+    (24, "diamond_block"),
+    (28, "red_mushroom"),
+    (29, "brown_mushroom"),
+    (32, "gold_ore"),
+    (33, "iron_ore"),
+    (34, "coal_ore"),
+    (35, "bookshelf"),
+    (36, "mossy_cobblestone"),
+    (37, "obsidian"),
+    (38, "grass_block_side_overlay"),
+    (39, "short_grass"),
+    (43, "crafting_table_top"),
+    (44, "furnace_front"),
+    (45, "furnace_side"),
+    (46, "dispenser_front"),
+    (48, "sponge"),
+    (49, "glass"),
+    (50, "diamond_ore"),
+    (51, "redstone_ore"),
+    (52, "oak_leaves"),
+    (55, "dead_bush"),
+    (56, "fern"),
+    (59, "crafting_table_side"),
+    (60, "crafting_table_front"),
+    (61, "furnace_front_on"),
+    (62, "furnace_top"),
+    (63, "spruce_sapling"),
+    (64, "white_wool"),
+    (65, "spawner"),
+    (66, "snow"),
+    (67, "ice"),
+    (68, "grass_block_snow"),
+    (69, "cactus_top"),
+    (70, "cactus_side"),
+    (71, "cactus_bottom"),
+    (72, "clay"),
+    (73, "sugar_cane"),
+    (74, "jukebox_side"),
+    (75, "jukebox_top"),
+    (79, "birch_sapling"),
+    (80, "torch"),
+    (81, "oak_door_top"),
+    (82, "iron_door_top"),
+    (83, "ladder"),
+    (84, "oak_trapdoor"),
+    (86, "farmland_moist"),
+    (87, "farmland"),
+    (88, "wheat_stage0"),
+    (89, "wheat_stage1"),
+    (90, "wheat_stage2"),
+    (91, "wheat_stage3"),
+    (92, "wheat_stage4"),
+    (93, "wheat_stage5"),
+    (94, "wheat_stage6"),
+    (95, "wheat_stage7"),
+    (96, "lever"),
+    (97, "oak_door_bottom"),
+    (98, "iron_door_bottom"),
+    (99, "redstone_torch"),
+    (102, "pumpkin_top"),
+    (103, "netherrack"),
+    (104, "soul_sand"),
+    (105, "glowstone"),
+    (106, "piston_top_sticky"),
+    (107, "piston_top"),
+    (108, "piston_side"),
+    (109, "piston_bottom"),
+    (110, "piston_inner"),
+    (112, "rail_corner"),
+    (113, "black_wool"),
+    (114, "gray_wool"),
+    (115, "redstone_torch_off"),
+    (116, "spruce_log"),
+    (117, "birch_log"),
+    (118, "pumpkin_side"),
+    (119, "carved_pumpkin"),
+    (120, "jack_o_lantern"),
+    (121, "cake_top"),
+    (122, "cake_side"),
+    (123, "cake_inner"),
+    (124, "cake_bottom"),
+    (128, "rail"),
+    (129, "red_wool"),
+    (130, "pink_wool"),
+    (131, "repeater"),
+    (132, "spruce_leaves"),
+    (144, "lapis_block"),
+    (145, "green_wool"),
+    (146, "lime_wool"),
+    (147, "repeater_on"),
+    (160, "lapis_ore"),
+    (161, "brown_wool"),
+    (162, "yellow_wool"),
+    (163, "powered_rail"),
+    (176, "sandstone_top"),
+    (177, "blue_wool"),
+    (178, "light_blue_wool"),
+    (179, "powered_rail_on"),
+    (192, "sandstone"),
+    (193, "purple_wool"),
+    (194, "magenta_wool"),
+    (195, "detector_rail"),
+    (208, "sandstone_bottom"),
+    (209, "cyan_wool"),
+    (210, "orange_wool"),
+    (225, "light_gray_wool"),
+    (240, "destroy_stage_0"),
+    (241, "destroy_stage_1"),
+    (242, "destroy_stage_2"),
+    (243, "destroy_stage_3"),
+    (244, "destroy_stage_4"),
+    (245, "destroy_stage_5"),
+    (246, "destroy_stage_6"),
+    (247, "destroy_stage_7"),
+    (248, "destroy_stage_8"),
+    (249, "destroy_stage_9"),
+];
+
+/// Cells of `terrain.png` added from 1.0 on
+const TERRAIN_RELEASE: &[(u8, &str)] = &[
+    (30, "jungle_sapling"),
+    (54, "stone_bricks"),
+    (76, "lily_pad"),
+    (77, "mycelium_side"),
+    (78, "mycelium_top"),
+    (85, "iron_bars"),
+    (100, "mossy_stone_bricks"),
+    (101, "cracked_stone_bricks"),
+    (111, "melon_stem"),
+    (125, "red_mushroom_block"),
+    (126, "brown_mushroom_block"),
+    (127, "attached_melon_stem"),
+    (136, "melon_side"),
+    (137, "melon_top"),
+    (141, "mushroom_stem"),
+    (142, "mushroom_block_inside"),
+    (143, "vine"),
+    (148, "glass_pane_top"),
+    (153, "jungle_log"),
+    (158, "end_portal_frame_top"),
+    (159, "end_portal_frame_side"),
+    (166, "enchanting_table_top"),
+    (167, "dragon_egg"),
+    (174, "end_portal_frame_eye"),
+    (175, "end_stone"),
+    (182, "enchanting_table_side"),
+    (183, "enchanting_table_bottom"),
+    (196, "jungle_leaves"),
+    (198, "spruce_planks"),
+    (199, "jungle_planks"),
+    (211, "redstone_lamp"),
+    (212, "redstone_lamp_on"),
+    (213, "chiseled_stone_bricks"),
+    (214, "birch_planks"),
+    (224, "nether_bricks"),
+    (226, "nether_wart_stage0"),
+    (227, "nether_wart_stage1"),
+    (228, "nether_wart_stage2"),
+    (229, "chiseled_sandstone"),
+    (230, "cut_sandstone"),
+];
+
+/// Cells of `gui/items.png` present since Beta
+const ITEMS_BETA: &[(u8, &str)] = &[
+    (0, "leather_helmet"),
+    (1, "chainmail_helmet"),
+    (2, "iron_helmet"),
+    (3, "diamond_helmet"),
+    (4, "golden_helmet"),
+    (5, "flint_and_steel"),
+    (6, "flint"),
+    (7, "coal"),
+    (8, "string"),
+    (9, "wheat_seeds"),
+    (10, "apple"),
+    (11, "golden_apple"),
+    (12, "egg"),
+    (13, "sugar"),
+    (14, "snowball"),
+    (16, "leather_chestplate"),
+    (17, "chainmail_chestplate"),
+    (18, "iron_chestplate"),
+    (19, "diamond_chestplate"),
+    (20, "golden_chestplate"),
+    (21, "bow"),
+    (22, "brick"),
+    (23, "iron_ingot"),
+    (24, "feather"),
+    (25, "wheat"),
+    (26, "painting"),
+    (27, "sugar_cane"),
+    (28, "bone"),
+    (29, "cake"),
+    (30, "slime_ball"),
+    (32, "leather_leggings"),
+    (33, "chainmail_leggings"),
+    (34, "iron_leggings"),
+    (35, "diamond_leggings"),
+    (36, "golden_leggings"),
+    (37, "arrow"),
+    (39, "gold_ingot"),
+    (40, "gunpowder"),
+    (41, "bread"),
+    (42, "oak_sign"),
+    (43, "oak_door"),
+    (44, "iron_door"),
+    (48, "leather_boots"),
+    (49, "chainmail_boots"),
+    (50, "iron_boots"),
+    (51, "diamond_boots"),
+    (52, "golden_boots"),
+    (53, "stick"),
+    (54, "compass"),
+    (55, "diamond"),
+    (56, "redstone"),
+    (57, "clay_ball"),
+    (58, "paper"),
+    (59, "book"),
+    (60, "map"),
+    (64, "wooden_sword"),
+    (65, "stone_sword"),
+    (66, "iron_sword"),
+    (67, "diamond_sword"),
+    (68, "golden_sword"),
+    (69, "fishing_rod"),
+    (70, "clock"),
+    (71, "bowl"),
+    (72, "mushroom_stew"),
+    (73, "glowstone_dust"),
+    (74, "bucket"),
+    (75, "water_bucket"),
+    (76, "lava_bucket"),
+    (77, "milk_bucket"),
+    (78, "ink_sac"),
+    (79, "gray_dye"),
+    (80, "wooden_shovel"),
+    (81, "stone_shovel"),
+    (82, "iron_shovel"),
+    (83, "diamond_shovel"),
+    (84, "golden_shovel"),
+    (87, "porkchop"),
+    (88, "cooked_porkchop"),
+    (89, "cod"),
+    (90, "cooked_cod"),
+    (92, "cookie"),
+    (93, "shears"),
+    (94, "red_dye"),
+    (95, "pink_dye"),
+    (96, "wooden_pickaxe"),
+    (97, "stone_pickaxe"),
+    (98, "iron_pickaxe"),
+    (99, "diamond_pickaxe"),
+    (100, "golden_pickaxe"),
+    (101, "leather"),
+    (102, "saddle"),
+    (110, "green_dye"),
+    (111, "lime_dye"),
+    (112, "wooden_axe"),
+    (113, "stone_axe"),
+    (114, "iron_axe"),
+    (115, "diamond_axe"),
+    (116, "golden_axe"),
+    (126, "cocoa_beans"),
+    (127, "yellow_dye"),
+    (128, "wooden_hoe"),
+    (129, "stone_hoe"),
+    (130, "iron_hoe"),
+    (131, "diamond_hoe"),
+    (132, "golden_hoe"),
+    (135, "minecart"),
+    (142, "lapis_lazuli"),
+    (143, "light_blue_dye"),
+    (152, "chest_minecart"),
+    (158, "purple_dye"),
+    (159, "magenta_dye"),
+    (167, "furnace_minecart"),
+    (174, "cyan_dye"),
+    (175, "orange_dye"),
+    (190, "light_gray_dye"),
+    (191, "bone_meal"),
+];
+
+/// Cells of `gui/items.png` added from 1.0 on
+const ITEMS_RELEASE: &[(u8, &str)] = &[
+    (61, "pumpkin_seeds"),
+    (62, "melon_seeds"),
+    (103, "beef"),
+    (104, "cooked_beef"),
+    (105, "ender_pearl"),
+];
+
+/// Known cells of the classic sheet `atlas` names, if it's one: the
+/// `terrain` and `items` sheets, in any directory
+fn known_cells(
+    atlas: &str,
+    era: Era,
+) -> Option<Vec<&'static (u8, &'static str)>> {
+    let stem = Path::new(atlas).file_stem()?.to_str()?;
+    let (beta, release) = match stem {
+        "terrain" => (TERRAIN_BETA, TERRAIN_RELEASE),
+        "items" => (ITEMS_BETA, ITEMS_RELEASE),
+        _ => return None,
+    };
+    let mut cells: Vec<_> = beta.iter().collect();
+    if era == Era::Release {
+        cells.extend(release);
+    }
+    Some(cells)
+}
+
+/// Maps cells of a classic `terrain.png` or `items.png` that aren't mapped
+/// yet to names of their vanilla textures, so only mod-specific cells have
+/// to be written by hand. Returns how many were added, none for strips.
+pub fn suggest_names(name: &str, atlas: &mut Atlas, era: Era) -> usize {
+    let cells = match known_cells(name, era) {
+        Some(cells) if atlas.kind == AtlasKind::Grid => cells,
+        _ => return 0,
+    };
+    let covered = atlas.covered();
+    let mut suggested = BTreeMap::new();
+    for &(index, name) in cells {
+        let pos = AtlasPos::from_pos(index % 16, index / 16);
+        if !covered.contains(&pos) {
+            suggested.insert(pos, Tile::named(name.to_owned()));
+        }
+    }
+    let count = suggested.len();
+    atlas.tiles.extend(suggested);
+    count
+}
@@ -0,0 +1,2751 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+    fmt, fs,
+    io::Read,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::{
+    apply_colorkey, check_output_paths, fix_semitransparent_edges, load_ids,
+    locate_config_error, normalize_names, read_descriptor, resolve_ids,
+    sound_event, templated_model, Category, Checksums, Color, ConfigSource,
+    Failure, IdMap, Layout, NameStyle, OutputDirs, Rename, Scale, Sources,
+    Transform, MAX_CELLS, MOD_PREFIX,
+};
+use image::{GrayImage, Luma, RgbaImage};
+use serde::{
+    de::{value::MapAccessDeserializer, MapAccess, Unexpected, Visitor},
+    Deserialize,
+};
+
+/// Position of a cell in an atlas grid, written as hex digits: row, then
+/// column. Two digits, one per axis, cover the classic 16x16 grid, four
+/// digits, two per axis, reach up to 256x256. Three digits are an index
+/// counted row by row, placed once `Atlas::with_grid` knows the grid width.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AtlasPos {
+    // Cells first, then indices, as those have no row yet
+    index: Option<u16>,
+    // Row first, so positions sort in reading order
+    y: u8,
+    x: u8,
+}
+
+impl AtlasPos {
+    pub fn from_pos(x: u8, y: u8) -> Self {
+        Self { index: None, y, x }
+    }
+
+    /// Index of a three digit key, counted row by row over the whole grid
+    pub fn from_index(index: u16) -> Self {
+        Self {
+            index: Some(index),
+            y: 0,
+            x: 0,
+        }
+    }
+
+    /// Column, 0 for an index not placed on a grid yet
+    pub fn x(&self) -> u8 {
+        self.x
+    }
+
+    /// Row, 0 for an index not placed on a grid yet
+    pub fn y(&self) -> u8 {
+        self.y
+    }
+
+    /// Index of a three digit key, until it's placed on a grid
+    pub fn index(&self) -> Option<u16> {
+        self.index
+    }
+
+    /// Cell an index falls on in a grid `width` cells wide. Cells, and
+    /// indices past what one byte per axis addresses, stay as they are.
+    pub fn place(self, width: u32) -> Self {
+        let index = match self.index {
+            Some(index) if width > 0 => index as u32,
+            _ => return self,
+        };
+        match (u8::try_from(index % width), u8::try_from(index / width)) {
+            (Ok(x), Ok(y)) => Self::from_pos(x, y),
+            _ => self,
+        }
+    }
+
+    /// Whether it's within the classic 16x16 grid
+    fn is_classic(&self) -> bool {
+        self.index.is_none() && self.x < 16 && self.y < 16
+    }
+}
+
+impl fmt::Debug for AtlasPos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(index) = self.index {
+            write!(f, "{:03x}", index)
+        } else if self.is_classic() {
+            write!(f, "{:x}{:x}", self.y, self.x)
+        } else {
+            write!(f, "{:02x}{:02x}", self.y, self.x)
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ParseError {
+    NotHexDigits,
+    WrongSize(usize),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotHexDigits => f.write_str("expecting hex digits"),
+            Self::WrongSize(len) => {
+                write!(f, "expecting two to four hex digits, got {}", len)
+            }
+        }
+    }
+}
+
+impl FromStr for AtlasPos {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let len = s.len();
+        if !(2..=4).contains(&len) {
+            return Err(ParseError::WrongSize(len));
+        }
+        if !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(ParseError::NotHexDigits);
+        }
+        if len == 3 {
+            let index = u16::from_str_radix(s, 16).unwrap();
+            return Ok(Self::from_index(index));
+        }
+        let (y, x) = s.split_at(len / 2);
+        let digits = |s| u8::from_str_radix(s, 16).unwrap();
+        Ok(Self::from_pos(digits(x), digits(y)))
+    }
+}
+
+struct AtlasPosVisitor;
+impl<'v> Visitor<'v> for AtlasPosVisitor {
+    type Value = AtlasPos;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("expecting two to four hex digits")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        AtlasPos::from_str(v).map_err(|e| match e {
+            ParseError::NotHexDigits => {
+                E::invalid_value(Unexpected::Str(v), &"hex digit")
+            }
+            ParseError::WrongSize(_) => {
+                E::invalid_value(Unexpected::Str(v), &"2 to 4 hex digits")
+            }
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for AtlasPos {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_str(AtlasPosVisitor)
+    }
+}
+
+/// Brightness, as Rec.601 luma, from which pixels glow with
+/// `emissive_mask = "threshold"`
+pub const DEFAULT_EMISSIVE_THRESHOLD: u8 = 200;
+
+/// Which pixels of a tile go into its emissive overlay, written as
+/// `"threshold"`, `"threshold:160"`, or position of a mask cell like `"3f"`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmissiveMask {
+    /// Pixels at least this bright
+    Threshold(u8),
+    /// Pixels where the same area starting at this cell isn't transparent
+    /// or black
+    Cell(AtlasPos),
+}
+
+impl FromStr for EmissiveMask {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "threshold" {
+            return Ok(Self::Threshold(DEFAULT_EMISSIVE_THRESHOLD));
+        }
+        if let Some(value) = s.strip_prefix("threshold:") {
+            return value.parse().map(Self::Threshold).map_err(|_| {
+                anyhow::anyhow!("threshold has to be 0-255, found {:?}", value)
+            });
+        }
+        s.parse().map(Self::Cell).map_err(|_| {
+            anyhow::anyhow!(
+                "expected \"threshold\", \"threshold:<0-255>\" or a cell \
+                 position, found {:?}",
+                s
+            )
+        })
+    }
+}
+
+struct EmissiveMaskVisitor;
+impl<'v> Visitor<'v> for EmissiveMaskVisitor {
+    type Value = EmissiveMask;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("\"threshold\", \"threshold:<0-255>\" or cell position")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        v.parse().map_err(E::custom)
+    }
+}
+
+impl<'de> Deserialize<'de> for EmissiveMask {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_str(EmissiveMaskVisitor)
+    }
+}
+
+/// Name of the emissive overlay written for texture `name`, as OptiFine
+/// and Continuity expect it
+pub fn emissive_name(name: &str) -> String {
+    format!("{}_e", name)
+}
+
+/// Run of consecutive cells, in row-major order, written as `"40-47"`.
+/// A single position is a range of one. Within the classic 16x16 grid
+/// ranges wrap onto the next row, past it they have to stay in one row.
+/// Ranges of three digit indices run over them, like `"100-13f"`.
+struct AtlasRange {
+    start: AtlasPos,
+    end: AtlasPos,
+}
+
+impl AtlasRange {
+    fn positions(&self) -> Vec<AtlasPos> {
+        let (start, end) = (self.start, self.end);
+        if let (Some(start), Some(end)) = (start.index, end.index) {
+            (start..=end).map(AtlasPos::from_index).collect()
+        } else if start.is_classic() && end.is_classic() {
+            let index = |pos: AtlasPos| pos.y * 16 + pos.x;
+            (index(start)..=index(end))
+                .map(|i| AtlasPos::from_pos(i % 16, i / 16))
+                .collect()
+        } else {
+            (start.x..=end.x)
+                .map(|x| AtlasPos::from_pos(x, start.y))
+                .collect()
+        }
+    }
+}
+
+struct AtlasRangeVisitor;
+impl<'v> Visitor<'v> for AtlasRangeVisitor {
+    type Value = AtlasRange;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("expecting two to four hex digits, or range like \"40-47\"")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        let (start, end) = match v.split_once('-') {
+            Some((start, end)) => (start, end),
+            None => (v, v),
+        };
+        let start = AtlasPosVisitor.visit_str::<E>(start)?;
+        let end = AtlasPosVisitor.visit_str::<E>(end)?;
+        if start > end {
+            return Err(E::invalid_value(
+                Unexpected::Str(v),
+                &"ascending range",
+            ));
+        }
+        if start.index.is_some() != end.index.is_some() {
+            return Err(E::invalid_value(
+                Unexpected::Str(v),
+                &"range with three digits at both or neither end",
+            ));
+        }
+        let classic = start.is_classic() && end.is_classic();
+        if start.index.is_none() && !classic && start.y != end.y {
+            return Err(E::invalid_value(
+                Unexpected::Str(v),
+                &"range within one row, when past the 16x16 grid",
+            ));
+        }
+        Ok(AtlasRange { start, end })
+    }
+}
+
+/// Positions covered by a tile key like `0a` or `40-47`, if it is one
+pub(crate) fn key_positions(key: &str) -> Option<Vec<AtlasPos>> {
+    let range = AtlasRangeVisitor
+        .visit_str::<serde::de::value::Error>(key)
+        .ok()?;
+    Some(range.positions())
+}
+
+impl<'de> Deserialize<'de> for AtlasRange {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_str(AtlasRangeVisitor)
+    }
+}
+
+/// Expands range keys, substituting `%d` in names with index within range
+fn deserialize_tiles<'de, D>(
+    deserializer: D,
+) -> Result<BTreeMap<AtlasPos, Tile>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::Error;
+
+    let ranges: Vec<(AtlasRange, Tile)> =
+        BTreeMap::<String, Tile>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(key, tile)| {
+                AtlasRangeVisitor
+                    .visit_str::<D::Error>(&key)
+                    .map(|r| (r, tile))
+            })
+            .collect::<Result<_, _>>()?;
+    let mut tiles = BTreeMap::new();
+    for (range, tile) in ranges {
+        let is_range = range.start != range.end;
+        if is_range && !tile.name.contains("%d") {
+            return Err(D::Error::custom(format!(
+                "range {:?}-{:?} needs %d in name {:?}",
+                range.start, range.end, tile.name
+            )));
+        }
+        for (index, pos) in range.positions().into_iter().enumerate() {
+            let mut tile = tile.clone();
+            if is_range {
+                tile.name = tile.name.replace("%d", &index.to_string());
+            }
+            if tiles.insert(pos, tile).is_some() {
+                return Err(D::Error::custom(format!(
+                    "position {:?} is mapped more than once",
+                    pos
+                )));
+            }
+        }
+    }
+    Ok(tiles)
+}
+
+/// Single-channel conversion applied to tiles before saving
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Grayscale {
+    /// Rec.601 luma: `0.299 R + 0.587 G + 0.114 B`
+    Luma,
+    /// Red channel, as-is
+    Red,
+    /// Alpha channel, as-is
+    Alpha,
+}
+
+/// Rec.601 luma of a color, rounded
+pub fn luma(r: u8, g: u8, b: u8) -> u8 {
+    let luma = 299 * r as u32 + 587 * g as u32 + 114 * b as u32;
+    ((luma + 500) / 1000) as u8
+}
+
+impl Grayscale {
+    pub fn convert(self, image: &RgbaImage) -> GrayImage {
+        GrayImage::from_fn(image.width(), image.height(), |x, y| {
+            let [r, g, b, a] = image.get_pixel(x, y).0;
+            let value = match self {
+                Grayscale::Luma => luma(r, g, b),
+                Grayscale::Red => r,
+                Grayscale::Alpha => a,
+            };
+            Luma([value])
+        })
+    }
+}
+
+pub type AtlasMap = BTreeMap<String, Atlas>;
+
+/// How cells of a sheet are laid out
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AtlasKind {
+    /// Rows and columns of cells, like `terrain.png`, addressed as `yx`
+    #[default]
+    Grid,
+    /// Single column of frames, like legacy animation strips. Keys are
+    /// frame indices from the top, in hex like cells of the classic grid
+    /// counted row by row, so `"00-1f" = "lava_%d"` names 32 frames.
+    Strip,
+}
+
+/// Corner of a grid sheet keys count cells from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Origin {
+    #[default]
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// Which axis each half of a key counts along
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Order {
+    /// Row, then column, like sheets read line by line
+    #[default]
+    RowMajor,
+    /// Column, then row, like sheets filled top to bottom first
+    ColumnMajor,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Atlas {
+    #[serde(default)]
+    pub kind: AtlasKind,
+    /// Whole strip written as a single animated texture, like
+    /// `{ name = "water_still", frametime = 2 }`, instead of frames mapped
+    /// one by one
+    pub animation: Option<Tile>,
+    /// Corner of the grid keys count from. Tiles are counted from it as a
+    /// whole, so with `bottom_left` a tile spanning two rows at `00` takes
+    /// the two bottom rows. Spans and frames still go right and down.
+    #[serde(default)]
+    pub origin: Origin,
+    /// Whether keys are row then column, or column then row
+    #[serde(default)]
+    pub order: Order,
+    /// Save tiles as 8-bit greyscale, e.g. for heightmaps
+    pub grayscale: Option<Grayscale>,
+    /// Overrides global `tile_size` for this atlas
+    pub tile_size: Option<u32>,
+    /// Empty border around the whole grid, in pixels
+    #[serde(default)]
+    pub margin: u32,
+    /// Gutter between neighbouring cells, in pixels
+    #[serde(default)]
+    pub spacing: u32,
+    /// Border inside every cell around its tile, in pixels
+    #[serde(default)]
+    pub padding: u32,
+    /// Color standing in for transparency, like `"#FF00FF"`, turned into
+    /// real alpha
+    pub colorkey: Option<Color>,
+    /// Undo premultiplied alpha on partly transparent pixels, and fill
+    /// colors of fully transparent ones from their neighbours
+    #[serde(default)]
+    pub fix_semitransparent_edges: bool,
+    /// Crop fully transparent borders off every tile, unless a tile says
+    /// otherwise
+    #[serde(default)]
+    pub trim: bool,
+    #[serde(flatten, deserialize_with = "deserialize_tiles")]
+    pub tiles: BTreeMap<AtlasPos, Tile>,
+}
+
+impl Atlas {
+    /// Alpha cleanup of a tile just cut out, before any of its transforms
+    pub fn clean_alpha(&self, strip: &mut RgbaImage) {
+        if let Some(key) = self.colorkey {
+            apply_colorkey(strip, key);
+        }
+        if self.fix_semitransparent_edges {
+            fix_semitransparent_edges(strip);
+        }
+    }
+
+    /// Sheet cell key `pos` of a tile covering `footprint` cells names, in
+    /// a grid of `columns` by `rows`. Keys past the grid stay past it.
+    pub fn cell(
+        &self,
+        pos: AtlasPos,
+        footprint: (u32, u32),
+        columns: u32,
+        rows: u32,
+    ) -> AtlasPos {
+        // Indices count along the key's rows, which are columns when swapped
+        let pos = match self.order {
+            Order::RowMajor => pos.place(columns),
+            Order::ColumnMajor => pos.place(rows),
+        };
+        let (x, y) = match self.order {
+            Order::RowMajor => (pos.x(), pos.y()),
+            Order::ColumnMajor => (pos.y(), pos.x()),
+        };
+        let flip = |i: u8, span: u32, cells: u32| {
+            cells
+                .checked_sub(i as u32 + span)
+                .map_or(i, |flipped| flipped as u8)
+        };
+        let (w, h) = footprint;
+        let x = match self.origin {
+            Origin::TopRight | Origin::BottomRight => flip(x, w, columns),
+            Origin::TopLeft | Origin::BottomLeft => x,
+        };
+        let y = match self.origin {
+            Origin::BottomLeft | Origin::BottomRight => flip(y, h, rows),
+            Origin::TopLeft | Origin::TopRight => y,
+        };
+        AtlasPos::from_pos(x, y)
+    }
+
+    /// Key naming sheet `cell`, undoing `cell`
+    pub fn key(
+        &self,
+        cell: AtlasPos,
+        footprint: (u32, u32),
+        columns: u32,
+        rows: u32,
+    ) -> AtlasPos {
+        // Flipping is its own inverse, and only the order swaps axes
+        let flipped = Self {
+            order: Order::RowMajor,
+            ..self.clone()
+        };
+        let pos = flipped.cell(cell, footprint, columns, rows);
+        match self.order {
+            Order::RowMajor => pos,
+            Order::ColumnMajor => AtlasPos::from_pos(pos.y(), pos.x()),
+        }
+    }
+
+    /// Atlas keyed by sheet cells of a grid of `columns` by `rows`, which is
+    /// only known once the sheet is read: three digit indices, and keys
+    /// counted from another `origin` or in another `order`, are moved to the
+    /// cells they name, and a strip's `animation` runs over all of its rows.
+    /// Fails if two keys end up naming the same cell, like `010` and `10` on
+    /// a grid 16 cells wide.
+    pub fn with_grid(
+        &self,
+        columns: u32,
+        rows: u32,
+    ) -> anyhow::Result<Cow<'_, Self>> {
+        let whole = |tile: &Tile| tile.frames == 0;
+        let indexed = self.tiles.iter().any(|(pos, tile)| {
+            std::iter::once(*pos)
+                .chain(tile.mask_cell())
+                .any(|pos| pos.index().is_some())
+        });
+        let oriented = indexed
+            || self.origin != Origin::TopLeft
+            || self.order != Order::RowMajor;
+        let strip = self.kind == AtlasKind::Strip;
+        let filled = strip && self.tiles.values().any(whole);
+        if !oriented && !filled {
+            return Ok(Cow::Borrowed(self));
+        }
+        let mut atlas = self.clone();
+        if strip {
+            for tile in atlas.tiles.values_mut().filter(|tile| whole(tile)) {
+                tile.frames = rows;
+            }
+        }
+        if oriented {
+            let tiles = std::mem::take(&mut atlas.tiles);
+            for (pos, mut tile) in tiles {
+                let footprint = tile.footprint();
+                if let Some(mask) = tile.mask_cell() {
+                    let mask = self.cell(mask, footprint, columns, rows);
+                    tile.emissive_mask = Some(EmissiveMask::Cell(mask));
+                }
+                let cell = self.cell(pos, footprint, columns, rows);
+                anyhow::ensure!(
+                    atlas.tiles.insert(cell, tile).is_none(),
+                    "position {:?} is mapped more than once",
+                    cell
+                );
+            }
+            atlas.origin = Origin::TopLeft;
+            atlas.order = Order::RowMajor;
+        }
+        Ok(Cow::Owned(atlas))
+    }
+
+    /// Every cell some tile covers, with all of its span and frames. Three
+    /// digit indices only count once `with_grid` placed them.
+    pub fn covered(&self) -> BTreeSet<AtlasPos> {
+        let mut covered = BTreeSet::new();
+        for (pos, tile) in &self.tiles {
+            let (w, h) = tile.footprint();
+            let cells = std::iter::once(*pos).chain(tile.mask_cell());
+            for pos in cells.filter(|pos| pos.index().is_none()) {
+                let (x, y) = (pos.x() as u32, pos.y() as u32);
+                for y in y..(y + h).min(MAX_CELLS) {
+                    for x in x..(x + w).min(MAX_CELLS) {
+                        covered.insert(AtlasPos::from_pos(x as u8, y as u8));
+                    }
+                }
+            }
+        }
+        covered
+    }
+}
+
+/// Texture sliced out of an atlas, written either as just its name, or as
+/// `{ name = "lava_still", frames = 4, frametime = 2 }` for animations
+#[derive(Debug, Clone, Deserialize)]
+#[serde(from = "TileDef")]
+pub struct Tile {
+    pub name: String,
+    /// Legacy numeric id, as `251` or `251:3`, the name is looked up with
+    pub id: Option<String>,
+    /// Number of animation frames, stacked downwards from the tile position.
+    /// 0 for a strip's `animation` until `Atlas::with_grid` counts them.
+    pub frames: u32,
+    /// Size of a single frame in cells, as `[columns, rows]`, for textures
+    /// like doors or 32x32 faces
+    pub span: (u32, u32),
+    /// Ticks per frame, written to `.png.mcmeta` for animated tiles
+    pub frametime: Option<u32>,
+    /// Rotation, flip and recolor applied after cutting the tile out
+    pub transform: Transform,
+    /// Name of palette in `[palettes]` to write tinted copies with
+    pub palette: Option<String>,
+    /// Part of every frame to keep, as `[x, y, w, h]` in pixels, for sprites
+    /// drawn smaller than their cell
+    pub rect: Option<[u32; 4]>,
+    /// Parent of the block model generated for this tile, instead of
+    /// `auto_models`
+    pub parent: Option<String>,
+    /// Name of template in `[templates]` to generate the block model from
+    pub template: Option<String>,
+    /// Also write `<name>_e` with just the glowing pixels, picked by
+    /// brightness or by a mask cell
+    pub emissive_mask: Option<EmissiveMask>,
+    /// Size change of just this tile, like `2` or `0.5`, for targets that
+    /// want a few textures at another resolution than the rest
+    pub scale: Option<Scale>,
+    /// Crop fully transparent borders off, the same for every frame, with
+    /// the offset listed in `--report`. Defaults to atlas `trim`.
+    pub trim: Option<bool>,
+    /// Function of the config's `script` to run on the tile before saving
+    pub hook: Option<String>,
+    /// Other keys, filled into `{key}` placeholders of the template
+    pub vars: BTreeMap<String, String>,
+    /// Names its texture is written as too, from `[aliases]`
+    pub aliases: Vec<String>,
+    /// Names of models inheriting from this tile's, from `[aliases]`
+    pub model_aliases: Vec<String>,
+}
+
+/// What a tile's block model is generated from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileModel<'a> {
+    Parent(&'a str),
+    Template(&'a str),
+}
+
+impl Tile {
+    /// Plain tile, as written with just its name
+    pub fn named(name: String) -> Self {
+        Tile {
+            name,
+            id: None,
+            frames: 1,
+            frametime: None,
+            span: default_span(),
+            transform: Transform::default(),
+            palette: None,
+            rect: None,
+            parent: None,
+            template: None,
+            emissive_mask: None,
+            scale: None,
+            trim: None,
+            hook: None,
+            vars: BTreeMap::new(),
+            aliases: vec![],
+            model_aliases: vec![],
+        }
+    }
+
+    /// Source of this tile's generated block model, if it gets one, with
+    /// `auto_models` as default parent
+    pub fn model<'a>(
+        &'a self,
+        default: Option<&'a str>,
+    ) -> Option<TileModel<'a>> {
+        match &self.template {
+            Some(template) => Some(TileModel::Template(template)),
+            None => self.parent.as_deref().or(default).map(TileModel::Parent),
+        }
+    }
+
+    /// Cell the emissive mask starts at, if it's given by one
+    pub fn mask_cell(&self) -> Option<AtlasPos> {
+        match self.emissive_mask {
+            Some(EmissiveMask::Cell(pos)) => Some(pos),
+            _ => None,
+        }
+    }
+
+    pub fn is_animated(&self) -> bool {
+        self.frames != 1
+    }
+
+    /// Its name, then its texture aliases
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.name).chain(&self.aliases)
+    }
+
+    /// Names it gets generated models and blockstates as: its name, texture
+    /// aliases, then model aliases
+    pub fn model_names(&self) -> impl Iterator<Item = &String> {
+        self.names().chain(&self.model_aliases)
+    }
+
+    /// Names to write this tile as, with the tint for each: untinted tile
+    /// itself and its aliases, then `<name>_<key>` for every color in its
+    /// palette
+    pub fn variants(
+        &self,
+        palettes: &Palettes,
+    ) -> Vec<(String, Option<Color>)> {
+        let mut variants: Vec<_> =
+            self.names().map(|name| (name.clone(), None)).collect();
+        let palette = self.palette.as_ref().and_then(|name| palettes.get(name));
+        for (key, color) in palette.into_iter().flatten() {
+            variants.push((format!("{}_{}", self.name, key), Some(*color)));
+        }
+        variants
+    }
+
+    /// Every texture name written for this tile: its variants, and their
+    /// emissive overlays if it has any
+    pub fn output_names(&self, palettes: &Palettes) -> Vec<String> {
+        let mut names: Vec<_> = self
+            .variants(palettes)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        if self.emissive_mask.is_some() {
+            let emissive: Vec<_> =
+                names.iter().map(|name| emissive_name(name)).collect();
+            names.extend(emissive);
+        }
+        names
+    }
+
+    /// Cells covered by all the frames together, as `(columns, rows)`
+    pub fn footprint(&self) -> (u32, u32) {
+        (self.span.0, self.span.1 * self.frames)
+    }
+
+    /// Whether `rect`, if any, is non-empty and lies within a single frame
+    /// made of `tile_size` pixel cells
+    pub fn rect_fits(&self, tile_size: u32) -> bool {
+        match self.rect {
+            Some([x, y, w, h]) => {
+                w > 0
+                    && h > 0
+                    && x + w <= self.span.0 * tile_size
+                    && y + h <= self.span.1 * tile_size
+            }
+            None => true,
+        }
+    }
+}
+
+fn default_frames() -> u32 {
+    1
+}
+
+fn default_span() -> (u32, u32) {
+    (1, 1)
+}
+
+#[derive(Deserialize)]
+struct TileTable {
+    #[serde(default)]
+    name: String,
+    id: Option<String>,
+    #[serde(default = "default_frames")]
+    frames: u32,
+    frametime: Option<u32>,
+    #[serde(default = "default_span")]
+    span: (u32, u32),
+    #[serde(flatten)]
+    transform: Transform,
+    palette: Option<String>,
+    rect: Option<[u32; 4]>,
+    parent: Option<String>,
+    template: Option<String>,
+    emissive_mask: Option<EmissiveMask>,
+    scale: Option<Scale>,
+    trim: Option<bool>,
+    hook: Option<String>,
+    // Has to come after `transform`, so it only gets keys left over by it
+    #[serde(flatten)]
+    vars: BTreeMap<String, String>,
+}
+
+/// Either form of a tile. Not `#[serde(untagged)]`, so mistakes inside the
+/// table form still get a specific error.
+enum TileDef {
+    Name(String),
+    Full(Box<TileTable>),
+}
+
+struct TileDefVisitor;
+impl<'v> Visitor<'v> for TileDefVisitor {
+    type Value = TileDef;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("texture name or table with `name` or `id`")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(TileDef::Name(v.to_owned()))
+    }
+
+    fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'v>,
+    {
+        TileTable::deserialize(MapAccessDeserializer::new(map))
+            .map(|table| TileDef::Full(Box::new(table)))
+    }
+}
+
+impl<'de> Deserialize<'de> for TileDef {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(TileDefVisitor)
+    }
+}
+
+impl From<TileDef> for Tile {
+    fn from(def: TileDef) -> Self {
+        match def {
+            TileDef::Name(name) => Tile::named(name),
+            TileDef::Full(table) => {
+                let TileTable {
+                    name,
+                    id,
+                    frames,
+                    frametime,
+                    span,
+                    transform,
+                    palette,
+                    rect,
+                    parent,
+                    template,
+                    emissive_mask,
+                    scale,
+                    trim,
+                    hook,
+                    vars,
+                } = *table;
+                Tile {
+                    name,
+                    id,
+                    frames,
+                    frametime,
+                    span,
+                    transform,
+                    palette,
+                    rect,
+                    parent,
+                    template,
+                    emissive_mask,
+                    scale,
+                    trim,
+                    hook,
+                    vars,
+                    aliases: vec![],
+                    model_aliases: vec![],
+                }
+            }
+        }
+    }
+}
+
+pub type Folders = BTreeMap<String, Vec<String>>;
+
+/// Other name of a sliced tile, written as the tile's name for a copy of
+/// its texture, or as `{ model = "<tile>" }` for just a model inheriting
+/// from the tile's
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Alias {
+    Texture(String),
+    Model(String),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AliasTable {
+    model: String,
+}
+
+struct AliasVisitor;
+impl<'v> Visitor<'v> for AliasVisitor {
+    type Value = Alias;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("tile name or table with `model`")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(Alias::Texture(v.to_owned()))
+    }
+
+    fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'v>,
+    {
+        AliasTable::deserialize(MapAccessDeserializer::new(map))
+            .map(|table| Alias::Model(table.model))
+    }
+}
+
+impl<'de> Deserialize<'de> for Alias {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(AliasVisitor)
+    }
+}
+
+/// Placeholder written for a texture nothing is mapped to yet, from
+/// `[fallbacks]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fallback {
+    /// `"missing"`: magenta and black checkers, like the game's own
+    Missing,
+    /// `{ atlas = "terrain", cell = "03" }`: a cell of one of the atlases of
+    /// the same section
+    Cell { atlas: String, cell: AtlasPos },
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FallbackTable {
+    atlas: String,
+    cell: AtlasPos,
+}
+
+struct FallbackVisitor;
+impl<'v> Visitor<'v> for FallbackVisitor {
+    type Value = Fallback;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("\"missing\" or table with `atlas` and `cell`")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        match v {
+            "missing" => Ok(Fallback::Missing),
+            _ => Err(E::invalid_value(Unexpected::Str(v), &self)),
+        }
+    }
+
+    fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'v>,
+    {
+        FallbackTable::deserialize(MapAccessDeserializer::new(map)).map(
+            |table| Fallback::Cell {
+                atlas: table.atlas,
+                cell: table.cell,
+            },
+        )
+    }
+}
+
+impl<'de> Deserialize<'de> for Fallback {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(FallbackVisitor)
+    }
+}
+
+/// Placeholders for block and item textures that aren't mapped yet, by
+/// name, so packs are complete while mapping is in progress. Ones mapped
+/// since are left out.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Fallbacks {
+    #[serde(default)]
+    pub blocks: BTreeMap<String, Fallback>,
+    #[serde(default)]
+    pub items: BTreeMap<String, Fallback>,
+}
+
+impl Fallbacks {
+    pub fn len(&self) -> usize {
+        self.blocks.len() + self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Every fallback, with the section it's in
+    pub fn iter(&self) -> impl Iterator<Item = (Category, &String, &Fallback)> {
+        let blocks = self.blocks.iter().map(|(n, f)| (Category::Blocks, n, f));
+        let items = self.items.iter().map(|(n, f)| (Category::Items, n, f));
+        blocks.chain(items)
+    }
+}
+
+/// Block model templates with `{placeholder}`s, each written either as a
+/// table shaped like the model json, or as path of a json file with it
+pub type Templates = BTreeMap<String, serde_json::Value>;
+
+/// Texture references in copied models to rewrite, from old to new name,
+/// e.g. `"mymod:blocks/" = "mymod:block/"` for a whole directory
+pub type TextureRenames = BTreeMap<String, String>;
+
+/// Priority of sources in `folders`: higher ones are searched first, so their
+/// files win over the same files elsewhere
+pub type Priorities = BTreeMap<String, i32>;
+
+/// Named sets of tints, e.g. `[palettes.dyes]` with `red = "#b02e26"`
+pub type Palettes = BTreeMap<String, BTreeMap<String, Color>>;
+
+/// Rectangle cut out of a non-grid sheet, in pixels
+#[derive(Debug, Clone, Deserialize)]
+pub struct Crop {
+    pub name: String,
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+    /// Nine-slice borders, for widgets stretched to any size
+    #[serde(default)]
+    pub ninepatch: Option<NinePatch>,
+}
+
+impl Crop {
+    /// Files it's written as, relative to its output directory
+    pub fn outputs(&self) -> Vec<PathBuf> {
+        let png = |name: &str| Path::new(name).with_extension("png");
+        match &self.ninepatch {
+            Some(ninepatch) if ninepatch.split => NINE_SLICE_PIECES
+                .iter()
+                .map(|piece| png(&format!("{}_{}", self.name, piece)))
+                .collect(),
+            Some(_) => {
+                let path = png(&self.name);
+                let mut mcmeta = path.clone().into_os_string();
+                mcmeta.push(".mcmeta");
+                vec![path, mcmeta.into()]
+            }
+            None => vec![png(&self.name)],
+        }
+    }
+}
+
+/// Suffixes of nine-slice pieces, row by row
+pub const NINE_SLICE_PIECES: [&str; 9] = [
+    "top_left",
+    "top",
+    "top_right",
+    "left",
+    "center",
+    "right",
+    "bottom_left",
+    "bottom",
+    "bottom_right",
+];
+
+/// Nine-slice borders of a gui crop, e.g. `ninepatch = { border = 3 }`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NinePatch {
+    /// Width of the edges that aren't stretched
+    pub border: Border,
+    /// Write the nine pieces, as `<name>_top_left.png` and so on, instead
+    /// of the whole crop with a `.png.mcmeta` describing its borders, the
+    /// way `nine_slice` gui sprites are
+    #[serde(default)]
+    pub split: bool,
+}
+
+/// One width for every side, or `[left, top, right, bottom]`
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(untagged)]
+pub enum Border {
+    All(u32),
+    Sides([u32; 4]),
+}
+
+impl Border {
+    /// `[left, top, right, bottom]`
+    pub fn sides(self) -> [u32; 4] {
+        match self {
+            Self::All(width) => [width; 4],
+            Self::Sides(sides) => sides,
+        }
+    }
+}
+
+/// Sheet cut the way a sprite sheet descriptor lists, instead of as a grid,
+/// e.g. `"textures/terrain.png" = { descriptor = "terrain.json" }`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SpriteSheet {
+    /// TexturePacker or Aseprite json, relative to the main toml. Every
+    /// frame, or Aseprite slice, is written as a texture named after it.
+    pub descriptor: PathBuf,
+    /// Texture category sprites are written to
+    #[serde(default = "default_sprite_category")]
+    pub category: Category,
+    /// Rects read from `descriptor`
+    #[serde(skip)]
+    pub crops: Vec<Crop>,
+}
+
+fn default_sprite_category() -> Category {
+    Category::Blocks
+}
+
+/// Where to copy a whole archive directory, e.g.
+/// `"lang" = { to = "lang", extensions = ["lang"] }`
+#[derive(Debug, Clone, Deserialize)]
+pub struct CopyDir {
+    /// Output directory, relative to `assets/<modid>`
+    pub to: String,
+    /// Only copy files with these extensions, all files if empty
+    #[serde(default)]
+    pub extensions: Vec<String>,
+}
+
+impl CopyDir {
+    pub fn accepts(&self, file: &str) -> bool {
+        self.extensions.is_empty()
+            || Path::new(file).extension().is_some_and(|ext| {
+                self.extensions
+                    .iter()
+                    .any(|allowed| ext.eq_ignore_ascii_case(allowed.as_str()))
+            })
+    }
+}
+
+/// Animation of a copied texture, written to its `.png.mcmeta`, e.g.
+/// `"lava.png" = { frametime = 2, interpolate = true }`
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Animation {
+    /// Ticks per frame, the game's default of 1 if unset
+    pub frametime: Option<u32>,
+    /// Blend between frames, like still water does
+    #[serde(default)]
+    pub interpolate: bool,
+    /// Order of frames by their index, top to bottom if empty
+    #[serde(default)]
+    pub frames: Vec<u32>,
+}
+
+/// Sound files, copied to `assets/<modid>/sounds` and listed in `sounds.json`
+#[derive(Debug, Default, Deserialize)]
+pub struct Sounds {
+    #[serde(default)]
+    pub files: Vec<String>,
+    /// Event name for given file, instead of one derived from its path
+    #[serde(default)]
+    pub events: BTreeMap<String, String>,
+}
+
+impl Sounds {
+    /// Every event, with files playing it
+    pub fn events(&self) -> BTreeMap<String, Vec<String>> {
+        let mut events = BTreeMap::<_, Vec<_>>::new();
+        for file in &self.files {
+            let event = match self.events.get(file) {
+                Some(event) => event.clone(),
+                None => sound_event(file),
+            };
+            events.entry(event).or_default().push(file.clone());
+        }
+        events
+    }
+}
+
+/// Legacy `.lang` files, converted to `assets/<modid>/lang/<locale>.json`
+#[derive(Debug, Default, Deserialize)]
+pub struct Lang {
+    #[serde(default)]
+    pub files: Vec<String>,
+    /// New name for given key, e.g. `"tile.machine.name"` to
+    /// `"block.modid.machine"`
+    #[serde(default)]
+    pub rename: BTreeMap<String, String>,
+}
+
+/// Sheet file, e.g. `gui/furnace.png`, to list of crops taken from it
+pub type CropMap = BTreeMap<String, Vec<Crop>>;
+
+/// Connected texture layouts, as OptiFine's `method`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CtmMethod {
+    /// Full set of 47 tiles, laid out 12x4 like OptiFine's template
+    #[default]
+    Ctm,
+    /// 5 tiles in a row, combined into the full set at runtime
+    CtmCompact,
+    /// 4 tiles in a row, connecting sideways
+    Horizontal,
+    /// 4 tiles in a row, connecting up and down
+    Vertical,
+}
+
+impl CtmMethod {
+    /// Number of tiles, and columns they're laid out in on the sheet
+    pub fn layout(self) -> (u32, u32) {
+        match self {
+            Self::Ctm => (47, 12),
+            Self::CtmCompact => (5, 5),
+            Self::Horizontal | Self::Vertical => (4, 4),
+        }
+    }
+
+    /// Size of the whole region in cells, as `(columns, rows)`
+    pub fn region(self) -> (u32, u32) {
+        let (tiles, columns) = self.layout();
+        (columns, tiles.div_ceil(columns))
+    }
+
+    /// Name in `.properties` files
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Ctm => "ctm",
+            Self::CtmCompact => "ctm_compact",
+            Self::Horizontal => "horizontal",
+            Self::Vertical => "vertical",
+        }
+    }
+}
+
+/// Connected textures sliced from a region of a sheet, e.g.
+/// `[ctm.glass]` with `atlas = "terrain"` and `start = "40"`
+#[derive(Debug, Clone, Deserialize)]
+pub struct Ctm {
+    /// Sheet, named like atlases
+    pub atlas: String,
+    /// Top-left cell of the region
+    pub start: AtlasPos,
+    #[serde(default)]
+    pub method: CtmMethod,
+    /// Blocks the textures apply to, just `<modid>:<name>` if empty
+    #[serde(default)]
+    pub blocks: Vec<String>,
+}
+
+/// Name of connected texture set, used for its directory, to where it's cut
+/// from
+pub type CtmMap = BTreeMap<String, Ctm>;
+
+/// Glyph sheet 16 glyphs wide, like `font/default.png`, written whole with a
+/// bitmap font provider, e.g. `[fonts.default]` with `sheet = "font/default"`
+#[derive(Debug, Clone, Deserialize)]
+pub struct Font {
+    /// Sheet, named like atlases
+    pub sheet: String,
+    /// Font the provider is added to, the name of the sheet if unset.
+    /// Sheets of the same font, like unicode pages, share its file.
+    pub font: Option<String>,
+    /// Code point of the top left glyph, e.g. `0x100` for `unicode_page_01`
+    #[serde(default)]
+    pub first: u32,
+    /// Glyphs of each row, instead of ones counted up from `first`
+    #[serde(default)]
+    pub chars: Vec<String>,
+    #[serde(default = "default_ascent")]
+    pub ascent: i32,
+    #[serde(default = "default_glyph_height")]
+    pub height: i32,
+}
+
+fn default_ascent() -> i32 {
+    7
+}
+
+fn default_glyph_height() -> i32 {
+    8
+}
+
+impl Font {
+    /// Name of the font provider file
+    pub fn font<'a>(&'a self, name: &'a str) -> &'a str {
+        self.font.as_deref().unwrap_or(name)
+    }
+}
+
+/// Name of glyph sheet, used for its texture, to where it's cut from
+pub type FontMap = BTreeMap<String, Font>;
+
+/// Assets registered under a single mod id: what to copy and which atlases
+/// to slice
+#[derive(Debug, Deserialize)]
+pub struct Namespace {
+    pub modid: String,
+    #[serde(default)]
+    pub models: Vec<String>,
+    /// Rewrites texture references in copied `models`, which otherwise
+    /// still point at old paths
+    #[serde(default)]
+    pub texture_renames: TextureRenames,
+    #[serde(default)]
+    pub gui: Vec<String>,
+    /// GUI elements cut out of sheets as pixel rects, written next to `gui`,
+    /// one `[[gui_crops."furnace.png"]]` table per rect
+    #[serde(default)]
+    pub gui_crops: CropMap,
+    /// Sheets cut as their TexturePacker or Aseprite descriptors list, for
+    /// ones already maintained for other tools
+    #[serde(default)]
+    pub sprite_sheets: BTreeMap<String, SpriteSheet>,
+    #[serde(default)]
+    pub blocks_copy: Vec<String>,
+    /// Sizes copied `gui` and `blocks_copy` textures have to be, as
+    /// `"furnace.png" = [256, 256]`, checked on every run
+    #[serde(default)]
+    pub texture_sizes: BTreeMap<String, (u32, u32)>,
+    /// Animations of copied textures, like `gui` or `entity` ones, written to
+    /// their `.png.mcmeta` in place of one next to them in the archive
+    #[serde(default)]
+    pub animations: BTreeMap<String, Animation>,
+    /// Mob skins and other entity textures, copied to `textures/entity`
+    #[serde(default)]
+    pub entity: Vec<String>,
+    /// Particle sheets, copied to `textures/particle`
+    #[serde(default)]
+    pub particle: Vec<String>,
+    /// Sky, weather and other world textures, copied to
+    /// `textures/environment`
+    #[serde(default)]
+    pub environment: Vec<String>,
+    /// Any other file, to its output path relative to `assets/<modid>`, e.g.
+    /// `"armor/steel_1.png" = "textures/models/armor/steel_layer_1.png"`
+    #[serde(default)]
+    pub misc: BTreeMap<String, String>,
+    #[serde(default)]
+    pub sounds: Sounds,
+    #[serde(default)]
+    pub lang: Lang,
+    /// Archive directories copied recursively, like sounds or lang files
+    #[serde(default)]
+    pub copy_dirs: BTreeMap<String, CopyDir>,
+    /// Connected textures, written with OptiFine `.properties` that
+    /// Continuity reads too
+    #[serde(default)]
+    pub ctm: CtmMap,
+    /// Glyph sheets, written to `textures/font` with providers in `font`
+    #[serde(default)]
+    pub fonts: FontMap,
+    /// Category directories single entries go to in place of their
+    /// section's, keyed by copied file or tile name, e.g. `"pipe.json" =
+    /// "item_models"` or `wrench = "blocks"`
+    #[serde(default)]
+    pub outputs: BTreeMap<String, Category>,
+    /// Other names of sliced tiles, keyed by the new name, e.g. `oak_slab =
+    /// "oak_planks"`, or `oak_slab = { model = "oak_planks" }` for a model
+    /// alias, so tiles sharing a texture don't need duplicate entries
+    #[serde(default)]
+    pub aliases: BTreeMap<String, Alias>,
+    /// Placeholders for textures that aren't mapped yet, as
+    /// `[fallbacks.blocks]` or `[fallbacks.items]` entries
+    #[serde(default)]
+    pub fallbacks: Fallbacks,
+    #[serde(default)]
+    pub blocks: AtlasMap,
+    #[serde(default)]
+    pub items: AtlasMap,
+}
+
+impl Namespace {
+    /// Category `name` of section `category` is written to, following
+    /// `outputs`
+    pub fn category(&self, name: &str, category: Category) -> Category {
+        self.outputs.get(name).copied().unwrap_or(category)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    /// Namespace of the mod itself, written at the top level
+    #[serde(flatten)]
+    pub main: Namespace,
+    /// More namespaces, e.g. of a bundled addon, one `[[namespaces]]` table
+    /// each, with the same keys as the main one
+    #[serde(default)]
+    pub namespaces: Vec<Namespace>,
+    /// Mod logo, copied to the output root, or left out for mods without
+    /// one
+    #[serde(default)]
+    pub banner: Option<String>,
+    /// Size to fit the banner into, as `[width, height]`, e.g. a mod menu's
+    /// logo size. Aspect ratio is kept, the rest is left transparent.
+    pub banner_size: Option<(u32, u32)>,
+    /// Convert the banner to png, e.g. from gif, with its name changed to
+    /// match. Implied by `banner_size`.
+    #[serde(default)]
+    pub banner_png: bool,
+    /// Also write the banner as `pack.png` at output root, so resources
+    /// double as a resource pack
+    #[serde(default)]
+    pub banner_pack_png: bool,
+    #[serde(default)]
+    pub imgs: Vec<String>,
+    #[serde(default)]
+    pub bin: String,
+    /// Size in pixels of a single atlas cell, e.g. 32 or 64 for HD sheets
+    #[serde(default = "default_tile_size")]
+    pub tile_size: u32,
+    /// Emit `blockstates/<name>.json` for every sliced block texture
+    #[serde(default)]
+    pub generate_blockstates: bool,
+    /// Decode every copied `gui` and `blocks_copy` png, warning about ones
+    /// that aren't really png, don't decode or aren't sized in powers of
+    /// two
+    #[serde(default)]
+    pub check_textures: bool,
+    /// Emit `models/item/<name>.json` for every sliced item texture
+    #[serde(default)]
+    pub generate_item_models: bool,
+    /// Parent of `models/block/<name>.json` emitted for every sliced block
+    /// texture, like `cube_all`. Tiles can pick another with `parent`.
+    pub auto_models: Option<String>,
+    /// Rewrite tile names, and other names written into resource
+    /// locations, as `snake_case` or `lowercase`, e.g. for sources using
+    /// `OreCopper`. Every rename is logged and listed in `--report`.
+    pub normalize_names: Option<NameStyle>,
+    /// Block model templates, used by tiles with `template`
+    #[serde(default)]
+    pub templates: Templates,
+    /// Output layout: forge, fabric or pack preset, or `[layout]` table
+    /// with paths of single categories
+    #[serde(default)]
+    pub layout: Layout,
+    /// Tints used by tiles with `palette` set
+    #[serde(default)]
+    pub palettes: Palettes,
+    /// Smallest CIE76 deltaE average colors of tinted copies of a tile can
+    /// differ by, with normal vision and simulated protanopia, deuteranopia
+    /// and tritanopia, before they're warned about. 0 turns the check off.
+    #[serde(default = "default_min_tint_delta_e")]
+    pub min_tint_delta_e: f32,
+    /// How many levels of archives inside archives to look into, for
+    /// entries like `outer.zip!inner.jar`
+    #[serde(default = "default_nested_depth")]
+    pub nested_depth: u32,
+    /// Match entry paths in archives exactly, instead of ignoring case and
+    /// `\` vs `/`
+    #[serde(default)]
+    pub exact_paths: bool,
+    /// URLs to download archives in `folders` from, if they're missing
+    #[serde(default)]
+    pub sources: Sources,
+    /// SHA-256 digests archives have to match before anything is read
+    #[serde(default)]
+    pub checksums: Checksums,
+    /// Archives or directories, relative to input directory, to paths
+    /// searched inside them. Archives can be zip or jar, tar, tar.gz, 7z or
+    /// rar, recognized by their contents. Keys can use `${VAR}` and `~`,
+    /// like `banner` and `gui`.
+    pub folders: Folders,
+    /// Minecraft `mods` directory, relative to input directory, for sources
+    /// in `folders` written as `mod:<modid>`: the jar in it declaring that
+    /// modid in its `mcmod.info`, `fabric.mod.json` or `mods.toml`, whatever
+    /// the file is called. Can use `${VAR}` and `~`, like `folders`.
+    pub mods_dir: Option<String>,
+    /// Which sources in `folders` to search first, when several have the
+    /// same file
+    #[serde(default)]
+    pub priorities: Priorities,
+    /// Sources in `folders` stacked lowest first, each shadowing the same
+    /// files in the ones below, e.g. a base jar, a patch zip, then a
+    /// directory of own fixes. Short for `priorities` counting up from 0.
+    #[serde(default)]
+    pub layers: Vec<String>,
+    /// Version of the mod sources are from, picking tables keyed
+    /// `@<version>` anywhere in config, like `[blocks.terrain."@1.7"]`, when
+    /// it's that version or one of its releases, like `1.7.10`. Their keys
+    /// replace ones of the table they're in, and tables of other versions
+    /// are left out.
+    pub mod_version: Option<String>,
+    /// Csv or toml file mapping legacy numeric ids to names, for tiles
+    /// written with `id`. Relative to the main toml.
+    pub ids: Option<PathBuf>,
+    /// Rhai script with functions tiles can name in `hook`, for fixes too
+    /// odd for config. Relative to the main toml.
+    pub script: Option<PathBuf>,
+    /// Every file read to load the config: config files, ids, templates and
+    /// script
+    #[serde(skip)]
+    pub files: Vec<PathBuf>,
+    /// Names changed by `normalize_names`
+    #[serde(skip)]
+    pub renames: Vec<Rename>,
+}
+
+impl Config {
+    /// Name the banner is written under, which ends in `.png` once it's
+    /// converted, if there's one
+    pub fn banner_output(&self) -> Option<String> {
+        let banner = self.banner.as_ref()?;
+        let is_png = banner.to_lowercase().ends_with(".png");
+        if is_png || !(self.banner_png || self.banner_size.is_some()) {
+            return Some(banner.clone());
+        }
+        let path = Path::new(banner).with_extension("png");
+        Some(path.to_string_lossy().into_owned())
+    }
+
+    /// Main namespace first, then the rest in config order
+    pub fn all_namespaces(&self) -> impl Iterator<Item = &Namespace> {
+        std::iter::once(&self.main).chain(&self.namespaces)
+    }
+
+    pub fn all_namespaces_mut(
+        &mut self,
+    ) -> impl Iterator<Item = &mut Namespace> {
+        std::iter::once(&mut self.main).chain(&mut self.namespaces)
+    }
+
+    /// Atlas called `name` and namespace it's in, looking through all of
+    /// them
+    pub fn find_atlas(&self, name: &str) -> Option<(&Namespace, &Atlas)> {
+        self.all_namespaces().find_map(|ns| {
+            let atlas = ns.blocks.get(name).or_else(|| ns.items.get(name))?;
+            Some((ns, atlas))
+        })
+    }
+}
+
+pub const DEFAULT_TILE_SIZE: u32 = 16;
+
+fn default_tile_size() -> u32 {
+    DEFAULT_TILE_SIZE
+}
+
+fn default_nested_depth() -> u32 {
+    1
+}
+
+fn default_min_tint_delta_e() -> f32 {
+    5.0
+}
+
+pub fn load_config(path: &Path) -> anyhow::Result<Config> {
+    let text = fs::read_to_string(path)?;
+    let value =
+        parse_config(path, &text).map_err(|e| Failure::Config.tag(e))?;
+    value.try_into().map_err(|e| Failure::Config.tag(e))
+}
+
+/// Extensions of config files, toml and the ones converted to it
+pub const CONFIG_EXTENSIONS: [&str; 4] = ["toml", "yaml", "yml", "json"];
+
+/// Converts yaml to toml values. Unlike `toml::Value::try_from`, it says
+/// where a null or non-string key is, e.g. an unquoted `00:` position.
+fn yaml_to_toml(
+    value: serde_yaml::Value,
+    key: &str,
+) -> anyhow::Result<toml::Value> {
+    use serde_yaml::Value as Yaml;
+    use toml::Value;
+    Ok(match value {
+        Yaml::Null => anyhow::bail!("`{}` is null", key),
+        Yaml::Bool(b) => Value::Boolean(b),
+        Yaml::Number(n) => match n.as_i64() {
+            Some(i) => Value::Integer(i),
+            None => Value::Float(n.as_f64().unwrap()),
+        },
+        Yaml::String(s) => Value::String(s),
+        Yaml::Sequence(values) => Value::Array(
+            values
+                .into_iter()
+                .map(|value| yaml_to_toml(value, key))
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        Yaml::Mapping(map) => {
+            let mut table = toml::value::Table::new();
+            for (name, value) in map {
+                let name = match name {
+                    Yaml::String(name) => name,
+                    name => anyhow::bail!(
+                        "`{}`: key {:?} has to be a string, quote it",
+                        key,
+                        name
+                    ),
+                };
+                let key = if key.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{}.{}", key, name)
+                };
+                table.insert(name, yaml_to_toml(value, &key)?);
+            }
+            Value::Table(table)
+        }
+        Yaml::Tagged(tagged) => yaml_to_toml(tagged.value, key)?,
+    })
+}
+
+/// Parses `text` of config `file` as yaml or json by extension, otherwise
+/// as toml
+fn parse_config(file: &Path, text: &str) -> anyhow::Result<toml::Value> {
+    let extension = file.extension().and_then(|ext| ext.to_str());
+    let value = match extension {
+        Some("yaml" | "yml") => serde_yaml::from_str(text)
+            .map_err(anyhow::Error::from)
+            .and_then(|value| yaml_to_toml(value, "")),
+        Some("json") => serde_json::from_str::<serde_json::Value>(text)
+            .map_err(anyhow::Error::from)
+            .and_then(|value| Ok(toml::Value::try_from(value)?)),
+        // Parsed by `toml_edit` first for errors showing the line
+        _ => toml_edit::Document::parse(text)
+            .map_err(anyhow::Error::from)
+            .and_then(|_| Ok(text.parse::<toml::Value>()?)),
+    };
+    value.map_err(|e| anyhow::anyhow!("{}: {}", file.display(), e))
+}
+
+/// Expands directories into the config files directly inside them, in name
+/// order
+fn config_files(paths: &[PathBuf]) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for path in paths {
+        if !path.is_dir() {
+            files.push(path.clone());
+            continue;
+        }
+        let mut found = vec![];
+        for entry in fs::read_dir(path)? {
+            let path = entry?.path();
+            let is_config = path.extension().is_some_and(|ext| {
+                CONFIG_EXTENSIONS.iter().any(|config| ext == *config)
+            });
+            if is_config {
+                found.push(path);
+            }
+        }
+        anyhow::ensure!(
+            !found.is_empty(),
+            "no config files in {}",
+            path.display()
+        );
+        found.sort();
+        files.extend(found);
+    }
+    Ok(files)
+}
+
+/// `value` with every `${VAR}` replaced by environment variable `VAR`, and
+/// a leading `~` by the home directory
+pub fn expand_env(value: &str) -> anyhow::Result<String> {
+    let var = |name: &str| {
+        std::env::var(name).map_err(|_| {
+            anyhow::anyhow!(
+                "{:?}: environment variable {} isn't set",
+                value,
+                name
+            )
+        })
+    };
+    let mut out = String::new();
+    let mut rest = value;
+    if rest == "~" || rest.starts_with("~/") {
+        let home = var("HOME").or_else(|_| var("USERPROFILE"))?;
+        out.push_str(&home);
+        rest = &rest[1..];
+    }
+    while let Some(start) = rest.find("${") {
+        out.push_str(&rest[..start]);
+        let end = rest[start..].find('}').ok_or_else(|| {
+            anyhow::anyhow!("{:?}: unterminated `${{`", value)
+        })?;
+        out.push_str(&var(&rest[start + 2..start + end])?);
+        rest = &rest[start + end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Same map with `expand_env` applied to its keys
+fn expand_keys<V>(
+    map: BTreeMap<String, V>,
+) -> anyhow::Result<BTreeMap<String, V>> {
+    map.into_iter()
+        .map(|(key, value)| Ok((expand_env(&key)?, value)))
+        .collect()
+}
+
+/// Expands environment variables in paths that differ between machines:
+/// sources in `folders`, and keys referring to them, banner and GUI files
+fn expand_config_env(config: &mut Config) -> anyhow::Result<()> {
+    config.folders = expand_keys(std::mem::take(&mut config.folders))?;
+    config.priorities = expand_keys(std::mem::take(&mut config.priorities))?;
+    for layer in &mut config.layers {
+        *layer = expand_env(layer)?;
+    }
+    config.sources = expand_keys(std::mem::take(&mut config.sources))?;
+    config.checksums = expand_keys(std::mem::take(&mut config.checksums))?;
+    // Empty, as older scaffolds wrote for mods without one, is no banner
+    config.banner = match config.banner.take() {
+        Some(banner) if !banner.is_empty() => Some(expand_env(&banner)?),
+        _ => None,
+    };
+    if let Some(dir) = &mut config.mods_dir {
+        *dir = expand_env(dir)?;
+    }
+    for ns in config.all_namespaces_mut() {
+        for gui in &mut ns.gui {
+            *gui = expand_env(gui)?;
+        }
+    }
+    Ok(())
+}
+
+/// Config path standing for stdin
+pub const STDIN: &str = "-";
+
+/// Text of config `file`, read from stdin for `-`
+fn read_config(file: &Path) -> anyhow::Result<String> {
+    if file != Path::new(STDIN) {
+        return Ok(fs::read_to_string(file)?);
+    }
+    let mut text = String::new();
+    std::io::stdin().read_to_string(&mut text)?;
+    Ok(text)
+}
+
+/// Value replacing a key of the merged config, like `--set modid=other` or
+/// `--set blocks.terrain.tile_size=32`. Values that don't parse as toml,
+/// like bare words, are taken as strings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigOverride {
+    pub key: Vec<String>,
+    pub value: toml::Value,
+}
+
+impl FromStr for ConfigOverride {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, value) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("expected key=value"))?;
+        let key: Vec<_> = key.trim().split('.').map(str::to_owned).collect();
+        anyhow::ensure!(
+            key.iter().all(|part| !part.is_empty()),
+            "empty key in {:?}",
+            s
+        );
+        let value = value.trim();
+        let value = format!("value = {}", value)
+            .parse::<toml::Value>()
+            .ok()
+            .and_then(|mut table| table.as_table_mut()?.remove("value"))
+            .unwrap_or_else(|| toml::Value::String(value.to_owned()));
+        Ok(Self { key, value })
+    }
+}
+
+impl ConfigOverride {
+    /// Sets `mod_version`, always as a string, unlike `1.7` given with
+    /// `--set`
+    pub fn mod_version(version: String) -> Self {
+        Self {
+            key: vec!["mod_version".to_owned()],
+            value: toml::Value::String(version),
+        }
+    }
+
+    /// Sets the key in `config`, adding missing tables on the way
+    fn apply(&self, config: &mut toml::Value) -> anyhow::Result<()> {
+        let (last, tables) = self.key.split_last().unwrap();
+        let mut table = config;
+        for (i, part) in tables.iter().enumerate() {
+            table = table
+                .as_table_mut()
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "--set {}: `{}` is not a table",
+                        self.key.join("."),
+                        self.key[..i].join(".")
+                    )
+                })?
+                .entry(part.clone())
+                .or_insert_with(|| toml::Value::Table(Default::default()));
+        }
+        let table = table.as_table_mut().ok_or_else(|| {
+            anyhow::anyhow!(
+                "--set {}: `{}` is not a table",
+                self.key.join("."),
+                tables.join(".")
+            )
+        })?;
+        table.insert(last.clone(), self.value.clone());
+        Ok(())
+    }
+}
+
+/// Merges `from` into `into`: tables key by key, arrays by appending new
+/// entries, while other values have to match exactly
+fn merge_value(
+    into: &mut toml::Value,
+    from: toml::Value,
+    key: &str,
+    file: &Path,
+) -> anyhow::Result<()> {
+    use toml::Value;
+    match (into, from) {
+        (Value::Table(into), Value::Table(from)) => {
+            for (name, value) in from {
+                let key = if key.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{}.{}", key, name)
+                };
+                match into.get_mut(&name) {
+                    Some(existing) => merge_value(existing, value, &key, file)?,
+                    None => {
+                        into.insert(name, value);
+                    }
+                }
+            }
+        }
+        (Value::Array(into), Value::Array(from)) => {
+            for value in from {
+                if !into.contains(&value) {
+                    into.push(value);
+                }
+            }
+        }
+        (into, from) => anyhow::ensure!(
+            *into == from,
+            "{}: `{}` conflicts with earlier config ({} vs {})",
+            file.display(),
+            key,
+            from,
+            into
+        ),
+    }
+    Ok(())
+}
+
+/// Whether `@<gate>` covers `mod_version`: the same version, or one of its
+/// releases, like `1.7.10` for `1.7`
+fn version_matches(gate: &str, mod_version: &str) -> bool {
+    mod_version
+        .strip_prefix(gate)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+}
+
+/// Replaces values of `into` by the ones in `from`, key by key in tables
+fn override_table(into: &mut toml::value::Table, from: toml::value::Table) {
+    for (name, value) in from {
+        match (into.get_mut(&name), value) {
+            (Some(toml::Value::Table(into)), toml::Value::Table(from)) => {
+                override_table(into, from)
+            }
+            (_, value) => {
+                into.insert(name, value);
+            }
+        }
+    }
+}
+
+/// Config `value` of `file` with the configs its `include` list names
+/// merged under it: every include in order, then `value` itself, each
+/// overriding keys of the ones before, so shared sections can be kept in
+/// one file. Paths are relative to `file`, or to `base` if it isn't one.
+/// Included files are added to `files`, `stack` holds the ones being
+/// included to catch cycles.
+fn resolve_includes(
+    mut value: toml::Value,
+    file: &Path,
+    base: &Path,
+    files: &mut Vec<PathBuf>,
+    stack: &mut Vec<PathBuf>,
+) -> anyhow::Result<toml::Value> {
+    let table = value
+        .as_table_mut()
+        .and_then(|table| table.remove("include"));
+    let includes = match table {
+        Some(toml::Value::Array(includes)) => includes,
+        Some(_) => anyhow::bail!(
+            "{}: include has to be a list of config files",
+            file.display()
+        ),
+        None => return Ok(value),
+    };
+    let dir = match file.is_file() {
+        true => file.parent().unwrap_or(base),
+        false => base,
+    };
+    let mut merged = toml::value::Table::new();
+    for include in includes {
+        let path = match include {
+            toml::Value::String(path) => dir.join(expand_env(&path)?),
+            other => anyhow::bail!(
+                "{}: include has to be a list of config files, not {}",
+                file.display(),
+                other
+            ),
+        };
+        let key = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+        anyhow::ensure!(
+            !stack.contains(&key),
+            "{}: including {} again makes a cycle",
+            file.display(),
+            path.display()
+        );
+        let text = fs::read_to_string(&path).map_err(|e| {
+            anyhow::anyhow!(
+                "{}: include {}: {}",
+                file.display(),
+                path.display(),
+                e
+            )
+        })?;
+        let included = parse_config(&path, &text)?;
+        stack.push(key);
+        let included = resolve_includes(included, &path, base, files, stack)?;
+        stack.pop();
+        if !files.contains(&path) {
+            files.push(path);
+        }
+        if let toml::Value::Table(included) = included {
+            override_table(&mut merged, included);
+        }
+    }
+    if let toml::Value::Table(table) = value {
+        override_table(&mut merged, table);
+    }
+    Ok(toml::Value::Table(merged))
+}
+
+/// Applies tables keyed `@<version>` in `value` matching `mod_version` to
+/// the table they're in, less specific versions first, and removes all of
+/// them
+fn select_versions(
+    value: &mut toml::Value,
+    key: &str,
+    mod_version: Option<&str>,
+) -> anyhow::Result<()> {
+    let table = match value {
+        toml::Value::Table(table) => table,
+        toml::Value::Array(values) => {
+            for value in values {
+                select_versions(value, key, mod_version)?;
+            }
+            return Ok(());
+        }
+        _ => return Ok(()),
+    };
+    let gates: Vec<_> = table
+        .keys()
+        .filter(|name| name.starts_with('@'))
+        .cloned()
+        .collect();
+    let mut selected = vec![];
+    for gate in gates {
+        let key = match key {
+            "" => gate.clone(),
+            _ => format!("{}.{}", key, gate),
+        };
+        let mut section = table.remove(&gate).unwrap();
+        let version = &gate[1..];
+        anyhow::ensure!(
+            !version.is_empty() && section.is_table(),
+            "`{}` has to be a table named after a version, like `@1.7`",
+            key
+        );
+        if mod_version.is_some_and(|v| version_matches(version, v)) {
+            select_versions(&mut section, &key, mod_version)?;
+            selected.push((version.split('.').count(), section));
+        }
+    }
+    selected.sort_by_key(|(parts, _)| *parts);
+    for (_, section) in selected {
+        if let toml::Value::Table(section) = section {
+            override_table(table, section);
+        }
+    }
+    for (name, value) in table.iter_mut() {
+        let key = match key {
+            "" => name.clone(),
+            _ => format!("{}.{}", key, name),
+        };
+        select_versions(value, &key, mod_version)?;
+    }
+    Ok(())
+}
+
+/// Reads descriptors of `sprite_sheets`, relative to `base`, into their
+/// crops
+fn load_sprite_sheets(config: &mut Config, base: &Path) -> anyhow::Result<()> {
+    let mut files = vec![];
+    for ns in config.all_namespaces_mut() {
+        for (sheet, sprites) in &mut ns.sprite_sheets {
+            anyhow::ensure!(
+                !sprites.category.is_model(),
+                "[sprite_sheets] {}: {} isn't a texture category",
+                sheet,
+                sprites.category.name()
+            );
+            let path = base.join(&sprites.descriptor);
+            sprites.crops = fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|text| read_descriptor(&serde_json::from_str(&text)?))
+                .map_err(|e| {
+                    anyhow::anyhow!(
+                        "[sprite_sheets] {}: {}: {}",
+                        sheet,
+                        path.display(),
+                        e
+                    )
+                })?;
+            files.push(path);
+        }
+    }
+    config.files.extend(files);
+    Ok(())
+}
+
+/// Reads templates given as paths, relative to `base`
+fn load_templates(config: &mut Config, base: &Path) -> anyhow::Result<()> {
+    for (name, template) in &mut config.templates {
+        if let serde_json::Value::String(path) = template {
+            let path = base.join(&path);
+            config.files.push(path.clone());
+            let json = fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|text| Ok(serde_json::from_str(&text)?))
+                .map_err(|e| {
+                    anyhow::anyhow!(
+                        "template {}: {}: {}",
+                        name,
+                        path.display(),
+                        e
+                    )
+                })?;
+            *template = json;
+        }
+    }
+    Ok(())
+}
+
+/// Checks every tile's template exists and all of its placeholders get a
+/// value, and that only tiles with a template have variables
+fn check_templates(config: &Config) -> anyhow::Result<()> {
+    for ns in config.all_namespaces() {
+        let blocks = ns.blocks.iter().map(|atlas| (atlas, true));
+        let items = ns.items.iter().map(|atlas| (atlas, false));
+        for ((name, map), is_block) in blocks.chain(items) {
+            for (pos, tile) in &map.tiles {
+                anyhow::ensure!(
+                    is_block || tile.template.is_none(),
+                    "atlas {}: {:?} uses a template, but only blocks get \
+                     generated models",
+                    name,
+                    pos
+                );
+                let template = match &tile.template {
+                    Some(template) => template,
+                    None => {
+                        anyhow::ensure!(
+                            tile.vars.is_empty(),
+                            "atlas {}: {:?} has unknown keys {:?}, template \
+                             variables need `template`",
+                            name,
+                            pos,
+                            tile.vars.keys().collect::<Vec<_>>()
+                        );
+                        continue;
+                    }
+                };
+                let json = config.templates.get(template).ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "atlas {}: {:?} uses unknown template {}",
+                        name,
+                        pos,
+                        template
+                    )
+                })?;
+                templated_model(json, &ns.modid, &tile.name, &tile.vars)
+                    .map_err(|e| {
+                        anyhow::anyhow!(
+                            "atlas {}: {:?}, template {}: {}",
+                            name,
+                            pos,
+                            template,
+                            e
+                        )
+                    })?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Errors listing every output file that more than one entry would write,
+/// e.g. two tiles with the same name, a tile and a `blocks_copy` file, or
+/// blocks and items of the same name in a layout sharing their directory
+pub fn check_output_names(config: &Config) -> anyhow::Result<()> {
+    check_output_paths(config)?;
+    // Output file, by mod id and path under `assets/<modid>`, to entries
+    // writing it
+    let mut outputs = BTreeMap::<(&str, PathBuf), Vec<String>>::new();
+    for ns in config.all_namespaces() {
+        let dirs =
+            OutputDirs::new(&config.layout, PathBuf::new(), &ns.modid, None);
+        let mut add = |dir: &Path, file: &str, entry: String| {
+            let path = dir.join(file);
+            let path = path.strip_prefix(&dirs.namespace).unwrap_or(&path);
+            let output = (ns.modid.as_str(), path.to_owned());
+            outputs.entry(output).or_default().push(entry);
+        };
+        for (category, atlas) in
+            [(Category::Blocks, &ns.blocks), (Category::Items, &ns.items)]
+        {
+            for (name, map) in atlas {
+                for (pos, tile) in &map.tiles {
+                    let dir = ns.category(&tile.name, category).dir(&dirs);
+                    for output in tile.output_names(&config.palettes) {
+                        let entry =
+                            format!("[{}.{}] {:?}", category.name(), name, pos);
+                        add(dir, &format!("{}.png", output), entry);
+                    }
+                }
+            }
+        }
+        for file in &ns.blocks_copy {
+            let dir = ns.category(file, Category::Blocks).dir(&dirs);
+            add(dir, file, "[blocks_copy]".to_owned());
+        }
+        for file in &ns.models {
+            let dir = ns.category(file, Category::Models).dir(&dirs);
+            add(dir, file, "[models]".to_owned());
+        }
+        for (name, map) in &ns.blocks {
+            for (pos, tile) in &map.tiles {
+                if tile.model(config.auto_models.as_deref()).is_some() {
+                    for model in tile.model_names() {
+                        let entry =
+                            format!("[blocks.{}] {:?} model", name, pos);
+                        let file = format!("{}.json", model);
+                        add(&dirs.models, &file, entry);
+                    }
+                }
+            }
+        }
+        for file in &ns.gui {
+            let dir = ns.category(file, Category::Gui).dir(&dirs);
+            add(dir, file, "[gui]".to_owned());
+        }
+        for (category, files) in [
+            (Category::Entity, &ns.entity),
+            (Category::Particle, &ns.particle),
+            (Category::Environment, &ns.environment),
+        ] {
+            for file in files {
+                let dir = ns.category(file, category).dir(&dirs);
+                add(dir, file, format!("[{}]", category.name()));
+            }
+        }
+        for (file, to) in &ns.misc {
+            anyhow::ensure!(
+                !to.is_empty()
+                    && Path::new(to)
+                        .components()
+                        .all(|part| matches!(part, Component::Normal(_))),
+                "[misc] {}: invalid output {:?}, expected a relative path",
+                file,
+                to
+            );
+            add(&dirs.namespace, to, format!("[misc] {}", file));
+        }
+        for name in ns.fonts.keys() {
+            let entry = format!("[fonts.{}]", name);
+            add(&dirs.fonts, &format!("{}.png", name), entry);
+        }
+        for (sheet, crops) in &ns.gui_crops {
+            for file in crops.iter().flat_map(Crop::outputs) {
+                let file = file.to_string_lossy();
+                add(&dirs.guis, &file, format!("[gui_crops] {}", sheet));
+            }
+        }
+        for (sheet, sprites) in &ns.sprite_sheets {
+            for crop in &sprites.crops {
+                let file = format!("{}.png", crop.name);
+                let entry = format!("[sprite_sheets] {}", sheet);
+                add(sprites.category.dir(&dirs), &file, entry);
+            }
+        }
+        for (category, name, _) in ns.fallbacks.iter() {
+            let dir = ns.category(name, category).dir(&dirs);
+            let entry = format!("[fallbacks.{}]", category.name());
+            add(dir, &format!("{}.png", name), entry);
+        }
+    }
+    let conflicts: Vec<_> = outputs
+        .iter()
+        .filter(|(_, entries)| entries.len() > 1)
+        .map(|((modid, path), entries)| {
+            let entries = entries.join(", ");
+            format!("  {}:{}: {}", modid, path.display(), entries)
+        })
+        .collect();
+    if !conflicts.is_empty() {
+        return Err(Failure::Config.tag(anyhow::anyhow!(
+            "output files written more than once:\n{}",
+            conflicts.join("\n")
+        )));
+    }
+    Ok(())
+}
+
+/// Checks every `outputs` key names an entry that can be written to its
+/// category: a copied model to a model one, a copied texture to a texture
+/// one, or a tile to blocks or items
+fn check_outputs(config: &Config) -> anyhow::Result<()> {
+    for ns in config.all_namespaces() {
+        let textures = [
+            &ns.gui,
+            &ns.blocks_copy,
+            &ns.entity,
+            &ns.particle,
+            &ns.environment,
+        ];
+        let is_tile = |name: &String| {
+            let mut atlases = ns.blocks.values().chain(ns.items.values());
+            atlases.any(|map| map.tiles.values().any(|tile| tile.name == *name))
+        };
+        for (name, category) in &ns.outputs {
+            let fits = if ns.models.contains(name) {
+                category.is_model()
+            } else if textures.iter().any(|files| files.contains(name)) {
+                !category.is_model()
+            } else if is_tile(name) {
+                matches!(category, Category::Blocks | Category::Items)
+            } else {
+                anyhow::bail!(
+                    "[outputs] {}: isn't a copied file or tile name",
+                    name
+                );
+            };
+            anyhow::ensure!(
+                fits,
+                "[outputs] {}: can't be written to {}",
+                name,
+                category.name()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Gives every source in `layers` its priority, the bottom one 0
+fn resolve_layers(config: &mut Config) -> anyhow::Result<()> {
+    for (priority, layer) in config.layers.iter().enumerate() {
+        anyhow::ensure!(
+            config.folders.contains_key(layer),
+            "layer {} isn't in folders",
+            layer
+        );
+        anyhow::ensure!(
+            !config.layers[..priority].contains(layer),
+            "layer {} is listed twice",
+            layer
+        );
+        anyhow::ensure!(
+            !config.priorities.contains_key(layer),
+            "{} is a layer, it can't have a priority too",
+            layer
+        );
+    }
+    for (priority, layer) in config.layers.iter().enumerate() {
+        config.priorities.insert(layer.clone(), priority as i32);
+    }
+    Ok(())
+}
+
+/// Makes sure sources found by modid have a `mods_dir` to look in
+fn check_mod_sources(config: &Config) -> anyhow::Result<()> {
+    if config.mods_dir.is_some() {
+        return Ok(());
+    }
+    let by_modid = config.folders.keys().find(|s| s.starts_with(MOD_PREFIX));
+    match by_modid {
+        Some(source) => anyhow::bail!("source {} needs mods_dir set", source),
+        None => Ok(()),
+    }
+}
+
+/// Hands `[aliases]` to the tiles they name. Model aliases need the tile
+/// to have a generated model to inherit from.
+fn resolve_aliases(config: &mut Config) -> anyhow::Result<()> {
+    let auto_models = config.auto_models.clone();
+    let item_models = config.generate_item_models;
+    for ns in config.all_namespaces_mut() {
+        for (alias, target) in &ns.aliases {
+            let name = match target {
+                Alias::Texture(name) | Alias::Model(name) => name,
+            };
+            let blocks = ns.blocks.values_mut().map(|map| (true, map));
+            let items = ns.items.values_mut().map(|map| (false, map));
+            let tile = blocks.chain(items).find_map(|(block, map)| {
+                let tile = map.tiles.values_mut().find(|t| t.name == *name)?;
+                Some((block, tile))
+            });
+            let (block, tile) = tile.ok_or_else(|| {
+                anyhow::anyhow!("[aliases] {}: `{}` isn't a tile", alias, name)
+            })?;
+            match target {
+                Alias::Texture(_) => tile.aliases.push(alias.clone()),
+                Alias::Model(_) => {
+                    let model = match block {
+                        true => tile.model(auto_models.as_deref()).is_some(),
+                        false => item_models,
+                    };
+                    anyhow::ensure!(
+                        model,
+                        "[aliases] {}: `{}` has no generated model",
+                        alias,
+                        name
+                    );
+                    tile.model_aliases.push(alias.clone());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Leaves out `[fallbacks]` for names something else writes by now, and
+/// checks the atlases the rest cut cells out of
+fn resolve_fallbacks(config: &mut Config) -> anyhow::Result<()> {
+    let palettes = config.palettes.clone();
+    for ns in config.all_namespaces_mut() {
+        let mut mapped = BTreeSet::new();
+        for (category, atlases) in
+            [(Category::Blocks, &ns.blocks), (Category::Items, &ns.items)]
+        {
+            for tile in atlases.values().flat_map(|map| map.tiles.values()) {
+                for name in tile.output_names(&palettes) {
+                    mapped.insert((
+                        ns.category(&tile.name, category).name(),
+                        name,
+                    ));
+                }
+            }
+        }
+        for file in &ns.blocks_copy {
+            let name = Path::new(file).with_extension("");
+            let name = name.to_string_lossy().into_owned();
+            mapped.insert((ns.category(file, Category::Blocks).name(), name));
+        }
+        for sprites in ns.sprite_sheets.values() {
+            for crop in &sprites.crops {
+                mapped.insert((sprites.category.name(), crop.name.clone()));
+            }
+        }
+        for (category, fallbacks) in [
+            (Category::Blocks, &mut ns.fallbacks.blocks),
+            (Category::Items, &mut ns.fallbacks.items),
+        ] {
+            let atlases = match category {
+                Category::Blocks => &ns.blocks,
+                _ => &ns.items,
+            };
+            let section = category.name();
+            let outputs = &ns.outputs;
+            let mut result = Ok(());
+            fallbacks.retain(|name, fallback| {
+                let dir = outputs.get(name).copied().unwrap_or(category);
+                if mapped.contains(&(dir.name(), name.clone())) {
+                    log::info!(
+                        "[fallbacks.{}] {}: mapped now, fallback unused",
+                        section,
+                        name
+                    );
+                    return false;
+                }
+                if let Fallback::Cell { atlas, .. } = fallback {
+                    if !atlases.contains_key(atlas) && result.is_ok() {
+                        result = Err(anyhow::anyhow!(
+                            "[fallbacks.{}] {}: `{}` isn't an atlas of {}",
+                            section,
+                            name,
+                            atlas,
+                            section
+                        ));
+                    }
+                }
+                true
+            });
+            result?;
+        }
+    }
+    Ok(())
+}
+
+/// Frame `index` of a strip, as the cell it's in
+fn strip_frame(index: AtlasPos) -> AtlasPos {
+    AtlasPos::from_pos(0, index.y * 16 + index.x)
+}
+
+/// Moves tiles of `kind = "strip"` atlases from frame indices they're keyed
+/// by to cells of a single column, and their `animation` in as a tile over
+/// every frame
+fn resolve_strips(config: &mut Config) -> anyhow::Result<()> {
+    for ns in config.all_namespaces_mut() {
+        let atlases = ns.blocks.iter_mut().chain(ns.items.iter_mut());
+        for (name, atlas) in atlases {
+            if atlas.kind == AtlasKind::Grid {
+                anyhow::ensure!(
+                    atlas.animation.is_none(),
+                    "atlas {}: `animation` needs `kind = \"strip\"`",
+                    name
+                );
+                continue;
+            }
+            anyhow::ensure!(
+                atlas.origin == Origin::TopLeft
+                    && atlas.order == Order::RowMajor,
+                "atlas {}: `origin` and `order` only apply to grids",
+                name
+            );
+            let mut frames = BTreeMap::new();
+            for (index, mut tile) in std::mem::take(&mut atlas.tiles) {
+                let mask = tile.mask_cell();
+                for pos in std::iter::once(index).chain(mask) {
+                    anyhow::ensure!(
+                        pos.is_classic(),
+                        "atlas {}: {:?} isn't a frame index, strips have \
+                         frames 00 to ff",
+                        name,
+                        pos
+                    );
+                }
+                anyhow::ensure!(
+                    tile.span.0 == 1 && tile.frames > 0,
+                    "atlas {}: {:?} ({}) has to be a single column, with at \
+                     least one frame",
+                    name,
+                    index,
+                    tile.name
+                );
+                if let Some(mask) = mask {
+                    tile.emissive_mask =
+                        Some(EmissiveMask::Cell(strip_frame(mask)));
+                }
+                frames.insert(strip_frame(index), tile);
+            }
+            if let Some(mut tile) = atlas.animation.take() {
+                anyhow::ensure!(
+                    frames.is_empty(),
+                    "atlas {}: has both `animation` and frames mapped one by \
+                     one",
+                    name
+                );
+                anyhow::ensure!(
+                    tile.span == (1, 1) && tile.frames == 1,
+                    "atlas {}: `animation` ({}) always spans the whole strip, \
+                     without `span` or `frames`",
+                    name,
+                    tile.name
+                );
+                // Counted by `Atlas::with_grid` once the sheet is read
+                tile.frames = 0;
+                frames.insert(AtlasPos::from_pos(0, 0), tile);
+            }
+            atlas.tiles = frames;
+        }
+    }
+    Ok(())
+}
+
+/// Loads several configs, or directories of them, merged into one, with
+/// `overrides` set on top. Sections are combined, conflicting values and
+/// output names are errors. A path of `-` reads a config from stdin, with
+/// relative paths in it based on the working directory. Each config can
+/// pull in shared ones with `include = ["common.toml"]`, its own keys
+/// overriding theirs.
+pub fn load_configs(
+    paths: &[PathBuf],
+    overrides: &[ConfigOverride],
+) -> anyhow::Result<Config> {
+    let sources = read_configs(paths)?;
+    let base = paths[0].parent().unwrap_or_else(|| Path::new(""));
+    parse_configs(&sources, base, overrides)
+}
+
+/// Text of every config file in `paths`, with directories expanded
+pub(crate) fn read_configs(
+    paths: &[PathBuf],
+) -> anyhow::Result<Vec<ConfigSource>> {
+    let files = config_files(paths)?;
+    let stdin = files.iter().filter(|file| *file == Path::new(STDIN));
+    anyhow::ensure!(stdin.count() <= 1, "stdin can only be read once");
+    files
+        .into_iter()
+        .map(|file| {
+            Ok(ConfigSource {
+                text: read_config(&file)?,
+                file,
+            })
+        })
+        .collect()
+}
+
+/// Like `load_configs`, but with configs already read, e.g. embedded in a
+/// build script. Format comes from extension of each `file`, relative
+/// paths in them are based on `base`. Errors are `Failure::Config`, unless
+/// reading a file they refer to fails.
+pub fn parse_configs(
+    sources: &[ConfigSource],
+    base: &Path,
+    overrides: &[ConfigOverride],
+) -> anyhow::Result<Config> {
+    merge_configs(sources, base, overrides).map_err(|e| Failure::Config.tag(e))
+}
+
+fn merge_configs(
+    sources: &[ConfigSource],
+    base: &Path,
+    overrides: &[ConfigOverride],
+) -> anyhow::Result<Config> {
+    let mut merged = toml::Value::Table(Default::default());
+    let mut included = vec![];
+    for source in sources {
+        let value = parse_config(&source.file, &source.text)?;
+        let file = &source.file;
+        let key = fs::canonicalize(file).unwrap_or_else(|_| file.clone());
+        let mut stack = vec![key];
+        let value =
+            resolve_includes(value, file, base, &mut included, &mut stack)?;
+        merge_value(&mut merged, value, "", &source.file)?;
+    }
+    for set in overrides {
+        set.apply(&mut merged)?;
+    }
+    let mod_version = match merged.get("mod_version") {
+        Some(toml::Value::String(version)) => Some(version.clone()),
+        Some(_) => anyhow::bail!(
+            "mod_version has to be a string, like \"1.7.10\", or given \
+             with --mod-version"
+        ),
+        None => None,
+    };
+    select_versions(&mut merged, "", mod_version.as_deref())?;
+    let mut config = merged.clone().try_into::<Config>().map_err(|e| {
+        let error = e.to_string();
+        let diagnostic =
+            locate_config_error(&merged, error, sources, overrides);
+        anyhow::anyhow!("{}", diagnostic)
+    })?;
+    // Ones embedded or read from stdin aren't files
+    config.files = sources
+        .iter()
+        .map(|source| source.file.clone())
+        .filter(|file| file.exists())
+        .collect();
+    config.files.extend(included);
+    expand_config_env(&mut config)?;
+    resolve_layers(&mut config)?;
+    check_mod_sources(&config)?;
+    let ids = match &config.ids {
+        Some(path) => {
+            config.files.push(base.join(path));
+            load_ids(&base.join(path))?
+        }
+        None => IdMap::new(),
+    };
+    resolve_strips(&mut config)?;
+    resolve_ids(&mut config, &ids)?;
+    load_sprite_sheets(&mut config, base)?;
+    normalize_names(&mut config);
+    resolve_aliases(&mut config)?;
+    resolve_fallbacks(&mut config)?;
+    config.script = config.script.map(|script| base.join(script));
+    config.files.extend(config.script.clone());
+    load_templates(&mut config, base)?;
+    check_templates(&config)?;
+    check_outputs(&config)?;
+    check_output_names(&config)?;
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pos(key: &str) -> AtlasPos {
+        key.parse().unwrap()
+    }
+
+    #[test]
+    fn three_digit_keys_are_indices() {
+        assert_eq!(pos("1ff"), AtlasPos::from_index(0x1ff));
+        assert_eq!(format!("{:?}", pos("01f")), "01f");
+        assert_eq!(format!("{:?}", pos("1f1f")), "1f1f");
+        assert!(matches!(
+            "12345".parse::<AtlasPos>(),
+            Err(ParseError::WrongSize(5))
+        ));
+    }
+
+    #[test]
+    fn indices_are_placed_row_by_row() {
+        assert_eq!(pos("1ff").place(32), AtlasPos::from_pos(31, 15));
+        assert_eq!(pos("3ff").place(32), AtlasPos::from_pos(31, 31));
+        assert_eq!(pos("020").place(16), AtlasPos::from_pos(0, 2));
+        // Cells stay as they are, and so do indices no grid can place
+        assert_eq!(pos("1f").place(32), pos("1f"));
+        assert_eq!(pos("fff").place(0), pos("fff"));
+        assert_eq!(pos("fff").place(1), pos("fff"));
+    }
+
+    #[test]
+    fn index_ranges() {
+        let range = |key| key_positions(key).map(|positions| positions.len());
+        assert_eq!(range("100-13f"), Some(0x40));
+        assert_eq!(range("3fe-3ff"), Some(2));
+        assert_eq!(range("00-100"), None);
+        assert_eq!(range("120-110"), None);
+    }
+
+    #[test]
+    fn with_grid_places_indices() {
+        let atlas: Atlas = toml::from_str(
+            r#"
+            "000" = "first"
+            "3ff" = "last"
+            "0a" = "cell"
+            "#,
+        )
+        .unwrap();
+        let placed = atlas.with_grid(32, 32).unwrap();
+        let names: BTreeMap<_, _> = placed
+            .tiles
+            .iter()
+            .map(|(pos, tile)| (*pos, tile.name.as_str()))
+            .collect();
+        assert_eq!(names[&AtlasPos::from_pos(0, 0)], "first");
+        assert_eq!(names[&AtlasPos::from_pos(31, 31)], "last");
+        assert_eq!(names[&AtlasPos::from_pos(10, 0)], "cell");
+
+        let columns: Atlas = toml::from_str(
+            r#"
+            order = "column_major"
+            "021" = "third"
+            "#,
+        )
+        .unwrap();
+        let placed = columns.with_grid(4, 16).unwrap();
+        // Index 0x21 is in column 2, row 1, counting down columns of 16
+        assert!(placed.tiles.contains_key(&AtlasPos::from_pos(2, 1)));
+    }
+
+    #[test]
+    fn with_grid_rejects_indices_on_mapped_cells() {
+        let atlas: Atlas = toml::from_str(
+            r#"
+            "010" = "index"
+            "10" = "cell"
+            "#,
+        )
+        .unwrap();
+        let err = atlas.with_grid(16, 16).unwrap_err();
+        assert_eq!(err.to_string(), "position 10 is mapped more than once");
+        // On a wider grid they're different cells
+        let placed = atlas.with_grid(32, 16).unwrap();
+        assert_eq!(placed.tiles.len(), 2);
+    }
+
+    fn parse(text: &str) -> anyhow::Result<Config> {
+        let source = ConfigSource {
+            file: PathBuf::from("test.toml"),
+            text: text.to_owned(),
+        };
+        parse_configs(&[source], Path::new(""), &[])
+    }
+
+    #[test]
+    fn output_names_follow_layout() {
+        let config = r#"
+            modid = "test"
+            folders = {}
+            [blocks."blocks.png"]
+            "00" = "stone"
+            [items."items.png"]
+            "00" = "stone"
+            "#;
+        parse(config).unwrap();
+        let shared = format!(
+            "{}\n[layout]\nitems = \"textures/block\"\n",
+            config
+        );
+        let err = parse(&shared).unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("test:textures/block/stone.png"),
+            "{:#}",
+            err
+        );
+    }
+
+    #[test]
+    fn misc_outputs_are_under_namespace() {
+        let config = r#"
+            modid = "test"
+            folders = {}
+            [blocks."blocks.png"]
+            "00" = "stone"
+            [misc]
+            "stone.png" = "textures/block/stone.png"
+            "#;
+        let err = parse(config).unwrap_err();
+        let err = format!("{:#}", err);
+        assert!(err.contains("[blocks.blocks.png] 00, [misc] stone.png"));
+    }
+}
@@ -0,0 +1,247 @@
+use std::{fmt, ops::Range, path::PathBuf};
+
+use toml_edit::{Document, Item, Key, Table};
+
+use crate::{Config, ConfigOverride};
+
+/// Config file as read, kept to point errors at their place in it
+pub struct ConfigSource {
+    pub file: PathBuf,
+    pub text: String,
+}
+
+/// Why `value` doesn't deserialize into `Config`, if it doesn't
+fn config_error(value: &toml::Value) -> Option<String> {
+    value
+        .clone()
+        .try_into::<Config>()
+        .err()
+        .map(|e| e.to_string())
+}
+
+/// Keys of tables, and indices of arrays, as strings
+fn children(value: &toml::Value) -> Vec<String> {
+    match value {
+        toml::Value::Table(table) => table.keys().cloned().collect(),
+        toml::Value::Array(array) => {
+            (0..array.len()).map(|i| i.to_string()).collect()
+        }
+        _ => vec![],
+    }
+}
+
+fn get_mut<'a>(
+    value: &'a mut toml::Value,
+    path: &[String],
+) -> Option<&'a mut toml::Value> {
+    path.iter().try_fold(value, |value, part| match value {
+        toml::Value::Table(table) => table.get_mut(part),
+        toml::Value::Array(array) => array.get_mut(part.parse::<usize>().ok()?),
+        _ => None,
+    })
+}
+
+/// `config` without the value at `path`
+fn without(config: &toml::Value, path: &[String]) -> toml::Value {
+    let mut config = config.clone();
+    let (last, parent) = path.split_last().unwrap();
+    match get_mut(&mut config, parent) {
+        Some(toml::Value::Table(table)) => {
+            table.remove(last);
+        }
+        Some(toml::Value::Array(array)) => {
+            array.remove(last.parse::<usize>().unwrap());
+        }
+        _ => {}
+    }
+    config
+}
+
+/// Deepest value under `path` that `error` comes from: one without which
+/// the error goes away or changes. Dropping a required key always changes
+/// it into a missing field, so those only count when nothing else does,
+/// preferring ones with a culprit further in.
+fn narrow(config: &toml::Value, path: Vec<String>, error: &str) -> Vec<String> {
+    let mut value = config.clone();
+    let node = match get_mut(&mut value, &path) {
+        Some(node) => node.clone(),
+        None => return path,
+    };
+    let mut required = vec![];
+    for child in children(&node) {
+        let mut child_path = path.clone();
+        child_path.push(child.clone());
+        match config_error(&without(config, &child_path)) {
+            Some(other) if other == error => {}
+            Some(other)
+                if other.contains(&format!("missing field `{}`", child)) =>
+            {
+                required.push(child_path);
+            }
+            _ => return narrow(config, child_path, error),
+        }
+    }
+    let narrowed: Vec<_> = required
+        .into_iter()
+        .map(|child| {
+            let depth = child.len();
+            (narrow(config, child, error), depth)
+        })
+        .collect();
+    narrowed
+        .iter()
+        .find(|(found, depth)| found.len() > *depth)
+        .or_else(|| narrowed.first())
+        .map(|(found, _)| found.clone())
+        .unwrap_or(path)
+}
+
+/// Any node of a parsed toml document
+#[derive(Clone, Copy)]
+enum Node<'a> {
+    Item(&'a Item),
+    Table(&'a Table),
+    Value(&'a toml_edit::Value),
+}
+
+impl<'a> Node<'a> {
+    fn child(self, part: &str) -> Option<(Option<&'a Key>, Node<'a>)> {
+        let index = || part.parse::<usize>().ok();
+        let entry = |(key, item)| Some((Some(key), Node::Item(item)));
+        match self {
+            Node::Item(Item::ArrayOfTables(tables)) => {
+                Some((None, Node::Table(tables.get(index()?)?)))
+            }
+            Node::Item(Item::Value(value)) => Node::Value(value).child(part),
+            Node::Item(item) => {
+                entry(item.as_table_like()?.get_key_value(part)?)
+            }
+            Node::Table(table) => entry(table.get_key_value(part)?),
+            Node::Value(toml_edit::Value::Array(array)) => {
+                Some((None, Node::Value(array.get(index()?)?)))
+            }
+            Node::Value(toml_edit::Value::InlineTable(table)) => {
+                entry(table.get_key_value(part)?)
+            }
+            Node::Value(_) => None,
+        }
+    }
+
+    fn span(self) -> Option<Range<usize>> {
+        match self {
+            Node::Item(item) => item.span(),
+            Node::Table(table) => table.span(),
+            Node::Value(value) => value.span(),
+        }
+    }
+}
+
+/// Byte range of the key at `path` in a toml document, or of its value if
+/// `error` is about that, e.g. a cell position that doesn't parse
+fn find_span(text: &str, path: &[String], error: &str) -> Option<Range<usize>> {
+    let doc = Document::parse(text).ok()?;
+    let mut node = Node::Table(doc.as_table());
+    let mut key = None;
+    for part in path {
+        let (k, child) = node.child(part)?;
+        key = k;
+        node = child;
+    }
+    let key_span = key.and_then(|key| key.span());
+    let about_key = path
+        .last()
+        .is_some_and(|last| error.contains(&format!("{:?}", last)));
+    match (about_key, key_span) {
+        (true, Some(span)) => Some(span),
+        (_, span) => node.span().or(span),
+    }
+}
+
+/// Where an error is, as 1-based line and column
+fn line_col(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset];
+    let line = before.matches('\n').count() + 1;
+    let start = before.rfind('\n').map_or(0, |i| i + 1);
+    (line, before[start..].chars().count() + 1)
+}
+
+/// Error with the line it's at, underlined
+pub struct Diagnostic {
+    pub message: String,
+    /// Key it's under, like `blocks.terrain.0g`
+    pub key: Option<String>,
+    /// File, with text and byte range within it, if known
+    pub location: Option<(PathBuf, String, Range<usize>)>,
+    /// Where the value comes from when it's not a file, like `--set`
+    pub origin: Option<String>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.key {
+            Some(key) => write!(f, "in `{}`: {}", key, self.message)?,
+            None => write!(f, "{}", self.message)?,
+        }
+        if let Some(origin) = &self.origin {
+            write!(f, "\n  --> {}", origin)?;
+        }
+        let (file, text, span) = match &self.location {
+            Some(location) => location,
+            None => return Ok(()),
+        };
+        let (line, column) = line_col(text, span.start);
+        writeln!(f, "\n  --> {}:{}:{}", file.display(), line, column)?;
+        let source_line = text.lines().nth(line - 1).unwrap_or("");
+        let width = line.to_string().len();
+        let start = column - 1;
+        let length = text[span.clone()]
+            .lines()
+            .next()
+            .map_or(0, |s| s.chars().count())
+            .clamp(1, (source_line.chars().count() + 1).saturating_sub(start));
+        writeln!(f, "{:w$} |", "", w = width)?;
+        writeln!(f, "{} | {}", line, source_line)?;
+        write!(
+            f,
+            "{:w$} | {:s$}{}",
+            "",
+            "",
+            "^".repeat(length),
+            w = width,
+            s = start
+        )
+    }
+}
+
+/// Points an error deserializing merged `config` at the key it's about, in
+/// the first of `sources` that has it, or at the `--set` that gave it
+pub fn locate_config_error(
+    config: &toml::Value,
+    message: String,
+    sources: &[ConfigSource],
+    overrides: &[ConfigOverride],
+) -> Diagnostic {
+    let path = narrow(config, vec![], &message);
+    let mut diagnostic = Diagnostic {
+        message,
+        key: (!path.is_empty()).then(|| path.join(".")),
+        location: None,
+        origin: None,
+    };
+    if let Some(set) = overrides.iter().find(|set| path.starts_with(&set.key)) {
+        diagnostic.origin = Some(format!("--set {}", set.key.join(".")));
+        return diagnostic;
+    }
+    let toml = sources.iter().filter(|source| {
+        source.file.extension().is_none_or(|ext| ext == "toml")
+    });
+    for source in toml {
+        let span = find_span(&source.text, &path, &diagnostic.message);
+        if let Some(span) = span {
+            let location = (source.file.clone(), source.text.clone(), span);
+            diagnostic.location = Some(location);
+            break;
+        }
+    }
+    diagnostic
+}
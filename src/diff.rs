@@ -0,0 +1,116 @@
+use image::{imageops, GenericImageView, Rgba, RgbaImage};
+
+use crate::{render_preview, AtlasInfo, AtlasPos, MAX_CELLS};
+
+const CHANGED: Rgba<u8> = Rgba([255, 255, 0, 255]);
+const ADDED: Rgba<u8> = Rgba([0, 255, 0, 255]);
+const REMOVED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+
+/// Gap between the two sheets in `render_diff`
+const GAP: u32 = 8;
+
+/// Cells that differ between two versions of a sheet
+#[derive(Debug, Default)]
+pub struct AtlasDiff {
+    /// Non-empty in both versions, with different content
+    pub changed: Vec<AtlasPos>,
+    /// Only non-empty in new version
+    pub added: Vec<AtlasPos>,
+    /// Only non-empty in old version
+    pub removed: Vec<AtlasPos>,
+}
+
+impl AtlasDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty()
+            && self.added.is_empty()
+            && self.removed.is_empty()
+    }
+}
+
+/// Cell at `(x, y)` in grid units, or `None` if the sheet doesn't reach that
+/// far or the cell is fully transparent
+fn cell(image: &RgbaImage, x: u32, y: u32, size: u32) -> Option<RgbaImage> {
+    let (x, y) = (x * size, y * size);
+    if x + size > image.width() || y + size > image.height() {
+        return None;
+    }
+    let cell = image.view(x, y, size, size).to_image();
+    cell.pixels().any(|pixel| pixel.0[3] != 0).then_some(cell)
+}
+
+/// Compares sheets cell by cell. They can differ in size, cells past the
+/// edge of one of them count as empty.
+pub fn diff_atlas(
+    old: &RgbaImage,
+    new: &RgbaImage,
+    tile_size: u32,
+) -> AtlasDiff {
+    let columns = old.width().max(new.width()) / tile_size;
+    let rows = old.height().max(new.height()) / tile_size;
+    let mut diff = AtlasDiff::default();
+    for y in 0..rows.min(MAX_CELLS) {
+        for x in 0..columns.min(MAX_CELLS) {
+            let pos = AtlasPos::from_pos(x as u8, y as u8);
+            match (cell(old, x, y, tile_size), cell(new, x, y, tile_size)) {
+                (Some(old), Some(new)) if old != new => diff.changed.push(pos),
+                (None, Some(_)) => diff.added.push(pos),
+                (Some(_), None) => diff.removed.push(pos),
+                _ => {}
+            }
+        }
+    }
+    diff
+}
+
+/// Draws a 2px frame just inside the cell at `pos`
+fn outline(image: &mut RgbaImage, pos: AtlasPos, cell: u32, color: Rgba<u8>) {
+    let (x0, y0) = (pos.x() as u32 * cell, pos.y() as u32 * cell);
+    for i in 0..cell {
+        for d in 1..3 {
+            for (x, y) in [
+                (x0 + i, y0 + d),
+                (x0 + i, y0 + cell - d),
+                (x0 + d, y0 + i),
+                (x0 + cell - d, y0 + i),
+            ] {
+                if x < image.width() && y < image.height() {
+                    image.put_pixel(x, y, color);
+                }
+            }
+        }
+    }
+}
+
+/// Old and new sheet side by side, as in `render_preview`, with changed
+/// cells framed in yellow, added in green and removed in red
+pub fn render_diff(
+    old: &RgbaImage,
+    new: &RgbaImage,
+    diff: &AtlasDiff,
+    tile_size: u32,
+    scale: u32,
+) -> RgbaImage {
+    let info = |image: &RgbaImage| {
+        AtlasInfo::new(image.width(), image.height(), tile_size)
+    };
+    let mut old = render_preview(old, None, info(old), scale);
+    let mut new = render_preview(new, None, info(new), scale);
+    let cell = tile_size * scale;
+    for &pos in &diff.changed {
+        outline(&mut old, pos, cell, CHANGED);
+        outline(&mut new, pos, cell, CHANGED);
+    }
+    for &pos in &diff.added {
+        outline(&mut new, pos, cell, ADDED);
+    }
+    for &pos in &diff.removed {
+        outline(&mut old, pos, cell, REMOVED);
+    }
+    let width = old.width() + GAP + new.width();
+    let height = old.height().max(new.height());
+    let mut out = RgbaImage::new(width, height);
+    imageops::replace(&mut out, &old, 0, 0);
+    imageops::replace(&mut out, &new, old.width() + GAP, 0);
+    out
+}
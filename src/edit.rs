@@ -0,0 +1,181 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use toml_edit::{DocumentMut, Item, Table};
+
+use crate::{config::key_positions, AtlasPos};
+
+/// Toml config opened for changes, keeping comments, key order and
+/// formatting of everything that isn't changed
+pub struct ConfigEditor {
+    path: PathBuf,
+    doc: DocumentMut,
+}
+
+/// Moves tables to the end of the file they're inserted into, instead of
+/// where they were in the one they come from
+fn clear_positions(item: &mut Item) {
+    if let Some(table) = item.as_table_mut() {
+        table.set_position(None);
+        for (_, item) in table.iter_mut() {
+            clear_positions(item);
+        }
+    }
+}
+
+/// Adds everything in `from` missing from `into`: new keys, tables merged
+/// key by key, and new array entries. Values already in `into` win.
+fn merge_missing(into: &mut Table, from: &Table) {
+    for (key, item) in from.iter() {
+        let existing = match into.get_mut(key) {
+            Some(existing) => existing,
+            None => {
+                let mut item = item.clone();
+                clear_positions(&mut item);
+                into.insert(key, item);
+                continue;
+            }
+        };
+        match (existing, item) {
+            (Item::Table(existing), Item::Table(table)) => {
+                merge_missing(existing, table)
+            }
+            (Item::Value(existing), Item::Value(value)) => {
+                let (existing, values) =
+                    match (existing.as_array_mut(), value.as_array()) {
+                        (Some(existing), Some(values)) => (existing, values),
+                        _ => continue,
+                    };
+                for value in values {
+                    let text = value.to_string();
+                    let text = text.trim();
+                    if !existing.iter().any(|v| v.to_string().trim() == text) {
+                        existing.push(value.clone());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl ConfigEditor {
+    /// Opens toml at `path`, or starts an empty one if there's no file yet
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let doc = match fs::read_to_string(path) {
+            Ok(text) => text
+                .parse::<DocumentMut>()
+                .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                DocumentMut::new()
+            }
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            path: path.to_owned(),
+            doc,
+        })
+    }
+
+    /// Table of atlas `[<section>.<atlas>]`, added if it isn't there
+    pub fn atlas_mut(
+        &mut self,
+        section: &str,
+        atlas: &str,
+    ) -> anyhow::Result<&mut Table> {
+        let sections = self
+            .doc
+            .entry(section)
+            .or_insert_with(|| {
+                let mut table = Table::new();
+                table.set_implicit(true);
+                Item::Table(table)
+            })
+            .as_table_mut()
+            .ok_or_else(|| anyhow::anyhow!("`{}` isn't a table", section))?;
+        sections
+            .entry(atlas)
+            .or_insert_with(toml_edit::table)
+            .as_table_mut()
+            .ok_or_else(|| {
+                anyhow::anyhow!("`{}.{}` isn't a table", section, atlas)
+            })
+    }
+
+    /// Key of the entry in `atlas` covering `pos`, if there's one
+    fn tile_key(atlas: &Table, pos: &AtlasPos) -> Option<String> {
+        atlas.iter().map(|(key, _)| key.to_owned()).find(|key| {
+            key_positions(key).is_some_and(|positions| positions.contains(pos))
+        })
+    }
+
+    /// Names cell `pos` of an atlas, keeping other keys of its tile table.
+    /// Cells mapped as part of a range can't be changed alone.
+    pub fn set_tile(
+        &mut self,
+        section: &str,
+        atlas: &str,
+        pos: &AtlasPos,
+        name: &str,
+    ) -> anyhow::Result<()> {
+        let table = self.atlas_mut(section, atlas)?;
+        let key = match Self::tile_key(table, pos) {
+            Some(key) => key,
+            None => {
+                let key = format!("{:?}", pos);
+                table.insert(&key, toml_edit::value(name));
+                return Ok(());
+            }
+        };
+        anyhow::ensure!(
+            key_positions(&key).unwrap().len() == 1,
+            "{:?} is part of range `{}`, edit that by hand",
+            pos,
+            key
+        );
+        let item = &mut table[&key];
+        match item.as_table_like_mut() {
+            Some(tile) => {
+                tile.insert("name", toml_edit::value(name));
+            }
+            None => *item = toml_edit::value(name),
+        }
+        Ok(())
+    }
+
+    /// Unmaps cell `pos` of an atlas, failing if it isn't mapped here
+    pub fn remove_tile(
+        &mut self,
+        section: &str,
+        atlas: &str,
+        pos: &AtlasPos,
+    ) -> anyhow::Result<()> {
+        let table = self.atlas_mut(section, atlas)?;
+        let key = Self::tile_key(table, pos).ok_or_else(|| {
+            anyhow::anyhow!("{:?} is mapped in another config file", pos)
+        })?;
+        anyhow::ensure!(
+            key_positions(&key).unwrap().len() == 1,
+            "{:?} is part of range `{}`, edit that by hand",
+            pos,
+            key
+        );
+        table.remove(&key);
+        Ok(())
+    }
+
+    /// Adds whatever toml `text` has that the config doesn't, e.g. assets
+    /// found by `scaffold` since the config was written
+    pub fn merge(&mut self, text: &str) -> anyhow::Result<()> {
+        let other = text.parse::<DocumentMut>()?;
+        merge_missing(self.doc.as_table_mut(), other.as_table());
+        Ok(())
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        fs::write(&self.path, self.doc.to_string())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,595 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use image::{DynamicImage, Rgba, RgbaImage};
+
+use crate::{
+    alias_model, atlas::mcmeta_path, block_model, blockstate,
+    check_copied_textures, check_output_path, ctm_properties,
+    ctm_properties_path, decode_image, fit, font_json, item_model, lang_json,
+    locale, output_count, parse_lang, process_atlas_map, process_crop_map,
+    process_ctm_map, process_font_map, process_sheet_crops,
+    rename_model_textures, sounds_json, templated_model, texture_mcmeta,
+    Aliases, Animation, ArchiveSet, AtlasInfo, AtlasMap, Cache, Category,
+    Config, Ctm, Failure, Fallback, Namespace, OutputDirs, SliceOptions,
+    TextureRenames, Tile, TileModel, Upscale, Writer,
+};
+
+/// Source listed for files made up by the tool, rather than copied
+pub const GENERATED: &str = "(generated)";
+
+/// Source listed for `[fallbacks]` placeholders
+pub const FALLBACK: &str = "(fallback)";
+
+/// Source of a `[fallbacks]` placeholder: `(fallback)`, followed by the
+/// cell it's cut out of for atlas cells
+pub fn fallback_source(fallback: &Fallback, zips: &ArchiveSet) -> String {
+    match fallback {
+        Fallback::Missing => FALLBACK.to_owned(),
+        Fallback::Cell { atlas, cell } => {
+            let sheet = zips.sheet(atlas);
+            let source = zips.locate(&sheet).unwrap_or(sheet);
+            format!("{} {} [{:?}]", FALLBACK, source, cell)
+        }
+    }
+}
+
+/// The game's missing texture: magenta and black checkers, `size` pixels
+/// square
+fn missing_texture(size: u32) -> RgbaImage {
+    let half = (size / 2).max(1);
+    RgbaImage::from_fn(size, size, |x, y| match (x / half + y / half) % 2 {
+        0 => Rgba([0xf8, 0x00, 0xf8, 0xff]),
+        _ => Rgba([0x00, 0x00, 0x00, 0xff]),
+    })
+}
+
+/// Writes `[fallbacks]` placeholders of `ns`, upscaled like tiles
+fn write_fallbacks(
+    toml: &Config,
+    ns: &Namespace,
+    dirs: &OutputDirs,
+    zips: &mut ArchiveSet,
+    writer: &Writer,
+    upscale: Option<Upscale>,
+) -> anyhow::Result<()> {
+    for (category, name, fallback) in ns.fallbacks.iter() {
+        let section = category.name();
+        let image = match fallback {
+            Fallback::Missing => missing_texture(toml.tile_size),
+            Fallback::Cell { atlas, cell } => {
+                let atlases = match category {
+                    Category::Blocks => &ns.blocks,
+                    _ => &ns.items,
+                };
+                // Atlases are dropped with missing sheets in lenient mode
+                let map = match atlases.get(atlas) {
+                    Some(map) => map,
+                    None => {
+                        log::warn!(
+                            "[fallbacks.{}] {}: skipping, atlas {} is gone",
+                            section,
+                            name,
+                            atlas
+                        );
+                        continue;
+                    }
+                };
+                let sheet = zips.image(&zips.sheet(atlas))?;
+                let (width, height) = sheet.dimensions();
+                let info = AtlasInfo::of(map, width, height, toml.tile_size);
+                let cell = &cell.place(info.columns());
+                anyhow::ensure!(
+                    info.tile_size > 0
+                        && u32::from(cell.x()) < info.columns()
+                        && u32::from(cell.y()) < info.rows(),
+                    "[fallbacks.{}] {}: cell {:?} is outside atlas {}",
+                    section,
+                    name,
+                    cell,
+                    atlas
+                );
+                info.crop(&sheet, cell, (1, 1))
+            }
+        };
+        let image = match upscale {
+            Some(upscale) => upscale.apply_frames(&image, 1),
+            None => image,
+        };
+        let dir = ns.category(name, category).dir(dirs);
+        let path = dir.join(name).with_extension("png");
+        writer.note_source(&path, fallback_source(fallback, zips));
+        writer.write_image(&path, &DynamicImage::ImageRgba8(image))?;
+    }
+    Ok(())
+}
+
+/// Expands glob patterns in file lists of every namespace against `zips`
+pub fn expand_files(
+    toml: &mut Config,
+    zips: &mut ArchiveSet,
+) -> anyhow::Result<()> {
+    for ns in toml.all_namespaces_mut() {
+        ns.models = zips.expand(&ns.models)?;
+        ns.gui = zips.expand(&ns.gui)?;
+        ns.blocks_copy = zips.expand(&ns.blocks_copy)?;
+        ns.entity = zips.expand(&ns.entity)?;
+        ns.particle = zips.expand(&ns.particle)?;
+        ns.environment = zips.expand(&ns.environment)?;
+        ns.sounds.files = zips.expand(&ns.sounds.files)?;
+        ns.lang.files = zips.expand(&ns.lang.files)?;
+    }
+    Ok(())
+}
+
+/// Every file the banner is written to: its place in the layout, and
+/// `pack.png` if it's asked for and not that already
+pub fn banner_outputs(toml: &Config, dirs: &OutputDirs) -> Vec<PathBuf> {
+    let banner = match &dirs.banner {
+        Some(banner) => banner,
+        None => return vec![],
+    };
+    let mut outputs = vec![banner.clone()];
+    let pack = dirs.root.join("pack.png");
+    if toml.banner_pack_png && *banner != pack {
+        outputs.push(pack);
+    }
+    outputs
+}
+
+/// Writes the banner to `banner_outputs`, converted to png and fitted to
+/// `banner_size` where needed, or copied as is
+pub fn write_banner(
+    toml: &Config,
+    zips: &mut ArchiveSet,
+    writer: &Writer,
+    dirs: &OutputDirs,
+) -> anyhow::Result<()> {
+    let banner = match &toml.banner {
+        Some(banner) => banner,
+        None => return Ok(()),
+    };
+    let outputs = banner_outputs(toml, dirs);
+    let asset = zips.find(banner)?.ok_or_else(|| {
+        anyhow::anyhow!("{} not found in archives", banner)
+    })?;
+    let is_png = |path: &Path| path.extension().is_some_and(|ext| ext == "png");
+    let converted = !is_png(Path::new(&banner.to_lowercase()))
+        || toml.banner_size.is_some();
+    for output in &outputs {
+        writer.note_source(output, asset.source.clone());
+        if !(converted && is_png(output)) {
+            writer.write(output, &asset.data)?;
+            continue;
+        }
+        let image = decode_image(&asset.data, banner)
+            .map_err(|e| anyhow::anyhow!("{}: {}", banner, e))?
+            .into_rgba8();
+        let image = match toml.banner_size {
+            Some((width, height)) => {
+                anyhow::ensure!(
+                    width > 0 && height > 0,
+                    "banner_size can't be 0"
+                );
+                fit(&image, (width, height))
+            }
+            None => image,
+        };
+        writer.write_image(output, &DynamicImage::ImageRgba8(image))?;
+    }
+    Ok(())
+}
+
+/// Copies a file as is. Ones that turned out unreadable are skipped, they
+/// were warned about already.
+fn copy_file(
+    zips: &mut ArchiveSet,
+    writer: &Writer,
+    file: &str,
+    output: &Path,
+) -> anyhow::Result<()> {
+    let asset = match zips.find(file)? {
+        Some(asset) => asset,
+        None if zips.unreadable().contains_key(file) => return Ok(()),
+        None => anyhow::bail!("{} not found in archives", file),
+    };
+    writer.note_source(output, asset.source);
+    writer.write(output, &asset.data)
+}
+
+/// Copies a model, with texture references rewritten by `renames` if it's
+/// json and any of them match
+fn copy_model(
+    zips: &mut ArchiveSet,
+    writer: &Writer,
+    file: &str,
+    output: &Path,
+    renames: &TextureRenames,
+) -> anyhow::Result<()> {
+    if renames.is_empty() || !file.ends_with(".json") {
+        return copy_file(zips, writer, file, output);
+    }
+    let asset = match zips.find(file)? {
+        Some(asset) => asset,
+        None if zips.unreadable().contains_key(file) => return Ok(()),
+        None => anyhow::bail!("{} not found in archives", file),
+    };
+    let renamed = rename_model_textures(&asset.data, renames)
+        .map_err(|e| anyhow::anyhow!("{}: {}", file, e))?;
+    writer.note_source(output, asset.source);
+    match renamed {
+        Some(json) => writer.write(output, json.as_bytes()),
+        None => writer.write(output, &asset.data),
+    }
+}
+
+/// Copies a file as is, unless it's a png that needs upscaling, along with
+/// its `.mcmeta`
+fn copy_texture(
+    zips: &mut ArchiveSet,
+    writer: &Writer,
+    file: &str,
+    output: &Path,
+    upscale: Option<Upscale>,
+    animation: Option<&Animation>,
+) -> anyhow::Result<()> {
+    match upscale {
+        Some(upscale) if file.ends_with(".png") => {
+            let image = zips.image(file)?;
+            let image = upscale.apply(&image);
+            writer.note_chunks(output, zips.color_chunks(file));
+            if let Some(source) = zips.locate(file) {
+                writer.note_source(output, source);
+            }
+            writer.write_image(output, &DynamicImage::ImageRgba8(image))?;
+        }
+        _ => copy_file(zips, writer, file, output)?,
+    }
+    copy_mcmeta(zips, writer, file, output, animation)
+}
+
+/// Writes `.mcmeta` of copied texture `file`, generated from `animation`,
+/// or else the archive's one next to it, so animations aren't lost
+fn copy_mcmeta(
+    zips: &mut ArchiveSet,
+    writer: &Writer,
+    file: &str,
+    output: &Path,
+    animation: Option<&Animation>,
+) -> anyhow::Result<()> {
+    let path = mcmeta_path(output);
+    if let Some(animation) = animation {
+        writer.note_source(&path, GENERATED);
+        return writer.write(&path, texture_mcmeta(animation).as_bytes());
+    }
+    if !file.ends_with(".png") {
+        return Ok(());
+    }
+    match zips.find(&format!("{}.mcmeta", file))? {
+        Some(asset) => {
+            writer.note_source(&path, asset.source);
+            writer.write(&path, &asset.data)
+        }
+        None => Ok(()),
+    }
+}
+
+/// Number of `.mcmeta` files `copy_texture` writes along with `files`
+fn mcmeta_count<'a>(
+    zips: &mut ArchiveSet,
+    ns: &Namespace,
+    files: impl IntoIterator<Item = &'a String>,
+) -> u64 {
+    let mut count = 0;
+    for file in files {
+        if ns.animations.contains_key(file)
+            || file.ends_with(".png")
+                && zips.locate(&format!("{}.mcmeta", file)).is_some()
+        {
+            count += 1;
+        }
+    }
+    count
+}
+
+/// Number of json files generated for tiles of `atlas`, if enabled
+fn generated_count(atlas: &AtlasMap, enabled: bool) -> u64 {
+    if !enabled {
+        return 0;
+    }
+    let tiles = atlas.values().flat_map(|map| map.tiles.values());
+    tiles.map(|tile| tile.model_names().count() as u64).sum()
+}
+
+/// Block tiles getting a generated model, with what each is made from
+pub fn tile_models<'a>(
+    atlas: &'a AtlasMap,
+    default: Option<&'a str>,
+) -> impl Iterator<Item = (&'a Tile, TileModel<'a>)> {
+    atlas
+        .values()
+        .flat_map(|map| map.tiles.values())
+        .filter_map(move |tile| Some((tile, tile.model(default)?)))
+}
+
+/// Blocks connected texture set `name` applies to, just the one it's named
+/// after unless given
+fn ctm_blocks(ns: &Namespace, name: &str, set: &Ctm) -> Vec<String> {
+    if set.blocks.is_empty() {
+        vec![format!("{}:{}", ns.modid, name)]
+    } else {
+        set.blocks.clone()
+    }
+}
+
+/// Copies assets and slices atlases of a single namespace, returning block
+/// and item aliases found with `--dedupe alias`
+pub fn extract_namespace(
+    toml: &Config,
+    ns: &Namespace,
+    dirs: &OutputDirs,
+    zips: &mut ArchiveSet,
+    writer: &Writer,
+    options: &SliceOptions,
+    mut cache: Option<&mut Cache>,
+) -> anyhow::Result<(Aliases, Aliases)> {
+    let progress = writer.progress();
+    progress.section("models", ns.models.len() as u64);
+    for model in &ns.models {
+        let output = dirs.entry_path(ns, Category::Models, model);
+        copy_model(zips, writer, model, &output, &ns.texture_renames)?;
+    }
+
+    check_copied_textures(toml, ns, zips, options.strict)?;
+    let crops = ns.gui_crops.values().flatten().map(|c| c.outputs().len());
+    let crops: usize = crops.sum();
+    let gui = (ns.gui.len() + crops) as u64 + mcmeta_count(zips, ns, &ns.gui);
+    progress.section("gui", gui);
+    for gui in &ns.gui {
+        let output = dirs.entry_path(ns, Category::Gui, gui);
+        let animation = ns.animations.get(gui);
+        copy_texture(zips, writer, gui, &output, options.upscale, animation)?;
+    }
+
+    process_crop_map(&ns.gui_crops, zips, &dirs.guis, writer, options.upscale)?;
+
+    let sprites = ns.sprite_sheets.values().map(|sheet| sheet.crops.len());
+    let sprites = sprites.sum::<usize>() as u64;
+    if sprites > 0 {
+        progress.section("sprites", sprites);
+    }
+    for (sheet, sprites) in &ns.sprite_sheets {
+        let dir = sprites.category.dir(dirs);
+        let crops = &sprites.crops;
+        process_sheet_crops(sheet, crops, zips, dir, writer, options.upscale)?;
+    }
+
+    let textures = [
+        (&ns.entity, Category::Entity),
+        (&ns.particle, Category::Particle),
+        (&ns.environment, Category::Environment),
+    ];
+    let count: usize = textures.iter().map(|(files, _)| files.len()).sum();
+    let count = (count + ns.misc.len()) as u64
+        + mcmeta_count(zips, ns, textures.iter().flat_map(|(files, _)| *files))
+        + mcmeta_count(zips, ns, ns.misc.keys());
+    if count > 0 {
+        progress.section("textures", count);
+    }
+    for (files, category) in textures {
+        for file in files {
+            let output = dirs.entry_path(ns, category, file);
+            let animation = ns.animations.get(file);
+            copy_texture(
+                zips,
+                writer,
+                file,
+                &output,
+                options.upscale,
+                animation,
+            )?;
+        }
+    }
+    for (file, to) in &ns.misc {
+        let output = dirs.namespace.join(to);
+        let animation = ns.animations.get(file);
+        copy_texture(zips, writer, file, &output, options.upscale, animation)?;
+    }
+
+    if !ns.sounds.files.is_empty() {
+        progress.section("sounds", ns.sounds.files.len() as u64 + 1);
+        for file in &ns.sounds.files {
+            let output = dirs.sounds.join(file);
+            copy_file(zips, writer, file, &output)?;
+        }
+        let json = sounds_json(&ns.modid, &ns.sounds.events());
+        let path = dirs.namespace.join("sounds.json");
+        writer.note_source(&path, GENERATED);
+        writer.write(&path, json.as_bytes())?;
+    }
+
+    // Files for the same locale, e.g. from core and addon, are merged
+    let mut locales = BTreeMap::<_, BTreeMap<_, _>>::new();
+    let mut lang_sources = BTreeMap::<_, Vec<_>>::new();
+    for file in &ns.lang.files {
+        let asset = zips
+            .find(file)?
+            .ok_or_else(|| anyhow::anyhow!("{} not found in archives", file))?;
+        let text = String::from_utf8(asset.data)?;
+        let entries = parse_lang(&text)
+            .map_err(|e| anyhow::anyhow!("{}: {}", file, e))?;
+        locales.entry(locale(file)).or_default().extend(entries);
+        lang_sources
+            .entry(locale(file))
+            .or_default()
+            .push(asset.source);
+    }
+    if !locales.is_empty() {
+        progress.section("lang", locales.len() as u64);
+    }
+    for (locale, entries) in &locales {
+        let json = lang_json(entries, &ns.lang.rename);
+        let output = dirs.lang.join(locale).with_extension("json");
+        writer.note_source(&output, lang_sources[locale].join(", "));
+        writer.write(&output, json.as_bytes())?;
+    }
+
+    let mut copies = vec![];
+    for (dir, copy) in &ns.copy_dirs {
+        for file in zips.list_dir(dir)? {
+            if copy.accepts(&file) {
+                // Entry names come from the archive, not the config
+                check_output_path(&file).map_err(|e| {
+                    let error = anyhow::anyhow!("[copy_dirs] {}: {}", dir, e);
+                    Failure::Validation.tag(error)
+                })?;
+                let output = dirs.namespace.join(&copy.to).join(&file);
+                copies.push((format!("{}/{}", dir, file), output));
+            }
+        }
+    }
+    if !copies.is_empty() {
+        progress.section("files", copies.len() as u64);
+        for (file, output) in &copies {
+            copy_file(zips, writer, file, output)?;
+        }
+    }
+
+    if !ns.ctm.is_empty() {
+        let tiles: u32 = ns.ctm.values().map(|set| set.method.layout().0).sum();
+        progress.section("ctm", (tiles as usize + ns.ctm.len()) as u64);
+        let atlases = [&ns.blocks, &ns.items];
+        process_ctm_map(&ns.ctm, &atlases, zips, &dirs.ctm, writer, options)?;
+        for (name, set) in &ns.ctm {
+            let blocks = ctm_blocks(ns, name, set);
+            let path = dirs.ctm.join(ctm_properties_path(name));
+            writer.note_source(&path, GENERATED);
+            let properties = ctm_properties(set.method, &blocks);
+            writer.write(&path, properties.as_bytes())?;
+        }
+    }
+
+    if !ns.fonts.is_empty() {
+        let fonts: BTreeSet<_> = ns
+            .fonts
+            .iter()
+            .map(|(name, font)| font.font(name))
+            .collect();
+        progress.section("fonts", (ns.fonts.len() + fonts.len()) as u64);
+        let providers = process_font_map(
+            &ns.fonts,
+            &ns.modid,
+            zips,
+            dirs,
+            writer,
+            options.upscale,
+        )?;
+        for (font, providers) in &providers {
+            let path = dirs.font_providers.join(font).with_extension("json");
+            writer.note_source(&path, GENERATED);
+            writer.write(&path, font_json(providers).as_bytes())?;
+        }
+    }
+
+    let auto_models = toml.auto_models.as_deref();
+    let blocks = ns.blocks_copy.len() as u64
+        + mcmeta_count(zips, ns, &ns.blocks_copy)
+        + output_count(&ns.blocks, options)
+        + generated_count(&ns.blocks, toml.generate_blockstates)
+        + tile_models(&ns.blocks, auto_models)
+            .map(|(tile, _)| tile.model_names().count() as u64)
+            .sum::<u64>();
+    progress.section("blocks", blocks);
+    for block in &ns.blocks_copy {
+        let output = dirs.entry_path(ns, Category::Blocks, block);
+        let animation = ns.animations.get(block);
+        copy_texture(zips, writer, block, &output, options.upscale, animation)?;
+    }
+
+    let block_aliases = process_atlas_map(
+        &ns.blocks,
+        zips,
+        &dirs.tile_dirs(ns, Category::Blocks),
+        writer,
+        options,
+        cache.as_deref_mut(),
+    )?;
+
+    for (tile, model) in tile_models(&ns.blocks, auto_models) {
+        for name in tile.names() {
+            let path = dirs.models.join(name).with_extension("json");
+            // Aliased blocks use the texture written in their place
+            let texture = block_aliases.get(name).unwrap_or(name);
+            let model = match model {
+                TileModel::Parent(parent) => {
+                    block_model(&ns.modid, texture, parent)
+                }
+                TileModel::Template(template) => templated_model(
+                    &toml.templates[template],
+                    &ns.modid,
+                    texture,
+                    &tile.vars,
+                )?,
+            };
+            writer.note_source(&path, GENERATED);
+            writer.write(&path, model.as_bytes())?;
+        }
+        let parent = format!("block/{}", tile.name);
+        for alias in &tile.model_aliases {
+            let path = dirs.models.join(alias).with_extension("json");
+            writer.note_source(&path, GENERATED);
+            let model = alias_model(&ns.modid, &parent);
+            writer.write(&path, model.as_bytes())?;
+        }
+    }
+
+    if toml.generate_blockstates {
+        for map in ns.blocks.values() {
+            for name in map.tiles.values().flat_map(Tile::model_names) {
+                let path = dirs.blockstates.join(name).with_extension("json");
+                writer.note_source(&path, GENERATED);
+                writer.write(&path, blockstate(&ns.modid, name).as_bytes())?;
+            }
+        }
+    }
+
+    let items = output_count(&ns.items, options)
+        + generated_count(&ns.items, toml.generate_item_models);
+    progress.section("items", items);
+    let item_aliases = process_atlas_map(
+        &ns.items,
+        zips,
+        &dirs.tile_dirs(ns, Category::Items),
+        writer,
+        options,
+        cache,
+    )?;
+    if toml.generate_item_models {
+        for tile in ns.items.values().flat_map(|map| map.tiles.values()) {
+            for name in tile.names() {
+                let path = dirs.item_models.join(name).with_extension("json");
+                // Aliased items use the texture written in their place
+                let texture = item_aliases.get(name).unwrap_or(name);
+                let model = item_model(&ns.modid, texture);
+                writer.note_source(&path, GENERATED);
+                writer.write(&path, model.as_bytes())?;
+            }
+            let parent = format!("item/{}", tile.name);
+            for alias in &tile.model_aliases {
+                let path = dirs.item_models.join(alias).with_extension("json");
+                writer.note_source(&path, GENERATED);
+                let model = alias_model(&ns.modid, &parent);
+                writer.write(&path, model.as_bytes())?;
+            }
+        }
+    }
+
+    if !ns.fallbacks.is_empty() {
+        progress.section("fallbacks", ns.fallbacks.len() as u64);
+        write_fallbacks(toml, ns, dirs, zips, writer, options.upscale)?;
+    }
+
+    Ok((block_aliases, item_aliases))
+}
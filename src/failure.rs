@@ -0,0 +1,72 @@
+use std::{error::Error, fmt, io};
+
+/// Broad kind of an error, for scripts wrapping the tool to react to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    /// Config doesn't parse, or is inconsistent, e.g. writes a file twice
+    Config,
+    /// Inputs don't match config: missing assets, checksum mismatches, or
+    /// lints with `strict`
+    Validation,
+    /// Reading inputs or writing outputs failed
+    Io,
+}
+
+/// Error tagged with a `Failure`, reading just like the original one
+#[derive(Debug)]
+struct Tagged {
+    failure: Failure,
+    error: anyhow::Error,
+}
+
+impl fmt::Display for Tagged {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.error, f)
+    }
+}
+
+impl Error for Tagged {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.error.source()
+    }
+}
+
+impl Failure {
+    /// Process exit code for this kind. 0 is success, and 1 any other error.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Self::Config => 2,
+            Self::Validation => 3,
+            Self::Io => 4,
+        }
+    }
+
+    /// Marks `error` as this kind, without changing its message
+    pub fn tag(self, error: impl Into<anyhow::Error>) -> anyhow::Error {
+        let error = error.into();
+        anyhow::Error::new(Tagged {
+            failure: self,
+            error,
+        })
+    }
+
+    /// Kind of `error`: `Io` if an io error caused it anywhere down the
+    /// chain, otherwise the innermost tag, as the most specific one
+    pub fn of(error: &anyhow::Error) -> Option<Self> {
+        let mut failure = None;
+        for cause in error.chain() {
+            if cause.is::<io::Error>() {
+                return Some(Self::Io);
+            }
+            if let Some(tagged) = cause.downcast_ref::<Tagged>() {
+                // Its own error is left out of the chain, behind its message
+                failure = match Self::of(&tagged.error) {
+                    Some(Self::Io) => return Some(Self::Io),
+                    Some(inner) => Some(inner),
+                    None => Some(tagged.failure),
+                };
+            }
+        }
+        failure
+    }
+}
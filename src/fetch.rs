@@ -0,0 +1,91 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use sha2::{Digest, Sha256};
+
+use crate::{open_source, Failure};
+
+/// Archive name, as used in `folders`, to URL it's downloaded from
+pub type Sources = BTreeMap<String, String>;
+
+/// Archive name to expected SHA-256 digest, in hex
+pub type Checksums = BTreeMap<String, String>;
+
+/// Downloads every source missing from `dir`. Files already there are kept,
+/// so `dir` doubles as download cache.
+pub fn fetch_sources(sources: &Sources, dir: &Path) -> anyhow::Result<()> {
+    for (name, url) in sources {
+        let path = dir.join(name);
+        if path.exists() {
+            continue;
+        }
+        anyhow::ensure!(
+            url.starts_with("http://") || url.starts_with("https://"),
+            "source {}: only http(s) urls are supported, not {}",
+            name,
+            url
+        );
+        download(name, url, dir)?;
+    }
+    Ok(())
+}
+
+/// Downloads `url` to `name` in `dir`
+#[cfg(feature = "cli")]
+fn download(name: &str, url: &str, dir: &Path) -> anyhow::Result<()> {
+    log::info!("downloading {} from {}", name, url);
+    let response = ureq::get(url)
+        .call()
+        .map_err(|e| anyhow::anyhow!("source {}: {}", name, e))?;
+    fs::create_dir_all(dir)?;
+    // Download next to the target first, so interrupted runs don't leave a
+    // truncated archive behind
+    let partial = dir.join(format!("{}.part", name));
+    let mut file = fs::File::create(&partial)?;
+    std::io::copy(&mut response.into_reader(), &mut file)?;
+    drop(file);
+    fs::rename(partial, dir.join(name))?;
+    Ok(())
+}
+
+#[cfg(not(feature = "cli"))]
+fn download(name: &str, _: &str, _: &Path) -> anyhow::Result<()> {
+    anyhow::bail!("source {}: downloading needs the `cli` feature", name)
+}
+
+/// Checks archives in `dir` against expected digests. Names like
+/// `outer.zip!inner.jar` check the inner archive.
+pub fn verify_checksums(
+    checksums: &Checksums,
+    dir: &Path,
+    nested_depth: u32,
+) -> anyhow::Result<()> {
+    for (name, expected) in checksums {
+        let data = match name.rsplit_once('!') {
+            Some((outer, inner)) => open_source(dir, outer, nested_depth)?
+                .read(inner)?
+                .ok_or_else(|| anyhow::anyhow!("{} not found", name))?,
+            None => {
+                let path = dir.join(name);
+                anyhow::ensure!(
+                    !path.is_dir(),
+                    "{} is a directory, checksums only work for archives",
+                    name
+                );
+                fs::read(path)?
+            }
+        };
+        let actual = Sha256::digest(&data)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>();
+        if !actual.eq_ignore_ascii_case(expected.trim()) {
+            return Err(Failure::Validation.tag(anyhow::anyhow!(
+                "{}: sha256 mismatch, expected {}, found {}",
+                name,
+                expected,
+                actual
+            )));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,118 @@
+use std::collections::BTreeMap;
+
+use image::{DynamicImage, RgbaImage};
+use serde_json::Value;
+
+use crate::{
+    bitmap_provider, ArchiveSet, Font, FontMap, OutputDirs, Upscale, Writer,
+};
+
+/// Glyphs in every row of a font sheet
+pub const GLYPHS_PER_ROW: u32 = 16;
+
+/// Glyphs of sheet `name`, one string per row. Unless set in config, they
+/// count up from `font.first`, with `\0` for empty cells and code points
+/// that aren't characters, which the game skips.
+pub fn glyph_rows(
+    name: &str,
+    font: &Font,
+    image: &RgbaImage,
+) -> anyhow::Result<Vec<String>> {
+    let (width, height) = image.dimensions();
+    let size = width / GLYPHS_PER_ROW;
+    anyhow::ensure!(
+        size > 0
+            && width.is_multiple_of(GLYPHS_PER_ROW)
+            && height.is_multiple_of(size),
+        "font {}: size {}x{} is not a grid {} square glyphs wide",
+        name,
+        width,
+        height,
+        GLYPHS_PER_ROW
+    );
+    let rows = height / size;
+    if !font.chars.is_empty() {
+        anyhow::ensure!(
+            font.chars.len() == rows as usize
+                && font
+                    .chars
+                    .iter()
+                    .all(|row| row.chars().count() == GLYPHS_PER_ROW as usize),
+            "font {}: `chars` needs {} rows of {} glyphs",
+            name,
+            rows,
+            GLYPHS_PER_ROW
+        );
+        return Ok(font.chars.clone());
+    }
+    let glyph = |x: u32, y: u32| {
+        let empty = (0..size)
+            .flat_map(|dy| (0..size).map(move |dx| (dx, dy)))
+            .all(|(dx, dy)| {
+                image.get_pixel(x * size + dx, y * size + dy)[3] == 0
+            });
+        let code = font.first + y * GLYPHS_PER_ROW + x;
+        match char::from_u32(code) {
+            Some(c) if !empty => c,
+            _ => '\0',
+        }
+    };
+    Ok((0..rows)
+        .map(|y| (0..GLYPHS_PER_ROW).map(|x| glyph(x, y)).collect())
+        .collect())
+}
+
+/// `file` of sheet `name`, as font providers refer to it
+fn texture_ref(
+    dirs: &OutputDirs,
+    modid: &str,
+    name: &str,
+) -> anyhow::Result<String> {
+    let textures = dirs.namespace.join("textures");
+    let dir = dirs.fonts.strip_prefix(&textures).map_err(|_| {
+        anyhow::anyhow!(
+            "fonts: {} is outside of {}, where providers can't refer to it",
+            dirs.fonts.display(),
+            textures.display()
+        )
+    })?;
+    let path = dir.join(name).with_extension("png");
+    let path = path.to_str().unwrap().replace('\\', "/");
+    Ok(format!("{}:{}", modid, path))
+}
+
+/// Writes every glyph sheet into `dirs.fonts`, returning their providers by
+/// font
+pub fn process_font_map(
+    fonts: &FontMap,
+    modid: &str,
+    zips: &mut ArchiveSet,
+    dirs: &OutputDirs,
+    writer: &Writer,
+    upscale: Option<Upscale>,
+) -> anyhow::Result<BTreeMap<String, Vec<Value>>> {
+    let mut providers = BTreeMap::<_, Vec<_>>::new();
+    for (name, font) in fonts {
+        let sheet = zips.sheet(&font.sheet);
+        let image = zips.image(&sheet)?;
+        let chars = glyph_rows(name, font, &image)?;
+        let file = texture_ref(dirs, modid, name)?;
+        let provider = bitmap_provider(&file, font.ascent, font.height, &chars);
+        providers
+            .entry(font.font(name).to_owned())
+            .or_default()
+            .push(provider);
+
+        let path = dirs.fonts.join(name).with_extension("png");
+        if let Some(source) = zips.locate(&sheet) {
+            writer.note_source(&path, source);
+        }
+        writer.note_chunks(&path, zips.color_chunks(&sheet));
+        let mut image = (*image).clone();
+        if let Some(upscale) = upscale {
+            image = upscale.apply(&image);
+        }
+        writer.write_image(&path, &DynamicImage::ImageRgba8(image))?;
+    }
+    Ok(providers)
+}
@@ -0,0 +1,111 @@
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use image::{
+    codecs::png::{CompressionType, FilterType, PngEncoder},
+    DynamicImage, GenericImageView, ImageFormat, RgbaImage,
+};
+
+/// Image format textures are written in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Png,
+    Tga,
+    /// Uncompressed 32-bit BGRA DirectDraw surface, without mipmaps
+    Dds,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "png" => Ok(Self::Png),
+            "tga" => Ok(Self::Tga),
+            "dds" => Ok(Self::Dds),
+            _ => anyhow::bail!("expected one of: png, tga, dds"),
+        }
+    }
+}
+
+impl OutputFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Tga => "tga",
+            Self::Dds => "dds",
+        }
+    }
+
+    /// Where a texture planned as `<name>.png` actually ends up. Other
+    /// files keep their path.
+    pub fn output_path(self, path: &Path) -> PathBuf {
+        match path.extension() {
+            Some(ext) if ext == "png" => path.with_extension(self.extension()),
+            _ => path.to_owned(),
+        }
+    }
+
+    pub fn encode(self, image: &DynamicImage) -> anyhow::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        match self {
+            // Settings pinned, so the same pixels always give the same
+            // file, even if the encoder's defaults change
+            Self::Png => {
+                let encoder = PngEncoder::new_with_quality(
+                    &mut data,
+                    CompressionType::Fast,
+                    FilterType::Sub,
+                );
+                encoder.encode(
+                    image.as_bytes(),
+                    image.width(),
+                    image.height(),
+                    image.color(),
+                )?
+            }
+            Self::Tga => image.write_to(&mut data, ImageFormat::Tga)?,
+            Self::Dds => data = encode_dds(&image.to_rgba8()),
+        }
+        Ok(data)
+    }
+}
+
+fn encode_dds(image: &RgbaImage) -> Vec<u8> {
+    const CAPS: u32 = 0x1;
+    const HEIGHT: u32 = 0x2;
+    const WIDTH: u32 = 0x4;
+    const PITCH: u32 = 0x8;
+    const PIXEL_FORMAT: u32 = 0x1000;
+    const ALPHA_PIXELS: u32 = 0x1;
+    const RGB: u32 = 0x40;
+    const TEXTURE: u32 = 0x1000;
+
+    let (width, height) = image.dimensions();
+    let header = [
+        124,
+        CAPS | HEIGHT | WIDTH | PITCH | PIXEL_FORMAT,
+        height,
+        width,
+        width * 4,
+        0,
+        0,
+    ]
+    .iter()
+    .chain(&[0; 11])
+    .chain(&[32, RGB | ALPHA_PIXELS, 0, 32])
+    .chain(&[0x00ff_0000, 0x0000_ff00, 0x0000_00ff, 0xff00_0000])
+    .chain(&[TEXTURE, 0, 0, 0, 0])
+    .flat_map(|field| field.to_le_bytes())
+    .collect::<Vec<_>>();
+    let mut data = b"DDS ".to_vec();
+    data.extend(header);
+    for pixel in image.pixels() {
+        let [r, g, b, a] = pixel.0;
+        data.extend([b, g, r, a]);
+    }
+    data
+}
@@ -0,0 +1,131 @@
+use std::{fmt::Write as _, fs, path::Path};
+
+use anyhow::Context;
+use image::{imageops, RgbaImage};
+
+use crate::{AtlasKind, Category, Config, OutputDirs, Writer};
+
+/// Width tiles are at least blown up to, so 16x16 ones can be made out
+const GALLERY_WIDTH: u32 = 128;
+
+const STYLE: &str = "body { font-family: sans-serif; background: #333; \
+color: #eee; }
+section { display: flex; flex-wrap: wrap; gap: 12px; }
+figure { margin: 0; text-align: center; }
+img { image-rendering: pixelated; background: repeating-conic-gradient(\
+#777 0 25%, #999 0 50%) 0 0 / 16px 16px; }
+figcaption { font-size: small; }
+code { color: #fc6; }";
+
+/// Escapes text put into html
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// First of the `frames` stacked in `image`, blown up by a whole factor to
+/// be at least `GALLERY_WIDTH` wide
+fn thumbnail(image: &RgbaImage, frames: u32) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let height = height / frames.max(1);
+    let frame = imageops::crop_imm(image, 0, 0, width, height).to_image();
+    let factor = (GALLERY_WIDTH / width.max(1)).max(1);
+    imageops::resize(
+        &frame,
+        width * factor,
+        height * factor,
+        imageops::FilterType::Nearest,
+    )
+}
+
+/// Writes `index.html` into `dir`, showing every tile `writer` sliced for
+/// `toml`, laid out as `dirs` of its namespaces in order, with its name,
+/// atlas and position. Tiles are blown up into `tiles` next to it. Returns
+/// how many tiles it shows.
+pub fn write_gallery(
+    dir: &Path,
+    toml: &Config,
+    dirs: &[OutputDirs],
+    writer: &Writer,
+) -> anyhow::Result<usize> {
+    let tiles_dir = dir.join("tiles");
+    fs::create_dir_all(&tiles_dir)
+        .with_context(|| format!("can't create {}", tiles_dir.display()))?;
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{} tiles</title>\n<style>\n{}\n</style>\n</head>\n<body>\n",
+        escape(&toml.main.modid),
+        STYLE
+    );
+    let mut count = 0;
+    for (ns, dirs) in toml.all_namespaces().zip(dirs) {
+        for (category, atlas) in
+            [(Category::Blocks, &ns.blocks), (Category::Items, &ns.items)]
+        {
+            let tile_dirs = dirs.tile_dirs(ns, category);
+            for (name, map) in atlas {
+                let mut figures = String::new();
+                for (pos, tile) in &map.tiles {
+                    for output in tile.output_names(&toml.palettes) {
+                        // Ones skipped, e.g. as duplicates, aren't shown
+                        let path = tile_dirs.tile_path(tile, &output);
+                        let image = match writer.kept_image(&path) {
+                            Some(image) => image,
+                            None => continue,
+                        };
+                        // A strip's `animation` is counted only when sliced,
+                        // its frames are square
+                        let (width, height) = image.dimensions();
+                        let frames = match tile.frames {
+                            0 => (height / width.max(1)).max(1),
+                            frames => frames,
+                        };
+                        let at = match map.kind {
+                            AtlasKind::Grid => format!("{:?}", pos),
+                            AtlasKind::Strip if tile.frames == 0 => {
+                                "every frame".to_owned()
+                            }
+                            AtlasKind::Strip => format!("frame {}", pos.y()),
+                        };
+                        let file = format!("{}.png", count);
+                        thumbnail(&image, frames)
+                            .save(tiles_dir.join(&file))?;
+                        let frames = match frames {
+                            1 => String::new(),
+                            frames => format!(", {} frames", frames),
+                        };
+                        writeln!(
+                            figures,
+                            "<figure><img src=\"tiles/{}\" alt=\"{}\">\
+                             <figcaption>{}<br><code>{}</code>{}\
+                             </figcaption></figure>",
+                            file,
+                            escape(&output),
+                            escape(&output),
+                            at,
+                            frames
+                        )?;
+                        count += 1;
+                    }
+                }
+                if !figures.is_empty() {
+                    writeln!(
+                        html,
+                        "<h2>{}: [{}.{}]</h2>\n<section>\n{}</section>",
+                        escape(&ns.modid),
+                        category.name(),
+                        escape(name),
+                        figures
+                    )?;
+                }
+            }
+        }
+    }
+    html.push_str("</body>\n</html>\n");
+    let index = dir.join("index.html");
+    fs::write(&index, html)
+        .with_context(|| format!("can't write {}", index.display()))?;
+    Ok(count)
+}
@@ -0,0 +1,233 @@
+use std::collections::BTreeMap;
+
+use serde_json::{json, Value};
+
+use crate::{Animation, CtmMethod};
+
+/// Blockstate with a single variant pointing at `<modid>:block/<name>`
+pub fn blockstate(modid: &str, name: &str) -> String {
+    let model = format!("{}:block/{}", modid, name);
+    let json = json!({ "variants": { "": { "model": model } } });
+    serde_json::to_string_pretty(&json).unwrap()
+}
+
+/// Texture variable a vanilla block model parent draws its only texture
+/// with: `all` for cubes, `cross` for plants and so on
+fn texture_variable(parent: &str) -> &'static str {
+    let name = parent.rsplit('/').next().unwrap_or(parent);
+    match name {
+        "cross" | "tinted_cross" => "cross",
+        "flower_pot_cross" => "plant",
+        "carpet" => "wool",
+        "rail_flat" => "rail",
+        "crop" => "crop",
+        _ => "all",
+    }
+}
+
+/// Block model inheriting from `parent`, like `cube_all` or
+/// `minecraft:block/cross`, drawn with `<modid>:block/<name>`
+pub fn block_model(modid: &str, name: &str, parent: &str) -> String {
+    let parent = if parent.contains(':') || parent.contains('/') {
+        parent.to_owned()
+    } else {
+        format!("block/{}", parent)
+    };
+    let texture = format!("{}:block/{}", modid, name);
+    let json = json!({
+        "parent": parent,
+        "textures": { texture_variable(&parent): texture },
+    });
+    serde_json::to_string_pretty(&json).unwrap()
+}
+
+/// Model of `[aliases]` inheriting everything from `parent`, e.g.
+/// `block/oak_planks`
+pub fn alias_model(modid: &str, parent: &str) -> String {
+    let json = json!({ "parent": format!("{}:{}", modid, parent) });
+    serde_json::to_string_pretty(&json).unwrap()
+}
+
+/// Replaces `{key}` placeholders in `text` with `lookup(key)`. Braces not
+/// around a plain identifier are kept as they are.
+fn fill(
+    text: &str,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> anyhow::Result<String> {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let key = after.find('}').map(|end| &after[..end]).filter(|key| {
+            !key.is_empty()
+                && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        });
+        match key {
+            Some(key) => {
+                let value = lookup(key).ok_or_else(|| {
+                    anyhow::anyhow!("no value for placeholder {{{}}}", key)
+                })?;
+                out.push_str(&value);
+                rest = &after[key.len() + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+fn fill_json(
+    json: &Value,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> anyhow::Result<Value> {
+    Ok(match json {
+        Value::String(text) => Value::String(fill(text, lookup)?),
+        Value::Array(values) => Value::Array(
+            values
+                .iter()
+                .map(|value| fill_json(value, lookup))
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, value)| {
+                    Ok((key.clone(), fill_json(value, lookup)?))
+                })
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        other => other.clone(),
+    })
+}
+
+/// Block model from `template`, with `{name}`, `{modid}`, `{texture}` (as
+/// `<modid>:block/<name>`) and every key of `vars` filled in all its strings
+pub fn templated_model(
+    template: &Value,
+    modid: &str,
+    name: &str,
+    vars: &BTreeMap<String, String>,
+) -> anyhow::Result<String> {
+    let lookup = |key: &str| match key {
+        "name" => Some(name.to_owned()),
+        "modid" => Some(modid.to_owned()),
+        "texture" => Some(format!("{}:block/{}", modid, name)),
+        _ => vars.get(key).cloned(),
+    };
+    let json = fill_json(template, &lookup)?;
+    Ok(serde_json::to_string_pretty(&json).unwrap())
+}
+
+/// `item/generated` model using `<modid>:item/<name>` as its only layer
+pub fn item_model(modid: &str, name: &str) -> String {
+    let texture = format!("{}:item/{}", modid, name);
+    let json = json!({
+        "parent": "item/generated",
+        "textures": { "layer0": texture },
+    });
+    serde_json::to_string_pretty(&json).unwrap()
+}
+
+/// `.png.mcmeta` for a vertical animation strip
+pub fn animation_mcmeta(frametime: Option<u32>) -> String {
+    let json = match frametime {
+        Some(frametime) => json!({ "animation": { "frametime": frametime } }),
+        None => json!({ "animation": {} }),
+    };
+    serde_json::to_string_pretty(&json).unwrap()
+}
+
+/// `.png.mcmeta` of a copied texture, from its entry in `animations`
+pub fn texture_mcmeta(animation: &Animation) -> String {
+    let mut fields = serde_json::Map::new();
+    if let Some(frametime) = animation.frametime {
+        fields.insert("frametime".to_owned(), frametime.into());
+    }
+    if animation.interpolate {
+        fields.insert("interpolate".to_owned(), true.into());
+    }
+    if !animation.frames.is_empty() {
+        fields.insert("frames".to_owned(), animation.frames.clone().into());
+    }
+    let json = json!({ "animation": fields });
+    serde_json::to_string_pretty(&json).unwrap()
+}
+
+/// OptiFine `.properties` of a connected texture set, with tiles numbered
+/// from 0 next to it
+pub fn ctm_properties(method: CtmMethod, blocks: &[String]) -> String {
+    let (tiles, _) = method.layout();
+    format!(
+        "method={}\ntiles=0-{}\nmatchBlocks={}\n",
+        method.name(),
+        tiles - 1,
+        blocks.join(" ")
+    )
+}
+
+/// Bitmap font provider of sheet `file`, as `<modid>:<path>.png` within
+/// `textures`, with a string of glyphs per row
+pub fn bitmap_provider(
+    file: &str,
+    ascent: i32,
+    height: i32,
+    chars: &[String],
+) -> Value {
+    json!({
+        "type": "bitmap",
+        "file": file,
+        "ascent": ascent,
+        "height": height,
+        "chars": chars,
+    })
+}
+
+/// Font definition made of `providers`, tried in order
+pub fn font_json(providers: &[Value]) -> String {
+    let json = json!({ "providers": providers });
+    serde_json::to_string_pretty(&json).unwrap()
+}
+
+/// Resource pack metadata
+pub fn pack_mcmeta(pack_format: u32, description: &str) -> String {
+    let json = json!({
+        "pack": { "pack_format": pack_format, "description": description },
+    });
+    serde_json::to_string_pretty(&json).unwrap()
+}
+
+/// Sound event for a file, derived from its path: `machine/hum2.ogg` becomes
+/// `machine.hum`, so numbered variants share an event like they used to
+pub fn sound_event(file: &str) -> String {
+    let name = file.rsplit_once('.').map_or(file, |(name, _)| name);
+    let name = name.trim_end_matches(|c: char| c.is_ascii_digit());
+    name.replace('/', ".")
+}
+
+/// `sounds.json`, with each event playing one of its files, given relative
+/// to `sounds` directory
+pub fn sounds_json(
+    modid: &str,
+    events: &BTreeMap<String, Vec<String>>,
+) -> String {
+    let events: serde_json::Map<_, _> = events
+        .iter()
+        .map(|(event, files)| {
+            let sounds: Vec<_> = files
+                .iter()
+                .map(|file| {
+                    let name = file
+                        .rsplit_once('.')
+                        .map_or(&file[..], |(name, _)| name);
+                    format!("{}:{}", modid, name)
+                })
+                .collect();
+            (event.clone(), json!({ "sounds": sounds }))
+        })
+        .collect();
+    serde_json::to_string_pretty(&events).unwrap()
+}
@@ -0,0 +1,186 @@
+use std::{
+    convert::TryFrom,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use image::{Rgba, RgbaImage};
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+
+use crate::{cache::hash, AtlasPos, Config, Failure, Tile};
+
+/// Tile as scripts see it, with `width`, `height`, `get(x, y)` returning
+/// `[r, g, b, a]` and `set(x, y, [r, g, b, a])`
+#[derive(Clone)]
+struct Image(RgbaImage);
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+impl Image {
+    fn check(&self, x: i64, y: i64) -> ScriptResult<(u32, u32)> {
+        let (width, height) = self.0.dimensions();
+        match (u32::try_from(x), u32::try_from(y)) {
+            (Ok(x), Ok(y)) if x < width && y < height => Ok((x, y)),
+            _ => Err(format!(
+                "pixel ({}, {}) is outside of {}x{} tile",
+                x, y, width, height
+            )
+            .into()),
+        }
+    }
+
+    fn get(&mut self, x: i64, y: i64) -> ScriptResult<Array> {
+        let (x, y) = self.check(x, y)?;
+        let Rgba(pixel) = self.0.get_pixel(x, y);
+        Ok(pixel.iter().map(|&c| Dynamic::from_int(c as i64)).collect())
+    }
+
+    fn set(&mut self, x: i64, y: i64, pixel: Array) -> ScriptResult<()> {
+        let (x, y) = self.check(x, y)?;
+        let channels: Vec<_> = pixel
+            .iter()
+            .map(|c| c.as_int().ok().and_then(|c| u8::try_from(c).ok()))
+            .collect();
+        match channels[..] {
+            [Some(r), Some(g), Some(b), Some(a)] => {
+                self.0.put_pixel(x, y, Rgba([r, g, b, a]));
+                Ok(())
+            }
+            _ => Err("pixel has to be [r, g, b, a], each 0 to 255".into()),
+        }
+    }
+}
+
+/// Script with functions tiles name in `hook`, called with every such
+/// tile after it's cut out and transformed, before it's saved
+pub struct Hooks {
+    path: PathBuf,
+    engine: Engine,
+    ast: AST,
+    /// Part of `Debug`, so cached atlases are redone when the script changes
+    source_hash: String,
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Hooks({}, {})", self.path.display(), self.source_hash)
+    }
+}
+
+impl Hooks {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let source = fs::read_to_string(path).map_err(|e| {
+            Failure::Io.tag(anyhow::anyhow!("{}: {}", path.display(), e))
+        })?;
+        let mut engine = Engine::new();
+        // Defaults of debug builds are too low for a couple nested loops
+        engine.set_max_expr_depths(64, 64);
+        engine
+            .register_type_with_name::<Image>("Image")
+            .register_get("width", |image: &mut Image| image.0.width() as i64)
+            .register_get("height", |image: &mut Image| image.0.height() as i64)
+            .register_result_fn("get", Image::get)
+            .register_result_fn("set", Image::set);
+        let ast = engine.compile(&source).map_err(|e| {
+            Failure::Config.tag(anyhow::anyhow!("{}: {}", path.display(), e))
+        })?;
+        Ok(Self {
+            path: path.to_owned(),
+            engine,
+            ast,
+            source_hash: hash(source.as_bytes()),
+        })
+    }
+
+    fn has(&self, name: &str) -> bool {
+        self.ast.iter_functions().any(|f| f.name == name)
+    }
+
+    /// Runs hook `name` on `strip` of `tile`, at `pos` of `atlas`. The
+    /// script function gets the image and a map with `name`, `atlas`,
+    /// `pos` and `frames`, and returns the changed image.
+    pub fn apply(
+        &self,
+        name: &str,
+        strip: RgbaImage,
+        atlas: &str,
+        pos: &AtlasPos,
+        tile: &Tile,
+    ) -> anyhow::Result<RgbaImage> {
+        let (width, height) = strip.dimensions();
+        let mut meta = Map::new();
+        meta.insert("name".into(), tile.name.clone().into());
+        meta.insert("atlas".into(), atlas.to_owned().into());
+        meta.insert("pos".into(), format!("{:?}", pos).into());
+        meta.insert("frames".into(), (tile.frames as i64).into());
+        let result = self
+            .engine
+            .call_fn::<Image>(
+                &mut Scope::new(),
+                &self.ast,
+                name,
+                (Image(strip), meta),
+            )
+            .map_err(|e| {
+                anyhow::anyhow!(
+                    "{}: hook {} on {} ({:?}): {}",
+                    self.path.display(),
+                    name,
+                    tile.name,
+                    pos,
+                    e
+                )
+            })?;
+        anyhow::ensure!(
+            result.0.dimensions() == (width, height),
+            "hook {} changed size of {}, which it can't",
+            name,
+            tile.name
+        );
+        Ok(result.0)
+    }
+}
+
+/// Checks that every tile's `hook` is a function of the config's script
+pub fn check_hooks(
+    config: &Config,
+    hooks: Option<&Hooks>,
+) -> anyhow::Result<()> {
+    find_missing_hooks(config, hooks).map_err(|e| Failure::Config.tag(e))
+}
+
+fn find_missing_hooks(
+    config: &Config,
+    hooks: Option<&Hooks>,
+) -> anyhow::Result<()> {
+    let atlases = config
+        .all_namespaces()
+        .flat_map(|ns| ns.blocks.iter().chain(&ns.items));
+    for (atlas, map) in atlases {
+        for (pos, tile) in &map.tiles {
+            let hook = match &tile.hook {
+                Some(hook) => hook,
+                None => continue,
+            };
+            match hooks {
+                Some(hooks) => anyhow::ensure!(
+                    hooks.has(hook),
+                    "atlas {}: {:?} ({}) has hook {}, which {} doesn't define",
+                    atlas,
+                    pos,
+                    tile.name,
+                    hook,
+                    hooks.path.display()
+                ),
+                None => anyhow::bail!(
+                    "atlas {}: {:?} ({}) has hook {}, but there's no `script`",
+                    atlas,
+                    pos,
+                    tile.name,
+                    hook
+                ),
+            }
+        }
+    }
+    Ok(())
+}
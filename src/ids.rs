@@ -0,0 +1,104 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use crate::{Config, Failure};
+
+/// Legacy numeric id, as `block` or `block:meta`, to flattened name
+pub type IdMap = BTreeMap<String, String>;
+
+/// Parses `id,name` or `id,meta,name` lines, skipping blank lines, `#`
+/// comments and a header line
+pub fn parse_ids_csv(text: &str) -> anyhow::Result<IdMap> {
+    let mut ids = IdMap::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<_> = line.split(',').map(str::trim).collect();
+        let is_number = |s: &str| s.parse::<u32>().is_ok();
+        let (id, name) = match fields[..] {
+            [id, name] => (id.to_owned(), name),
+            [id, meta, name] if is_number(meta) => {
+                (format!("{}:{}", id, meta), name)
+            }
+            _ if i == 0 => continue,
+            _ => anyhow::bail!(
+                "line {}: expected id,name or id,meta,name, found {:?}",
+                i + 1,
+                line
+            ),
+        };
+        let valid = match id.split_once(':') {
+            Some((block, meta)) => is_number(block) && is_number(meta),
+            None => is_number(&id),
+        };
+        if !valid && i == 0 {
+            continue;
+        }
+        anyhow::ensure!(
+            valid,
+            "line {}: {:?} is not an id like 251 or 251:3",
+            i + 1,
+            id
+        );
+        ids.insert(id, name.to_owned());
+    }
+    Ok(ids)
+}
+
+/// Reads an id mapping, as csv or, for `.toml` files, a table like
+/// `"251:3" = "light_blue_concrete"`
+pub fn load_ids(path: &Path) -> anyhow::Result<IdMap> {
+    let text = fs::read_to_string(path).map_err(|e| {
+        let error =
+            anyhow::anyhow!("can't read ids from {}: {}", path.display(), e);
+        Failure::Io.tag(error)
+    })?;
+    let ids = if path.extension().is_some_and(|ext| ext == "toml") {
+        toml::from_str(&text).map_err(anyhow::Error::from)
+    } else {
+        parse_ids_csv(&text)
+    };
+    ids.map_err(|e| {
+        Failure::Config.tag(anyhow::anyhow!("{}: {}", path.display(), e))
+    })
+}
+
+/// Name for `id`, falling back to the one of its block for any metadata
+fn lookup<'a>(ids: &'a IdMap, id: &str) -> Option<&'a String> {
+    ids.get(id).or_else(|| {
+        let (block, _) = id.split_once(':')?;
+        ids.get(block)
+    })
+}
+
+/// Names every tile given only by `id`, using `ids`. Tiles with both keep
+/// their name.
+pub fn resolve_ids(config: &mut Config, ids: &IdMap) -> anyhow::Result<()> {
+    for ns in config.all_namespaces_mut() {
+        let atlases = ns.blocks.iter_mut().chain(ns.items.iter_mut());
+        for (name, atlas) in atlases {
+            for (pos, tile) in &mut atlas.tiles {
+                if !tile.name.is_empty() {
+                    continue;
+                }
+                let id = tile.id.as_ref().ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "atlas {}: {:?} needs either name or id",
+                        name,
+                        pos
+                    )
+                })?;
+                tile.name = lookup(ids, id).cloned().ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "atlas {}: {:?} has id {}, which isn't in `ids` file",
+                        name,
+                        pos,
+                        id
+                    )
+                })?;
+            }
+        }
+    }
+    Ok(())
+}
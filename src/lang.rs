@@ -0,0 +1,44 @@
+use std::{collections::BTreeMap, path::Path};
+
+/// Parses legacy `key=value` lang file, skipping blank lines and `#`
+/// comments
+pub fn parse_lang(text: &str) -> anyhow::Result<BTreeMap<String, String>> {
+    let mut entries = BTreeMap::new();
+    let text = text.trim_start_matches('\u{feff}');
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line.split_once('=').ok_or_else(|| {
+            anyhow::anyhow!(
+                "line {}: expected key=value, found {:?}",
+                i + 1,
+                line
+            )
+        })?;
+        entries.insert(key.trim().to_owned(), value.to_owned());
+    }
+    Ok(entries)
+}
+
+/// Modern locale code for a lang file, e.g. `lang/en_US.lang` is `en_us`
+pub fn locale(file: &str) -> String {
+    let stem = Path::new(file).file_stem().unwrap_or_default();
+    stem.to_string_lossy().to_lowercase()
+}
+
+/// Modern `<locale>.json`, with keys renamed by `rename` where listed
+pub fn lang_json(
+    entries: &BTreeMap<String, String>,
+    rename: &BTreeMap<String, String>,
+) -> String {
+    let json: serde_json::Map<_, _> = entries
+        .iter()
+        .map(|(key, value)| {
+            let key = rename.get(key).unwrap_or(key);
+            (key.clone(), value.clone().into())
+        })
+        .collect();
+    serde_json::to_string_pretty(&json).unwrap()
+}
@@ -0,0 +1,566 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use serde::{
+    de::{value::MapAccessDeserializer, MapAccess, Visitor},
+    Deserialize,
+};
+
+use crate::{AtlasMap, Config, Failure, Namespace, Tile, TileDirs, Writer};
+
+/// Project shape assets are written for
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    /// Gradle workspace, banner at the root of resources
+    #[default]
+    Forge,
+    /// Gradle workspace, banner as `assets/<modid>/<banner>`
+    Fabric,
+    /// Plain resource pack, banner as `pack.png`
+    #[serde(alias = "resourcepack")]
+    Pack,
+}
+
+/// Directories of single categories, relative to `assets/<modid>`, with
+/// `{modid}` replaced by mod id. Unset ones keep the preset's.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CategoryPaths {
+    pub models: Option<String>,
+    pub item_models: Option<String>,
+    pub blockstates: Option<String>,
+    pub gui: Option<String>,
+    pub blocks: Option<String>,
+    pub items: Option<String>,
+    pub entity: Option<String>,
+    pub particle: Option<String>,
+    pub environment: Option<String>,
+    pub sounds: Option<String>,
+    pub lang: Option<String>,
+    pub ctm: Option<String>,
+    pub fonts: Option<String>,
+    pub font_providers: Option<String>,
+}
+
+/// Where assets go within the output root, written either as just a preset
+/// name, or as a `[layout]` table like `{ preset = "fabric", blocks =
+/// "textures/block" }`
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(from = "LayoutDef")]
+pub struct Layout {
+    pub preset: Preset,
+    pub paths: CategoryPaths,
+}
+
+impl Layout {
+    pub fn from_preset(preset: Preset) -> Self {
+        Self {
+            preset,
+            paths: CategoryPaths::default(),
+        }
+    }
+
+    /// Output root relative to the project directory, used when no explicit
+    /// output directory is given
+    pub fn default_root(&self, project_dir: &Path) -> PathBuf {
+        match self.preset {
+            Preset::Forge | Preset::Fabric => {
+                project_dir.join("src").join("main").join("resources")
+            }
+            Preset::Pack => project_dir.to_owned(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LayoutTable {
+    #[serde(default)]
+    preset: Preset,
+    models: Option<String>,
+    item_models: Option<String>,
+    blockstates: Option<String>,
+    gui: Option<String>,
+    blocks: Option<String>,
+    items: Option<String>,
+    entity: Option<String>,
+    particle: Option<String>,
+    environment: Option<String>,
+    sounds: Option<String>,
+    lang: Option<String>,
+    ctm: Option<String>,
+    fonts: Option<String>,
+    font_providers: Option<String>,
+}
+
+/// Either form of a layout, like `TileDef`
+enum LayoutDef {
+    Preset(Preset),
+    Full(Box<LayoutTable>),
+}
+
+struct LayoutDefVisitor;
+impl<'v> Visitor<'v> for LayoutDefVisitor {
+    type Value = LayoutDef;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("forge, fabric, pack or resourcepack, or table with paths")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        use serde::de::IntoDeserializer;
+        Preset::deserialize(v.into_deserializer()).map(LayoutDef::Preset)
+    }
+
+    fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'v>,
+    {
+        LayoutTable::deserialize(MapAccessDeserializer::new(map))
+            .map(|table| LayoutDef::Full(Box::new(table)))
+    }
+}
+
+impl<'de> Deserialize<'de> for LayoutDef {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(LayoutDefVisitor)
+    }
+}
+
+impl From<LayoutDef> for Layout {
+    fn from(def: LayoutDef) -> Self {
+        match def {
+            LayoutDef::Preset(preset) => Layout::from_preset(preset),
+            LayoutDef::Full(table) => {
+                let LayoutTable {
+                    preset,
+                    models,
+                    item_models,
+                    blockstates,
+                    gui,
+                    blocks,
+                    items,
+                    entity,
+                    particle,
+                    environment,
+                    sounds,
+                    lang,
+                    ctm,
+                    fonts,
+                    font_providers,
+                } = *table;
+                Layout {
+                    preset,
+                    paths: CategoryPaths {
+                        models,
+                        item_models,
+                        blockstates,
+                        gui,
+                        blocks,
+                        items,
+                        entity,
+                        particle,
+                        environment,
+                        sounds,
+                        lang,
+                        ctm,
+                        fonts,
+                        font_providers,
+                    },
+                }
+            }
+        }
+    }
+}
+
+/// Resolved output paths for a single mod
+#[derive(Debug, Clone)]
+pub struct OutputDirs {
+    pub root: PathBuf,
+    pub namespace: PathBuf,
+    /// Where the banner goes, if there's one
+    pub banner: Option<PathBuf>,
+    pub models: PathBuf,
+    pub item_models: PathBuf,
+    pub blockstates: PathBuf,
+    pub guis: PathBuf,
+    pub blocks: PathBuf,
+    pub items: PathBuf,
+    pub entity: PathBuf,
+    pub particle: PathBuf,
+    pub environment: PathBuf,
+    pub sounds: PathBuf,
+    pub lang: PathBuf,
+    pub ctm: PathBuf,
+    pub fonts: PathBuf,
+    pub font_providers: PathBuf,
+}
+
+impl OutputDirs {
+    pub fn new(
+        layout: &Layout,
+        root: PathBuf,
+        modid: &str,
+        banner: Option<&str>,
+    ) -> Self {
+        let namespace = root.join("assets").join(modid);
+        let banner = banner.map(|banner| match layout.preset {
+            Preset::Forge => root.join(banner),
+            Preset::Fabric => namespace.join(banner),
+            Preset::Pack => root.join("pack.png"),
+        });
+        let dir = |path: &Option<String>, default: &str| {
+            let path = path.as_deref().unwrap_or(default);
+            namespace.join(path.replace("{modid}", modid))
+        };
+        let paths = &layout.paths;
+        Self {
+            banner,
+            models: dir(&paths.models, "models/block"),
+            item_models: dir(&paths.item_models, "models/item"),
+            blockstates: dir(&paths.blockstates, "blockstates"),
+            sounds: dir(&paths.sounds, "sounds"),
+            lang: dir(&paths.lang, "lang"),
+            ctm: dir(&paths.ctm, "optifine/ctm"),
+            font_providers: dir(&paths.font_providers, "font"),
+            guis: dir(&paths.gui, "textures/gui"),
+            blocks: dir(&paths.blocks, "textures/block"),
+            items: dir(&paths.items, "textures/item"),
+            entity: dir(&paths.entity, "textures/entity"),
+            particle: dir(&paths.particle, "textures/particle"),
+            environment: dir(&paths.environment, "textures/environment"),
+            fonts: dir(&paths.fonts, "textures/font"),
+            namespace,
+            root,
+        }
+    }
+}
+
+/// Category directory single entries can be written to in place of their
+/// section's, with `outputs`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Category {
+    Models,
+    ItemModels,
+    Gui,
+    Blocks,
+    Items,
+    Entity,
+    Particle,
+    Environment,
+}
+
+impl Category {
+    /// Name as written in config, matching the `[layout]` key
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Models => "models",
+            Self::ItemModels => "item_models",
+            Self::Gui => "gui",
+            Self::Blocks => "blocks",
+            Self::Items => "items",
+            Self::Entity => "entity",
+            Self::Particle => "particle",
+            Self::Environment => "environment",
+        }
+    }
+
+    pub fn is_model(self) -> bool {
+        matches!(self, Self::Models | Self::ItemModels)
+    }
+
+    pub fn dir(self, dirs: &OutputDirs) -> &Path {
+        match self {
+            Self::Models => &dirs.models,
+            Self::ItemModels => &dirs.item_models,
+            Self::Gui => &dirs.guis,
+            Self::Blocks => &dirs.blocks,
+            Self::Items => &dirs.items,
+            Self::Entity => &dirs.entity,
+            Self::Particle => &dirs.particle,
+            Self::Environment => &dirs.environment,
+        }
+    }
+}
+
+/// Checks that `path`, from a config or an archive, stays inside directory
+/// it's joined to. Both separators and drive letters count, as configs are
+/// shared between platforms.
+pub fn check_output_path(path: &str) -> anyhow::Result<()> {
+    let drive = path.as_bytes().get(1) == Some(&b':')
+        && path.as_bytes()[0].is_ascii_alphabetic();
+    anyhow::ensure!(
+        !path.starts_with(['/', '\\']) && !drive,
+        "{:?} is absolute, expected a path relative to its output directory",
+        path
+    );
+    anyhow::ensure!(
+        !path.split(['/', '\\']).any(|part| part == ".."),
+        "{:?} contains `..`, which would leave its output directory",
+        path
+    );
+    Ok(())
+}
+
+/// Checks every name in `config` that's joined into an output path with
+/// `check_output_path`, along with directories of the layout
+pub fn check_output_paths(config: &Config) -> anyhow::Result<()> {
+    let check = |section: &str, path: &str| {
+        check_output_path(path).map_err(|e| {
+            Failure::Config.tag(anyhow::anyhow!("[{}] {}", section, e))
+        })
+    };
+    if let Some(banner) = &config.banner {
+        check("banner", banner)?;
+    }
+    let paths = &config.layout.paths;
+    for (name, path) in [
+        ("models", &paths.models),
+        ("item_models", &paths.item_models),
+        ("blockstates", &paths.blockstates),
+        ("gui", &paths.gui),
+        ("blocks", &paths.blocks),
+        ("items", &paths.items),
+        ("entity", &paths.entity),
+        ("particle", &paths.particle),
+        ("environment", &paths.environment),
+        ("sounds", &paths.sounds),
+        ("lang", &paths.lang),
+        ("ctm", &paths.ctm),
+        ("fonts", &paths.fonts),
+        ("font_providers", &paths.font_providers),
+    ] {
+        if let Some(path) = path {
+            check(&format!("layout.{}", name), path)?;
+        }
+    }
+    for ns in config.all_namespaces() {
+        if ns.modid.is_empty() || ns.modid.contains(['/', '\\', ':']) {
+            return Err(Failure::Config.tag(anyhow::anyhow!(
+                "invalid modid {:?}, it can't be empty or contain separators",
+                ns.modid
+            )));
+        }
+        for (section, files) in [
+            ("models", &ns.models),
+            ("gui", &ns.gui),
+            ("blocks_copy", &ns.blocks_copy),
+            ("entity", &ns.entity),
+            ("particle", &ns.particle),
+            ("environment", &ns.environment),
+            ("sounds", &ns.sounds.files),
+        ] {
+            for file in files {
+                check(section, file)?;
+            }
+        }
+        for to in ns.misc.values() {
+            check("misc", to)?;
+        }
+        for crop in ns.gui_crops.values().flatten() {
+            check("gui_crops", &crop.name)?;
+        }
+        for sprites in ns.sprite_sheets.values() {
+            for crop in &sprites.crops {
+                check("sprite_sheets", &crop.name)?;
+            }
+        }
+        for (_, name, _) in ns.fallbacks.iter() {
+            check("fallbacks", name)?;
+        }
+        for copy in ns.copy_dirs.values() {
+            check("copy_dirs", &copy.to)?;
+        }
+        for name in ns.ctm.keys() {
+            check("ctm", name)?;
+        }
+        for (name, font) in &ns.fonts {
+            check("fonts", name)?;
+            check("fonts", font.font(name))?;
+        }
+        for (dir, atlas) in [("blocks", &ns.blocks), ("items", &ns.items)] {
+            for (name, map) in atlas {
+                for tile in map.tiles.values() {
+                    check(&format!("{}.{}", dir, name), &tile.name)?;
+                }
+            }
+        }
+        for alias in ns.aliases.keys() {
+            check("aliases", alias)?;
+        }
+    }
+    Ok(())
+}
+
+/// `.properties` of connected texture set `name`, relative to ctm directory,
+/// with its tiles next to it
+pub fn ctm_properties_path(name: &str) -> String {
+    format!("{0}/{0}.properties", name)
+}
+
+/// Names every tile in `atlas` writes its texture as
+fn tile_names(atlas: &AtlasMap) -> impl Iterator<Item = &String> {
+    atlas
+        .values()
+        .flat_map(|map| map.tiles.values())
+        .flat_map(Tile::names)
+}
+
+/// Names every tile in `atlas` gets generated models as
+fn model_names(atlas: &AtlasMap) -> impl Iterator<Item = &String> {
+    atlas
+        .values()
+        .flat_map(|map| map.tiles.values())
+        .flat_map(Tile::model_names)
+}
+
+impl OutputDirs {
+    /// Path `file` of section `category` in `ns` is written to, following
+    /// `outputs`
+    pub fn entry_path(
+        &self,
+        ns: &Namespace,
+        category: Category,
+        file: &str,
+    ) -> PathBuf {
+        ns.category(file, category).dir(self).join(file)
+    }
+
+    /// Where tiles of `ns` atlases in `category` are written, either blocks
+    /// or items
+    pub fn tile_dirs(&self, ns: &Namespace, category: Category) -> TileDirs {
+        let atlas = match category {
+            Category::Items => &ns.items,
+            _ => &ns.blocks,
+        };
+        let moved = tile_names(atlas)
+            .filter_map(|name| {
+                let to = ns.outputs.get(name)?;
+                Some((name.clone(), to.dir(self).to_owned()))
+            })
+            .collect();
+        TileDirs {
+            dir: category.dir(self).to_owned(),
+            moved,
+        }
+    }
+
+    /// Every directory `ns` writes into: category directories, plus
+    /// subdirectories for nested names like `machine/top`. Directories copied
+    /// with `copy_dirs` depend on archive contents and aren't included.
+    pub fn planned(
+        &self,
+        config: &Config,
+        ns: &Namespace,
+    ) -> BTreeSet<PathBuf> {
+        let mut dirs = BTreeSet::new();
+        let mut files =
+            |dir: &Path, names: &mut dyn Iterator<Item = &String>| {
+                dirs.insert(dir.to_owned());
+                for name in names {
+                    if let Some(parent) = dir.join(name).parent() {
+                        dirs.insert(parent.to_owned());
+                    }
+                }
+            };
+        files(&self.models, &mut ns.models.iter());
+        files(&self.guis, &mut ns.gui.iter());
+        files(
+            &self.guis,
+            &mut ns.gui_crops.values().flatten().map(|crop| &crop.name),
+        );
+        for sprites in ns.sprite_sheets.values() {
+            files(
+                sprites.category.dir(self),
+                &mut sprites.crops.iter().map(|crop| &crop.name),
+            );
+        }
+        files(&self.blocks, &mut ns.blocks_copy.iter());
+        for (category, name, _) in ns.fallbacks.iter() {
+            let dir = ns.category(name, category).dir(self);
+            files(dir, &mut std::iter::once(name));
+        }
+        files(&self.blocks, &mut tile_names(&ns.blocks));
+        files(&self.items, &mut tile_names(&ns.items));
+        for (dir, names) in [
+            (&self.entity, &ns.entity),
+            (&self.particle, &ns.particle),
+            (&self.environment, &ns.environment),
+        ] {
+            if !names.is_empty() {
+                files(dir, &mut names.iter());
+            }
+        }
+        if !ns.misc.is_empty() {
+            files(&self.namespace, &mut ns.misc.values());
+        }
+        if !ns.sounds.files.is_empty() {
+            files(&self.sounds, &mut ns.sounds.files.iter());
+        }
+        if !ns.ctm.is_empty() {
+            let properties: Vec<_> = ns
+                .ctm
+                .keys()
+                .map(|name| ctm_properties_path(name))
+                .collect();
+            files(&self.ctm, &mut properties.iter());
+        }
+        if !ns.fonts.is_empty() {
+            files(&self.fonts, &mut ns.fonts.keys());
+            let providers: BTreeSet<_> = ns
+                .fonts
+                .iter()
+                .map(|(name, font)| font.font(name).to_owned())
+                .collect();
+            files(&self.font_providers, &mut providers.iter());
+        }
+        if !ns.lang.files.is_empty() {
+            files(&self.lang, &mut std::iter::empty());
+        }
+        for (name, category) in &ns.outputs {
+            files(category.dir(self), &mut std::iter::once(name));
+        }
+        let auto_models = config.auto_models.as_deref();
+        files(
+            &self.models,
+            &mut ns
+                .blocks
+                .values()
+                .flat_map(|map| map.tiles.values())
+                .filter(|tile| tile.model(auto_models).is_some())
+                .flat_map(Tile::model_names),
+        );
+        if config.generate_blockstates {
+            files(&self.blockstates, &mut model_names(&ns.blocks));
+        }
+        if config.generate_item_models {
+            files(&self.item_models, &mut model_names(&ns.items));
+        }
+        if let Some(parent) = self.banner.as_deref().and_then(Path::parent) {
+            dirs.insert(parent.to_owned());
+        }
+        dirs
+    }
+
+    /// Creates every directory in `planned` up front
+    pub fn create_all(
+        &self,
+        config: &Config,
+        ns: &Namespace,
+        writer: &Writer,
+    ) -> anyhow::Result<()> {
+        for dir in self.planned(config, ns) {
+            writer.create_dir_all(&dir)?;
+        }
+        Ok(())
+    }
+}
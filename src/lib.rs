@@ -0,0 +1,133 @@
+//! Slices legacy texture atlases from mod archives into loose textures
+
+mod atlas;
+mod cache;
+mod chunks;
+mod classic;
+mod config;
+mod diagnostic;
+mod diff;
+mod edit;
+mod extract;
+mod failure;
+mod fetch;
+mod font;
+mod format;
+mod gallery;
+mod generate;
+mod hook;
+mod ids;
+mod lang;
+mod layout;
+mod manifest;
+#[cfg(feature = "cli")]
+mod mapper;
+mod mapping;
+mod mods;
+mod names;
+mod optimize;
+mod palette;
+mod plan;
+mod preview;
+mod progress;
+mod provenance;
+mod retexture;
+mod scaffold;
+mod slasher;
+mod source;
+mod staging;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+mod transform;
+mod upscale;
+mod validate;
+mod verify;
+#[cfg(feature = "cli")]
+mod watch;
+mod writer;
+mod zips;
+
+pub use atlas::{
+    output_count, process_atlas, process_atlas_map, process_crop_map,
+    process_crops, process_ctm_map, process_sheet_crops, slice_atlas,
+    stitch_atlas, stitch_atlas_map, validate_atlas_map, Aliases, AtlasInfo,
+    Dedupe, SliceOptions, TileDirs, MAX_CELLS,
+};
+pub use cache::{Cache, CACHE_FILE};
+pub use chunks::{
+    color_chunks, insert_color_chunks, insert_text_chunk, read_color_chunks,
+    read_png_head, strip_color_chunks, ColorChunks,
+};
+pub use classic::{suggest_names, Era};
+pub use config::{
+    check_output_names, emissive_name, expand_env, load_config, load_configs,
+    luma, parse_configs, Alias, Animation, Atlas, AtlasKind, AtlasMap,
+    AtlasPos, Border, Config, ConfigOverride, CopyDir, Crop, CropMap, Ctm,
+    CtmMap, CtmMethod, EmissiveMask, Fallback, Fallbacks, Folders, Font,
+    FontMap, Grayscale, Namespace, NinePatch, Order, Origin, Palettes,
+    ParseError, Priorities, SpriteSheet, Templates, TextureRenames, Tile,
+    TileModel, CONFIG_EXTENSIONS, DEFAULT_EMISSIVE_THRESHOLD,
+    DEFAULT_TILE_SIZE, NINE_SLICE_PIECES, STDIN,
+};
+pub use diagnostic::{locate_config_error, ConfigSource, Diagnostic};
+pub use diff::{diff_atlas, render_diff, AtlasDiff};
+pub use edit::ConfigEditor;
+pub use extract::{
+    banner_outputs, expand_files, extract_namespace, fallback_source,
+    tile_models, write_banner, FALLBACK, GENERATED,
+};
+pub use failure::Failure;
+pub use fetch::{fetch_sources, verify_checksums, Checksums, Sources};
+pub use font::{glyph_rows, process_font_map, GLYPHS_PER_ROW};
+pub use format::OutputFormat;
+pub use gallery::write_gallery;
+pub use generate::{
+    alias_model, animation_mcmeta, bitmap_provider, block_model, blockstate,
+    ctm_properties, font_json, item_model, pack_mcmeta, sound_event,
+    sounds_json, templated_model, texture_mcmeta,
+};
+pub use hook::{check_hooks, Hooks};
+pub use ids::{load_ids, parse_ids_csv, resolve_ids, IdMap};
+pub use lang::{lang_json, locale, parse_lang};
+pub use layout::{
+    check_output_path, check_output_paths, ctm_properties_path, Category,
+    CategoryPaths, Layout, OutputDirs, Preset,
+};
+pub use manifest::{clean, Cleaned, Manifest, MANIFEST_FILE};
+#[cfg(feature = "cli")]
+pub use mapper::{apply_edits, map_cells, Edit, Edits};
+pub use mapping::{export_mapping, read_descriptor, MappingFormat};
+pub use mods::{locate_mods, MOD_PREFIX};
+pub use names::{normalize_names, NameStyle, Rename};
+pub use optimize::optimize_png;
+pub use palette::{delta_e, swap_palette, Palette, Vision};
+pub use plan::{plan_extraction, Plan, PlannedFile};
+pub use preview::render_preview;
+pub use progress::{
+    Counts, Progress, SectionSummary, Stage, StageTimes, Summary, Timings,
+};
+pub use provenance::{provenance, PROVENANCE_FILE, PROVENANCE_KEYWORD};
+pub use retexture::{model_textures, rename_model_textures, rename_texture};
+pub use scaffold::scaffold;
+pub use slasher::{Extracted, Slasher};
+pub use source::{
+    open_archive, open_source, AssetSource, DirSource, MemorySource, ZipSource,
+    ARCHIVE_EXTENSIONS,
+};
+pub use transform::{
+    apply_colorkey, fix_semitransparent_edges, Color, Flip, Rotation, Transform,
+};
+pub use upscale::{fit, Filter, Scale, Upscale};
+pub use validate::{
+    check_copied_textures, check_texture_usage, find_missing, lint_config,
+    remove_missing, MissingAsset,
+};
+pub use verify::{verify_pack, Difference, Mismatch};
+#[cfg(feature = "cli")]
+pub use watch::Watch;
+pub use writer::{Outcome, Trim, WritePolicy, WriteRecord, Writer, ZipOutput};
+pub use zips::{
+    decode_image, decode_image_from, decode_image_stream, decoded_size,
+    ArchiveSet, Asset, Found, Layer, Shadowed, DECODE_BUDGET,
+    SHEET_EXTENSIONS,
+};
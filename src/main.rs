@@ -1,235 +1,1641 @@
 use std::{
     collections::BTreeMap,
-    fmt,
-    fs::{self, File},
-    io::{BufRead, Cursor, Read, Seek},
+    env,
+    ffi::OsString,
+    fs,
+    io::{self, IsTerminal},
     path::{Path, PathBuf},
+    process,
     str::FromStr,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    time::{Duration, Instant},
 };
 
-use image::{GenericImageView, ImageFormat};
-use serde::{
-    de::{Unexpected, Visitor},
-    Deserialize,
-};
+use anyhow::Context;
+use crossterm::style::{StyledContent, Stylize};
+use glob::{MatchOptions, Pattern};
+use image::RgbaImage;
+use indicatif::MultiProgress;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use rayon::prelude::*;
+use regex::Regex;
 use structopt::StructOpt;
-use zip::{read::ZipFile, ZipArchive};
+use tex_slasher::{
+    apply_edits, banner_outputs, check_copied_textures, check_hooks,
+    check_output_names, check_texture_usage, clean, decode_image, diff_atlas,
+    expand_files, export_mapping, extract_namespace, fetch_sources,
+    find_missing, lint_config, load_configs, locate_mods, map_cells,
+    open_source, pack_mcmeta, plan_extraction, provenance, remove_missing,
+    render_diff, render_preview, scaffold, slice_atlas, stitch_atlas_map,
+    suggest_names, swap_palette, validate_atlas_map, verify_checksums,
+    verify_pack, write_banner, write_gallery, Aliases, ArchiveSet, AssetSource,
+    Atlas, AtlasInfo, AtlasKind, AtlasPos, Cache, ColorChunks, Config,
+    ConfigEditor, ConfigOverride, Counts, Dedupe, Era, Failure, Filter, Hooks,
+    Layout, Manifest, MappingFormat, MissingAsset, Namespace, Order, Origin,
+    OutputDirs, OutputFormat, Palette, Palettes, Plan, PlannedFile, Preset,
+    Progress, Rename, SliceOptions, StageTimes, Summary, Tile, TileDirs,
+    Timings, Upscale, Watch, WritePolicy, Writer, CACHE_FILE,
+    CONFIG_EXTENSIONS, FALLBACK, GENERATED, PROVENANCE_FILE, STDIN,
+};
 
 #[derive(StructOpt)]
-struct Opt {
+struct Inputs {
     /// Path to folder with original mod files
     input_dir: PathBuf,
     /// Path to toml file, using headers as atlas names, keys as positions,
-    /// and values as result names
+    /// and values as result names. Can also be yaml or json with the same
+    /// structure, a directory of config files, or `-` for toml on stdin.
     toml: PathBuf,
+    /// More toml files or directories, merged with the first one
+    #[structopt(short, long = "config", number_of_values = 1)]
+    configs: Vec<PathBuf>,
+    /// Replace a key of the merged config, e.g. `modid=other` or
+    /// `blocks.terrain.tile_size=32`, with a toml value or a bare string
+    #[structopt(long = "set", value_name = "key=value", number_of_values = 1)]
+    sets: Vec<ConfigOverride>,
+    /// Name unmapped cells of classic `terrain.png` and `items.png` sheets
+    /// after vanilla textures of this era: beta or release
+    #[structopt(long)]
+    era: Option<Era>,
+    /// Version of the mod sources are from, picking config tables keyed
+    /// `@<version>` matching it, e.g. `1.7.10` for ones keyed `@1.7`
+    #[structopt(long, value_name = "version")]
+    mod_version: Option<String>,
 }
 
-#[derive(PartialEq, Eq, PartialOrd, Ord)]
-struct AtlasPos(u8);
+impl Inputs {
+    fn load(&self) -> anyhow::Result<Config> {
+        let mut paths = vec![self.toml.clone()];
+        paths.extend(self.configs.iter().cloned());
+        let mut sets = self.sets.clone();
+        sets.extend(self.mod_version.clone().map(ConfigOverride::mod_version));
+        let mut config = load_configs(&paths, &sets)?;
+        if let Some(era) = self.era {
+            for ns in config.all_namespaces_mut() {
+                let atlases = ns.blocks.iter_mut().chain(ns.items.iter_mut());
+                for (name, atlas) in atlases {
+                    let count = suggest_names(name, atlas, era);
+                    if count > 0 {
+                        log::info!("atlas {}: {} vanilla names", name, count);
+                    }
+                }
+            }
+            check_output_names(&config)?;
+        }
+        Ok(config)
+    }
+
+    /// Finds mods in `mods_dir`, downloads missing sources, verifies
+    /// checksums and opens all the archives, expanding glob patterns in file
+    /// lists against them
+    fn open(&self, toml: &mut Config) -> anyhow::Result<ArchiveSet> {
+        locate_mods(toml, &self.input_dir)?;
+        fetch_sources(&toml.sources, &self.input_dir)?;
+        verify_checksums(&toml.checksums, &self.input_dir, toml.nested_depth)?;
+        let mut zips = ArchiveSet::new(
+            &toml.folders,
+            &toml.priorities,
+            &self.input_dir,
+            toml.nested_depth,
+            toml.exact_paths,
+        )?;
+        expand_files(toml, &mut zips)?;
+        // Patterns could only be checked once expanded
+        check_output_names(toml)?;
+        Ok(zips)
+    }
 
-impl AtlasPos {
-    fn from_pos(x: u8, y: u8) -> Self {
-        Self((y << 4) | x)
+    /// Directory containing the main toml, used as base for default paths
+    fn project_dir(&self) -> &Path {
+        self.toml.parent().unwrap()
     }
 }
 
-impl fmt::Debug for AtlasPos {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let x = self.0 & 0xf;
-        let y = self.0 >> 4;
-        write!(f, "{:x}{:x}", y, x)
+#[derive(StructOpt)]
+struct WriteOpts {
+    /// What to do when an output file already exists with different content:
+    /// error, skip or overwrite
+    #[structopt(long, default_value = "error")]
+    write_policy: WritePolicy,
+    /// Shorthand for `--write-policy overwrite`
+    #[structopt(
+        long,
+        visible_alias = "force",
+        conflicts_with_all =
+            &["skip-existing", "error-on-existing", "write-policy"]
+    )]
+    overwrite: bool,
+    /// Shorthand for `--write-policy skip`, leaving existing files untouched
+    #[structopt(
+        long,
+        conflicts_with_all = &["error-on-existing", "write-policy"]
+    )]
+    skip_existing: bool,
+    /// Shorthand for `--write-policy error`
+    #[structopt(long, conflicts_with = "write-policy")]
+    error_on_existing: bool,
+    /// Recompress written pngs losslessly, using indexed colors where they
+    /// fit, to make outputs smaller
+    #[structopt(long)]
+    optimize: bool,
+    /// Gamma and color profile chunks of png outputs: keep the ones of the
+    /// source, sliced tiles included, or strip them from copies too
+    #[structopt(long, value_name = "policy", default_value = "strip")]
+    color_chunks: ColorChunks,
+    /// Format textures are written in: png, or tga or dds for engines
+    /// other than Minecraft
+    #[structopt(long, value_name = "format", default_value = "png")]
+    output_format: OutputFormat,
+}
+
+impl WriteOpts {
+    fn policy(&self) -> WritePolicy {
+        if self.overwrite {
+            WritePolicy::Overwrite
+        } else if self.skip_existing {
+            WritePolicy::Skip
+        } else if self.error_on_existing {
+            WritePolicy::Error
+        } else {
+            self.write_policy
+        }
+    }
+
+    fn writer(&self) -> Writer {
+        self.configure(Writer::new(self.policy()))
+    }
+
+    fn configure(&self, writer: Writer) -> Writer {
+        writer
+            .with_optimize(self.optimize)
+            .with_color_chunks(self.color_chunks)
+            .with_format(self.output_format)
+    }
+}
+
+#[derive(StructOpt)]
+struct ExtractOpts {
+    #[structopt(flatten)]
+    inputs: Inputs,
+    #[structopt(flatten)]
+    write: WriteOpts,
+    /// Turn lint warnings into errors
+    #[structopt(long)]
+    strict: bool,
+    /// Skip missing assets instead of failing, listing them at the end
+    #[structopt(long)]
+    lenient: bool,
+    /// Only print files that would be created and where they come from
+    #[structopt(long)]
+    dry_run: bool,
+    /// Save every file extraction would write, with where it comes from, as
+    /// a json plan instead, to look over or edit before `--from-plan`
+    #[structopt(long, value_name = "file.json", conflicts_with = "dry-run")]
+    plan: Option<PathBuf>,
+    /// Only write files listed in a plan saved with `--plan`, each to its
+    /// `output`
+    #[structopt(long, value_name = "file.json", conflicts_with = "plan")]
+    from_plan: Option<PathBuf>,
+    /// Root to extract into, instead of the one implied by layout
+    #[structopt(long)]
+    output_dir: Option<PathBuf>,
+    /// Write a resource pack zip instead, using `pack` layout
+    #[structopt(long, value_name = "file.zip", conflicts_with = "output-dir")]
+    export_pack: Option<PathBuf>,
+    /// Where to put outputs: `dir:<path>` like `--output-dir`, or
+    /// `zip:<file>` like `--export-pack`, with `zip:-` streaming the pack to
+    /// stdout, e.g. into `unzip -l` or an upload script
+    #[structopt(
+        long,
+        value_name = "target",
+        conflicts_with_all = &["output-dir", "export-pack"]
+    )]
+    output: Option<OutputTarget>,
+    /// Save non-empty cells without mapping into `unmapped` in output root
+    #[structopt(long)]
+    dump_unmapped: bool,
+    /// `pack_format` written to `pack.mcmeta` for `pack` layout
+    #[structopt(long, default_value = "6")]
+    pack_format: u32,
+    /// Redo every atlas, even if its inputs didn't change since last run
+    #[structopt(long)]
+    no_cache: bool,
+    /// Enlarge every sliced tile and copied texture by this factor
+    #[structopt(long, value_name = "factor")]
+    upscale: Option<u32>,
+    /// Filter used by `--upscale`: nearest or scale2x
+    #[structopt(long, default_value = "nearest")]
+    upscale_filter: Filter,
+    /// Save a json summary of the run: outputs, their sources, skipped and
+    /// unreadable assets and timing
+    #[structopt(long, value_name = "file.json")]
+    report: Option<PathBuf>,
+    /// Write an html page into this directory showing every sliced tile
+    /// blown up, with its name, atlas and position, to check mappings at a
+    /// glance. Every atlas is sliced again for it.
+    #[structopt(long, value_name = "dir", conflicts_with_all = &["dry-run", "plan"])]
+    gallery: Option<PathBuf>,
+    /// Also write where every tile is on its sheet into `mapping` in output
+    /// root, as sprite sheet descriptors other tools and engines read:
+    /// `texturepacker` json or `aseprite` slices
+    #[structopt(long, value_name = "format", conflicts_with = "export-pack")]
+    export_mapping: Option<MappingFormat>,
+    /// Look for tiles identical to an earlier one: `warn` about them, or
+    /// only write the first one with `alias`, listing the rest in
+    /// `aliases.json` next to toml
+    #[structopt(long, value_name = "mode")]
+    dedupe: Option<Dedupe>,
+    /// Hide progress bars, the final summary and anything below warnings
+    #[structopt(short, long)]
+    quiet: bool,
+    /// List files also found in lower-priority sources, which were ignored
+    #[structopt(long)]
+    report_shadowed: bool,
+    /// Write `tex-slasher.json` in output root, recording tool version and
+    /// hashes of config files and sources outputs were made from
+    #[structopt(long)]
+    provenance: bool,
+    /// Also name the source sheet and cell, or file, of every png in a
+    /// `tEXt` chunk
+    #[structopt(long, requires = "provenance")]
+    provenance_chunks: bool,
+    /// Print time spent reading, decoding, cropping and encoding each
+    /// atlas, to tell whether a slow run is bound by IO or by encoding
+    #[structopt(long)]
+    timings: bool,
+    /// Fail on files found in several sources of the same priority
+    #[structopt(long)]
+    error_on_ambiguous: bool,
+    /// Make outputs byte-identical between runs on the same inputs: no
+    /// timestamps in `--export-pack` zips, and a `--report` in path order
+    /// and without timing
+    #[structopt(long)]
+    deterministic: bool,
+    /// Keep running, extracting again whenever toml files, files next to
+    /// them or inputs change
+    #[structopt(long, conflicts_with_all = &["dry-run", "plan"])]
+    watch: bool,
+}
+
+impl ExtractOpts {
+    /// Moves `--output` into the options it stands for
+    fn resolve_output(&mut self) {
+        match self.output.take() {
+            Some(OutputTarget::Dir(dir)) => self.output_dir = Some(dir),
+            Some(OutputTarget::Zip(file)) => self.export_pack = Some(file),
+            None => {}
+        }
+    }
+
+    /// Whether the pack is streamed to stdout, which has to be kept clear
+    /// of anything else
+    fn to_stdout(&self) -> bool {
+        self.export_pack.as_deref() == Some(Path::new(STDOUT))
     }
 }
 
-enum ParseError {
-    NotHexDigits,
-    WrongSize(usize),
+/// Pack path standing for stdout
+const STDOUT: &str = "-";
+
+/// Where `--output` puts the extracted tree
+#[derive(Debug, Clone)]
+enum OutputTarget {
+    /// `dir:<path>`, like `--output-dir`
+    Dir(PathBuf),
+    /// `zip:<file>`, like `--export-pack`, or `zip:-` for stdout
+    Zip(PathBuf),
 }
 
-impl FromStr for AtlasPos {
-    type Err = ParseError;
+impl FromStr for OutputTarget {
+    type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.as_bytes() {
-            [a, b] if a.is_ascii_hexdigit() && b.is_ascii_hexdigit() => {
-                u8::from_str_radix(s, 16)
-                    .map(AtlasPos)
-                    .map_err(|_| ParseError::NotHexDigits)
+        match s.split_once(':') {
+            Some(("dir", path)) if !path.is_empty() => {
+                Ok(Self::Dir(path.into()))
             }
-            [_, _] => Err(ParseError::NotHexDigits),
-            bytes => Err(ParseError::WrongSize(bytes.len())),
+            Some(("zip", file)) if !file.is_empty() => {
+                Ok(Self::Zip(file.into()))
+            }
+            _ => Err(format!(
+                "unknown output {:?}, expected dir:<path> or zip:<file>, \
+                 with zip:- for stdout",
+                s
+            )),
         }
     }
 }
 
-struct AtlasPosVisitor;
-impl<'v> Visitor<'v> for AtlasPosVisitor {
-    type Value = AtlasPos;
+#[derive(StructOpt)]
+#[structopt(after_help = "EXIT CODES:
+    0    success
+    1    other errors, e.g. bad arguments, or some mods of a batch failed
+    2    config doesn't parse or is inconsistent
+    3    validation failed: missing assets, checksum mismatches, or lints
+         with --strict
+    4    reading inputs or writing outputs failed
+    5    extraction finished, but logged warnings")]
+struct Args {
+    /// Print every file written (-v), and archive lookups and image
+    /// decoding (-vv)
+    #[structopt(short, long, parse(from_occurrences), global = true)]
+    verbose: u8,
+    /// Don't color the summary, e.g. in CI logs. It's also plain when
+    /// output isn't a terminal, or `NO_COLOR` is set.
+    #[structopt(long, global = true)]
+    no_color: bool,
+    #[structopt(subcommand)]
+    opt: Opt,
+}
+
+#[derive(StructOpt)]
+enum Opt {
+    /// Copy assets and slice atlases into mod source tree (default)
+    Extract(ExtractOpts),
+    /// Slice a single tile out of a sheet in an archive, without any config
+    ExtractOne {
+        /// Archive, or directory with extracted files
+        archive: PathBuf,
+        /// Path of the sheet inside the archive
+        atlas: String,
+        /// Cell position, as in toml keys, like `0a`
+        pos: AtlasPos,
+        /// Where to write the tile, as png
+        output: PathBuf,
+        #[structopt(long, default_value = "16")]
+        tile_size: u32,
+    },
+    /// Print a starter toml listing assets found in archives
+    Scaffold {
+        /// Path to folder with original mod files
+        input_dir: PathBuf,
+        /// Write toml here instead of printing it. An existing file only
+        /// gets what it's missing, keeping its comments and formatting.
+        #[structopt(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Render enlarged atlas with grid, cell positions and mapped names
+    Preview {
+        #[structopt(flatten)]
+        inputs: Inputs,
+        /// Atlas name, as used in toml headers
+        atlas: String,
+        /// Output image, `<atlas>_preview.png` by default
+        #[structopt(short, long)]
+        output: Option<PathBuf>,
+        /// Enlargement factor, by default cells are made 64px wide
+        #[structopt(long)]
+        scale: Option<u32>,
+    },
+    /// Check config against archives without writing anything
+    Validate {
+        #[structopt(flatten)]
+        inputs: Inputs,
+        /// Turn lint warnings into errors
+        #[structopt(long)]
+        strict: bool,
+    },
+    /// Check config for mistakes that don't stop extraction, without
+    /// reading any images: overlapping tiles, names that aren't valid
+    /// resource locations, unused sources in `folders` and archives missing
+    /// from it. Duplicate positions and output names already fail loading.
+    Lint {
+        #[structopt(flatten)]
+        inputs: Inputs,
+    },
+    /// List entries of archives in `folders` matching a pattern, anywhere in
+    /// them, with the name each is read by if it's in one of the folders
+    Find {
+        #[structopt(flatten)]
+        inputs: Inputs,
+        /// Glob matched against whole entry paths, or only file names if it
+        /// has no `/`, ignoring case
+        pattern: String,
+        /// Take pattern as a regex, matched anywhere in entry paths
+        #[structopt(long)]
+        regex: bool,
+    },
+    /// Rebuild atlases from loose textures in `<dir>/block` and `<dir>/item`,
+    /// writing sheets into `stitched` next to toml
+    Stitch {
+        #[structopt(flatten)]
+        inputs: Inputs,
+        /// Directory with loose textures
+        loose_dir: PathBuf,
+        #[structopt(flatten)]
+        write: WriteOpts,
+        /// Turn lint warnings into errors
+        #[structopt(long)]
+        strict: bool,
+    },
+    /// Compare an atlas between two versions of an archive, listing cells
+    /// that changed
+    Diff {
+        /// Old archive, or directory with extracted files
+        old: PathBuf,
+        /// New archive, or directory with extracted files
+        new: PathBuf,
+        /// Path of the atlas inside both archives
+        atlas: String,
+        #[structopt(long, default_value = "16")]
+        tile_size: u32,
+        /// Also save both sheets side by side, with changed cells framed
+        #[structopt(short, long)]
+        output: Option<PathBuf>,
+        /// Enlargement factor for `--output`, by default cells are made 64px
+        /// wide
+        #[structopt(long)]
+        scale: Option<u32>,
+    },
+    /// Compare extracted files under `assets` with a known-good resource
+    /// pack, listing every file that's missing or differs
+    Verify {
+        /// Output root, pack zip, or any directory or archive with `assets`
+        output: PathBuf,
+        /// Pack to compare against, as zip or directory
+        #[structopt(long)]
+        reference: PathBuf,
+        /// Largest difference in any color channel of a pixel still counted
+        /// as the same, for packs saved with slight color shifts
+        #[structopt(long, default_value = "0")]
+        tolerance: u8,
+        /// Save differences as json
+        #[structopt(long, value_name = "file.json")]
+        report: Option<PathBuf>,
+    },
+    /// Name atlas cells interactively in the terminal, saving them into toml
+    Map {
+        #[structopt(flatten)]
+        inputs: Inputs,
+        /// Atlas name, as used in toml headers
+        atlas: String,
+        /// Add atlas under `items` if it isn't in toml yet, not `blocks`
+        #[structopt(long)]
+        items: bool,
+    },
+    /// Remove files earlier runs wrote into an output root, e.g. ones left
+    /// over after renaming entries in toml
+    Clean {
+        /// Output root, where `.tex-slasher-manifest.json` is
+        output_dir: PathBuf,
+        /// Also remove files changed since they were written
+        #[structopt(long)]
+        force: bool,
+        /// Only list files that would be removed
+        #[structopt(long)]
+        dry_run: bool,
+    },
+    /// Make color variants of a texture by swapping palettes
+    Palette(PaletteOpt),
+    /// Extract every mod of a workspace in parallel: each subdirectory with
+    /// a `config.toml`, taking inputs from its `inputs` directory if there
+    /// is one, or from the subdirectory itself
+    Batch {
+        workspace: PathBuf,
+        /// Extract options for every mod, after `--`
+        #[structopt(last = true)]
+        args: Vec<String>,
+    },
+}
+
+#[derive(StructOpt)]
+enum PaletteOpt {
+    /// Save colors of a texture as a JASC-PAL palette, most used first
+    Extract {
+        /// Texture, e.g. a base tile written by `extract`
+        texture: PathBuf,
+        /// Palette file, `<texture>.pal` by default
+        #[structopt(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Recolor a texture once for every palette in `to`, writing
+    /// `<texture>_<palette>.png` for each
+    Swap {
+        /// Palette with the colors the texture uses
+        from: PathBuf,
+        /// Palettes with replacements, in the same order
+        #[structopt(required = true)]
+        to: Vec<PathBuf>,
+        /// Texture to recolor
+        #[structopt(short, long)]
+        texture: PathBuf,
+        /// Where to write variants, next to the texture by default
+        #[structopt(long)]
+        output_dir: Option<PathBuf>,
+    },
+}
 
-    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        f.write_str("expecting two hex digits")
+const SUBCOMMANDS: &[&str] = &[
+    "extract",
+    "extract-one",
+    "scaffold",
+    "preview",
+    "validate",
+    "lint",
+    "find",
+    "stitch",
+    "diff",
+    "verify",
+    "map",
+    "clean",
+    "palette",
+    "batch",
+    "help",
+];
+
+/// Parses args, treating invocations without a subcommand as `extract`, so
+/// that `tex-slasher <input_dir> <toml>` keeps working
+fn parse_args() -> Args {
+    let mut args: Vec<OsString> = env::args_os().collect();
+    // Global flags can come before the subcommand
+    let is_global = |arg: &OsString| match arg.to_str() {
+        Some("--verbose" | "--no-color") => true,
+        Some(arg) => arg.strip_prefix('-').is_some_and(|flags| {
+            !flags.is_empty() && flags.chars().all(|c| c == 'v')
+        }),
+        None => false,
+    };
+    let at = 1 + args
+        .iter()
+        .skip(1)
+        .take_while(|arg| is_global(arg))
+        .count();
+    if let Some(first) = args.get(at) {
+        let first = first.to_string_lossy();
+        if !first.starts_with('-') && !SUBCOMMANDS.contains(&&*first) {
+            args.insert(at, "extract".into());
+        }
     }
+    Args::from_iter(args)
+}
 
-    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
-    where
-        E: serde::de::Error,
-    {
-        AtlasPos::from_str(v).map_err(|e| match e {
-            ParseError::NotHexDigits => {
-                E::invalid_value(Unexpected::Str(v), &"hex digit")
+/// Warnings logged so far, counted towards run summaries
+static WARNINGS: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether summaries are colored
+static COLOR: AtomicBool = AtomicBool::new(true);
+
+/// Styles `text` as `style` does, unless colors are off
+fn paint(
+    text: impl Into<String>,
+    style: fn(String) -> StyledContent<String>,
+) -> String {
+    let text = text.into();
+    match COLOR.load(Ordering::Relaxed) {
+        true => style(text).to_string(),
+        false => text,
+    }
+}
+
+/// Prints log records of this crate to stderr, hiding progress bars while
+/// doing so
+struct Logger {
+    level: LevelFilter,
+    bars: MultiProgress,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+            && metadata.target().starts_with("tex_slasher")
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = match record.level() {
+            Level::Info => record.args().to_string(),
+            Level::Warn => {
+                WARNINGS.fetch_add(1, Ordering::Relaxed);
+                format!("warning: {}", record.args())
             }
-            ParseError::WrongSize(len) => E::invalid_length(len, &"2"),
-        })
+            level => {
+                format!("{}: {}", level.as_str().to_lowercase(), record.args())
+            }
+        };
+        self.bars.suspend(|| eprintln!("{}", line));
     }
+
+    fn flush(&self) {}
 }
 
-impl<'de> Deserialize<'de> for AtlasPos {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        deserializer.deserialize_str(AtlasPosVisitor)
+fn plural(count: impl Into<u64>, word: &str) -> String {
+    let count = count.into();
+    format!("{} {}{}", count, word, if count == 1 { "" } else { "s" })
+}
+
+/// Files that weren't written, and warnings, if there were any
+fn summary_extras(counts: &Counts, warnings: usize) -> Vec<String> {
+    let mut extras = vec![];
+    if counts.identical > 0 {
+        let unchanged = format!("{} unchanged", counts.identical);
+        extras.push(paint(unchanged, Stylize::dim));
+    }
+    if counts.skipped > 0 {
+        let skipped = format!("{} skipped", counts.skipped);
+        extras.push(paint(skipped, Stylize::yellow));
+    }
+    if warnings > 0 {
+        extras.push(paint(plural(warnings as u64, "warning"), Stylize::yellow));
+    }
+    extras
+}
+
+/// Prints time spent on each sliced atlas by stage, slowest first, then
+/// totals of every stage
+fn print_timings(timings: &Timings, out: &mut dyn io::Write) -> io::Result<()> {
+    let mut atlases: Vec<_> = timings.atlases().into_iter().collect();
+    atlases.sort_by_key(|(_, times)| std::cmp::Reverse(times.total()));
+    let width = atlases.iter().map(|(name, _)| name.len()).max();
+    let width = width.unwrap_or(0).max("atlas".len());
+    writeln!(
+        out,
+        "{}",
+        paint(
+            format!(
+                "{:<width$} {:>10} {:>10} {:>10} {:>10}",
+                "atlas",
+                "read",
+                "decode",
+                "crop",
+                "encode",
+                width = width
+            ),
+            Stylize::bold
+        )
+    )?;
+    let row = |out: &mut dyn io::Write, name: &str, times: &StageTimes| {
+        writeln!(
+            out,
+            "{:<width$} {:>10.2?} {:>10.2?} {:>10.2?} {:>10.2?}",
+            name,
+            times.read,
+            times.decode,
+            times.crop,
+            times.encode,
+            width = width
+        )
+    };
+    let mut total = StageTimes::default();
+    for (name, times) in &atlases {
+        row(out, name, times)?;
+        total.read += times.read;
+        total.decode += times.decode;
+        total.crop += times.crop;
+        total.encode += times.encode;
+    }
+    row(out, "total", &total)?;
+    writeln!(out, "(summed over threads, cached atlases aren't listed)")
+}
+
+/// Prints what every section wrote, then totals of the whole run
+fn print_summary(summary: &Summary, out: &mut dyn io::Write) -> io::Result<()> {
+    for section in &summary.sections {
+        let verb = if section.sliced { "sliced" } else { "copied" };
+        let written = format!("{} {}", section.counts.written, verb);
+        let mut parts = vec![paint(written, Stylize::green)];
+        parts.extend(summary_extras(&section.counts, section.warnings));
+        writeln!(
+            out,
+            "{}: {} in {:.2?}",
+            paint(format!("{:>8}", section.name), Stylize::cyan),
+            parts.join(", "),
+            section.elapsed
+        )?;
+    }
+    let (sliced, copied) = summary.totals();
+    let skipped = sliced.skipped + copied.skipped;
+    let totals = format!(
+        "{} sliced, {} copied, {} skipped, {}",
+        plural(sliced.written, "tile"),
+        plural(copied.written, "file"),
+        skipped,
+        plural(summary.warnings as u64, "warning")
+    );
+    let totals = match summary.warnings > 0 || skipped > 0 {
+        true => paint(totals, |s| s.bold().yellow()),
+        false => paint(totals, |s| s.bold().green()),
+    };
+    let identical = sliced.identical + copied.identical;
+    let unchanged = match identical {
+        0 => String::new(),
+        _ => paint(format!(" ({} unchanged)", identical), Stylize::dim),
+    };
+    writeln!(
+        out,
+        "{}: {}{} in {:.2?}",
+        paint(format!("{:>8}", "total"), Stylize::bold),
+        totals,
+        unchanged,
+        summary.elapsed
+    )
+}
+
+fn print_missing(missing: &[MissingAsset]) -> anyhow::Result<()> {
+    if !missing.is_empty() {
+        eprintln!("missing assets:");
+        for asset in missing {
+            eprintln!("  {}", asset);
+        }
+        return Err(Failure::Validation.tag(anyhow::anyhow!(
+            "{} referenced assets not found",
+            missing.len()
+        )));
+    }
+    Ok(())
+}
+
+fn print_planned(file: &PlannedFile) {
+    let output = file.output.display();
+    match &file.source {
+        Some(source) => println!("{} <- {}", output, source),
+        None => println!("{} <- (missing)", output),
+    }
+}
+
+/// Writes `--report` json for a finished run
+fn write_report(
+    path: &Path,
+    writer: &Writer,
+    missing: &[MissingAsset],
+    renames: &[Rename],
+    zips: &ArchiveSet,
+    elapsed: Option<Duration>,
+) -> anyhow::Result<()> {
+    let records = writer.records();
+    let fallbacks: Vec<_> = records
+        .iter()
+        .filter(|record| {
+            let source = record.source.as_deref().unwrap_or_default();
+            source.starts_with(FALLBACK)
+        })
+        .collect();
+    let mut report = serde_json::json!({
+        "outputs": records,
+        "fallbacks": fallbacks,
+        "missing": missing,
+        "renames": renames,
+        "layers": zips.layers(),
+        "shadowed": zips.shadowed(),
+        "unreadable": zips.unreadable(),
+    });
+    if let Some(elapsed) = elapsed {
+        report["elapsed_secs"] = elapsed.as_secs_f64().into();
     }
+    fs::write(path, serde_json::to_string_pretty(&report)?)?;
+    Ok(())
 }
 
-type AtlasMap = BTreeMap<String, Atlas>;
-type Atlas = BTreeMap<AtlasPos, String>;
-type Folders = BTreeMap<String, Vec<String>>;
+fn stitch(
+    inputs: &Inputs,
+    loose_dir: &Path,
+    write: &WriteOpts,
+    strict: bool,
+) -> anyhow::Result<()> {
+    let mut toml = inputs.load()?;
+    let mut zips = inputs.open(&mut toml)?;
+    let writer = write.writer();
+    let options = SliceOptions {
+        tile_size: toml.tile_size,
+        strict,
+        palettes: toml.palettes.clone(),
+        min_tint_delta_e: toml.min_tint_delta_e,
+        upscale: None,
+        unmapped_dir: None,
+        dedupe: None,
+        hooks: None,
+    };
+    let stitched = inputs.project_dir().join("stitched");
+    stitch_atlas_map(
+        &toml.main.items,
+        &mut zips,
+        &loose_dir.join("item"),
+        &stitched,
+        &writer,
+        &options,
+    )?;
+    stitch_atlas_map(
+        &toml.main.blocks,
+        &mut zips,
+        &loose_dir.join("block"),
+        &stitched,
+        &writer,
+        &options,
+    )?;
+    println!("{}", writer);
+    writer.finish()?;
+    log::info!("done");
+    Ok(())
+}
 
-#[derive(Debug, Deserialize)]
-struct Toml {
-    modid: String,
-    banner: String,
-    models: Vec<String>,
-    gui: Vec<String>,
-    blocks_copy: Vec<String>,
-    imgs: Vec<String>,
-    bin: String,
-    folders: Folders,
-    blocks: AtlasMap,
-    items: AtlasMap,
+/// Layout of a sheet, mapped in `map` or not
+fn sheet_info(
+    map: Option<&Atlas>,
+    image: &RgbaImage,
+    tile_size: u32,
+) -> AtlasInfo {
+    let (width, height) = image.dimensions();
+    match map {
+        Some(map) => AtlasInfo::of(map, width, height, tile_size),
+        None => AtlasInfo::new(width, height, tile_size),
+    }
 }
 
-fn process_atlas<R: BufRead + Seek>(
-    atlas: &Atlas,
-    input: R,
-    output_dir: &Path,
+fn preview(
+    inputs: &Inputs,
+    atlas: &str,
+    output: Option<&Path>,
+    scale: Option<u32>,
 ) -> anyhow::Result<()> {
-    let image = image::load(input, ImageFormat::Png)?.to_rgba8();
-    for y in 0..16 {
-        for x in 0..16 {
-            let slice = AtlasPos::from_pos(x as u8, y as u8);
-            if let Some(output) = atlas.get(&slice) {
-                let output = output_dir.join(output).with_extension("png");
-                image
-                    .view(x * 16, y * 16, 16, 16)
-                    .to_image()
-                    .save_with_format(output, ImageFormat::Png)?;
+    let mut toml = inputs.load()?;
+    let zips = inputs.open(&mut toml)?;
+    let sheet = zips.sheet(atlas);
+    let image = zips.image(&sheet)?;
+    let map = toml.find_atlas(atlas).map(|(_, map)| map);
+    let info = sheet_info(map, &image, toml.tile_size);
+    let scale = scale.unwrap_or_else(|| (64 / info.tile_size).max(1));
+    let preview = render_preview(&image, map, info, scale);
+    let output = match output {
+        Some(output) => output.to_owned(),
+        None => {
+            PathBuf::from(format!("{}_preview.png", atlas.replace('/', "_")))
+        }
+    };
+    preview.save(&output)?;
+    println!("{}", output.display());
+    Ok(())
+}
+
+fn read_palette(path: &Path) -> anyhow::Result<Palette> {
+    Palette::parse(&fs::read_to_string(path)?)
+        .with_context(|| format!("reading palette {}", path.display()))
+}
+
+fn palette(opt: PaletteOpt) -> anyhow::Result<()> {
+    match opt {
+        PaletteOpt::Extract { texture, output } => {
+            let palette = Palette::extract(&image::open(&texture)?.to_rgba8());
+            let output =
+                output.unwrap_or_else(|| texture.with_extension("pal"));
+            fs::write(&output, palette.to_pal())?;
+            println!("{}: {} colors", output.display(), palette.0.len());
+        }
+        PaletteOpt::Swap {
+            from,
+            to,
+            texture,
+            output_dir,
+        } => {
+            let from = read_palette(&from)?;
+            let image = image::open(&texture)?.to_rgba8();
+            let dir = match output_dir {
+                Some(dir) => dir,
+                None => texture.parent().unwrap_or(Path::new("")).to_owned(),
+            };
+            let stem =
+                texture.file_stem().unwrap_or_default().to_string_lossy();
+            for path in to {
+                let name =
+                    path.file_stem().unwrap_or_default().to_string_lossy();
+                let recolored =
+                    swap_palette(&image, &from, &read_palette(&path)?)
+                        .with_context(|| {
+                            format!("swapping to {}", path.display())
+                        })?;
+                let output = dir.join(format!("{}_{}.png", stem, name));
+                recolored.save(&output)?;
+                println!("{}", output.display());
             }
         }
     }
     Ok(())
 }
-fn process_atlas_map(
-    atlas: &AtlasMap,
-    zips: &mut Zips,
-    output_dir: &Path,
+
+/// Opens an archive, or a directory with extracted files, given by path
+fn open_path(archive: &Path) -> anyhow::Result<Box<dyn AssetSource>> {
+    let dir = archive.parent().unwrap_or_else(|| Path::new(""));
+    let name = archive
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("{}: not a file", archive.display()))?;
+    open_source(dir, &name.to_string_lossy(), 1)
+}
+
+/// Decodes `atlas` out of an archive given by path
+fn read_sheet(archive: &Path, atlas: &str) -> anyhow::Result<RgbaImage> {
+    let mut source = open_path(archive)?;
+    let data = source.read(atlas)?.ok_or_else(|| {
+        anyhow::anyhow!("{} not found in {}", atlas, archive.display())
+    })?;
+    Ok(decode_image(&data, atlas)?.into_rgba8())
+}
+
+/// Compares `output` with `reference`, failing on files missing from or
+/// differing in `output`. Extra files are only listed.
+fn verify(
+    output: &Path,
+    reference: &Path,
+    tolerance: u8,
+    report: Option<&Path>,
+) -> anyhow::Result<()> {
+    let differences = verify_pack(
+        &mut *open_path(output)?,
+        &mut *open_path(reference)?,
+        tolerance,
+    )?;
+    if let Some(path) = report {
+        fs::write(path, serde_json::to_string_pretty(&differences)?)?;
+    }
+    for difference in &differences {
+        println!("{}: {}", difference.path, difference.mismatch);
+    }
+    let count = differences.iter().filter(|d| d.is_regression()).count();
+    if count > 0 {
+        return Err(Failure::Validation.tag(anyhow::anyhow!(
+            "{} file(s) missing or different from {}",
+            count,
+            reference.display()
+        )));
+    }
+    println!("ok");
+    Ok(())
+}
+
+/// Slices the tile at `pos` of `atlas` into `output`, through the same
+/// path as a config mapping just that cell would
+fn extract_one(
+    archive: &Path,
+    atlas: &str,
+    pos: AtlasPos,
+    output: &Path,
+    tile_size: u32,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(tile_size > 0, "tile size can't be 0");
+    anyhow::ensure!(
+        output.extension().is_some_and(|ext| ext == "png"),
+        "{}: tiles are written as png",
+        output.display()
+    );
+    let sheet = read_sheet(archive, atlas)?;
+    let name = output.file_stem().unwrap().to_string_lossy().into_owned();
+    let map = Atlas {
+        kind: AtlasKind::Grid,
+        animation: None,
+        origin: Origin::TopLeft,
+        order: Order::RowMajor,
+        grayscale: None,
+        tile_size: None,
+        margin: 0,
+        spacing: 0,
+        padding: 0,
+        colorkey: None,
+        fix_semitransparent_edges: false,
+        trim: false,
+        tiles: BTreeMap::from([(pos, Tile::named(name))]),
+    };
+    let options = SliceOptions {
+        tile_size,
+        strict: false,
+        palettes: Palettes::new(),
+        min_tint_delta_e: 0.0,
+        upscale: None,
+        unmapped_dir: None,
+        dedupe: None,
+        hooks: None,
+    };
+    let dir = TileDirs::new(output.parent().unwrap_or_else(|| Path::new("")));
+    let writer = Writer::new(WritePolicy::Overwrite);
+    let aliases = Aliases::new();
+    slice_atlas(atlas, &map, &sheet, &dir, &writer, &options, &aliases)?;
+    writer.finish()?;
+    println!("{}", output.display());
+    Ok(())
+}
+
+fn diff(
+    old: &Path,
+    new: &Path,
+    atlas: &str,
+    tile_size: u32,
+    output: Option<&Path>,
+    scale: Option<u32>,
 ) -> anyhow::Result<()> {
-    for (atlas, map) in atlas {
-        let path = Path::new(atlas).with_extension("png");
-        let name = path.to_str().unwrap();
-        let mut image = zips.find(name).unwrap();
-        let mut data = Vec::with_capacity(image.size() as usize);
-        image.read_to_end(&mut data)?;
-        process_atlas(map, Cursor::new(data), output_dir)?;
+    anyhow::ensure!(tile_size > 0, "tile size can't be 0");
+    let old = read_sheet(old, atlas)?;
+    let new = read_sheet(new, atlas)?;
+    if old.dimensions() != new.dimensions() {
+        log::warn!(
+            "size changed from {}x{} to {}x{}",
+            old.width(),
+            old.height(),
+            new.width(),
+            new.height()
+        );
+    }
+    let diff = diff_atlas(&old, &new, tile_size);
+    for (label, cells) in [
+        ("changed", &diff.changed),
+        ("added", &diff.added),
+        ("removed", &diff.removed),
+    ] {
+        if !cells.is_empty() {
+            println!("{}: {:?}", label, cells);
+        }
+    }
+    if diff.is_empty() {
+        println!("no changes");
+    }
+    if let Some(output) = output {
+        let scale = scale.unwrap_or_else(|| (64 / tile_size).max(1));
+        render_diff(&old, &new, &diff, tile_size, scale).save(output)?;
+        println!("{}", output.display());
     }
     Ok(())
 }
 
-struct Zips<'a>(Vec<(ZipArchive<File>, &'a [String])>);
+fn map(inputs: &Inputs, atlas: &str, items: bool) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        inputs.toml.is_file()
+            && inputs.toml.extension().is_some_and(|ext| ext == "toml"),
+        "map saves into a single toml file, {} isn't one",
+        inputs.toml.display()
+    );
+    let mut toml = inputs.load()?;
+    let zips = inputs.open(&mut toml)?;
+    let sheet = zips.sheet(atlas);
+    let image = zips.image(&sheet)?;
+    let section = if toml.main.items.contains_key(atlas) {
+        "items"
+    } else if toml.main.blocks.contains_key(atlas) || !items {
+        "blocks"
+    } else {
+        "items"
+    };
+    let map = match section {
+        "items" => toml.main.items.get(atlas),
+        _ => toml.main.blocks.get(atlas),
+    };
+    let info = sheet_info(map, &image, toml.tile_size);
+    let names = map
+        .map(|map| {
+            map.tiles
+                .iter()
+                .map(|(pos, tile)| (*pos, tile.name.clone()))
+                .collect()
+        })
+        .unwrap_or_default();
+    map_cells(atlas, &image, info, names, |edits| {
+        apply_edits(&inputs.toml, section, atlas, edits)
+    })
+}
 
-// Yes, this is dumb, I don't care
-// This works around bug in current (NLL, pre-polonius) borrowck
-unsafe fn cheat_lifetime<'a, 'b>(t: ZipFile<'a>) -> ZipFile<'b> {
-    std::mem::transmute(t)
+fn validate(inputs: &Inputs, strict: bool) -> anyhow::Result<()> {
+    let mut toml = inputs.load()?;
+    let mut zips = inputs.open(&mut toml)?;
+    print_missing(&find_missing(&toml, &mut zips))?;
+    let options = SliceOptions {
+        tile_size: toml.tile_size,
+        strict,
+        palettes: toml.palettes.clone(),
+        min_tint_delta_e: toml.min_tint_delta_e,
+        upscale: None,
+        unmapped_dir: None,
+        dedupe: None,
+        // Compiled only to check the script and the hooks tiles name
+        hooks: toml.script.as_deref().map(Hooks::load).transpose()?,
+    };
+    check_hooks(&toml, options.hooks.as_ref())?;
+    for ns in toml.all_namespaces() {
+        check_copied_textures(&toml, ns, &mut zips, strict)?;
+        validate_atlas_map(&ns.items, &mut zips, &options)?;
+        validate_atlas_map(&ns.blocks, &mut zips, &options)?;
+    }
+    println!("ok");
+    Ok(())
 }
 
-impl<'a> Zips<'a> {
-    fn new(folders: &'a Folders, input_dir: &Path) -> anyhow::Result<Self> {
-        let zips = folders
-            .iter()
-            .map(|(file, paths)| -> anyhow::Result<_> {
-                Ok((
-                    zip::ZipArchive::new(File::open(input_dir.join(file))?)?,
-                    &paths[..],
-                ))
-            })
-            .collect::<anyhow::Result<Vec<_>>>()?;
-        Ok(Self(zips))
-    }
-
-    fn find(&mut self, file: &str) -> Option<ZipFile> {
-        for (zip, paths) in self.0.iter_mut() {
-            for path in paths.iter() {
-                if let Ok(file) = zip.by_name(&format!("{}/{}", path, file)) {
-                    return Some(unsafe { cheat_lifetime(file) });
-                }
+fn lint(inputs: &Inputs) -> anyhow::Result<()> {
+    let mut toml = inputs.load()?;
+    let mut zips = inputs.open(&mut toml)?;
+    let findings = lint_config(&toml, &mut zips, &inputs.input_dir)?;
+    for finding in &findings {
+        eprintln!("{}", finding);
+    }
+    if !findings.is_empty() {
+        return Err(Failure::Validation
+            .tag(anyhow::anyhow!("{} lint findings", findings.len())));
+    }
+    println!("ok");
+    Ok(())
+}
+
+/// Prints entries matching `pattern` as `archive:entry`, followed by the
+/// name they're read by if they're in a folder
+fn find(inputs: &Inputs, pattern: &str, regex: bool) -> anyhow::Result<()> {
+    let mut toml = inputs.load()?;
+    locate_mods(&mut toml, &inputs.input_dir)?;
+    fetch_sources(&toml.sources, &inputs.input_dir)?;
+    let mut zips = ArchiveSet::new(
+        &toml.folders,
+        &toml.priorities,
+        &inputs.input_dir,
+        toml.nested_depth,
+        toml.exact_paths,
+    )?;
+    let found = if regex {
+        let regex = Regex::new(pattern)?;
+        zips.search(|entry| regex.is_match(entry))?
+    } else {
+        let glob = Pattern::new(pattern)?;
+        let options = MatchOptions {
+            case_sensitive: false,
+            ..MatchOptions::new()
+        };
+        let whole = pattern.contains('/');
+        zips.search(|entry| {
+            let file = entry.rsplit(['/', '\\']).next().unwrap();
+            glob.matches_with(if whole { entry } else { file }, options)
+        })?
+    };
+    for found in &found {
+        match &found.name {
+            Some(name) => {
+                println!("{}:{} ({})", found.archive, found.entry, name)
             }
+            None => println!("{}:{}", found.archive, found.entry),
+        }
+    }
+    if found.is_empty() {
+        eprintln!("nothing matches {}", pattern);
+    }
+    Ok(())
+}
+
+/// Layout and root to extract into, as set by config and command line
+fn output_root(opts: &ExtractOpts, toml: &Config) -> (Layout, PathBuf) {
+    match (&opts.export_pack, &opts.output_dir) {
+        (Some(_), _) => {
+            let layout = Layout {
+                preset: Preset::Pack,
+                ..toml.layout.clone()
+            };
+            (layout, PathBuf::new())
+        }
+        (None, Some(dir)) => (toml.layout.clone(), dir.clone()),
+        (None, None) => {
+            let root = toml.layout.default_root(opts.inputs.project_dir());
+            (toml.layout.clone(), root)
         }
-        None
     }
 }
 
-fn main() -> anyhow::Result<()> {
-    let opt = Opt::from_args();
-    let toml = fs::read_to_string(&opt.toml)?;
-    let toml: Toml = toml::from_str(&toml)?;
-    let mut zips = Zips::new(&toml.folders, &opt.input_dir)?;
-    let res = opt
-        .toml
-        .parent()
-        .unwrap()
-        .join("src")
-        .join("main")
-        .join("resources");
-    let namespace = res.join("assets").join(toml.modid);
-    let textures = namespace.join("textures");
-    let models_dir = namespace.join("models").join("block");
-    let guis_dir = textures.join("gui");
-    let blocks_dir = textures.join("block");
-    let items_dir = textures.join("item");
+/// Extracts again after every change to config or inputs. Failed runs are
+/// only logged, so fixing the config picks up from there.
+fn watch(opts: &ExtractOpts, bars: &MultiProgress) -> anyhow::Result<()> {
+    let inputs = &opts.inputs;
+    let stdin = Path::new(STDIN);
+    anyhow::ensure!(
+        inputs.toml != stdin && !inputs.configs.iter().any(|c| c == stdin),
+        "can't watch config read from stdin"
+    );
+    let mut paths = vec![inputs.toml.clone(), inputs.input_dir.clone()];
+    paths.extend(inputs.configs.iter().cloned());
+    let mut watch = Watch::new(&paths)?;
+    loop {
+        // Outputs of the run itself don't count as changes
+        let mut ignored: Vec<_> = opts
+            .export_pack
+            .iter()
+            .chain(&opts.report)
+            .cloned()
+            .collect();
+        ignored.push(inputs.project_dir().join("aliases.json"));
+        if let Ok(toml) = inputs.load() {
+            ignored.push(output_root(opts, &toml).1);
+        }
+        watch.ignore(&ignored)?;
+        if let Err(e) = extract(opts, bars) {
+            log::error!("{:#}", e);
+        }
+        log::info!("watching for changes, press ctrl-c to stop");
+        let changed = watch.wait()?;
+        match &changed[..] {
+            [path] => log::info!("{} changed", path.display()),
+            _ => log::info!("{} files changed", changed.len()),
+        }
+    }
+}
+
+/// Runs extraction, returning counts of written files
+fn extract(opts: &ExtractOpts, bars: &MultiProgress) -> anyhow::Result<String> {
+    let start = Instant::now();
+    let progress =
+        Progress::new(opts.quiet, bars.clone()).with_warnings(&WARNINGS);
+    let inputs = &opts.inputs;
+    let mut toml = inputs.load()?;
+    let mut zips = inputs.open(&mut toml)?;
+    if opts.report_shadowed || opts.error_on_ambiguous || opts.report.is_some()
+    {
+        zips.track_shadowed(opts.error_on_ambiguous);
+    }
+    if opts.timings {
+        zips.track_load_times();
+    }
+    let (layout, root) = output_root(opts, &toml);
+    let banner = toml.banner_output();
+    let dirs = |ns: &Namespace| {
+        OutputDirs::new(&layout, root.clone(), &ns.modid, banner.as_deref())
+    };
+    let main_dirs = dirs(&toml.main);
+    let options = SliceOptions {
+        tile_size: toml.tile_size,
+        strict: opts.strict,
+        palettes: toml.palettes.clone(),
+        min_tint_delta_e: toml.min_tint_delta_e,
+        upscale: opts
+            .upscale
+            .map(|factor| Upscale::new(factor, opts.upscale_filter))
+            .transpose()?,
+        unmapped_dir: opts.dump_unmapped.then(|| root.join("unmapped")),
+        dedupe: opts.dedupe,
+        hooks: toml.script.as_deref().map(Hooks::load).transpose()?,
+    };
+    check_hooks(&toml, options.hooks.as_ref())?;
+
+    if opts.dry_run || opts.plan.is_some() {
+        let format = opts.write.output_format;
+        let plan = plan_extraction(&toml, &layout, &root, &mut zips, format);
+        if let Some(path) = &opts.plan {
+            plan.save(path)?;
+            log::info!(
+                "{} files planned in {}",
+                plan.files.len(),
+                path.display()
+            );
+            return Ok("planned".to_owned());
+        }
+        plan.files.iter().for_each(print_planned);
+        return Ok("dry run".to_owned());
+    }
+
+    let missing = find_missing(&toml, &mut zips);
+    if opts.lenient {
+        for asset in &missing {
+            log::warn!("skipping missing {}", asset);
+        }
+        remove_missing(&mut toml, &missing);
+    } else {
+        print_missing(&missing)?;
+    }
+
+    let writer = match &opts.export_pack {
+        Some(_) if opts.to_stdout() => opts
+            .write
+            .configure(Writer::zip_stdout(opts.write.policy())),
+        Some(path) => opts
+            .write
+            .configure(Writer::zip(opts.write.policy(), path.clone())),
+        None => opts.write.writer(),
+    };
+    let writer = writer
+        .with_deterministic(opts.deterministic)
+        .with_progress(progress)
+        .with_timings(opts.timings)
+        .with_provenance(opts.provenance_chunks);
+    let writer = match &opts.from_plan {
+        Some(path) => writer.with_plan(&Plan::load(path)?),
+        None => writer,
+    };
+    let writer = match &opts.gallery {
+        Some(_) => writer.with_kept_images(),
+        None => writer,
+    };
+    let progress = writer.progress();
+    for ns in toml.all_namespaces() {
+        dirs(ns).create_all(&toml, ns, &writer)?;
+    }
+
+    let files = banner_outputs(&toml, &main_dirs).len();
+    let files = files + (layout.preset == Preset::Pack) as usize;
+    progress.section("banner", files as u64);
+    if layout.preset == Preset::Pack {
+        let mcmeta = pack_mcmeta(opts.pack_format, &toml.main.modid);
+        let path = root.join("pack.mcmeta");
+        writer.note_source(&path, GENERATED);
+        writer.write(&path, mcmeta.as_bytes())?;
+    }
+
+    // Cleared when missing in lenient mode
+    write_banner(&toml, &mut zips, &writer, &main_dirs)?;
 
-    std::fs::create_dir_all(&models_dir).unwrap();
-    std::fs::create_dir_all(&guis_dir).unwrap();
-    std::fs::create_dir_all(&blocks_dir).unwrap();
-    std::fs::create_dir_all(&items_dir).unwrap();
+    // Hashes only make sense for files on disk, pack is always rebuilt, a
+    // plan can leave out or move any output, and a gallery needs every tile
+    // sliced
+    let mut cache = match (&opts.export_pack, &opts.from_plan, &opts.gallery) {
+        (Some(_), ..) | (_, Some(_), _) | (.., Some(_)) => None,
+        (None, None, None) => Some(Cache::new(
+            root.join(CACHE_FILE),
+            &toml.folders,
+            &inputs.input_dir,
+            opts.no_cache,
+        )?),
+    };
+    let mut aliases = serde_json::Value::Null;
+    for (i, ns) in toml.all_namespaces().enumerate() {
+        let (blocks, items) = extract_namespace(
+            &toml,
+            ns,
+            &dirs(ns),
+            &mut zips,
+            &writer,
+            &options,
+            cache.as_mut(),
+        )?;
+        let json = serde_json::json!({ "blocks": blocks, "items": items });
+        // Other namespaces are listed under their mod id
+        if i == 0 {
+            aliases = json;
+        } else {
+            aliases[&ns.modid] = json;
+        }
+    }
 
-    let mut banner = zips.find(&toml.banner).unwrap();
-    let mut banner_file = File::create(res.join(toml.banner))?;
-    std::io::copy(&mut banner, &mut banner_file)?;
-    drop(banner);
+    let all_dirs: Vec<_> = toml.all_namespaces().map(dirs).collect();
+    check_texture_usage(&all_dirs, &writer, opts.strict)?;
 
-    for model in toml.models {
-        let mut model_file = zips.find(&model).unwrap();
-        let mut file = File::create(models_dir.join(model))?;
-        std::io::copy(&mut model_file, &mut file)?;
+    if opts.provenance {
+        let path = root.join(PROVENANCE_FILE);
+        writer.note_source(&path, GENERATED);
+        let json = provenance(&toml, &inputs.input_dir)?;
+        writer.write(&path, json.as_bytes())?;
     }
 
-    for gui in toml.gui {
-        let mut image = zips.find(&gui).unwrap();
-        let mut file = File::create(guis_dir.join(gui))?;
-        std::io::copy(&mut image, &mut file)?;
+    if let Some(format) = opts.export_mapping {
+        let dir = root.join("mapping");
+        let count = export_mapping(&toml, &mut zips, &dir, &writer, format)?;
+        log::info!("{} atlas mappings in {}", count, dir.display());
     }
 
-    for block in toml.blocks_copy {
-        let mut image = zips.find(&block).unwrap();
-        let mut file = File::create(blocks_dir.join(block))?;
-        std::io::copy(&mut image, &mut file)?;
+    // Only now is anything changed, after every output was made
+    writer.finish()?;
+    // Kept out of staging, as it sits next to the toml instead of in the
+    // output tree, so only written once outputs were applied
+    if opts.dedupe == Some(Dedupe::Alias) {
+        let path = inputs.project_dir().join("aliases.json");
+        fs::write(&path, serde_json::to_string_pretty(&aliases)?)?;
+        log::info!("aliases listed in {}", path.display());
+    }
+    if let Some(dir) = &opts.gallery {
+        let count = write_gallery(dir, &toml, &all_dirs, &writer)?;
+        log::info!("gallery of {} tiles in {}", count, dir.display());
     }
+    if opts.export_pack.is_none() {
+        let mut manifest = Manifest::load(&root)?;
+        manifest.record(&writer, &root)?;
+        manifest.save(&root)?;
+    }
+    // Stdout is taken by the pack
+    let mut out: Box<dyn io::Write> = match opts.to_stdout() {
+        true => Box::new(io::stderr()),
+        false => Box::new(io::stdout()),
+    };
+    if let Some(summary) = writer.progress().finish() {
+        print_summary(&summary, &mut out)?;
+    }
+    if let Some(timings) = writer.timings() {
+        print_timings(timings, &mut out)?;
+    }
+    if let Some(path) = &opts.report {
+        let elapsed = (!opts.deterministic).then(|| start.elapsed());
+        let renames = &toml.renames;
+        write_report(path, &writer, &missing, renames, &zips, elapsed)?;
+    }
+    if !missing.is_empty() {
+        eprintln!("skipped {} missing assets:", missing.len());
+        for asset in &missing {
+            eprintln!("  {}", asset);
+        }
+    }
+    if opts.report_shadowed && !zips.shadowed().is_empty() {
+        eprintln!("files found in more than one source:");
+        for (file, shadowed) in zips.shadowed() {
+            eprintln!("  {} <- {}", file, shadowed.source);
+            for other in &shadowed.shadowed {
+                eprintln!("    shadows {}", other);
+            }
+        }
+    }
+    if let Some(cache) = cache {
+        cache.save()?;
+    }
+    log::info!("done");
+    Ok(writer.to_string())
+}
 
-    process_atlas_map(&toml.items, &mut zips, &items_dir)?;
-    process_atlas_map(&toml.blocks, &mut zips, &blocks_dir)?;
+/// Config of a mod in a batch workspace, `<mod>/config.<ext>`
+fn batch_config(dir: &Path) -> Option<PathBuf> {
+    CONFIG_EXTENSIONS
+        .iter()
+        .map(|ext| dir.join("config").with_extension(ext))
+        .find(|path| path.is_file())
+}
 
-    println!("done");
+/// Extracts every mod of `workspace` in parallel, with `args` passed to
+/// each run, then lists how each went
+fn batch(
+    workspace: &Path,
+    args: &[String],
+    bars: &MultiProgress,
+) -> anyhow::Result<()> {
+    let mut mods = vec![];
+    for entry in fs::read_dir(workspace)? {
+        let dir = entry?.path();
+        if let Some(config) = batch_config(&dir) {
+            mods.push((dir, config));
+        }
+    }
+    mods.sort();
+    anyhow::ensure!(
+        !mods.is_empty(),
+        "no `<mod>/config.toml` found in {}",
+        workspace.display()
+    );
+    let runs: Vec<_> = mods
+        .iter()
+        .map(|(dir, config)| {
+            // Inputs can be kept apart from the rest of the mod's files
+            let inputs = dir.join("inputs");
+            let input_dir = if inputs.is_dir() { &inputs } else { dir };
+            let mut run = vec![
+                OsString::from("extract"),
+                input_dir.into(),
+                config.into(),
+                "--quiet".into(),
+            ];
+            run.extend(args.iter().map(OsString::from));
+            let mut opts = ExtractOpts::from_iter_safe(run)?;
+            opts.resolve_output();
+            anyhow::ensure!(
+                opts.output_dir.is_none()
+                    && opts.export_pack.is_none()
+                    && opts.report.is_none()
+                    && !opts.watch,
+                "--output, --output-dir, --export-pack, --report and --watch \
+                 can't be shared by every mod of a batch"
+            );
+            Ok(opts)
+        })
+        .collect::<anyhow::Result<_>>()?;
+    let results: Vec<_> =
+        runs.par_iter().map(|opts| extract(opts, bars)).collect();
+    let mut failed = 0;
+    for ((dir, _), result) in mods.iter().zip(results) {
+        let name = dir.file_name().unwrap_or_default().to_string_lossy();
+        match result {
+            Ok(summary) => println!("{}: {}", name, summary),
+            Err(e) => {
+                failed += 1;
+                println!("{}: failed: {:#}", name, e);
+            }
+        }
+    }
+    anyhow::ensure!(failed == 0, "{} of {} mods failed", failed, mods.len());
     Ok(())
 }
+
+/// Exit code of extractions that succeeded, but logged warnings
+const PARTIAL: i32 = 5;
+
+fn main() {
+    let args = parse_args();
+    // Only ones writing outputs can be partial, `validate --strict` is the
+    // way to turn warnings into a failure
+    let extracts = matches!(args.opt, Opt::Extract(_) | Opt::Batch { .. });
+    let code = match run(args) {
+        Ok(()) if extracts && WARNINGS.load(Ordering::Relaxed) > 0 => PARTIAL,
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            Failure::of(&e).map_or(1, Failure::exit_code)
+        }
+    };
+    process::exit(code);
+}
+
+fn run(mut args: Args) -> anyhow::Result<()> {
+    if let Opt::Extract(opts) = &mut args.opt {
+        opts.resolve_output();
+        anyhow::ensure!(
+            !(opts.to_stdout() && opts.watch),
+            "can't watch while streaming the pack to stdout"
+        );
+    }
+    // Logs of mods in a batch would interleave, it prints its own summary
+    let quiet = match &args.opt {
+        Opt::Extract(opts) => opts.quiet,
+        Opt::Batch { .. } => true,
+        _ => false,
+    };
+    let level = match args.verbose {
+        _ if quiet => LevelFilter::Warn,
+        0 => LevelFilter::Info,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    let no_color = env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    if args.no_color || no_color || !io::stdout().is_terminal() {
+        COLOR.store(false, Ordering::Relaxed);
+    }
+    let bars = MultiProgress::new();
+    let logger = Logger {
+        level,
+        bars: bars.clone(),
+    };
+    log::set_boxed_logger(Box::new(logger))?;
+    log::set_max_level(level);
+    match args.opt {
+        Opt::Extract(opts) if opts.watch => watch(&opts, &bars),
+        Opt::Extract(opts) => extract(&opts, &bars).map(drop),
+        Opt::Batch { workspace, args } => batch(&workspace, &args, &bars),
+        Opt::ExtractOne {
+            archive,
+            atlas,
+            pos,
+            output,
+            tile_size,
+        } => extract_one(&archive, &atlas, pos, &output, tile_size),
+        Opt::Scaffold { input_dir, output } => {
+            let toml = scaffold(&input_dir)?;
+            match output {
+                Some(path) if path.exists() => {
+                    let mut config = ConfigEditor::open(&path)?;
+                    config.merge(&toml)?;
+                    config.save()?;
+                }
+                Some(path) => fs::write(path, toml)?,
+                None => print!("{}", toml),
+            }
+            Ok(())
+        }
+        Opt::Preview {
+            inputs,
+            atlas,
+            output,
+            scale,
+        } => preview(&inputs, &atlas, output.as_deref(), scale),
+        Opt::Validate { inputs, strict } => validate(&inputs, strict)
+            // Ones not tagged more precisely, like broken images
+            .map_err(|e| match Failure::of(&e) {
+                Some(_) => e,
+                None => Failure::Validation.tag(e),
+            }),
+        Opt::Lint { inputs } => lint(&inputs),
+        Opt::Find {
+            inputs,
+            pattern,
+            regex,
+        } => find(&inputs, &pattern, regex),
+        Opt::Stitch {
+            inputs,
+            loose_dir,
+            write,
+            strict,
+        } => stitch(&inputs, &loose_dir, &write, strict),
+        Opt::Diff {
+            old,
+            new,
+            atlas,
+            tile_size,
+            output,
+            scale,
+        } => diff(&old, &new, &atlas, tile_size, output.as_deref(), scale),
+        Opt::Verify {
+            output,
+            reference,
+            tolerance,
+            report,
+        } => verify(&output, &reference, tolerance, report.as_deref()),
+        Opt::Map {
+            inputs,
+            atlas,
+            items,
+        } => map(&inputs, &atlas, items),
+        Opt::Palette(opt) => palette(opt),
+        Opt::Clean {
+            output_dir,
+            force,
+            dry_run,
+        } => {
+            let cleaned = clean(&output_dir, force, dry_run)?;
+            for path in &cleaned.removed {
+                log::debug!("removed {}", path.display());
+            }
+            for path in &cleaned.modified {
+                log::warn!("kept modified {}", path.display());
+            }
+            let verb = if dry_run { "would remove" } else { "removed" };
+            println!("{} {} files", verb, cleaned.removed.len());
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{cache::hash, Outcome, Writer, CACHE_FILE};
+
+/// Name of the list of generated files, stored in output root
+pub const MANIFEST_FILE: &str = ".tex-slasher-manifest.json";
+
+/// Every file runs wrote into an output root, so they can be removed again
+/// once they're stale, e.g. after renaming entries in config
+#[derive(Default, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Hash of each file as it was written, by path relative to output root
+    files: BTreeMap<PathBuf, String>,
+}
+
+/// What `clean` did
+#[derive(Debug, Default)]
+pub struct Cleaned {
+    pub removed: Vec<PathBuf>,
+    /// Files changed since they were written, kept unless forced
+    pub modified: Vec<PathBuf>,
+}
+
+impl Manifest {
+    /// Manifest in output `root`, empty if there's none yet
+    pub fn load(root: &Path) -> anyhow::Result<Self> {
+        let path = root.join(MANIFEST_FILE);
+        match fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data).map_err(|e| {
+                anyhow::anyhow!("broken {}: {}", path.display(), e)
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                Ok(Self::default())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Adds every file `writer` left in place under `root`. Ones from
+    /// earlier runs stay listed, they may be stale.
+    pub fn record(
+        &mut self,
+        writer: &Writer,
+        root: &Path,
+    ) -> anyhow::Result<()> {
+        for record in writer.records() {
+            // Skipped files were already there, and aren't ours
+            if record.outcome == Outcome::Skipped {
+                continue;
+            }
+            let relative = match record.path.strip_prefix(root) {
+                Ok(relative) => relative.to_owned(),
+                Err(_) => continue,
+            };
+            let data = writer.read(&record.path)?;
+            self.files.insert(relative, hash(&data));
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, root: &Path) -> anyhow::Result<()> {
+        let data = serde_json::to_string_pretty(self)?;
+        fs::write(root.join(MANIFEST_FILE), data)?;
+        Ok(())
+    }
+}
+
+/// Removes files listed in manifest of output `root`, along with
+/// directories left empty, the manifest itself and the cache. Files changed
+/// since they were written are kept, unless `force` is given.
+pub fn clean(
+    root: &Path,
+    force: bool,
+    dry_run: bool,
+) -> anyhow::Result<Cleaned> {
+    let manifest = Manifest::load(root)?;
+    let mut cleaned = Cleaned::default();
+    let mut kept = Manifest::default();
+    for (relative, expected) in manifest.files {
+        let path = root.join(&relative);
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        if !force && hash(&data) != expected {
+            cleaned.modified.push(path);
+            kept.files.insert(relative, expected);
+            continue;
+        }
+        if !dry_run {
+            fs::remove_file(&path)?;
+            remove_empty_parents(root, &path);
+        }
+        cleaned.removed.push(path);
+    }
+    if !dry_run {
+        if kept.files.is_empty() {
+            remove_if_exists(&root.join(MANIFEST_FILE))?;
+        } else {
+            kept.save(root)?;
+        }
+        remove_if_exists(&root.join(CACHE_FILE))?;
+    }
+    Ok(cleaned)
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Removes directories between `path` and `root` that are left empty
+fn remove_empty_parents(root: &Path, path: &Path) {
+    for dir in path.ancestors().skip(1) {
+        if dir == root || !dir.starts_with(root) || fs::remove_dir(dir).is_err()
+        {
+            break;
+        }
+    }
+}
@@ -0,0 +1,352 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+    path::Path,
+};
+
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind},
+    queue,
+    style::{self, Color},
+    terminal,
+};
+use image::{Rgba, RgbaImage};
+
+use crate::{AtlasInfo, AtlasPos, ConfigEditor, MAX_CELLS};
+
+/// Change to a single cell, made in `map_cells`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Edit {
+    Name(String),
+    Unmap,
+}
+
+pub type Edits = BTreeMap<AtlasPos, Edit>;
+
+/// Character cells each atlas cell is drawn with, every one showing two
+/// pixels stacked on top of each other
+const CELL_COLUMNS: u32 = 4;
+const CELL_ROWS: u32 = 2;
+/// Size of the enlarged selected cell in side panel
+const ZOOM_COLUMNS: u32 = 16;
+const ZOOM_ROWS: u32 = 8;
+/// Room kept for side panel next to the grid
+const PANEL_WIDTH: u32 = ZOOM_COLUMNS + 4;
+/// Transparent pixels are shown blended over this
+const BACKGROUND: u8 = 40;
+
+const HELP: &str = "arrows: move  enter: name  x: unmap  s: save  q: quit";
+
+struct Mapper<'a> {
+    atlas: &'a str,
+    image: &'a RgbaImage,
+    info: AtlasInfo,
+    columns: u32,
+    rows: u32,
+    names: BTreeMap<AtlasPos, String>,
+    edits: Edits,
+    selected: (u32, u32),
+    /// First grid column and row on screen
+    scroll: (u32, u32),
+    /// Name being typed, if any
+    input: Option<String>,
+    status: String,
+    /// Set after `q` with unsaved edits, second `q` quits anyway
+    quitting: bool,
+}
+
+impl Mapper<'_> {
+    fn pos(&self) -> AtlasPos {
+        let (x, y) = self.selected;
+        AtlasPos::from_pos(x as u8, y as u8)
+    }
+
+    fn name(&self, pos: &AtlasPos) -> Option<&str> {
+        match self.edits.get(pos) {
+            Some(Edit::Name(name)) => Some(name),
+            Some(Edit::Unmap) => None,
+            None => self.names.get(pos).map(String::as_str),
+        }
+    }
+
+    fn edit(&mut self, edit: Edit) {
+        let pos = self.pos();
+        let unchanged = match &edit {
+            Edit::Name(name) => self.names.get(&pos) == Some(name),
+            Edit::Unmap => !self.names.contains_key(&pos),
+        };
+        if unchanged {
+            self.edits.remove(&pos);
+        } else {
+            self.edits.insert(pos, edit);
+        }
+    }
+
+    /// Pixel `(sx, sy)` out of `w` by `h` samples spread over cell `(x, y)`
+    fn sample(
+        &self,
+        x: u32,
+        y: u32,
+        (sx, sy): (u32, u32),
+        w: u32,
+        h: u32,
+    ) -> Color {
+        let size = self.info.tile_size;
+        let (x0, y0) = self.info.origin(x, y);
+        let px = x0 + sx * size / w;
+        let py = y0 + sy * size / h;
+        let Rgba([r, g, b, a]) = *self.image.get_pixel(px, py);
+        let blend = |c: u8| {
+            (c as u32 * a as u32 + BACKGROUND as u32 * (255 - a as u32)) / 255
+        };
+        Color::Rgb {
+            r: blend(r) as u8,
+            g: blend(g) as u8,
+            b: blend(b) as u8,
+        }
+    }
+
+    /// Draws `w` by `h` character cells of cell `(x, y)`, from `line`
+    fn draw_pixels(
+        &self,
+        out: &mut impl Write,
+        (x, y): (u32, u32),
+        line: u32,
+        w: u32,
+        h: u32,
+    ) -> io::Result<()> {
+        for column in 0..w {
+            let top = self.sample(x, y, (column, line * 2), w, h * 2);
+            let bottom = self.sample(x, y, (column, line * 2 + 1), w, h * 2);
+            queue!(
+                out,
+                style::SetForegroundColor(top),
+                style::SetBackgroundColor(bottom),
+                style::Print('▀'),
+            )?;
+        }
+        queue!(out, style::ResetColor)
+    }
+
+    fn draw(&mut self, out: &mut impl Write) -> io::Result<()> {
+        let (width, height) = terminal::size()?;
+        let grid_width = (width as u32).saturating_sub(PANEL_WIDTH);
+        let visible = (
+            (grid_width.saturating_sub(1) / (CELL_COLUMNS + 1)).max(1),
+            ((height as u32).saturating_sub(2) / CELL_ROWS).max(1),
+        );
+        let (x, y) = self.selected;
+        let follow = |scroll: &mut u32, selected: u32, visible: u32| {
+            if selected < *scroll {
+                *scroll = selected;
+            } else if selected >= *scroll + visible {
+                *scroll = selected + 1 - visible;
+            }
+        };
+        follow(&mut self.scroll.0, x, visible.0);
+        follow(&mut self.scroll.1, y, visible.1);
+        let (left, top) = self.scroll;
+        let right = (left + visible.0).min(self.columns);
+        queue!(out, terminal::Clear(terminal::ClearType::All))?;
+        let last = (top + visible.1).min(self.rows);
+        for row in top..last {
+            for line in 0..CELL_ROWS {
+                let at = (row - top) * CELL_ROWS + line;
+                queue!(out, cursor::MoveTo(0, at as u16))?;
+                for column in left..=right {
+                    let separator = if (column, row) == (x, y) {
+                        '['
+                    } else if column > left && (column - 1, row) == (x, y) {
+                        ']'
+                    } else {
+                        ' '
+                    };
+                    queue!(out, style::Print(separator))?;
+                    if column < right {
+                        self.draw_pixels(
+                            out,
+                            (column, row),
+                            line,
+                            CELL_COLUMNS,
+                            CELL_ROWS,
+                        )?;
+                    }
+                }
+            }
+        }
+
+        let panel = ((right - left) * (CELL_COLUMNS + 1) + 3) as u16;
+        let pos = self.pos();
+        let name = self.name(&pos).unwrap_or("(unmapped)");
+        let edited = if self.edits.contains_key(&pos) {
+            " *"
+        } else {
+            ""
+        };
+        queue!(
+            out,
+            cursor::MoveTo(panel, 0),
+            style::Print(format!("{} {:?}", self.atlas, pos)),
+            cursor::MoveTo(panel, 1),
+            style::Print(format!("{}{}", name, edited)),
+        )?;
+        for line in 0..ZOOM_ROWS {
+            queue!(out, cursor::MoveTo(panel, (line + 3) as u16))?;
+            self.draw_pixels(out, (x, y), line, ZOOM_COLUMNS, ZOOM_ROWS)?;
+        }
+
+        let bottom = match &self.input {
+            Some(input) => format!("name: {}_", input),
+            None if self.status.is_empty() => HELP.to_owned(),
+            None => self.status.clone(),
+        };
+        queue!(out, cursor::MoveTo(0, height - 1), style::Print(bottom))?;
+        out.flush()
+    }
+
+    /// Handles a key press, returns whether to quit
+    fn key(
+        &mut self,
+        key: KeyEvent,
+        save: &mut impl FnMut(&Edits) -> anyhow::Result<()>,
+    ) -> bool {
+        if let Some(input) = &mut self.input {
+            match key.code {
+                KeyCode::Char(c) => input.push(c),
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Enter => {
+                    let name = input.trim().to_owned();
+                    self.input = None;
+                    if name.is_empty() {
+                        self.edit(Edit::Unmap);
+                    } else {
+                        self.edit(Edit::Name(name));
+                    }
+                    // Sheets are usually filled in reading order
+                    let (x, y) = &mut self.selected;
+                    if *x + 1 < self.columns {
+                        *x += 1;
+                    } else if *y + 1 < self.rows {
+                        *x = 0;
+                        *y += 1;
+                    }
+                }
+                KeyCode::Esc => self.input = None,
+                _ => {}
+            }
+            return false;
+        }
+        let quitting = std::mem::take(&mut self.quitting);
+        self.status.clear();
+        let (x, y) = &mut self.selected;
+        match key.code {
+            KeyCode::Left | KeyCode::Char('h') => *x = x.saturating_sub(1),
+            KeyCode::Right | KeyCode::Char('l') => {
+                *x = (*x + 1).min(self.columns - 1)
+            }
+            KeyCode::Up | KeyCode::Char('k') => *y = y.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => {
+                *y = (*y + 1).min(self.rows - 1)
+            }
+            KeyCode::Enter | KeyCode::Char('n') => {
+                let pos = self.pos();
+                self.input = Some(self.name(&pos).unwrap_or("").to_owned());
+            }
+            KeyCode::Delete | KeyCode::Char('x') => self.edit(Edit::Unmap),
+            KeyCode::Char('s') => match save(&self.edits) {
+                Ok(()) => {
+                    self.status = format!("saved {} changes", self.edits.len());
+                    for (pos, edit) in std::mem::take(&mut self.edits) {
+                        match edit {
+                            Edit::Name(name) => self.names.insert(pos, name),
+                            Edit::Unmap => self.names.remove(&pos),
+                        };
+                    }
+                }
+                Err(e) => self.status = format!("error: {}", e),
+            },
+            KeyCode::Char('q') | KeyCode::Esc => {
+                if self.edits.is_empty() || quitting {
+                    return true;
+                }
+                self.quitting = true;
+                self.status =
+                    "unsaved changes, s to save or q again to discard".into();
+            }
+            _ => {}
+        }
+        false
+    }
+}
+
+/// Shows `image` cell by cell in the terminal, letting the user move around
+/// and name cells. `save` is called with changes made since the last save.
+pub fn map_cells(
+    atlas: &str,
+    image: &RgbaImage,
+    info: AtlasInfo,
+    names: BTreeMap<AtlasPos, String>,
+    mut save: impl FnMut(&Edits) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(info.tile_size > 0, "tile size can't be 0");
+    let columns = info.columns().min(MAX_CELLS);
+    let rows = info.rows().min(MAX_CELLS);
+    anyhow::ensure!(
+        columns > 0 && rows > 0,
+        "atlas {} is smaller than a single tile",
+        atlas
+    );
+    let mut mapper = Mapper {
+        atlas,
+        image,
+        info,
+        columns,
+        rows,
+        names,
+        edits: Edits::new(),
+        selected: (0, 0),
+        scroll: (0, 0),
+        input: None,
+        status: String::new(),
+        quitting: false,
+    };
+    let mut out = io::stdout();
+    terminal::enable_raw_mode()?;
+    crossterm::execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+    let result = (|| -> anyhow::Result<()> {
+        loop {
+            mapper.draw(&mut out)?;
+            if let Event::Key(key) = event::read()? {
+                let pressed = key.kind != KeyEventKind::Release;
+                if pressed && mapper.key(key, &mut save) {
+                    return Ok(());
+                }
+            }
+        }
+    })();
+    // Restore terminal even if drawing failed
+    crossterm::execute!(out, cursor::Show, terminal::LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+    result
+}
+
+/// Writes `edits` into `[<section>.<atlas>]` of toml at `path`, keeping
+/// the rest of the file, comments included, as it was
+pub fn apply_edits(
+    path: &Path,
+    section: &str,
+    atlas: &str,
+    edits: &Edits,
+) -> anyhow::Result<()> {
+    let mut config = ConfigEditor::open(path)?;
+    for (pos, edit) in edits {
+        match edit {
+            Edit::Name(name) => config.set_tile(section, atlas, pos, name)?,
+            Edit::Unmap => config.remove_tile(section, atlas, pos)?,
+        }
+    }
+    config.save()
+}
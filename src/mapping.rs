@@ -0,0 +1,252 @@
+use std::{convert::TryFrom, path::Path, str::FromStr};
+
+use serde_json::{json, Value};
+
+use crate::{ArchiveSet, Atlas, AtlasInfo, Config, Crop, Writer};
+
+/// Sprite sheet descriptor `--export-mapping` writes for every atlas
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MappingFormat {
+    /// TexturePacker's json hash, a frame per tile
+    TexturePacker,
+    /// Aseprite's json, the whole sheet as one frame with a slice per tile
+    Aseprite,
+}
+
+impl FromStr for MappingFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "texturepacker" => Ok(Self::TexturePacker),
+            "aseprite" => Ok(Self::Aseprite),
+            _ => Err(format!(
+                "unknown format {:?}, expected texturepacker or aseprite",
+                s
+            )),
+        }
+    }
+}
+
+/// Part of the sheet a tile, or one frame of it, is cut from
+struct Sprite {
+    name: String,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+}
+
+impl Sprite {
+    fn rect(&self) -> Value {
+        json!({ "x": self.x, "y": self.y, "w": self.w, "h": self.h })
+    }
+}
+
+/// Sprites of every tile in `map`, laid out as `info`, a frame of an
+/// animated tile each, named `<name>_<frame>`
+fn sprites(map: &Atlas, info: &AtlasInfo) -> anyhow::Result<Vec<Sprite>> {
+    let map = map.with_grid(info.columns(), info.rows())?;
+    let mut sprites = vec![];
+    for (pos, tile) in &map.tiles {
+        let (columns, rows) = tile.span;
+        // Gutters inside a tile spanning several cells are left in
+        let size = |cells: u32| (cells - 1) * info.pitch() + info.tile_size;
+        let frames = tile.frames.max(1);
+        for frame in 0..frames {
+            let y = pos.y() as u32 + frame * rows;
+            let (x, y) = info.origin(pos.x() as u32, y);
+            let (x, y, w, h) = match tile.rect {
+                Some([dx, dy, w, h]) => (x + dx, y + dy, w, h),
+                None => (x, y, size(columns), size(rows)),
+            };
+            let name = match frames {
+                1 => tile.name.clone(),
+                _ => format!("{}_{}", tile.name, frame),
+            };
+            sprites.push(Sprite { name, x, y, w, h });
+        }
+    }
+    Ok(sprites)
+}
+
+/// Descriptor of `sprites` cut from `image`, `width` by `height`
+fn descriptor(
+    format: MappingFormat,
+    sprites: &[Sprite],
+    image: &str,
+    (width, height): (u32, u32),
+) -> Value {
+    let size = json!({ "w": width, "h": height });
+    let mut meta = json!({
+        "app": env!("CARGO_PKG_NAME"),
+        "version": env!("CARGO_PKG_VERSION"),
+        "image": image,
+        "format": "RGBA8888",
+        "size": size,
+        "scale": "1",
+    });
+    match format {
+        MappingFormat::TexturePacker => {
+            let frames: serde_json::Map<_, _> = sprites
+                .iter()
+                .map(|sprite| {
+                    let frame = json!({
+                        "frame": sprite.rect(),
+                        "rotated": false,
+                        "trimmed": false,
+                        "spriteSourceSize":
+                            { "x": 0, "y": 0, "w": sprite.w, "h": sprite.h },
+                        "sourceSize": { "w": sprite.w, "h": sprite.h },
+                    });
+                    (format!("{}.png", sprite.name), frame)
+                })
+                .collect();
+            json!({ "frames": frames, "meta": meta })
+        }
+        MappingFormat::Aseprite => {
+            let slices: Vec<_> = sprites
+                .iter()
+                .map(|sprite| {
+                    json!({
+                        "name": sprite.name,
+                        "color": "#0000ffff",
+                        "keys": [{ "frame": 0, "bounds": sprite.rect() }],
+                    })
+                })
+                .collect();
+            meta["frameTags"] = json!([]);
+            meta["layers"] = json!([]);
+            meta["slices"] = Value::Array(slices);
+            let frame = json!({
+                "filename": image,
+                "frame": { "x": 0, "y": 0, "w": width, "h": height },
+                "rotated": false,
+                "trimmed": false,
+                "spriteSourceSize":
+                    { "x": 0, "y": 0, "w": width, "h": height },
+                "sourceSize": size,
+                "duration": 100,
+            });
+            json!({ "frames": [frame], "meta": meta })
+        }
+    }
+}
+
+/// `[x, y, w, h]` of a `{ "x", "y", "w", "h" }` object
+fn read_rect(rect: &Value) -> anyhow::Result<[u32; 4]> {
+    let mut out = [0; 4];
+    for (value, key) in out.iter_mut().zip(["x", "y", "w", "h"]) {
+        let number = rect.get(key).and_then(Value::as_u64);
+        *value = number
+            .and_then(|number| u32::try_from(number).ok())
+            .ok_or_else(|| anyhow::anyhow!("expected {} in {}", key, rect))?;
+    }
+    Ok(out)
+}
+
+/// Crop named `name`, without a `.png`, of `rect`
+fn crop(name: &str, rect: &Value) -> anyhow::Result<Crop> {
+    let name = name.strip_suffix(".png").unwrap_or(name).to_owned();
+    let [x, y, w, h] = read_rect(rect)
+        .map_err(|e| anyhow::anyhow!("sprite {}: {}", name, e))?;
+    Ok(Crop {
+        name,
+        x,
+        y,
+        w,
+        h,
+        ninepatch: None,
+    })
+}
+
+/// Rects of every sprite a TexturePacker or Aseprite json lists: Aseprite
+/// slices if there are any, frames otherwise, given as a hash or an array.
+/// Trimmed frames are cut as packed, without their transparent border.
+pub fn read_descriptor(descriptor: &Value) -> anyhow::Result<Vec<Crop>> {
+    let slices = descriptor.pointer("/meta/slices").and_then(Value::as_array);
+    if let Some(slices) = slices.filter(|slices| !slices.is_empty()) {
+        return slices
+            .iter()
+            .map(|slice| {
+                let name = slice.get("name").and_then(Value::as_str);
+                let bounds = slice.pointer("/keys/0/bounds");
+                match (name, bounds) {
+                    (Some(name), Some(bounds)) => crop(name, bounds),
+                    _ => anyhow::bail!("slice without name or bounds"),
+                }
+            })
+            .collect();
+    }
+    let frames: Vec<(&str, &Value)> = match descriptor.get("frames") {
+        Some(Value::Object(frames)) => frames
+            .iter()
+            .map(|(name, frame)| (&name[..], frame))
+            .collect(),
+        Some(Value::Array(frames)) => frames
+            .iter()
+            .map(|frame| {
+                let name = frame.get("filename").and_then(Value::as_str);
+                let name = name.ok_or_else(|| {
+                    anyhow::anyhow!("frame without filename: {}", frame)
+                })?;
+                Ok((name, frame))
+            })
+            .collect::<anyhow::Result<_>>()?,
+        _ => anyhow::bail!("no frames or slices, expected sprite sheet json"),
+    };
+    frames
+        .into_iter()
+        .map(|(name, frame)| {
+            let rotated = frame.get("rotated").and_then(Value::as_bool);
+            anyhow::ensure!(
+                rotated != Some(true),
+                "sprite {} is rotated, pack without rotation",
+                name
+            );
+            let rect = frame.get("frame").ok_or_else(|| {
+                anyhow::anyhow!("sprite {} has no frame rect", name)
+            })?;
+            crop(name, rect)
+        })
+        .collect()
+}
+
+/// Writes a `format` descriptor of every atlas in `toml` into `dir`, as
+/// `<modid>/<atlas>.json`, listing where each tile is on the sheet. Returns
+/// how many were written.
+pub fn export_mapping(
+    toml: &Config,
+    zips: &mut ArchiveSet,
+    dir: &Path,
+    writer: &Writer,
+    format: MappingFormat,
+) -> anyhow::Result<usize> {
+    let mut count = 0;
+    for ns in toml.all_namespaces() {
+        for (name, map) in ns.blocks.iter().chain(&ns.items) {
+            let sheet = zips.sheet(name);
+            let image = zips.image(&sheet)?;
+            let (width, height) = image.dimensions();
+            let info = AtlasInfo::of(map, width, height, toml.tile_size);
+            // Bad layouts are reported while slicing
+            if info.tile_size == 0 {
+                continue;
+            }
+            let sprites = sprites(map, &info)
+                .map_err(|e| anyhow::anyhow!("atlas {}: {}", name, e))?;
+            let file = Path::new(&sheet).file_name().unwrap();
+            let descriptor = descriptor(
+                format,
+                &sprites,
+                &file.to_string_lossy(),
+                (width, height),
+            );
+            let path = dir.join(&ns.modid).join(format!("{}.json", name));
+            writer.create_dir_all(path.parent().unwrap())?;
+            writer.write(&path, &serde_json::to_vec_pretty(&descriptor)?)?;
+            count += 1;
+        }
+    }
+    Ok(count)
+}
@@ -0,0 +1,175 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use crate::{open_source, AssetSource, Config};
+
+/// Prefix of sources in `folders` naming a mod by its modid, found in
+/// `mods_dir` whatever its jar is called
+pub const MOD_PREFIX: &str = "mod:";
+
+/// Jars of a Minecraft `mods` directory, by the modids they declare
+#[derive(Debug)]
+struct ModsDir {
+    /// As given in `mods_dir`
+    dir: String,
+    /// Modid to file name in `dir`
+    jars: BTreeMap<String, String>,
+}
+
+impl ModsDir {
+    /// Reads metadata of every jar and zip in `dir`: `mcmod.info`,
+    /// `fabric.mod.json`, `quilt.mod.json` and `META-INF/mods.toml`. Ones
+    /// that can't be opened are skipped with a warning. Fails if two declare
+    /// the same modid, e.g. an old version left next to an update.
+    fn scan(input_dir: &Path, dir: &str) -> anyhow::Result<Self> {
+        let path = input_dir.join(dir);
+        let mut jars = BTreeMap::<String, String>::new();
+        let mut files: Vec<String> = vec![];
+        for entry in fs::read_dir(&path)
+            .map_err(|e| anyhow::anyhow!("mods_dir {}: {}", dir, e))?
+        {
+            let file = entry?.path();
+            let is_jar = file
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| matches!(ext, "jar" | "zip"));
+            if is_jar && file.is_file() {
+                files.push(file.file_name().unwrap().to_string_lossy().into());
+            }
+        }
+        files.sort();
+        for file in files {
+            let ids = open_source(&path, &file, 0)
+                .and_then(|mut jar| mod_ids(&mut *jar));
+            let ids = match ids {
+                Ok(ids) => ids,
+                Err(e) => {
+                    log::warn!("mods_dir: skipping {}: {}", file, e);
+                    continue;
+                }
+            };
+            for id in ids {
+                if let Some(other) = jars.get(&id) {
+                    anyhow::bail!(
+                        "mods_dir: both {} and {} are mod {}",
+                        other,
+                        file,
+                        id
+                    );
+                }
+                log::debug!("mods_dir: {} is mod {}", file, id);
+                jars.insert(id, file.clone());
+            }
+        }
+        Ok(Self {
+            dir: dir.to_owned(),
+            jars,
+        })
+    }
+
+    /// Source `name` with `mod:<modid>` replaced by the path of the jar
+    /// declaring that modid, relative to input directory. Other names are
+    /// kept as they are.
+    fn locate(&self, name: String) -> anyhow::Result<String> {
+        let rest = match name.strip_prefix(MOD_PREFIX) {
+            Some(rest) => rest,
+            None => return Ok(name),
+        };
+        let (modid, nested) = match rest.find('!') {
+            Some(i) => rest.split_at(i),
+            None => (rest, ""),
+        };
+        let jar = self.jars.get(modid).ok_or_else(|| {
+            anyhow::anyhow!("no jar in mods_dir {} is mod {}", self.dir, modid)
+        })?;
+        let dir = self.dir.trim_end_matches('/');
+        log::info!("{}{} is {}/{}", MOD_PREFIX, modid, dir, jar);
+        Ok(format!("{}/{}{}", dir, jar, nested))
+    }
+}
+
+/// Keys of `map` with sources given by modid located in `mods`
+fn locate_keys<V>(
+    mods: &ModsDir,
+    map: &mut BTreeMap<String, V>,
+) -> anyhow::Result<()> {
+    *map = std::mem::take(map)
+        .into_iter()
+        .map(|(name, value)| Ok((mods.locate(name)?, value)))
+        .collect::<anyhow::Result<_>>()?;
+    Ok(())
+}
+
+/// Replaces sources in `folders` written as `mod:<modid>`, and keys
+/// referring to them, by the jar in `mods_dir` declaring that modid, so
+/// config doesn't need jar names that change with every mod version
+pub fn locate_mods(
+    config: &mut Config,
+    input_dir: &Path,
+) -> anyhow::Result<()> {
+    let by_modid = |name: &String| name.starts_with(MOD_PREFIX);
+    let dir = match &config.mods_dir {
+        Some(dir) if config.folders.keys().any(by_modid) => dir,
+        _ => return Ok(()),
+    };
+    let mods = ModsDir::scan(input_dir, dir)?;
+    locate_keys(&mods, &mut config.folders)?;
+    locate_keys(&mods, &mut config.priorities)?;
+    locate_keys(&mods, &mut config.sources)?;
+    locate_keys(&mods, &mut config.checksums)?;
+    for layer in &mut config.layers {
+        *layer = mods.locate(std::mem::take(layer))?;
+    }
+    Ok(())
+}
+
+/// Values of `"modid"` keys in `text` of an `mcmod.info`. Old ones are often
+/// not valid json, so they're picked out instead of parsing it.
+fn mcmod_info_ids(text: &str) -> Vec<String> {
+    let mut ids = vec![];
+    let mut rest = text;
+    while let Some(start) = rest.find("\"modid\"") {
+        rest = rest[start + "\"modid\"".len()..].trim_start();
+        let value = rest.strip_prefix(':').map(str::trim_start);
+        if let Some(value) = value.and_then(|v| v.strip_prefix('"')) {
+            if let Some(end) = value.find('"') {
+                ids.push(value[..end].to_owned());
+            }
+        }
+    }
+    ids
+}
+
+/// Modids `jar` declares in any of the metadata files loaders read
+fn mod_ids(jar: &mut dyn AssetSource) -> anyhow::Result<Vec<String>> {
+    let mut ids = vec![];
+    if let Some(data) = jar.read("mcmod.info")? {
+        ids.extend(mcmod_info_ids(&String::from_utf8_lossy(&data)));
+    }
+    for (file, pointer) in [
+        ("fabric.mod.json", "/id"),
+        ("quilt.mod.json", "/quilt_loader/id"),
+    ] {
+        if let Some(data) = jar.read(file)? {
+            let value: serde_json::Value = serde_json::from_slice(&data)
+                .map_err(|e| anyhow::anyhow!("{}: {}", file, e))?;
+            ids.extend(
+                value
+                    .pointer(pointer)
+                    .and_then(|id| id.as_str())
+                    .map(str::to_owned),
+            );
+        }
+    }
+    if let Some(data) = jar.read("META-INF/mods.toml")? {
+        let value: toml::Value =
+            toml::from_str(&String::from_utf8_lossy(&data))
+                .map_err(|e| anyhow::anyhow!("META-INF/mods.toml: {}", e))?;
+        let mods = value.get("mods").and_then(|mods| mods.as_array());
+        ids.extend(mods.into_iter().flatten().filter_map(|entry| {
+            Some(entry.get("modId")?.as_str()?.to_owned())
+        }));
+    }
+    ids.sort();
+    ids.dedup();
+    Ok(ids)
+}
@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Alias, Config};
+
+/// How `normalize_names` rewrites configured names into valid resource
+/// locations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NameStyle {
+    /// Words split at case changes and joined with `_`: `OreCopper` becomes
+    /// `ore_copper`
+    SnakeCase,
+    /// Only lowercased, with anything else not allowed turned into `_`:
+    /// `OreCopper` becomes `orecopper`
+    Lowercase,
+}
+
+impl NameStyle {
+    /// `name` in this style. Each `/`-separated part is done on its own,
+    /// and characters resource locations don't allow become `_`.
+    pub fn apply(self, name: &str) -> String {
+        let chars: Vec<char> = name.chars().collect();
+        let mut out = String::new();
+        for (i, &c) in chars.iter().enumerate() {
+            if c.is_ascii_uppercase() {
+                let prev = i.checked_sub(1).map(|i| chars[i]);
+                let next = chars.get(i + 1);
+                // `OreCopper`, `ore2Copper`, and `HTMLParser` before `Parser`
+                let word = prev.is_some_and(|prev| {
+                    prev.is_ascii_lowercase()
+                        || prev.is_ascii_digit()
+                        || prev.is_ascii_uppercase()
+                            && next.is_some_and(char::is_ascii_lowercase)
+                });
+                if self == Self::SnakeCase && word && !out.ends_with('_') {
+                    out.push('_');
+                }
+                out.push(c.to_ascii_lowercase());
+            } else if c.is_ascii_lowercase()
+                || c.is_ascii_digit()
+                || matches!(c, '/' | '.' | '-')
+            {
+                out.push(c);
+            } else if !out.is_empty() && !out.ends_with(['_', '/']) {
+                out.push('_');
+            }
+        }
+        out
+    }
+}
+
+/// Name changed by `normalize_names`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Rename {
+    /// Config section the name is in, e.g. `blocks.terrain` or `aliases`
+    pub section: String,
+    pub from: String,
+    pub to: String,
+}
+
+/// Rewrites configured names in `normalize_names` style, if set: tiles and
+/// keys referring to them in `outputs` and `aliases`, aliases themselves,
+/// gui crops, sprites, fallbacks, ctm sets and fonts. Every name changed is logged and kept in
+/// `renames`.
+pub fn normalize_names(config: &mut Config) {
+    let style = match config.normalize_names {
+        Some(style) => style,
+        None => return,
+    };
+    let mut renames = vec![];
+    let mut rename = |section: &str, name: &mut String| {
+        let to = style.apply(name);
+        if to != *name {
+            log::info!("[{}] {} renamed to {}", section, name, to);
+            renames.push(Rename {
+                section: section.to_owned(),
+                from: std::mem::replace(name, to.clone()),
+                to,
+            });
+        }
+    };
+    for ns in config.all_namespaces_mut() {
+        // Old tile name to new, for keys referring to tiles
+        let mut tiles = BTreeMap::new();
+        let atlases = [("blocks", &mut ns.blocks), ("items", &mut ns.items)];
+        for (section, atlas) in atlases {
+            for (name, map) in atlas.iter_mut() {
+                let section = format!("{}.{}", section, name);
+                for tile in map.tiles.values_mut() {
+                    let from = tile.name.clone();
+                    rename(&section, &mut tile.name);
+                    tiles.insert(from, tile.name.clone());
+                }
+            }
+        }
+        let renamed = |name: String| tiles.get(&name).cloned().unwrap_or(name);
+        let outputs = std::mem::take(&mut ns.outputs);
+        ns.outputs = outputs
+            .into_iter()
+            .map(|(name, category)| (renamed(name), category))
+            .collect();
+        let aliases = std::mem::take(&mut ns.aliases);
+        for (mut name, alias) in aliases {
+            rename("aliases", &mut name);
+            let alias = match alias {
+                Alias::Texture(tile) => Alias::Texture(renamed(tile)),
+                Alias::Model(tile) => Alias::Model(renamed(tile)),
+            };
+            ns.aliases.insert(name, alias);
+        }
+        for (sheet, crops) in &mut ns.gui_crops {
+            let section = format!("gui_crops.{}", sheet);
+            for crop in crops {
+                rename(&section, &mut crop.name);
+            }
+        }
+        for (sheet, sprites) in &mut ns.sprite_sheets {
+            let section = format!("sprite_sheets.{}", sheet);
+            for crop in &mut sprites.crops {
+                rename(&section, &mut crop.name);
+            }
+        }
+        for (section, fallbacks) in [
+            ("fallbacks.blocks", &mut ns.fallbacks.blocks),
+            ("fallbacks.items", &mut ns.fallbacks.items),
+        ] {
+            for (mut name, fallback) in std::mem::take(fallbacks) {
+                rename(section, &mut name);
+                fallbacks.insert(name, fallback);
+            }
+        }
+        let ctm = std::mem::take(&mut ns.ctm);
+        for (mut name, set) in ctm {
+            rename("ctm", &mut name);
+            ns.ctm.insert(name, set);
+        }
+        let fonts = std::mem::take(&mut ns.fonts);
+        for (mut name, font) in fonts {
+            rename("fonts", &mut name);
+            ns.fonts.insert(name, font);
+        }
+    }
+    config.renames = renames;
+}
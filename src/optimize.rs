@@ -0,0 +1,164 @@
+use std::collections::{HashMap, HashSet};
+
+use image::{ColorType as ImageColor, RgbaImage};
+use png::{BitDepth, ColorType, Compression, Encoder, FilterType};
+
+/// Pixel data in the smallest color type that keeps the image lossless
+struct Packed {
+    color: ColorType,
+    depth: BitDepth,
+    palette: Option<(Vec<u8>, Vec<u8>)>,
+    rows: Vec<Vec<u8>>,
+}
+
+/// Index of every color, if there are at most 256 of them. Transparent
+/// colors come first, so `tRNS` can stop after the last one.
+fn palette(image: &RgbaImage) -> Option<Vec<[u8; 4]>> {
+    let mut colors = Vec::new();
+    let mut seen = HashSet::new();
+    for pixel in image.pixels() {
+        if seen.insert(pixel.0) {
+            colors.push(pixel.0);
+            if colors.len() > 256 {
+                return None;
+            }
+        }
+    }
+    colors.sort_by_key(|color| (color[3] == 255, *color));
+    Some(colors)
+}
+
+fn bit_depth(colors: usize) -> (BitDepth, u32) {
+    match colors {
+        0..=2 => (BitDepth::One, 1),
+        3..=4 => (BitDepth::Two, 2),
+        5..=16 => (BitDepth::Four, 4),
+        _ => (BitDepth::Eight, 8),
+    }
+}
+
+fn indexed(image: &RgbaImage, colors: &[[u8; 4]]) -> Packed {
+    let index: HashMap<_, _> = colors
+        .iter()
+        .enumerate()
+        .map(|(i, color)| (*color, i as u8))
+        .collect();
+    let (depth, bits) = bit_depth(colors.len());
+    let per_byte = 8 / bits;
+    let rows = image
+        .rows()
+        .map(|row| {
+            let mut bytes =
+                vec![0; (image.width() as usize * bits as usize).div_ceil(8)];
+            for (x, pixel) in row.enumerate() {
+                let shift = 8 - bits * (x as u32 % per_byte + 1);
+                bytes[x / per_byte as usize] |= index[&pixel.0] << shift;
+            }
+            bytes
+        })
+        .collect();
+    let plte = colors
+        .iter()
+        .flat_map(|color| &color[..3])
+        .copied()
+        .collect();
+    let trns = colors
+        .iter()
+        .map(|color| color[3])
+        .take_while(|&alpha| alpha < 255)
+        .collect();
+    Packed {
+        color: ColorType::Indexed,
+        depth,
+        palette: Some((plte, trns)),
+        rows,
+    }
+}
+
+fn direct(image: &RgbaImage) -> Packed {
+    let opaque = image.pixels().all(|pixel| pixel[3] == 255);
+    let gray = image
+        .pixels()
+        .all(|pixel| pixel[0] == pixel[1] && pixel[1] == pixel[2]);
+    let (color, channels): (_, &[usize]) = match (gray, opaque) {
+        (true, true) => (ColorType::Grayscale, &[0]),
+        (true, false) => (ColorType::GrayscaleAlpha, &[0, 3]),
+        (false, true) => (ColorType::RGB, &[0, 1, 2]),
+        (false, false) => (ColorType::RGBA, &[0, 1, 2, 3]),
+    };
+    let rows = image
+        .rows()
+        .map(|row| {
+            row.flat_map(|pixel| channels.iter().map(move |&c| pixel[c]))
+                .collect()
+        })
+        .collect();
+    Packed {
+        color,
+        depth: BitDepth::Eight,
+        palette: None,
+        rows,
+    }
+}
+
+fn encode(
+    packed: &Packed,
+    width: u32,
+    height: u32,
+    filter: FilterType,
+) -> anyhow::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    let mut encoder = Encoder::new(&mut data, width, height);
+    encoder.set_color(packed.color);
+    encoder.set_depth(packed.depth);
+    encoder.set_compression(Compression::Best);
+    encoder.set_filter(filter);
+    if let Some((plte, trns)) = &packed.palette {
+        encoder.set_palette(plte.clone());
+        if !trns.is_empty() {
+            encoder.set_trns(trns.clone());
+        }
+    }
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&packed.rows.concat())?;
+    drop(writer);
+    Ok(data)
+}
+
+/// Recompresses a png losslessly: as indexed colors when there are few
+/// enough of them, otherwise without unused channels, trying each filter.
+/// Returns the smallest result, which may be `data` itself. 16-bit images
+/// are kept as they are.
+pub fn optimize_png(data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let image = image::load_from_memory(data)?;
+    let eight_bit = matches!(
+        image.color(),
+        ImageColor::L8 | ImageColor::La8 | ImageColor::Rgb8 | ImageColor::Rgba8
+    );
+    if !eight_bit {
+        return Ok(data.to_owned());
+    }
+    let image = image.to_rgba8();
+    let (width, height) = image.dimensions();
+    let mut candidates = vec![direct(&image)];
+    if let Some(colors) = palette(&image) {
+        candidates.push(indexed(&image, &colors));
+    }
+    let filters = [
+        FilterType::NoFilter,
+        FilterType::Sub,
+        FilterType::Up,
+        FilterType::Avg,
+        FilterType::Paeth,
+    ];
+    let mut best = data.to_owned();
+    for packed in &candidates {
+        for filter in filters {
+            let encoded = encode(packed, width, height, filter)?;
+            if encoded.len() < best.len() {
+                best = encoded;
+            }
+        }
+    }
+    Ok(best)
+}
@@ -0,0 +1,182 @@
+use std::{collections::HashMap, fmt, fmt::Write as _};
+
+use image::{Rgba, RgbaImage};
+
+/// Ordered list of colors, read and written as JASC-PAL `.pal` files
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette(pub Vec<[u8; 3]>);
+
+impl Palette {
+    /// Colors of the visible pixels of `image`, most used first
+    pub fn extract(image: &RgbaImage) -> Self {
+        let mut counts = HashMap::<[u8; 3], usize>::new();
+        for Rgba([r, g, b, a]) in image.pixels() {
+            if *a > 0 {
+                *counts.entry([*r, *g, *b]).or_default() += 1;
+            }
+        }
+        let mut colors: Vec<_> = counts.into_iter().collect();
+        // Ties broken by color, so the order doesn't change between runs
+        colors.sort_by(|(a, a_count), (b, b_count)| {
+            b_count.cmp(a_count).then(a.cmp(b))
+        });
+        Self(colors.into_iter().map(|(color, _)| color).collect())
+    }
+
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut lines = text.lines().map(str::trim);
+        anyhow::ensure!(
+            lines.next() == Some("JASC-PAL") && lines.next() == Some("0100"),
+            "not a JASC-PAL palette"
+        );
+        let count: usize = lines
+            .next()
+            .and_then(|count| count.parse().ok())
+            .ok_or_else(|| anyhow::anyhow!("missing color count"))?;
+        let colors = lines
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let channels: Vec<_> =
+                    line.split_whitespace().map(str::parse::<u8>).collect();
+                match channels[..] {
+                    [Ok(r), Ok(g), Ok(b)] => Ok([r, g, b]),
+                    _ => anyhow::bail!(
+                        "expected `r g b` color, found {:?}",
+                        line
+                    ),
+                }
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        anyhow::ensure!(
+            colors.len() == count,
+            "palette says it has {} colors, but lists {}",
+            count,
+            colors.len()
+        );
+        Ok(Self(colors))
+    }
+
+    /// Palette as text of a `.pal` file
+    pub fn to_pal(&self) -> String {
+        let mut text = format!("JASC-PAL\r\n0100\r\n{}\r\n", self.0.len());
+        for [r, g, b] in &self.0 {
+            let _ = write!(text, "{} {} {}\r\n", r, g, b);
+        }
+        text
+    }
+}
+
+/// Recolors `image`, replacing every color of `from` with the one at the
+/// same place in `to`. Other colors and alpha are kept.
+pub fn swap_palette(
+    image: &RgbaImage,
+    from: &Palette,
+    to: &Palette,
+) -> anyhow::Result<RgbaImage> {
+    anyhow::ensure!(
+        from.0.len() == to.0.len(),
+        "palettes have {} and {} colors, they need the same number",
+        from.0.len(),
+        to.0.len()
+    );
+    let swaps: HashMap<_, _> = from.0.iter().zip(&to.0).collect();
+    let mut image = image.clone();
+    for Rgba(pixel) in image.pixels_mut() {
+        let color = [pixel[0], pixel[1], pixel[2]];
+        if let Some(&&[r, g, b]) = swaps.get(&color) {
+            pixel[..3].copy_from_slice(&[r, g, b]);
+        }
+    }
+    Ok(image)
+}
+
+/// Color vision colors are compared with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Vision {
+    Normal,
+    Protanopia,
+    Deuteranopia,
+    Tritanopia,
+}
+
+impl Vision {
+    pub const ALL: [Self; 4] = [
+        Self::Normal,
+        Self::Protanopia,
+        Self::Deuteranopia,
+        Self::Tritanopia,
+    ];
+
+    /// Linear RGB `rgb` as seen with this vision, simulated as in Machado et
+    /// al. 2009 at full severity
+    fn simulate(self, rgb: [f32; 3]) -> [f32; 3] {
+        let matrix = match self {
+            Self::Normal => return rgb,
+            Self::Protanopia => [
+                [0.152286, 1.052583, -0.204868],
+                [0.114503, 0.786281, 0.099216],
+                [-0.003882, -0.048116, 1.051998],
+            ],
+            Self::Deuteranopia => [
+                [0.367322, 0.860646, -0.227968],
+                [0.280085, 0.672501, 0.047413],
+                [-0.01182, 0.04294, 0.968881],
+            ],
+            Self::Tritanopia => [
+                [1.255528, -0.076749, -0.178779],
+                [-0.078411, 0.930809, 0.147602],
+                [0.004733, 0.691367, 0.3039],
+            ],
+        };
+        matrix.map(|row| {
+            let value: f32 = row.iter().zip(rgb).map(|(m, c)| m * c).sum();
+            value.clamp(0.0, 1.0)
+        })
+    }
+}
+
+impl fmt::Display for Vision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Normal => "normal vision",
+            Self::Protanopia => "protanopia",
+            Self::Deuteranopia => "deuteranopia",
+            Self::Tritanopia => "tritanopia",
+        })
+    }
+}
+
+/// CIELAB of linear RGB `rgb`, with D65 white
+fn lab(rgb: [f32; 3]) -> [f32; 3] {
+    let [r, g, b] = rgb;
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+    let f = |t: f32| match t > 216.0 / 24389.0 {
+        true => t.cbrt(),
+        false => (24389.0 / 27.0 * t + 16.0) / 116.0,
+    };
+    let (x, y, z) = (f(x), f(y), f(z));
+    [116.0 * y - 16.0, 500.0 * (x - y), 200.0 * (y - z)]
+}
+
+/// CIE76 difference of sRGB colors `a` and `b`, channels from 0 to 255, as
+/// seen with `vision`. Around 2.3 is barely noticeable side by side.
+pub fn delta_e(a: [f32; 3], b: [f32; 3], vision: Vision) -> f32 {
+    let linear = |rgb: [f32; 3]| {
+        rgb.map(|c| {
+            let c = c / 255.0;
+            match c <= 0.04045 {
+                true => c / 12.92,
+                false => ((c + 0.055) / 1.055).powf(2.4),
+            }
+        })
+    };
+    let a = lab(vision.simulate(linear(a)));
+    let b = lab(vision.simulate(linear(b)));
+    a.iter()
+        .zip(b)
+        .map(|(a, b)| (a - b).powi(2))
+        .sum::<f32>()
+        .sqrt()
+}
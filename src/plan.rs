@@ -0,0 +1,322 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    banner_outputs, ctm_properties_path, fallback_source, locale, tile_models,
+    ArchiveSet, AtlasMap, AtlasPos, Category, Config, Layout, Namespace,
+    OutputDirs, OutputFormat, Palettes, Preset, Tile, TileDirs, GENERATED,
+};
+
+/// Single file extraction writes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedFile {
+    /// Path extraction writes it to, in output format
+    pub path: PathBuf,
+    /// Where it's written instead, the same as `path` unless changed in a
+    /// saved plan
+    pub output: PathBuf,
+    /// Archive entry it comes from, with the cell or rect it's cut out of,
+    /// `(generated)`, or none if the entry is missing
+    pub source: Option<String>,
+}
+
+/// Every file extraction writes, in order, with where it comes from. Saved
+/// as json, it can be looked over, trimmed or have outputs moved, and then
+/// be handed to `Writer::with_plan` to write just what's still listed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Plan {
+    pub files: Vec<PlannedFile>,
+}
+
+impl Plan {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("can't read {}", path.display()))?;
+        serde_json::from_str(&json)
+            .with_context(|| format!("{} isn't a valid plan", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("can't write {}", path.display()))
+    }
+
+    /// Output of every planned path
+    pub fn outputs(&self) -> HashMap<PathBuf, PathBuf> {
+        self.files
+            .iter()
+            .map(|file| (file.path.clone(), file.output.clone()))
+            .collect()
+    }
+
+    fn add(
+        &mut self,
+        format: OutputFormat,
+        path: &Path,
+        source: Option<String>,
+    ) {
+        let path = format.output_path(path);
+        self.files.push(PlannedFile {
+            output: path.clone(),
+            path,
+            source,
+        });
+    }
+
+    fn add_generated(&mut self, format: OutputFormat, path: &Path) {
+        self.add(format, path, Some(GENERATED.to_owned()));
+    }
+
+    /// Adds `.mcmeta` written along with copied texture `file`, if there's
+    /// one
+    fn add_mcmeta(
+        &mut self,
+        format: OutputFormat,
+        ns: &Namespace,
+        zips: &mut ArchiveSet,
+        file: &str,
+        output: &Path,
+    ) {
+        let source = match ns.animations.contains_key(file) {
+            true => Some(GENERATED.to_owned()),
+            false if file.ends_with(".png") => {
+                zips.locate(&format!("{}.mcmeta", file))
+            }
+            false => None,
+        };
+        if source.is_some() {
+            let mut mcmeta = output.as_os_str().to_owned();
+            mcmeta.push(".mcmeta");
+            self.add(format, Path::new(&mcmeta), source);
+        }
+    }
+
+    fn add_atlas_map(
+        &mut self,
+        format: OutputFormat,
+        atlas: &AtlasMap,
+        zips: &mut ArchiveSet,
+        dirs: &TileDirs,
+        palettes: &Palettes,
+    ) {
+        for (atlas, map) in atlas {
+            let sheet = zips.sheet(atlas);
+            let source = zips.locate(&sheet);
+            for (pos, tile) in &map.tiles {
+                for name in tile.output_names(palettes) {
+                    let output = dirs.tile_path(tile, &name);
+                    let cell = source
+                        .as_ref()
+                        .map(|source| format!("{} [{:?}]", source, pos));
+                    self.add(format, &output, cell);
+                    if tile.is_animated() {
+                        let mcmeta = output.with_extension("png.mcmeta");
+                        self.add_generated(format, &mcmeta);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Adds every file `ns` writes
+    fn add_namespace(
+        &mut self,
+        format: OutputFormat,
+        toml: &Config,
+        ns: &Namespace,
+        dirs: &OutputDirs,
+        zips: &mut ArchiveSet,
+    ) {
+        for model in &ns.models {
+            let output = dirs.entry_path(ns, Category::Models, model);
+            self.add(format, &output, zips.locate(model));
+        }
+        let textures = [
+            (&ns.gui, Category::Gui),
+            (&ns.blocks_copy, Category::Blocks),
+            (&ns.entity, Category::Entity),
+            (&ns.particle, Category::Particle),
+            (&ns.environment, Category::Environment),
+        ];
+        for (files, category) in textures {
+            for file in files {
+                let output = dirs.entry_path(ns, category, file);
+                self.add(format, &output, zips.locate(file));
+                self.add_mcmeta(format, ns, zips, file, &output);
+            }
+        }
+        for (file, to) in &ns.misc {
+            let output = dirs.namespace.join(to);
+            self.add(format, &output, zips.locate(file));
+            self.add_mcmeta(format, ns, zips, file, &output);
+        }
+        for file in &ns.sounds.files {
+            self.add(format, &dirs.sounds.join(file), zips.locate(file));
+        }
+        if !ns.sounds.files.is_empty() {
+            self.add_generated(format, &dirs.namespace.join("sounds.json"));
+        }
+        for file in &ns.lang.files {
+            let output = dirs.lang.join(locale(file)).with_extension("json");
+            self.add(format, &output, zips.locate(file));
+        }
+        for (dir, copy) in &ns.copy_dirs {
+            for file in zips.list_dir(dir).unwrap_or_default() {
+                if copy.accepts(&file) {
+                    self.add(
+                        format,
+                        &dirs.namespace.join(&copy.to).join(&file),
+                        zips.locate(&format!("{}/{}", dir, file)),
+                    );
+                }
+            }
+        }
+        for (sheet, crops) in &ns.gui_crops {
+            let source = zips.locate(sheet);
+            for crop in crops {
+                for file in crop.outputs() {
+                    let output = dirs.guis.join(&file);
+                    if output.extension().is_some_and(|ext| ext == "mcmeta") {
+                        self.add_generated(format, &output);
+                        continue;
+                    }
+                    self.add(
+                        format,
+                        &output,
+                        source.as_ref().map(|source| {
+                            format!(
+                                "{} [{}x{} at {},{}]",
+                                source, crop.w, crop.h, crop.x, crop.y
+                            )
+                        }),
+                    );
+                }
+            }
+        }
+        for (sheet, sprites) in &ns.sprite_sheets {
+            let source = zips.locate(sheet);
+            for crop in &sprites.crops {
+                self.add(
+                    format,
+                    &sprites
+                        .category
+                        .dir(dirs)
+                        .join(&crop.name)
+                        .with_extension("png"),
+                    source.as_ref().map(|source| {
+                        format!(
+                            "{} [{}x{} at {},{}]",
+                            source, crop.w, crop.h, crop.x, crop.y
+                        )
+                    }),
+                );
+            }
+        }
+        for (name, set) in &ns.ctm {
+            let sheet = zips.sheet(&set.atlas);
+            let source = zips.locate(&sheet);
+            let (columns, _) = set.method.region();
+            for i in 0..set.method.layout().0 {
+                let (x, y) = (i % columns, i / columns);
+                self.add(
+                    format,
+                    &dirs.ctm.join(name).join(format!("{}.png", i)),
+                    source.as_ref().map(|source| {
+                        let pos = AtlasPos::from_pos(
+                            set.start.x() + x as u8,
+                            set.start.y() + y as u8,
+                        );
+                        format!("{} [{:?}]", source, pos)
+                    }),
+                );
+            }
+            self.add_generated(
+                format,
+                &dirs.ctm.join(ctm_properties_path(name)),
+            );
+        }
+        let mut fonts = BTreeSet::new();
+        for (name, font) in &ns.fonts {
+            let sheet = zips.sheet(&font.sheet);
+            self.add(
+                format,
+                &dirs.fonts.join(name).with_extension("png"),
+                zips.locate(&sheet),
+            );
+            fonts.insert(font.font(name));
+        }
+        for font in fonts {
+            let output = dirs.font_providers.join(font).with_extension("json");
+            self.add_generated(format, &output);
+        }
+        for (atlas, category) in
+            [(&ns.items, Category::Items), (&ns.blocks, Category::Blocks)]
+        {
+            let tile_dirs = dirs.tile_dirs(ns, category);
+            self.add_atlas_map(format, atlas, zips, &tile_dirs, &toml.palettes);
+        }
+        for (category, name, fallback) in ns.fallbacks.iter() {
+            let dir = ns.category(name, category).dir(dirs);
+            let output = dir.join(name).with_extension("png");
+            self.add(format, &output, Some(fallback_source(fallback, zips)));
+        }
+        let auto_models = toml.auto_models.as_deref();
+        for (tile, _) in tile_models(&ns.blocks, auto_models) {
+            for name in tile.model_names() {
+                let output = dirs.models.join(name).with_extension("json");
+                self.add_generated(format, &output);
+            }
+        }
+        if toml.generate_blockstates {
+            for map in ns.blocks.values() {
+                for name in map.tiles.values().flat_map(Tile::model_names) {
+                    let output =
+                        dirs.blockstates.join(name).with_extension("json");
+                    self.add_generated(format, &output);
+                }
+            }
+        }
+        if toml.generate_item_models {
+            for map in ns.items.values() {
+                for name in map.tiles.values().flat_map(Tile::model_names) {
+                    let output =
+                        dirs.item_models.join(name).with_extension("json");
+                    self.add_generated(format, &output);
+                }
+            }
+        }
+    }
+}
+
+/// Plans extraction of `toml` into `root` laid out as `layout`, without
+/// reading anything but archive listings
+pub fn plan_extraction(
+    toml: &Config,
+    layout: &Layout,
+    root: &Path,
+    zips: &mut ArchiveSet,
+    format: OutputFormat,
+) -> Plan {
+    let banner = toml.banner_output();
+    let dirs = |ns: &Namespace| {
+        OutputDirs::new(layout, root.to_owned(), &ns.modid, banner.as_deref())
+    };
+    let mut plan = Plan::default();
+    if layout.preset == Preset::Pack {
+        plan.add_generated(format, &root.join("pack.mcmeta"));
+    }
+    for output in banner_outputs(toml, &dirs(&toml.main)) {
+        let source = toml.banner.as_ref().and_then(|b| zips.locate(b));
+        plan.add(format, &output, source);
+    }
+    for ns in toml.all_namespaces() {
+        plan.add_namespace(format, toml, ns, &dirs(ns), zips);
+    }
+    plan
+}
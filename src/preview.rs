@@ -0,0 +1,162 @@
+use image::{imageops, Rgba, RgbaImage};
+
+use crate::{Atlas, AtlasInfo, AtlasPos, MAX_CELLS};
+
+/// 3x5 pixel glyphs, one row per string, `#` is set
+const GLYPHS: &[(char, [&str; 5])] = &[
+    ('0', ["###", "#.#", "#.#", "#.#", "###"]),
+    ('1', [".#.", "##.", ".#.", ".#.", "###"]),
+    ('2', ["##.", "..#", ".#.", "#..", "###"]),
+    ('3', ["##.", "..#", ".#.", "..#", "##."]),
+    ('4', ["#.#", "#.#", "###", "..#", "..#"]),
+    ('5', ["###", "#..", "##.", "..#", "##."]),
+    ('6', [".##", "#..", "###", "#.#", "###"]),
+    ('7', ["###", "..#", ".#.", ".#.", ".#."]),
+    ('8', ["###", "#.#", "###", "#.#", "###"]),
+    ('9', ["###", "#.#", "###", "..#", "##."]),
+    ('a', [".#.", "#.#", "###", "#.#", "#.#"]),
+    ('b', ["##.", "#.#", "##.", "#.#", "##."]),
+    ('c', [".##", "#..", "#..", "#..", ".##"]),
+    ('d', ["##.", "#.#", "#.#", "#.#", "##."]),
+    ('e', ["###", "#..", "##.", "#..", "###"]),
+    ('f', ["###", "#..", "##.", "#..", "#.."]),
+    ('g', [".##", "#..", "#.#", "#.#", ".##"]),
+    ('h', ["#.#", "#.#", "###", "#.#", "#.#"]),
+    ('i', ["###", ".#.", ".#.", ".#.", "###"]),
+    ('j', ["..#", "..#", "..#", "#.#", ".#."]),
+    ('k', ["#.#", "#.#", "##.", "#.#", "#.#"]),
+    ('l', ["#..", "#..", "#..", "#..", "###"]),
+    ('m', ["#.#", "###", "###", "#.#", "#.#"]),
+    ('n', ["##.", "#.#", "#.#", "#.#", "#.#"]),
+    ('o', [".#.", "#.#", "#.#", "#.#", ".#."]),
+    ('p', ["##.", "#.#", "##.", "#..", "#.."]),
+    ('q', [".#.", "#.#", "#.#", "##.", ".##"]),
+    ('r', ["##.", "#.#", "##.", "#.#", "#.#"]),
+    ('s', [".##", "#..", ".#.", "..#", "##."]),
+    ('t', ["###", ".#.", ".#.", ".#.", ".#."]),
+    ('u', ["#.#", "#.#", "#.#", "#.#", "###"]),
+    ('v', ["#.#", "#.#", "#.#", "#.#", ".#."]),
+    ('w', ["#.#", "#.#", "###", "###", "#.#"]),
+    ('x', ["#.#", "#.#", ".#.", "#.#", "#.#"]),
+    ('y', ["#.#", "#.#", ".#.", ".#.", ".#."]),
+    ('z', ["###", "..#", ".#.", "#..", "###"]),
+    ('_', ["...", "...", "...", "...", "###"]),
+    ('-', ["...", "...", "###", "...", "..."]),
+    ('.', ["...", "...", "...", "...", ".#."]),
+    ('/', ["..#", "..#", ".#.", "#..", "#.."]),
+    ('?', ["##.", "..#", ".#.", "...", ".#."]),
+];
+
+const GLYPH_WIDTH: u32 = 3;
+const GLYPH_HEIGHT: u32 = 5;
+/// Glyph width plus spacing
+const ADVANCE: u32 = GLYPH_WIDTH + 1;
+
+const GRID: Rgba<u8> = Rgba([255, 0, 255, 255]);
+const TEXT: Rgba<u8> = Rgba([255, 255, 255, 255]);
+const SHADOW: Rgba<u8> = Rgba([0, 0, 0, 255]);
+
+fn glyph(c: char) -> &'static [&'static str; 5] {
+    let c = c.to_ascii_lowercase();
+    GLYPHS
+        .iter()
+        .find(|(glyph, _)| *glyph == c)
+        .or_else(|| GLYPHS.iter().find(|(glyph, _)| *glyph == '?'))
+        .map(|(_, rows)| rows)
+        .unwrap()
+}
+
+fn put(image: &mut RgbaImage, x: u32, y: u32, color: Rgba<u8>) {
+    if x < image.width() && y < image.height() {
+        image.put_pixel(x, y, color);
+    }
+}
+
+/// Draws text with a 1px shadow, cutting it off at `max_width`
+fn draw_text(
+    image: &mut RgbaImage,
+    x: u32,
+    y: u32,
+    max_width: u32,
+    text: &str,
+) {
+    let fits = (max_width / ADVANCE) as usize;
+    for (i, c) in text.chars().take(fits).enumerate() {
+        let x = x + i as u32 * ADVANCE;
+        for (dy, row) in glyph(c).iter().enumerate() {
+            for (dx, pixel) in row.bytes().enumerate() {
+                if pixel == b'#' {
+                    let (px, py) = (x + dx as u32, y + dy as u32);
+                    put(image, px + 1, py + 1, SHADOW);
+                    put(image, px, py, TEXT);
+                }
+            }
+        }
+    }
+}
+
+/// Enlarges the atlas by `scale` and overlays the cell grid, labeling each
+/// cell with its position and, if mapped, its name. Cells separated by
+/// gutters get both of their edges outlined.
+pub fn render_preview(
+    image: &RgbaImage,
+    atlas: Option<&Atlas>,
+    info: AtlasInfo,
+    scale: u32,
+) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let mut out = imageops::resize(
+        image,
+        width * scale,
+        height * scale,
+        imageops::FilterType::Nearest,
+    );
+    let cell = (info.tile_size + 2 * info.padding) * scale;
+    let gutters = info.margin > 0 || info.spacing > 0;
+    let columns = info.columns().min(MAX_CELLS);
+    let rows = info.rows().min(MAX_CELLS);
+    // Where cells start on the enlarged image, and end too if there are gutters
+    let edges = |cells: u32| {
+        let mut edges = vec![];
+        for i in 0..cells {
+            let start = (info.margin + i * info.pitch()) * scale;
+            edges.push(start);
+            if gutters {
+                edges.push(start + cell - 1);
+            }
+        }
+        edges
+    };
+    for y in edges(rows) {
+        for x in 0..out.width() {
+            out.put_pixel(x, y, GRID);
+        }
+    }
+    for x in edges(columns) {
+        for y in 0..out.height() {
+            out.put_pixel(x, y, GRID);
+        }
+    }
+    // Cells are labeled with keys as written, whichever corner they count
+    // from
+    let grid = (info.columns(), info.rows());
+    // Keys clashing once placed are left for validation to report
+    let resolved =
+        atlas.and_then(|atlas| atlas.with_grid(grid.0, grid.1).ok());
+    for row in 0..rows {
+        for column in 0..columns {
+            let pos = AtlasPos::from_pos(column as u8, row as u8);
+            let key = atlas.map_or(pos, |a| a.key(pos, (1, 1), grid.0, grid.1));
+            let x = (info.margin + column * info.pitch()) * scale + 2;
+            let y = (info.margin + row * info.pitch()) * scale + 2;
+            draw_text(&mut out, x, y, cell - 2, &format!("{:?}", key));
+            let tile =
+                resolved.as_ref().and_then(|atlas| atlas.tiles.get(&pos));
+            if let Some(tile) = tile {
+                let y = y + cell - GLYPH_HEIGHT - 4;
+                draw_text(&mut out, x, y, cell - 2, &tile.name);
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,266 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+
+use crate::Outcome;
+
+/// Sections whose outputs are sliced out of sheets, rather than copied
+const SLICED: [&str; 4] = ["blocks", "items", "ctm", "fonts"];
+
+/// Progress bar for each output section, e.g. models or blocks, plus what
+/// happened to the files each one wrote and how long it took
+pub struct Progress {
+    /// Nothing is drawn, counted or timed without them, so quiet progress
+    /// also works where there's no terminal or clock, like on wasm
+    bars: Option<(MultiProgress, Instant)>,
+    /// Counter of logged warnings, and its value when counting started
+    warnings: Option<(&'static AtomicUsize, usize)>,
+    current: Mutex<Option<Section>>,
+    done: Mutex<Vec<SectionSummary>>,
+}
+
+struct Section {
+    bar: ProgressBar,
+    start: Instant,
+    /// Warnings logged before it started
+    warned: usize,
+    summary: SectionSummary,
+}
+
+/// Files written by a section, or a whole run, by what happened to them
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Counts {
+    /// Created or overwritten
+    pub written: u64,
+    pub identical: u64,
+    /// Existing ones kept by `WritePolicy::Skip`
+    pub skipped: u64,
+}
+
+impl Counts {
+    fn add(&mut self, outcome: Outcome) {
+        match outcome {
+            Outcome::Created | Outcome::Overwritten => self.written += 1,
+            Outcome::Identical => self.identical += 1,
+            Outcome::Skipped => self.skipped += 1,
+        }
+    }
+
+    /// Every file, whatever happened to it
+    pub fn total(&self) -> u64 {
+        self.written + self.identical + self.skipped
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SectionSummary {
+    pub name: String,
+    /// Whether outputs were sliced out of sheets, like blocks, rather than
+    /// copied, like models
+    pub sliced: bool,
+    pub counts: Counts,
+    pub warnings: usize,
+    pub elapsed: Duration,
+}
+
+/// What a run did, section by section
+#[derive(Debug, Clone)]
+pub struct Summary {
+    pub sections: Vec<SectionSummary>,
+    /// Warnings logged since `with_warnings`, including outside of sections
+    pub warnings: usize,
+    pub elapsed: Duration,
+}
+
+impl Summary {
+    /// Counts of sliced sections, then of copied ones
+    pub fn totals(&self) -> (Counts, Counts) {
+        let (mut sliced, mut copied) = (Counts::default(), Counts::default());
+        for section in &self.sections {
+            let total = match section.sliced {
+                true => &mut sliced,
+                false => &mut copied,
+            };
+            total.written += section.counts.written;
+            total.identical += section.counts.identical;
+            total.skipped += section.counts.skipped;
+        }
+        (sliced, copied)
+    }
+}
+
+/// Part of slicing a sheet `Timings` keeps apart
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Reading the sheet out of its archive
+    Read,
+    /// Decoding it to RGBA
+    Decode,
+    /// Cutting tiles out, with transforms, upscaling and hooks
+    Crop,
+    /// Encoding and writing tiles
+    Encode,
+}
+
+/// Time spent on a sheet in each stage
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StageTimes {
+    pub read: Duration,
+    pub decode: Duration,
+    pub crop: Duration,
+    pub encode: Duration,
+}
+
+impl StageTimes {
+    fn get_mut(&mut self, stage: Stage) -> &mut Duration {
+        match stage {
+            Stage::Read => &mut self.read,
+            Stage::Decode => &mut self.decode,
+            Stage::Crop => &mut self.crop,
+            Stage::Encode => &mut self.encode,
+        }
+    }
+
+    pub fn total(&self) -> Duration {
+        self.read + self.decode + self.crop + self.encode
+    }
+}
+
+/// Time spent slicing each atlas, by stage, to tell whether a run is bound
+/// by IO or by encoding. Tiles are cut on all threads, so times are summed
+/// over threads and can add up to more than the run took.
+#[derive(Debug, Default)]
+pub struct Timings {
+    atlases: Mutex<BTreeMap<String, StageTimes>>,
+}
+
+impl Timings {
+    pub fn add(&self, atlas: &str, stage: Stage, elapsed: Duration) {
+        let mut atlases = self.atlases.lock().unwrap();
+        *atlases.entry(atlas.to_owned()).or_default().get_mut(stage) += elapsed;
+    }
+
+    /// Runs `f`, counting the time it took towards `stage` of `atlas`
+    pub fn time<T>(
+        &self,
+        atlas: &str,
+        stage: Stage,
+        f: impl FnOnce() -> T,
+    ) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.add(atlas, stage, start.elapsed());
+        result
+    }
+
+    pub fn atlases(&self) -> BTreeMap<String, StageTimes> {
+        self.atlases.lock().unwrap().clone()
+    }
+}
+
+impl Progress {
+    /// Draws into `bars`, so other output can be printed around them with
+    /// `MultiProgress::suspend`
+    pub fn new(quiet: bool, bars: MultiProgress) -> Self {
+        Self {
+            bars: (!quiet).then(|| (bars, Instant::now())),
+            warnings: None,
+            current: Mutex::default(),
+            done: Mutex::default(),
+        }
+    }
+
+    /// Neither shows bars nor keeps track of sections
+    pub fn hidden() -> Self {
+        Self {
+            bars: None,
+            warnings: None,
+            current: Mutex::default(),
+            done: Mutex::default(),
+        }
+    }
+
+    /// Counts warnings from now on by reading `warnings`, which the logger
+    /// is expected to increase for every one it logs, attributing them to
+    /// sections they happened in
+    pub fn with_warnings(self, warnings: &'static AtomicUsize) -> Self {
+        let start = warnings.load(Ordering::Relaxed);
+        Self {
+            warnings: Some((warnings, start)),
+            ..self
+        }
+    }
+
+    fn warned(&self) -> usize {
+        self.warnings.map_or(0, |(warnings, start)| {
+            warnings.load(Ordering::Relaxed) - start
+        })
+    }
+
+    /// Ends current section and starts one expecting `len` files
+    pub fn section(&self, name: &str, len: u64) {
+        let (bars, _) = match &self.bars {
+            Some(bars) => bars,
+            None => return,
+        };
+        self.end_section();
+        let bar = bars.add(ProgressBar::new(len));
+        let style =
+            ProgressStyle::with_template("{prefix:>8} [{bar:30}] {pos}/{len}")
+                .unwrap()
+                .progress_chars("=> ");
+        bar.set_style(style);
+        bar.set_prefix(name.to_owned());
+        *self.current.lock().unwrap() = Some(Section {
+            bar,
+            start: Instant::now(),
+            warned: self.warned(),
+            summary: SectionSummary {
+                name: name.to_owned(),
+                sliced: SLICED.contains(&name),
+                counts: Counts::default(),
+                warnings: 0,
+                elapsed: Duration::ZERO,
+            },
+        });
+    }
+
+    /// Counts one file towards current section
+    pub fn inc(&self, outcome: Outcome) {
+        if let Some(section) = &mut *self.current.lock().unwrap() {
+            section.bar.inc(1);
+            section.summary.counts.add(outcome);
+        }
+    }
+
+    fn end_section(&self) {
+        if let Some(section) = self.current.lock().unwrap().take() {
+            section.bar.finish_and_clear();
+            if let Some((bars, _)) = &self.bars {
+                bars.remove(&section.bar);
+            }
+            let mut summary = section.summary;
+            summary.elapsed = section.start.elapsed();
+            summary.warnings = self.warned() - section.warned;
+            self.done.lock().unwrap().push(summary);
+        }
+    }
+
+    /// Ends last section, returns summary to print, or `None` when quiet
+    pub fn finish(&self) -> Option<Summary> {
+        self.end_section();
+        let (_, start) = self.bars.as_ref()?;
+        Some(Summary {
+            sections: self.done.lock().unwrap().clone(),
+            warnings: self.warned(),
+            elapsed: start.elapsed(),
+        })
+    }
+}
@@ -0,0 +1,84 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::Config;
+
+/// Name of the record of what outputs were made from, stored in output
+/// root
+pub const PROVENANCE_FILE: &str = "tex-slasher.json";
+
+/// Keyword of the `tEXt` chunk naming where a png comes from
+pub const PROVENANCE_KEYWORD: &str = "tex-slasher source";
+
+fn sha256(hasher: Sha256) -> String {
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// sha256 of an archive, or of every file in a directory source together
+/// with its relative path
+fn hash_source(path: &Path) -> anyhow::Result<String> {
+    let mut hasher = Sha256::new();
+    if !path.is_dir() {
+        hasher.update(fs::read(path)?);
+        return Ok(sha256(hasher));
+    }
+    let mut files: Vec<PathBuf> = vec![];
+    let mut stack = vec![path.to_owned()];
+    while let Some(dir) = stack.pop() {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?.path();
+            if entry.is_dir() {
+                stack.push(entry);
+            } else {
+                files.push(entry);
+            }
+        }
+    }
+    files.sort();
+    for file in files {
+        let relative = file.strip_prefix(path)?;
+        hasher.update(relative.to_string_lossy().as_bytes());
+        hasher.update(fs::read(&file)?);
+    }
+    Ok(sha256(hasher))
+}
+
+/// Json recording what outputs of `config` are made from, to trace them
+/// back to their inputs later: tool version, sha256 of every config file
+/// together, and of every source in `input_dir`. Nested archives are
+/// covered by the one they're in.
+pub fn provenance(config: &Config, input_dir: &Path) -> anyhow::Result<String> {
+    let mut hasher = Sha256::new();
+    for file in &config.files {
+        hasher.update(fs::read(file)?);
+    }
+    let mut sources = BTreeMap::new();
+    for file in config.folders.keys() {
+        let outer = file.split('!').next().unwrap();
+        if !sources.contains_key(outer) {
+            let hash = hash_source(&input_dir.join(outer))?;
+            sources.insert(outer.to_owned(), hash);
+        }
+    }
+    let files: Vec<_> = config
+        .files
+        .iter()
+        .map(|file| file.display().to_string())
+        .collect();
+    let provenance = serde_json::json!({
+        "tool": env!("CARGO_PKG_NAME"),
+        "version": env!("CARGO_PKG_VERSION"),
+        "config": { "files": files, "sha256": sha256(hasher) },
+        "sources": sources,
+    });
+    Ok(serde_json::to_string_pretty(&provenance)?)
+}
@@ -0,0 +1,66 @@
+use serde_json::Value;
+
+use crate::TextureRenames;
+
+/// New name for texture reference `texture`: an exact entry of `renames`,
+/// or else the longest one ending with `/` that `texture` starts with, with
+/// that prefix replaced
+pub fn rename_texture(
+    renames: &TextureRenames,
+    texture: &str,
+) -> Option<String> {
+    if let Some(renamed) = renames.get(texture) {
+        return Some(renamed.clone());
+    }
+    renames
+        .iter()
+        .filter(|(from, _)| from.ends_with('/') && texture.starts_with(*from))
+        .max_by_key(|(from, _)| from.len())
+        .map(|(from, to)| format!("{}{}", to, &texture[from.len()..]))
+}
+
+/// Rewrites references in `textures` of a model json using `renames`,
+/// leaving `#variable` ones alone. Returns `None` if nothing changed, so
+/// such files can be copied byte for byte.
+pub fn rename_model_textures(
+    json: &[u8],
+    renames: &TextureRenames,
+) -> anyhow::Result<Option<String>> {
+    let mut model: Value = serde_json::from_slice(json)?;
+    let textures = match model.get_mut("textures") {
+        Some(Value::Object(textures)) => textures,
+        _ => return Ok(None),
+    };
+    let mut changed = false;
+    for texture in textures.values_mut() {
+        if let Value::String(name) = texture {
+            if name.starts_with('#') {
+                continue;
+            }
+            if let Some(renamed) = rename_texture(renames, name) {
+                changed |= *name != renamed;
+                *name = renamed;
+            }
+        }
+    }
+    Ok(changed.then(|| serde_json::to_string_pretty(&model).unwrap()))
+}
+
+/// Texture references in `textures` of a model json, skipping `#variable`
+/// ones. Files that aren't model json have none.
+pub fn model_textures(json: &[u8]) -> Vec<String> {
+    let model: Value = match serde_json::from_slice(json) {
+        Ok(model) => model,
+        Err(_) => return vec![],
+    };
+    let textures = match model.get("textures") {
+        Some(Value::Object(textures)) => textures,
+        _ => return vec![],
+    };
+    textures
+        .values()
+        .filter_map(Value::as_str)
+        .filter(|name| !name.starts_with('#'))
+        .map(str::to_owned)
+        .collect()
+}
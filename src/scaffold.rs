@@ -0,0 +1,148 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::Path,
+};
+
+use image::GenericImageView;
+use toml::Value;
+
+use crate::{decode_image, open_source, ARCHIVE_EXTENSIONS, SHEET_EXTENSIONS};
+
+/// Entries of interest found in one archive
+#[derive(Default)]
+struct Listing {
+    folders: BTreeSet<String>,
+    models: BTreeSet<String>,
+    gui: BTreeSet<String>,
+    atlases: BTreeSet<String>,
+    banner: Option<String>,
+}
+
+fn split_entry(entry: &str) -> (&str, &str) {
+    entry.rsplit_once('/').unwrap_or(("", entry))
+}
+
+/// Sheet of 16x16 cells at 16x or any higher resolution
+fn is_atlas(data: &[u8], file: &str) -> bool {
+    match decode_image(data, file) {
+        Ok(image) => {
+            let (width, height) = image.dimensions();
+            width == height && width.is_multiple_of(256)
+        }
+        Err(_) => false,
+    }
+}
+
+fn list_archive(input_dir: &Path, archive: &str) -> anyhow::Result<Listing> {
+    let mut source = open_source(input_dir, archive, 0)?;
+    let mut listing = Listing::default();
+    for name in source.list()? {
+        let (folder, file) = split_entry(&name);
+        let lower = name.to_lowercase();
+        if lower.ends_with(".json") && lower.contains("models") {
+            listing.models.insert(file.to_owned());
+        } else if SHEET_EXTENSIONS
+            .iter()
+            .any(|ext| lower.ends_with(&format!(".{}", ext)))
+        {
+            // Only atlases get converted, anything else is copied as is
+            let is_png = lower.ends_with(".png");
+            if is_png && lower.contains("gui") {
+                listing.gui.insert(file.to_owned());
+            } else if is_png
+                && (lower.contains("logo") || lower.contains("banner"))
+            {
+                listing.banner.get_or_insert_with(|| file.to_owned());
+            } else {
+                let data = source.read(&name)?.unwrap_or_default();
+                if is_atlas(&data, file) {
+                    let stem =
+                        file.rsplit_once('.').map_or(file, |(stem, _)| stem);
+                    listing.atlases.insert(stem.to_owned());
+                } else {
+                    continue;
+                }
+            }
+        } else {
+            continue;
+        }
+        listing.folders.insert(folder.to_owned());
+    }
+    Ok(listing)
+}
+
+fn modid_guess(folders: &BTreeSet<String>) -> String {
+    folders
+        .iter()
+        .find_map(|folder| {
+            let rest = folder.strip_prefix("assets/")?;
+            Some(rest.split('/').next()?.to_owned())
+        })
+        .unwrap_or_default()
+}
+
+fn quote(s: &str) -> String {
+    Value::String(s.to_owned()).to_string()
+}
+
+fn list(items: &BTreeSet<String>) -> String {
+    let items: Vec<_> = items.iter().map(|item| quote(item)).collect();
+    format!("[{}]", items.join(", "))
+}
+
+/// Builds a starter config from every archive in `input_dir`, recognized by
+/// `ARCHIVE_EXTENSIONS`: asset lists and folders are filled in, atlases get
+/// empty tables
+pub fn scaffold(input_dir: &Path) -> anyhow::Result<String> {
+    let mut archives = BTreeMap::new();
+    for entry in fs::read_dir(input_dir)? {
+        let path = entry?.path();
+        let is_archive = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ARCHIVE_EXTENSIONS.contains(&ext));
+        if is_archive {
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            let listing = list_archive(input_dir, &name)?;
+            archives.insert(name, listing);
+        }
+    }
+
+    let all = |f: fn(&Listing) -> &BTreeSet<String>| {
+        archives
+            .values()
+            .flat_map(f)
+            .cloned()
+            .collect::<BTreeSet<_>>()
+    };
+    let folders = all(|listing| &listing.folders);
+    let banner = archives
+        .values()
+        .find_map(|listing| listing.banner.clone());
+
+    let mut out = String::new();
+    out += &format!("modid = {}\n", quote(&modid_guess(&folders)));
+    if let Some(banner) = &banner {
+        out += &format!("banner = {}\n", quote(banner));
+    }
+    out += &format!("models = {}\n", list(&all(|listing| &listing.models)));
+    out += &format!("gui = {}\n", list(&all(|listing| &listing.gui)));
+    out += "blocks_copy = []\n";
+    out += "\n[folders]\n";
+    for (name, listing) in &archives {
+        out += &format!("{} = {}\n", quote(name), list(&listing.folders));
+    }
+    let mut blocks = String::from("\n[blocks]\n");
+    let mut items = String::from("\n[items]\n");
+    for atlas in all(|listing| &listing.atlases) {
+        if atlas.contains("item") {
+            items += &format!("[items.{}]\n", quote(&atlas));
+        } else {
+            blocks += &format!("[blocks.{}]\n", quote(&atlas));
+        }
+    }
+    out += &blocks;
+    out += &items;
+    Ok(out)
+}
@@ -0,0 +1,385 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{Read, Seek},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    check_hooks, check_output_names, check_texture_usage, config::read_configs,
+    expand_files, extract_namespace, fetch_sources, find_missing, locate_mods,
+    open_archive, open_source, pack_mcmeta, parse_configs, plan_extraction,
+    source::NestedSource, verify_checksums, write_banner, ArchiveSet, Config,
+    ConfigOverride, ConfigSource, Failure, Hooks, Layout, Manifest, OutputDirs,
+    OutputFormat, Plan, Preset, SliceOptions, WritePolicy, WriteRecord, Writer,
+    ZipOutput, GENERATED,
+};
+
+/// Archive given as reader
+trait ReadSeek: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
+/// Where a `Slasher` writes to
+enum Output {
+    /// Layout's default root, next to the main config
+    Default,
+    Dir(PathBuf),
+    /// Zip in pack layout
+    Zip(Box<dyn ZipOutput>),
+    /// Files kept in `Extracted::files`
+    Memory,
+}
+
+/// Whole extraction driven from code rather than the command line, e.g. by
+/// a mod's `build.rs` regenerating its assets on every build. Configs and
+/// archives can be given as paths or as readers, output can be a
+/// directory or a zip written into anything seekable. What a run read
+/// is listed for `cargo:rerun-if-changed` by `Extracted::rerun_if_changed`.
+pub struct Slasher {
+    config_paths: Vec<PathBuf>,
+    config_readers: Vec<(PathBuf, Box<dyn Read>)>,
+    overrides: Vec<ConfigOverride>,
+    base_dir: Option<PathBuf>,
+    input_dir: PathBuf,
+    archives: HashMap<String, Box<dyn ReadSeek>>,
+    output: Output,
+    policy: WritePolicy,
+    format: OutputFormat,
+    deterministic: bool,
+    pack_format: u32,
+    plan: Option<Plan>,
+}
+
+/// What a `Slasher` run read and wrote
+#[derive(Debug)]
+pub struct Extracted {
+    /// Every file read from disk: configs, files they name, and archives or
+    /// directories in input directory
+    pub inputs: Vec<PathBuf>,
+    pub records: Vec<WriteRecord>,
+    /// Output files by `/`-separated path, if kept in memory
+    pub files: BTreeMap<String, Vec<u8>>,
+}
+
+impl Extracted {
+    /// Tells cargo to run the build script again when any input changes
+    pub fn rerun_if_changed(&self) {
+        for path in &self.inputs {
+            println!("cargo:rerun-if-changed={}", path.display());
+        }
+    }
+}
+
+impl Default for Slasher {
+    fn default() -> Self {
+        Self {
+            config_paths: vec![],
+            config_readers: vec![],
+            overrides: vec![],
+            base_dir: None,
+            input_dir: PathBuf::new(),
+            archives: HashMap::new(),
+            output: Output::Default,
+            // Build scripts own their output, so it's always regenerated
+            policy: WritePolicy::Overwrite,
+            format: OutputFormat::default(),
+            deterministic: false,
+            pack_format: 6,
+            plan: None,
+        }
+    }
+}
+
+impl Slasher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds config file, or directory of them, merged like `--config`
+    pub fn with_config(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config_paths.push(path.into());
+        self
+    }
+
+    /// Adds config read out of `reader`, in format of `file`'s extension,
+    /// which is also what errors in it point at
+    pub fn with_config_reader(
+        mut self,
+        file: impl Into<PathBuf>,
+        reader: impl Read + 'static,
+    ) -> Self {
+        self.config_readers.push((file.into(), Box::new(reader)));
+        self
+    }
+
+    /// Replaces a key of the merged config, like `--set`
+    pub fn with_set(mut self, set: ConfigOverride) -> Self {
+        self.overrides.push(set);
+        self
+    }
+
+    /// Picks sections of this source mod version, like `--mod-version`
+    pub fn with_mod_version(self, version: impl Into<String>) -> Self {
+        self.with_set(ConfigOverride::mod_version(version.into()))
+    }
+
+    /// Directory relative paths in configs are based on, by default the
+    /// one of the first config file
+    pub fn with_base_dir(self, base_dir: impl Into<PathBuf>) -> Self {
+        let base_dir = Some(base_dir.into());
+        Self { base_dir, ..self }
+    }
+
+    /// Directory archives in `folders` are opened from, unless given with
+    /// `with_archive`
+    pub fn with_input_dir(self, input_dir: impl Into<PathBuf>) -> Self {
+        let input_dir = input_dir.into();
+        Self { input_dir, ..self }
+    }
+
+    /// Reads archive `name` of `folders` out of `reader` instead of input
+    /// directory
+    pub fn with_archive(
+        mut self,
+        name: impl Into<String>,
+        reader: impl Read + Seek + Send + 'static,
+    ) -> Self {
+        self.archives.insert(name.into(), Box::new(reader));
+        self
+    }
+
+    /// Writes into `dir` in config's layout
+    pub fn with_output_dir(self, dir: impl Into<PathBuf>) -> Self {
+        let output = Output::Dir(dir.into());
+        Self { output, ..self }
+    }
+
+    /// Writes a resource pack zip into `output`, like `--export-pack`
+    pub fn with_zip_output(self, output: impl ZipOutput + 'static) -> Self {
+        let output = Output::Zip(Box::new(output));
+        Self { output, ..self }
+    }
+
+    /// Keeps outputs in `Extracted::files` instead of writing them anywhere,
+    /// so with archives and configs given as readers nothing touches the
+    /// filesystem
+    pub fn with_memory_output(self) -> Self {
+        let output = Output::Memory;
+        Self { output, ..self }
+    }
+
+    /// What to do about existing outputs with other content, overwriting
+    /// them by default
+    pub fn with_policy(self, policy: WritePolicy) -> Self {
+        Self { policy, ..self }
+    }
+
+    pub fn with_format(self, format: OutputFormat) -> Self {
+        Self { format, ..self }
+    }
+
+    pub fn with_deterministic(self, deterministic: bool) -> Self {
+        Self {
+            deterministic,
+            ..self
+        }
+    }
+
+    /// `pack_format` written to `pack.mcmeta` for `pack` layout
+    pub fn with_pack_format(self, pack_format: u32) -> Self {
+        Self {
+            pack_format,
+            ..self
+        }
+    }
+
+    /// Only writes files `plan` lists, each to its `output`
+    pub fn with_plan(self, plan: Plan) -> Self {
+        Self {
+            plan: Some(plan),
+            ..self
+        }
+    }
+
+    /// Loads configs and opens archives, returning them along with every
+    /// file read from disk so far, and the directory config paths are
+    /// relative to
+    fn open(
+        &mut self,
+    ) -> anyhow::Result<(Config, ArchiveSet, Vec<PathBuf>, PathBuf)> {
+        anyhow::ensure!(
+            !self.config_paths.is_empty() || !self.config_readers.is_empty(),
+            "no config given"
+        );
+        let mut sources = match self.config_paths.is_empty() {
+            true => vec![],
+            false => read_configs(&self.config_paths)?,
+        };
+        for (file, mut reader) in std::mem::take(&mut self.config_readers) {
+            let mut text = String::new();
+            reader.read_to_string(&mut text).map_err(|e| {
+                Failure::Io.tag(anyhow::anyhow!("{}: {}", file.display(), e))
+            })?;
+            sources.push(ConfigSource { file, text });
+        }
+        let base = match (&self.base_dir, self.config_paths.first()) {
+            (Some(dir), _) => dir.clone(),
+            (None, Some(path)) => path.parent().unwrap_or(Path::new("")).into(),
+            (None, None) => PathBuf::new(),
+        };
+        let mut toml = parse_configs(&sources, &base, &self.overrides)?;
+        locate_mods(&mut toml, &self.input_dir)?;
+        let mut inputs = toml.files.clone();
+
+        let mut archives = std::mem::take(&mut self.archives);
+        for name in archives.keys() {
+            anyhow::ensure!(
+                toml.folders.contains_key(name),
+                "archive {} given, but it isn't in folders",
+                name
+            );
+        }
+        // Only ones read from input directory can be fetched or checked
+        let on_disk = |name: &String| {
+            let archive = name.split('!').next().unwrap();
+            !archives.contains_key(archive)
+        };
+        let fetched = toml.sources.clone().into_iter();
+        let fetched = fetched.filter(|(name, _)| on_disk(name)).collect();
+        fetch_sources(&fetched, &self.input_dir)?;
+        let checked = toml.checksums.clone().into_iter();
+        let checked = checked.filter(|(name, _)| on_disk(name)).collect();
+        verify_checksums(&checked, &self.input_dir, toml.nested_depth)?;
+        let depth = toml.nested_depth;
+        let input_dir = &self.input_dir;
+        let mut zips = ArchiveSet::with_sources(
+            &toml.folders,
+            &toml.priorities,
+            toml.exact_paths,
+            |name| match archives.remove(name) {
+                Some(reader) => {
+                    let archive = open_archive(reader, name)
+                        .map_err(|e| anyhow::anyhow!("{}: {}", name, e))?;
+                    Ok(Box::new(NestedSource::new(archive, depth)))
+                }
+                None => {
+                    let archive = name.split('!').next().unwrap();
+                    inputs.push(input_dir.join(archive));
+                    open_source(input_dir, name, depth)
+                }
+            },
+        )?;
+        expand_files(&mut toml, &mut zips)?;
+        check_output_names(&toml)?;
+        Ok((toml, zips, inputs, base))
+    }
+
+    /// Layout and root outputs are written with
+    fn output_root(&self, toml: &Config, base: &Path) -> (Layout, PathBuf) {
+        match &self.output {
+            Output::Zip(_) => {
+                let layout = Layout {
+                    preset: Preset::Pack,
+                    ..toml.layout.clone()
+                };
+                (layout, PathBuf::new())
+            }
+            Output::Memory => (toml.layout.clone(), PathBuf::new()),
+            Output::Dir(dir) => (toml.layout.clone(), dir.clone()),
+            Output::Default => {
+                (toml.layout.clone(), toml.layout.default_root(base))
+            }
+        }
+    }
+
+    /// Loads configs and opens archives like `run`, but only lists what it
+    /// would write, see `Plan`
+    pub fn plan(mut self) -> anyhow::Result<Plan> {
+        let (toml, mut zips, _, base) = self.open()?;
+        let (layout, root) = self.output_root(&toml, &base);
+        Ok(plan_extraction(
+            &toml,
+            &layout,
+            &root,
+            &mut zips,
+            self.format,
+        ))
+    }
+
+    /// Loads configs, opens archives and extracts everything, failing on
+    /// missing assets. Outputs are only changed if all of it succeeds.
+    pub fn run(mut self) -> anyhow::Result<Extracted> {
+        let (toml, mut zips, mut inputs, base) = self.open()?;
+        let missing = find_missing(&toml, &mut zips);
+        let list: Vec<_> = missing.iter().map(|m| format!("  {}", m)).collect();
+        if !missing.is_empty() {
+            return Err(Failure::Validation.tag(anyhow::anyhow!(
+                "{} referenced assets not found:\n{}",
+                missing.len(),
+                list.join("\n")
+            )));
+        }
+
+        let options = SliceOptions {
+            tile_size: toml.tile_size,
+            strict: false,
+            palettes: toml.palettes.clone(),
+            min_tint_delta_e: toml.min_tint_delta_e,
+            upscale: None,
+            unmapped_dir: None,
+            dedupe: None,
+            hooks: toml.script.as_deref().map(Hooks::load).transpose()?,
+        };
+        check_hooks(&toml, options.hooks.as_ref())?;
+
+        let to_disk = matches!(self.output, Output::Dir(_) | Output::Default);
+        let (layout, root) = self.output_root(&toml, &base);
+        let writer = match self.output {
+            Output::Zip(output) => Writer::zip_to(self.policy, output),
+            Output::Memory => Writer::memory(self.policy),
+            Output::Dir(_) | Output::Default => Writer::new(self.policy),
+        };
+        let writer = writer
+            .with_format(self.format)
+            .with_deterministic(self.deterministic);
+        let writer = match &self.plan {
+            Some(plan) => writer.with_plan(plan),
+            None => writer,
+        };
+        let banner = toml.banner_output();
+        let dirs = |modid: &str| {
+            OutputDirs::new(&layout, root.clone(), modid, banner.as_deref())
+        };
+        for ns in toml.all_namespaces() {
+            dirs(&ns.modid).create_all(&toml, ns, &writer)?;
+        }
+        if layout.preset == Preset::Pack {
+            let mcmeta = pack_mcmeta(self.pack_format, &toml.main.modid);
+            let path = root.join("pack.mcmeta");
+            writer.note_source(&path, GENERATED);
+            writer.write(&path, mcmeta.as_bytes())?;
+        }
+        write_banner(&toml, &mut zips, &writer, &dirs(&toml.main.modid))?;
+        let all_dirs: Vec<_> =
+            toml.all_namespaces().map(|ns| dirs(&ns.modid)).collect();
+        for (ns, dirs) in toml.all_namespaces().zip(&all_dirs) {
+            extract_namespace(
+                &toml, ns, dirs, &mut zips, &writer, &options, None,
+            )?;
+        }
+        check_texture_usage(&all_dirs, &writer, options.strict)?;
+        writer.finish()?;
+        if to_disk {
+            let mut manifest = Manifest::load(&root)?;
+            manifest.record(&writer, &root)?;
+            manifest.save(&root)?;
+        }
+        inputs.sort();
+        inputs.dedup();
+        Ok(Extracted {
+            inputs,
+            records: writer.records(),
+            files: writer.take_files(),
+        })
+    }
+}
@@ -0,0 +1,469 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs::{self, File},
+    io::{self, BufReader, Cursor, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use zip::{result::ZipError, ZipArchive};
+
+use crate::Failure;
+
+/// Something input files can be read out of, addressed by `/`-separated
+/// paths. Sent between threads, so different sources can be read at once.
+pub trait AssetSource: Send {
+    /// Reads `path` into memory, if it exists
+    fn read(&mut self, path: &str) -> anyhow::Result<Option<Vec<u8>>>;
+    /// Opens `path` to be read bit by bit, if it exists, so large files
+    /// don't have to be in memory whole. By default it's read with `read`.
+    fn stream(
+        &mut self,
+        path: &str,
+    ) -> anyhow::Result<Option<Box<dyn Read + '_>>> {
+        let data = self.read(path)?;
+        Ok(data.map(|data| Box::new(Cursor::new(data)) as Box<dyn Read>))
+    }
+    fn contains(&mut self, path: &str) -> bool;
+    /// Paths of all files, without looking into nested archives
+    fn list(&mut self) -> anyhow::Result<Vec<String>>;
+}
+
+/// Zip archive, like a mod jar
+pub struct ZipSource<R> {
+    zip: ZipArchive<R>,
+    /// Files that can be read, without directories
+    names: BTreeSet<String>,
+    skipped: Vec<(String, String)>,
+}
+
+impl<R: Read + Seek> ZipSource<R> {
+    pub fn new(reader: R) -> anyhow::Result<Self> {
+        let mut zip = ZipArchive::new(reader)?;
+        let all: Vec<_> = zip.file_names().map(str::to_owned).collect();
+        let mut names = BTreeSet::new();
+        let mut skipped = vec![];
+        // Opening an entry checks its header, encryption and compression
+        // method without decompressing it, so entries that would fail
+        // halfway through a run are found up front
+        for name in all {
+            match zip.by_name(&name) {
+                Ok(entry) if entry.is_dir() => {}
+                Ok(_) => {
+                    names.insert(name);
+                }
+                // Its message alone doesn't say what isn't supported
+                Err(ZipError::UnsupportedArchive(detail)) => {
+                    skipped.push((name, detail.to_lowercase()))
+                }
+                Err(e) => skipped.push((name, e.to_string())),
+            }
+        }
+        skipped.sort();
+        Ok(Self {
+            zip,
+            names,
+            skipped,
+        })
+    }
+
+    /// Entries that can't be read, like encrypted ones or ones compressed
+    /// with unsupported methods, with why. They're left out of the source.
+    pub fn skipped(&self) -> &[(String, String)] {
+        &self.skipped
+    }
+}
+
+impl<R: Read + Seek + Send> AssetSource for ZipSource<R> {
+    fn read(&mut self, path: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        if !self.names.contains(path) {
+            return Ok(None);
+        }
+        let mut entry = self
+            .zip
+            .by_name(path)
+            .map_err(|e| anyhow::anyhow!("{}: {}", path, e))?;
+        let mut data = Vec::with_capacity(entry.size() as usize);
+        entry
+            .read_to_end(&mut data)
+            .map_err(|e| Failure::Io.tag(anyhow::anyhow!("{}: {}", path, e)))?;
+        Ok(Some(data))
+    }
+
+    fn stream(
+        &mut self,
+        path: &str,
+    ) -> anyhow::Result<Option<Box<dyn Read + '_>>> {
+        if !self.names.contains(path) {
+            return Ok(None);
+        }
+        let entry = self
+            .zip
+            .by_name(path)
+            .map_err(|e| anyhow::anyhow!("{}: {}", path, e))?;
+        Ok(Some(Box::new(entry)))
+    }
+
+    fn contains(&mut self, path: &str) -> bool {
+        self.names.contains(path)
+    }
+
+    fn list(&mut self) -> anyhow::Result<Vec<String>> {
+        Ok(self.names.iter().cloned().collect())
+    }
+}
+
+/// Files read into memory up front, like entries of archives that can't be
+/// read one at a time
+pub struct MemorySource(BTreeMap<String, Vec<u8>>);
+
+impl MemorySource {
+    pub fn new(files: BTreeMap<String, Vec<u8>>) -> Self {
+        Self(files)
+    }
+}
+
+impl AssetSource for MemorySource {
+    fn read(&mut self, path: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self.0.get(path).cloned())
+    }
+
+    fn contains(&mut self, path: &str) -> bool {
+        self.0.contains_key(path)
+    }
+
+    fn list(&mut self) -> anyhow::Result<Vec<String>> {
+        Ok(self.0.keys().cloned().collect())
+    }
+}
+
+/// Already extracted archive
+pub struct DirSource(PathBuf);
+
+impl DirSource {
+    pub fn new(root: PathBuf) -> Self {
+        Self(root)
+    }
+
+    /// Only plain relative paths, so entries can't escape the directory
+    fn path(&self, path: &str) -> Option<PathBuf> {
+        let path = Path::new(path.trim_start_matches('/'));
+        let plain = path
+            .components()
+            .all(|c| matches!(c, std::path::Component::Normal(_)));
+        plain.then(|| self.0.join(path))
+    }
+}
+
+impl AssetSource for DirSource {
+    fn read(&mut self, path: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let path = match self.path(path) {
+            Some(path) if path.is_file() => path,
+            _ => return Ok(None),
+        };
+        match fs::read(path) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn stream(
+        &mut self,
+        path: &str,
+    ) -> anyhow::Result<Option<Box<dyn Read + '_>>> {
+        let path = match self.path(path) {
+            Some(path) if path.is_file() => path,
+            _ => return Ok(None),
+        };
+        match File::open(path) {
+            Ok(file) => Ok(Some(Box::new(BufReader::new(file)))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn contains(&mut self, path: &str) -> bool {
+        self.path(path).is_some_and(|path| path.is_file())
+    }
+
+    fn list(&mut self) -> anyhow::Result<Vec<String>> {
+        let mut files = vec![];
+        let mut stack = vec![self.0.clone()];
+        while let Some(dir) = stack.pop() {
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                if path.is_dir() {
+                    stack.push(path);
+                    continue;
+                }
+                let relative = path.strip_prefix(&self.0)?;
+                let parts: Vec<_> = relative
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect();
+                files.push(parts.join("/"));
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+}
+
+/// Source that also looks inside archives stored in it, for entries like
+/// `inner.jar!textures/blocks.png`, up to `depth` levels down
+pub struct NestedSource {
+    inner: Box<dyn AssetSource>,
+    depth: u32,
+    opened: HashMap<String, NestedSource>,
+}
+
+impl NestedSource {
+    pub fn new(inner: Box<dyn AssetSource>, depth: u32) -> Self {
+        Self {
+            inner,
+            depth,
+            opened: HashMap::new(),
+        }
+    }
+
+    fn open(&mut self, archive: &str) -> anyhow::Result<Option<&mut Self>> {
+        anyhow::ensure!(
+            self.depth > 0,
+            "{}: archive nested deeper than allowed",
+            archive
+        );
+        if !self.opened.contains_key(archive) {
+            let data = match self.inner.read(archive)? {
+                Some(data) => data,
+                None => return Ok(None),
+            };
+            let source = open_archive(Cursor::new(data), archive)?;
+            let nested = Self::new(source, self.depth - 1);
+            self.opened.insert(archive.to_owned(), nested);
+        }
+        Ok(self.opened.get_mut(archive))
+    }
+}
+
+impl AssetSource for NestedSource {
+    fn read(&mut self, path: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        match path.split_once('!') {
+            None => self.inner.read(path),
+            Some((archive, rest)) => match self.open(archive)? {
+                Some(nested) => nested.read(rest),
+                None => Ok(None),
+            },
+        }
+    }
+
+    fn stream(
+        &mut self,
+        path: &str,
+    ) -> anyhow::Result<Option<Box<dyn Read + '_>>> {
+        match path.split_once('!') {
+            None => self.inner.stream(path),
+            Some((archive, rest)) => match self.open(archive)? {
+                Some(nested) => nested.stream(rest),
+                None => Ok(None),
+            },
+        }
+    }
+
+    fn contains(&mut self, path: &str) -> bool {
+        match path.split_once('!') {
+            None => self.inner.contains(path),
+            Some((archive, rest)) => match self.open(archive) {
+                Ok(Some(nested)) => nested.contains(rest),
+                _ => false,
+            },
+        }
+    }
+
+    fn list(&mut self) -> anyhow::Result<Vec<String>> {
+        self.inner.list()
+    }
+}
+
+/// Extensions of files `scaffold` treats as archives
+pub const ARCHIVE_EXTENSIONS: [&str; 7] =
+    ["zip", "jar", "tar", "gz", "tgz", "7z", "rar"];
+
+/// Formats archives can be in, told apart by their magic bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArchiveFormat {
+    Zip,
+    Tar,
+    TarGz,
+    SevenZ,
+    Rar,
+}
+
+impl ArchiveFormat {
+    /// Anything unrecognized is taken as zip, like jars
+    fn detect(reader: &mut (impl Read + Seek)) -> anyhow::Result<Self> {
+        let mut header = vec![];
+        reader.by_ref().take(262).read_to_end(&mut header)?;
+        reader.seek(SeekFrom::Start(0))?;
+        Ok(if header.starts_with(b"7z\xbc\xaf\x27\x1c") {
+            Self::SevenZ
+        } else if header.starts_with(b"Rar!\x1a\x07") {
+            Self::Rar
+        } else if header.starts_with(&[0x1f, 0x8b]) {
+            Self::TarGz
+        } else if header.get(257..262) == Some(b"ustar") {
+            Self::Tar
+        } else {
+            Self::Zip
+        })
+    }
+}
+
+/// Opens an archive in any supported format: zip or jar, tar, tar.gz or 7z.
+/// Only zips are read lazily, others are read into memory whole. rar
+/// archives can only be opened from disk, by `open_source`.
+pub fn open_archive(
+    mut reader: impl Read + Seek + Send + 'static,
+    name: &str,
+) -> anyhow::Result<Box<dyn AssetSource>> {
+    Ok(match ArchiveFormat::detect(&mut reader)? {
+        ArchiveFormat::Zip => {
+            let zip = ZipSource::new(reader)?;
+            for (entry, reason) in zip.skipped() {
+                log::warn!("{}: skipping {}: {}", name, entry, reason);
+            }
+            Box::new(zip)
+        }
+        ArchiveFormat::Tar => Box::new(read_tar(reader, name)?),
+        ArchiveFormat::TarGz => Box::new(read_tar_gz(reader, name)?),
+        ArchiveFormat::SevenZ => Box::new(read_7z(reader, name)?),
+        ArchiveFormat::Rar => {
+            anyhow::bail!("{}: rar archives can only be read from disk", name)
+        }
+    })
+}
+
+#[cfg(feature = "tar")]
+fn read_tar(reader: impl Read, _: &str) -> anyhow::Result<MemorySource> {
+    let mut files = BTreeMap::new();
+    for entry in tar::Archive::new(reader).entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path()?.to_string_lossy().into_owned();
+        let mut data = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut data)?;
+        files.insert(path.trim_start_matches("./").to_owned(), data);
+    }
+    Ok(MemorySource::new(files))
+}
+
+#[cfg(feature = "tar")]
+fn read_tar_gz(reader: impl Read, name: &str) -> anyhow::Result<MemorySource> {
+    read_tar(flate2::read::GzDecoder::new(reader), name)
+}
+
+#[cfg(not(feature = "tar"))]
+fn read_tar(_: impl Read, name: &str) -> anyhow::Result<MemorySource> {
+    anyhow::bail!("{}: tar archives need the `tar` feature", name)
+}
+
+#[cfg(not(feature = "tar"))]
+fn read_tar_gz(reader: impl Read, name: &str) -> anyhow::Result<MemorySource> {
+    read_tar(reader, name)
+}
+
+#[cfg(feature = "sevenz")]
+fn read_7z(
+    mut reader: impl Read + Seek,
+    _: &str,
+) -> anyhow::Result<MemorySource> {
+    use sevenz_rust::{Error, Password, SevenZReader};
+
+    let len = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(0))?;
+    let mut archive = SevenZReader::new(reader, len, Password::empty())?;
+    let mut files = BTreeMap::new();
+    // Solid archives are compressed as a whole, so every entry is read in
+    // order anyway
+    archive.for_each_entries(|entry, data| {
+        if !entry.is_directory() {
+            let mut bytes = Vec::with_capacity(entry.size() as usize);
+            data.read_to_end(&mut bytes).map_err(Error::io)?;
+            files.insert(entry.name().to_owned(), bytes);
+        }
+        Ok(true)
+    })?;
+    Ok(MemorySource::new(files))
+}
+
+#[cfg(not(feature = "sevenz"))]
+fn read_7z(_: impl Read + Seek, name: &str) -> anyhow::Result<MemorySource> {
+    anyhow::bail!("{}: 7z archives need the `sevenz` feature", name)
+}
+
+/// unrar only reads archives from files, so these can't be nested
+#[cfg(feature = "rar")]
+fn read_rar(path: &Path) -> anyhow::Result<MemorySource> {
+    let mut files = BTreeMap::new();
+    let mut archive = unrar::Archive::new(path).open_for_processing()?;
+    while let Some(header) = archive.read_header()? {
+        archive = if header.entry().is_file() {
+            let parts: Vec<_> = header
+                .entry()
+                .filename
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                .collect();
+            let (data, rest) = header.read()?;
+            files.insert(parts.join("/"), data);
+            rest
+        } else {
+            header.skip()?
+        };
+    }
+    Ok(MemorySource::new(files))
+}
+
+#[cfg(not(feature = "rar"))]
+fn read_rar(path: &Path) -> anyhow::Result<MemorySource> {
+    anyhow::bail!("{}: rar archives need the `rar` feature", path.display())
+}
+
+/// Opens `name` in `input_dir` as a directory source if it's a directory,
+/// or as an archive in any format `open_archive` supports, or rar,
+/// otherwise. Names like `outer.zip!inner.jar` open an archive
+/// stored inside another one. Entries can be nested `depth` levels deep,
+/// counting the name itself.
+pub fn open_source(
+    input_dir: &Path,
+    name: &str,
+    depth: u32,
+) -> anyhow::Result<Box<dyn AssetSource>> {
+    let mut parts = name.split('!');
+    let archive = parts.next().unwrap();
+    let path = input_dir.join(archive);
+    let mut source: Box<dyn AssetSource> = if path.is_dir() {
+        Box::new(DirSource::new(path))
+    } else {
+        let mut file = File::open(&path)?;
+        match ArchiveFormat::detect(&mut file)? {
+            ArchiveFormat::Rar => Box::new(read_rar(&path)?),
+            _ => open_archive(file, archive)?,
+        }
+    };
+    let mut depth = depth;
+    for part in parts {
+        anyhow::ensure!(
+            depth > 0,
+            "{}: archive nested deeper than allowed",
+            name
+        );
+        depth -= 1;
+        let data = source
+            .read(part)?
+            .ok_or_else(|| anyhow::anyhow!("{}: {} not found", name, part))?;
+        source = open_archive(Cursor::new(data), part)?;
+    }
+    Ok(Box::new(NestedSource::new(source, depth)))
+}
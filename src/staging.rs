@@ -0,0 +1,168 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Suffix of files waiting to be moved into place
+const STAGED: &str = "tex-slasher-new";
+/// Suffix of files being replaced, kept until every output is in place
+const BACKUP: &str = "tex-slasher-old";
+
+/// Hidden file next to `path`, so renaming it over `path` stays on the same
+/// filesystem
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.{}", name, suffix))
+}
+
+/// Outputs written next to where they go, only moved into place once the
+/// whole run succeeded. Dropped without `commit`, everything staged is
+/// discarded and the output tree is left as it was.
+#[derive(Default)]
+pub struct Staging {
+    /// Staged file for every output path
+    files: BTreeMap<PathBuf, PathBuf>,
+    /// Directories that didn't exist before, parents first
+    dirs: Vec<PathBuf>,
+    /// Tells apart files staged for the same path more than once
+    next: usize,
+    done: bool,
+}
+
+impl Staging {
+    /// Content `path` will have once committed
+    pub fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(self.files.get(path).map_or(path, PathBuf::as_path))
+    }
+
+    /// Creates `dir` and its missing parents, remembering which were new
+    pub fn create_dir_all(&mut self, dir: &Path) -> io::Result<()> {
+        let mut missing: Vec<_> = dir
+            .ancestors()
+            .take_while(|dir| !dir.as_os_str().is_empty() && !dir.exists())
+            .map(Path::to_owned)
+            .collect();
+        fs::create_dir_all(dir)?;
+        missing.reverse();
+        self.dirs.extend(missing);
+        Ok(())
+    }
+
+    /// Name for the next file staged for `path`
+    pub fn reserve(&mut self, path: &Path) -> PathBuf {
+        self.next += 1;
+        sibling(path, &format!("{}.{}", self.next, STAGED))
+    }
+
+    /// Plans `staged` to replace `path`, dropping what was staged for it
+    /// before
+    pub fn insert(&mut self, path: &Path, staged: PathBuf) {
+        if let Some(old) = self.files.insert(path.to_owned(), staged) {
+            let _ = fs::remove_file(old);
+        }
+    }
+
+    /// Moves every staged file into place. If one can't be, those already
+    /// moved are put back, and the error says what could and couldn't be
+    /// undone.
+    pub fn commit(&mut self) -> anyhow::Result<()> {
+        self.done = true;
+        let files = std::mem::take(&mut self.files);
+        let mut applied = vec![];
+        let mut failed = None;
+        let mut pending = files.iter();
+        for (path, staged) in pending.by_ref() {
+            match apply(path, staged) {
+                Ok(backup) => applied.push((path, backup)),
+                Err(e) => {
+                    let _ = fs::remove_file(staged);
+                    failed = Some((path, e));
+                    break;
+                }
+            }
+        }
+        let (path, error) = match failed {
+            Some(failed) => failed,
+            None => {
+                for backup in applied.into_iter().filter_map(|(_, b)| b) {
+                    if let Err(e) = fs::remove_file(&backup) {
+                        log::warn!("can't remove {}: {}", backup.display(), e);
+                    }
+                }
+                return Ok(());
+            }
+        };
+        for (_, staged) in pending {
+            let _ = fs::remove_file(staged);
+        }
+        let mut stale = String::new();
+        for (path, backup) in applied.iter().rev() {
+            let undone = match backup {
+                Some(backup) => fs::rename(backup, path),
+                None => fs::remove_file(path),
+            };
+            if let Err(e) = undone {
+                let _ = write!(stale, "\n  {}: {}", path.display(), e);
+            }
+        }
+        self.remove_dirs();
+        let mut message = format!(
+            "can't write {}: {}\n{} of {} outputs were in place",
+            path.display(),
+            error,
+            applied.len(),
+            files.len(),
+        );
+        if stale.is_empty() {
+            message += ", rolled back, output is as before the run";
+        } else {
+            message += ", couldn't roll these back:";
+            message += &stale;
+        }
+        anyhow::bail!(message)
+    }
+
+    /// Removes directories created for outputs, if they're empty again
+    fn remove_dirs(&mut self) {
+        for dir in self.dirs.drain(..).rev() {
+            let _ = fs::remove_dir(dir);
+        }
+    }
+}
+
+/// Moves `staged` to `path`, returning where what it replaces was kept
+fn apply(path: &Path, staged: &Path) -> io::Result<Option<PathBuf>> {
+    let backup = sibling(path, BACKUP);
+    let backup = match fs::rename(path, &backup) {
+        Ok(()) => Some(backup),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    };
+    if let Err(e) = fs::rename(staged, path) {
+        if let Some(backup) = &backup {
+            let _ = fs::rename(backup, path);
+        }
+        return Err(e);
+    }
+    Ok(backup)
+}
+
+impl Drop for Staging {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        if !self.files.is_empty() {
+            log::warn!(
+                "run failed, discarding {} outputs, none were written",
+                self.files.len()
+            );
+        }
+        for staged in self.files.values() {
+            let _ = fs::remove_file(staged);
+        }
+        self.remove_dirs();
+    }
+}
@@ -0,0 +1,408 @@
+//! Fixtures and golden outputs for regression tests of configs, without
+//! shipping real mod jars
+
+use std::{
+    collections::BTreeSet,
+    fs,
+    io::{Cursor, Write},
+    path::{Path, PathBuf},
+};
+
+use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+use zip::{write::FileOptions, ZipWriter};
+
+use crate::{Extracted, OutputFormat, Slasher};
+
+/// Environment variable that, set to anything but empty, makes
+/// `assert_golden` write outputs as the new goldens instead of comparing
+pub const BLESS_VAR: &str = "TEX_SLASHER_BLESS";
+
+/// Color filling cell `x`, `y` of `grid_sheet`, different for every cell of
+/// a sheet up to 64 by 64
+pub fn cell_color(x: u32, y: u32) -> Rgba<u8> {
+    let channel = |n: u32| (n % 8 * 32 + 16) as u8;
+    let blue = (x / 8 % 8 * 8 + y / 8 % 8) * 4 + 2;
+    Rgba([channel(x), channel(y), blue as u8, 255])
+}
+
+/// Atlas `columns` by `rows` cells of `tile_size`, each filled with
+/// `cell_color` and with a white top-left pixel, so rotations and flips
+/// show
+pub fn grid_sheet(columns: u32, rows: u32, tile_size: u32) -> RgbaImage {
+    RgbaImage::from_fn(columns * tile_size, rows * tile_size, |x, y| {
+        match (x % tile_size, y % tile_size) {
+            (0, 0) => Rgba([255; 4]),
+            _ => cell_color(x / tile_size, y / tile_size),
+        }
+    })
+}
+
+/// Tile `grid_sheet` has at cell `x`, `y`
+pub fn grid_cell(x: u32, y: u32, tile_size: u32) -> RgbaImage {
+    let sheet = grid_sheet(x + 1, y + 1, tile_size);
+    let (x, y) = (x * tile_size, y * tile_size);
+    sheet.view(x, y, tile_size, tile_size).to_image()
+}
+
+/// `image` as png
+pub fn encode_png(image: &RgbaImage) -> Vec<u8> {
+    let image = DynamicImage::ImageRgba8(image.clone());
+    OutputFormat::Png
+        .encode(&image)
+        .expect("encoding png in memory")
+}
+
+/// Zip holding `files`, as `/`-separated path and content
+pub fn zip_of<'a>(
+    files: impl IntoIterator<Item = (&'a str, Vec<u8>)>,
+) -> anyhow::Result<Vec<u8>> {
+    let mut zip = ZipWriter::new(Cursor::new(vec![]));
+    for (path, data) in files {
+        zip.start_file(path, FileOptions::default())?;
+        zip.write_all(&data)?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+/// Config and archives of an extraction run entirely in memory
+#[derive(Debug, Clone)]
+pub struct Fixture {
+    config: String,
+    archives: Vec<(String, Vec<u8>)>,
+}
+
+impl Fixture {
+    /// Fixture with `config` as its only config, in toml
+    pub fn new(config: impl Into<String>) -> Self {
+        Self {
+            config: config.into(),
+            archives: vec![],
+        }
+    }
+
+    /// Adds archive `name` of `folders`, e.g. made by `zip_of`
+    pub fn with_archive(
+        mut self,
+        name: impl Into<String>,
+        data: Vec<u8>,
+    ) -> Self {
+        self.archives.push((name.into(), data));
+        self
+    }
+
+    /// `Slasher` reading this fixture and keeping outputs in memory, for
+    /// more settings before running it
+    pub fn slasher(self) -> Slasher {
+        let config = Cursor::new(self.config.into_bytes());
+        let mut slasher = Slasher::new()
+            .with_config_reader("fixture.toml", config)
+            .with_memory_output();
+        for (name, data) in self.archives {
+            slasher = slasher.with_archive(name, Cursor::new(data));
+        }
+        slasher
+    }
+
+    pub fn run(self) -> anyhow::Result<Extracted> {
+        self.slasher().run()
+    }
+}
+
+/// Decoded output `path`, panicking if it wasn't written
+pub fn output_image(extracted: &Extracted, path: &str) -> RgbaImage {
+    let data = extracted.files.get(path).unwrap_or_else(|| {
+        let files: Vec<_> = extracted.files.keys().collect();
+        panic!("no output {}, only {:?}", path, files)
+    });
+    image::load_from_memory(data)
+        .unwrap_or_else(|e| panic!("output {}: {}", path, e))
+        .into_rgba8()
+}
+
+/// Panics, naming the first differing pixel, unless `actual` and `expected`
+/// are the same size with the same pixels
+pub fn assert_image_eq(what: &str, actual: &RgbaImage, expected: &RgbaImage) {
+    assert_eq!(
+        actual.dimensions(),
+        expected.dimensions(),
+        "{}: size differs",
+        what
+    );
+    let differing = actual
+        .enumerate_pixels()
+        .zip(expected.pixels())
+        .filter(|((_, _, a), e)| a != e);
+    let (count, first) = differing.fold((0, None), |(count, first), p| {
+        (count + 1, first.or(Some(p)))
+    });
+    if let Some(((x, y, a), e)) = first {
+        panic!(
+            "{}: {} pixels differ, first at {},{}: {:?}, expected {:?}",
+            what, count, x, y, a.0, e.0
+        );
+    }
+}
+
+/// Panics unless output `path` has the same pixels as `expected`
+pub fn assert_output(extracted: &Extracted, path: &str, expected: &RgbaImage) {
+    assert_image_eq(path, &output_image(extracted, path), expected);
+}
+
+/// Compares every output against the file with the same path in
+/// `golden_dir`: pngs by pixels, so encoder settings don't matter, other
+/// files byte for byte. Missing, extra and differing files are all listed
+/// in one panic. With `TEX_SLASHER_BLESS` set, the outputs are written as
+/// the goldens instead, replacing the whole directory.
+pub fn assert_golden(extracted: &Extracted, golden_dir: impl AsRef<Path>) {
+    let golden_dir = golden_dir.as_ref();
+    let bless = std::env::var_os(BLESS_VAR).is_some_and(|v| !v.is_empty());
+    if bless {
+        bless_golden(extracted, golden_dir).unwrap_or_else(|e| {
+            panic!("writing goldens to {}: {}", golden_dir.display(), e)
+        });
+        return;
+    }
+    let problems = golden_problems(extracted, golden_dir).unwrap_or_else(|e| {
+        panic!(
+            "reading goldens in {}: {}, set {}=1 to write them",
+            golden_dir.display(),
+            e,
+            BLESS_VAR
+        )
+    });
+    assert!(
+        problems.is_empty(),
+        "outputs differ from goldens in {}, set {}=1 to update them:\n{}",
+        golden_dir.display(),
+        BLESS_VAR,
+        problems.join("\n")
+    );
+}
+
+/// Every output missing from, extra to or differing from `golden_dir`
+fn golden_problems(
+    extracted: &Extracted,
+    golden_dir: &Path,
+) -> anyhow::Result<Vec<String>> {
+    let goldens = golden_files(golden_dir)?;
+    let mut problems = vec![];
+    for (path, data) in &extracted.files {
+        if !goldens.contains(path) {
+            problems.push(format!("{}: not in goldens", path));
+            continue;
+        }
+        let golden = fs::read(golden_dir.join(path))?;
+        if let Some(problem) = compare(path, data, &golden) {
+            problems.push(problem);
+        }
+    }
+    for path in &goldens {
+        if !extracted.files.contains_key(path) {
+            problems.push(format!("{}: not written", path));
+        }
+    }
+    Ok(problems)
+}
+
+/// What's different between output `path` and its golden, if anything
+fn compare(path: &str, data: &[u8], golden: &[u8]) -> Option<String> {
+    if data == golden {
+        return None;
+    }
+    if !path.ends_with(".png") {
+        return Some(format!("{}: content differs", path));
+    }
+    let decode = |data| image::load_from_memory(data).map(|i| i.into_rgba8());
+    match (decode(data), decode(golden)) {
+        (Ok(actual), Ok(golden)) => {
+            if actual.dimensions() != golden.dimensions() {
+                return Some(format!(
+                    "{}: {:?}, golden is {:?}",
+                    path,
+                    actual.dimensions(),
+                    golden.dimensions()
+                ));
+            }
+            let differing = actual
+                .pixels()
+                .zip(golden.pixels())
+                .filter(|(a, g)| a != g)
+                .count();
+            match differing {
+                0 => None,
+                n => Some(format!("{}: {} pixels differ", path, n)),
+            }
+        }
+        (Err(e), _) => Some(format!("{}: {}", path, e)),
+        (_, Err(e)) => Some(format!("{}: golden: {}", path, e)),
+    }
+}
+
+/// `/`-separated paths of every file under `dir`
+fn golden_files(dir: &Path) -> anyhow::Result<BTreeSet<String>> {
+    let mut files = BTreeSet::new();
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(relative) = dirs.pop() {
+        for entry in fs::read_dir(dir.join(&relative))? {
+            let entry = entry?;
+            let path = relative.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                dirs.push(path);
+            } else {
+                let parts: Vec<_> = path
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect();
+                files.insert(parts.join("/"));
+            }
+        }
+    }
+    Ok(files)
+}
+
+fn bless_golden(extracted: &Extracted, dir: &Path) -> anyhow::Result<()> {
+    if dir.exists() {
+        fs::remove_dir_all(dir)?;
+    }
+    for (path, data) in &extracted.files {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, data)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+        modid = "demo"
+        tile_size = 4
+
+        [folders]
+        "demo.jar" = ["textures"]
+
+        [blocks.terrain]
+        00 = "stone"
+        02 = { name = "lava", frames = 2, frametime = 3 }
+        13 = { name = "ore", rotate = 90 }
+    "#;
+
+    const STONE: &str = "assets/demo/textures/block/stone.png";
+    const LAVA: &str = "assets/demo/textures/block/lava.png";
+    const LAVA_MCMETA: &str = "assets/demo/textures/block/lava.png.mcmeta";
+    const ORE: &str = "assets/demo/textures/block/ore.png";
+
+    fn extract() -> Extracted {
+        let sheet = encode_png(&grid_sheet(4, 2, 4));
+        let jar = zip_of([("textures/terrain.png", sheet)]).unwrap();
+        Fixture::new(CONFIG).with_archive("demo.jar", jar).run().unwrap()
+    }
+
+    fn golden_dir() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/grid")
+    }
+
+    /// Empty directory of its own for each test
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("tex-slasher-{}-{}", name, std::process::id()));
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn fixture_outputs_cells() {
+        let extracted = extract();
+        assert_output(&extracted, STONE, &grid_cell(0, 0, 4));
+        let ore = image::imageops::rotate90(&grid_cell(3, 1, 4));
+        assert_output(&extracted, ORE, &ore);
+        let mut lava = RgbaImage::new(4, 8);
+        image::imageops::replace(&mut lava, &grid_cell(2, 0, 4), 0, 0);
+        image::imageops::replace(&mut lava, &grid_cell(2, 1, 4), 0, 4);
+        assert_output(&extracted, LAVA, &lava);
+    }
+
+    #[test]
+    fn cell_colors_differ() {
+        let colors: BTreeSet<_> = (0..64)
+            .flat_map(|y| (0..64).map(move |x| cell_color(x, y).0))
+            .collect();
+        assert_eq!(colors.len(), 64 * 64);
+    }
+
+    #[test]
+    fn fixture_matches_goldens() {
+        assert_golden(&extract(), golden_dir());
+    }
+
+    #[test]
+    fn blessing_replaces_goldens() {
+        let dir = scratch_dir("bless");
+        fs::create_dir_all(dir.join("stale")).unwrap();
+        fs::write(dir.join("stale/old.png"), b"old").unwrap();
+        let extracted = extract();
+        bless_golden(&extracted, &dir).unwrap();
+        let files = golden_files(&dir).unwrap();
+        let written: BTreeSet<_> = extracted.files.keys().cloned().collect();
+        assert_eq!(files, written);
+        assert!(golden_problems(&extracted, &dir).unwrap().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn golden_problems_are_all_listed() {
+        let dir = scratch_dir("problems");
+        let extracted = extract();
+        bless_golden(&extracted, &dir).unwrap();
+        fs::remove_file(dir.join(ORE)).unwrap();
+        fs::write(dir.join("assets/demo/extra.txt"), b"extra").unwrap();
+        fs::write(dir.join(LAVA_MCMETA), b"{}").unwrap();
+        let mut stone = grid_cell(0, 0, 4);
+        stone.put_pixel(1, 1, Rgba([0; 4]));
+        stone.put_pixel(2, 2, Rgba([0; 4]));
+        fs::write(dir.join(STONE), encode_png(&stone)).unwrap();
+        // Same pixels, encoded differently, isn't a problem
+        let lava = output_image(&extracted, LAVA);
+        let lava = DynamicImage::ImageRgba8(lava).into_rgb8();
+        lava.save(dir.join(LAVA)).unwrap();
+
+        let problems = golden_problems(&extracted, &dir).unwrap();
+        assert_eq!(
+            problems,
+            [
+                format!("{}: content differs", LAVA_MCMETA),
+                format!("{}: not in goldens", ORE),
+                format!("{}: 2 pixels differ", STONE),
+                "assets/demo/extra.txt: not written".to_owned(),
+            ]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "outputs differ from goldens")]
+    fn differing_goldens_panic() {
+        let dir = scratch_dir("panic");
+        let extracted = extract();
+        bless_golden(&extracted, &dir).unwrap();
+        fs::remove_file(dir.join(STONE)).unwrap();
+        assert_golden(&extracted, &dir);
+    }
+
+    #[test]
+    fn image_mismatch_is_reported() {
+        let mut other = grid_cell(0, 0, 4);
+        other.put_pixel(3, 0, Rgba([1, 2, 3, 4]));
+        let result = std::panic::catch_unwind(|| {
+            assert_image_eq("tile", &other, &grid_cell(0, 0, 4))
+        });
+        let message = result.unwrap_err();
+        let message = message.downcast_ref::<String>().unwrap();
+        let expected = "1 pixels differ, first at 3,0";
+        assert!(message.contains(expected), "{}", message);
+    }
+}
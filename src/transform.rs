@@ -0,0 +1,252 @@
+use std::{convert::TryFrom, fmt, str::FromStr};
+
+use image::{imageops, GenericImageView, Luma, Rgba, RgbaImage};
+use serde::Deserialize;
+
+use crate::Grayscale;
+
+/// Clockwise rotation, in multiples of 90 degrees
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(try_from = "u32")]
+pub enum Rotation {
+    #[default]
+    None,
+    Cw90,
+    Cw180,
+    Cw270,
+}
+
+impl TryFrom<u32> for Rotation {
+    type Error = String;
+
+    fn try_from(degrees: u32) -> Result<Self, Self::Error> {
+        match degrees {
+            0 => Ok(Self::None),
+            90 => Ok(Self::Cw90),
+            180 => Ok(Self::Cw180),
+            270 => Ok(Self::Cw270),
+            _ => Err(format!(
+                "rotation must be 0, 90, 180 or 270, not {}",
+                degrees
+            )),
+        }
+    }
+}
+
+impl Rotation {
+    fn inverse(self) -> Self {
+        match self {
+            Self::Cw90 => Self::Cw270,
+            Self::Cw270 => Self::Cw90,
+            other => other,
+        }
+    }
+
+    fn apply(self, image: &RgbaImage) -> RgbaImage {
+        match self {
+            Self::None => image.clone(),
+            Self::Cw90 => imageops::rotate90(image),
+            Self::Cw180 => imageops::rotate180(image),
+            Self::Cw270 => imageops::rotate270(image),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Flip {
+    Horizontal,
+    Vertical,
+}
+
+impl Flip {
+    fn apply(self, image: &RgbaImage) -> RgbaImage {
+        match self {
+            Self::Horizontal => imageops::flip_horizontal(image),
+            Self::Vertical => imageops::flip_vertical(image),
+        }
+    }
+}
+
+/// RGB color written as `#rrggbb`
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Color(pub [u8; 3]);
+
+impl fmt::Debug for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [r, g, b] = self.0;
+        write!(f, "#{:02x}{:02x}{:02x}", r, g, b)
+    }
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s
+            .strip_prefix('#')
+            .filter(|hex| hex.len() == 6)
+            .ok_or_else(|| format!("expected #rrggbb color, found {:?}", s))?;
+        let mut rgb = [0; 3];
+        for (i, channel) in rgb.iter_mut().enumerate() {
+            *channel = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(
+                |_| format!("expected #rrggbb color, found {:?}", s),
+            )?;
+        }
+        Ok(Self(rgb))
+    }
+}
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl Color {
+    /// Multiplies every pixel by this color, keeping alpha
+    pub fn tint(self, image: &mut RgbaImage) {
+        for Rgba(pixel) in image.pixels_mut() {
+            for (channel, tint) in pixel.iter_mut().zip(self.0.iter()) {
+                *channel = (*channel as u32 * *tint as u32 / 255) as u8;
+            }
+        }
+    }
+}
+
+/// Changes applied to a tile after cutting it out, e.g.
+/// `{ name = "pipe_side", rotate = 90, flip = "horizontal" }`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct Transform {
+    #[serde(default)]
+    pub rotate: Rotation,
+    /// Applied before rotation
+    pub flip: Option<Flip>,
+    /// Turns the tile into a grayscale base for tinting, keeping alpha
+    #[serde(default)]
+    pub desaturate: bool,
+    /// Multiplies the tile by given color, after desaturating
+    pub recolor: Option<Color>,
+}
+
+impl Transform {
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Applies transform to each of `frames` frames stacked in `strip`
+    /// separately, so animations keep their frame order
+    pub fn apply(&self, strip: &RgbaImage, frames: u32) -> RgbaImage {
+        if self.is_identity() {
+            return strip.clone();
+        }
+        map_frames(strip, frames, |frame| {
+            let mut frame = match self.flip {
+                Some(flip) => flip.apply(&frame),
+                None => frame,
+            };
+            frame = self.rotate.apply(&frame);
+            if self.desaturate {
+                desaturate(&mut frame);
+            }
+            if let Some(color) = self.recolor {
+                color.tint(&mut frame);
+            }
+            frame
+        })
+    }
+
+    /// Reverses rotation and flip, colors can't be undone
+    pub fn undo(&self, strip: &RgbaImage, frames: u32) -> RgbaImage {
+        if self.rotate == Rotation::None && self.flip.is_none() {
+            return strip.clone();
+        }
+        map_frames(strip, frames, |frame| {
+            let frame = self.rotate.inverse().apply(&frame);
+            match self.flip {
+                Some(flip) => flip.apply(&frame),
+                None => frame,
+            }
+        })
+    }
+}
+
+/// Makes every pixel of color `key` fully transparent, for old sheets that
+/// use e.g. magenta in place of alpha
+pub fn apply_colorkey(image: &mut RgbaImage, key: Color) {
+    for Rgba(pixel) in image.pixels_mut() {
+        if pixel[..3] == key.0 {
+            *pixel = [0; 4];
+        }
+    }
+}
+
+/// Undoes premultiplied alpha that darkens partly transparent pixels, and
+/// gives fully transparent ones the color of their visible neighbours, so
+/// filtering and mipmaps don't pull black or placeholder color into edges
+pub fn fix_semitransparent_edges(image: &mut RgbaImage) {
+    for Rgba(pixel) in image.pixels_mut() {
+        let a = pixel[3] as u32;
+        // Only premultiplied colors are never brighter than alpha
+        if a > 0 && a < 255 && pixel[..3].iter().all(|&c| c as u32 <= a) {
+            for channel in &mut pixel[..3] {
+                *channel = ((*channel as u32 * 255 + a / 2) / a) as u8;
+            }
+        }
+    }
+    let source = image.clone();
+    let (width, height) = image.dimensions();
+    for (x, y, Rgba(pixel)) in image.enumerate_pixels_mut() {
+        if pixel[3] != 0 {
+            continue;
+        }
+        let mut sum = [0u32; 3];
+        let mut count = 0;
+        for (dx, dy) in (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+        {
+            let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+            if nx < 0 || ny < 0 || nx >= width as i64 || ny >= height as i64 {
+                continue;
+            }
+            let [r, g, b, a] = source.get_pixel(nx as u32, ny as u32).0;
+            if a > 0 {
+                for (sum, c) in sum.iter_mut().zip([r, g, b]) {
+                    *sum += c as u32;
+                }
+                count += 1;
+            }
+        }
+        for (channel, sum) in pixel.iter_mut().zip(sum) {
+            if let Some(average) = sum.checked_div(count) {
+                *channel = average as u8;
+            }
+        }
+    }
+}
+
+fn desaturate(image: &mut RgbaImage) {
+    let luma = Grayscale::Luma.convert(image);
+    for (Rgba(pixel), Luma([value])) in image.pixels_mut().zip(luma.pixels()) {
+        pixel[..3].copy_from_slice(&[*value; 3]);
+    }
+}
+
+pub(crate) fn map_frames(
+    strip: &RgbaImage,
+    frames: u32,
+    mut f: impl FnMut(RgbaImage) -> RgbaImage,
+) -> RgbaImage {
+    let height = strip.height() / frames;
+    let frames: Vec<_> = (0..frames)
+        .map(|i| f(strip.view(0, i * height, strip.width(), height).to_image()))
+        .collect();
+    let (width, height) = frames[0].dimensions();
+    let mut out = RgbaImage::new(width, height * frames.len() as u32);
+    for (i, frame) in frames.iter().enumerate() {
+        imageops::replace(&mut out, frame, 0, i as u32 * height);
+    }
+    out
+}
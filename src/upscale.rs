@@ -0,0 +1,229 @@
+use std::{convert::TryFrom, fmt, str::FromStr};
+
+use image::{imageops, Rgba, RgbaImage};
+use serde::Deserialize;
+
+use crate::transform::map_frames;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    Nearest,
+    /// EPX/Scale2x, rounds off diagonal edges of pixel art. Needs a power of
+    /// two factor.
+    Scale2x,
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nearest" => Ok(Self::Nearest),
+            "scale2x" => Ok(Self::Scale2x),
+            _ => Err(format!(
+                "unknown filter {:?}, expected nearest or scale2x",
+                s
+            )),
+        }
+    }
+}
+
+/// Enlarges textures by an integer `factor`, e.g. to make a 64x pack out of
+/// 16x originals
+#[derive(Debug, Clone, Copy)]
+pub struct Upscale {
+    factor: u32,
+    filter: Filter,
+}
+
+impl Upscale {
+    pub fn new(factor: u32, filter: Filter) -> anyhow::Result<Self> {
+        anyhow::ensure!(factor > 0, "upscale factor can't be 0");
+        anyhow::ensure!(
+            filter != Filter::Scale2x || factor.is_power_of_two(),
+            "scale2x needs a power of two factor, not {}",
+            factor
+        );
+        Ok(Self { factor, filter })
+    }
+
+    pub fn factor(&self) -> u32 {
+        self.factor
+    }
+
+    pub fn apply(&self, image: &RgbaImage) -> RgbaImage {
+        match self.filter {
+            Filter::Nearest => imageops::resize(
+                image,
+                image.width() * self.factor,
+                image.height() * self.factor,
+                imageops::FilterType::Nearest,
+            ),
+            Filter::Scale2x => {
+                let mut image = image.clone();
+                for _ in 0..self.factor.trailing_zeros() {
+                    image = scale2x(&image);
+                }
+                image
+            }
+        }
+    }
+
+    /// Like `apply`, but scales each of `frames` frames separately, so they
+    /// don't bleed into each other
+    pub fn apply_frames(&self, strip: &RgbaImage, frames: u32) -> RgbaImage {
+        map_frames(strip, frames, |frame| self.apply(&frame))
+    }
+}
+
+impl fmt::Display for Upscale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x {:?}", self.factor, self.filter)
+    }
+}
+
+/// Size change of a single tile, on top of `--upscale`: `2` makes it twice
+/// as large, `0.5` half as large
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "f64")]
+pub enum Scale {
+    Up(u32),
+    Down(u32),
+}
+
+impl TryFrom<f64> for Scale {
+    type Error = String;
+
+    fn try_from(scale: f64) -> Result<Self, Self::Error> {
+        let whole = |x: f64| x >= 1.0 && x.fract() == 0.0 && x <= 256.0;
+        let inverse = 1.0 / scale;
+        if whole(scale) {
+            Ok(Self::Up(scale as u32))
+        } else if whole(inverse.round())
+            && (inverse.round() * scale - 1.0).abs() < 1e-6
+        {
+            Ok(Self::Down(inverse.round() as u32))
+        } else {
+            Err(format!(
+                "scale must be a whole number up to 256, or one over it like \
+                 0.5, not {}",
+                scale
+            ))
+        }
+    }
+}
+
+impl Scale {
+    /// Scale undoing this one, as far as it can be
+    pub fn inverse(self) -> Self {
+        match self {
+            Self::Up(factor) => Self::Down(factor),
+            Self::Down(factor) => Self::Up(factor),
+        }
+    }
+
+    /// Scales each of `frames` frames of `strip` separately. Enlarging
+    /// repeats pixels, shrinking averages them, so frames have to divide
+    /// evenly.
+    pub fn apply_frames(
+        self,
+        strip: &RgbaImage,
+        frames: u32,
+    ) -> anyhow::Result<RgbaImage> {
+        let factor = match self {
+            Self::Up(factor) => {
+                let upscale = Upscale::new(factor, Filter::Nearest)?;
+                return Ok(upscale.apply_frames(strip, frames));
+            }
+            Self::Down(factor) => factor,
+        };
+        let (width, height) = (strip.width(), strip.height() / frames);
+        anyhow::ensure!(
+            width % factor == 0 && height % factor == 0,
+            "{}x{} can't be shrunk {} times",
+            width,
+            height,
+            factor
+        );
+        Ok(map_frames(strip, frames, |frame| shrink(&frame, factor)))
+    }
+}
+
+/// Resizes `image` to fit `width` by `height`, keeping its aspect ratio and
+/// centering it on a transparent background. Whole multiples are scaled
+/// with nearest neighbour, to keep pixel art crisp.
+pub fn fit(image: &RgbaImage, (width, height): (u32, u32)) -> RgbaImage {
+    let (w, h) = image.dimensions();
+    let scale = f64::min(width as f64 / w as f64, height as f64 / h as f64);
+    let fitted_w = ((w as f64 * scale).round() as u32).clamp(1, width);
+    let fitted_h = ((h as f64 * scale).round() as u32).clamp(1, height);
+    let whole = fitted_w % w == 0 && fitted_h % h == 0;
+    let filter = match whole {
+        true => imageops::FilterType::Nearest,
+        false => imageops::FilterType::Lanczos3,
+    };
+    let fitted = imageops::resize(image, fitted_w, fitted_h, filter);
+    let mut out = RgbaImage::new(width, height);
+    let x = (width - fitted_w) / 2;
+    let y = (height - fitted_h) / 2;
+    imageops::replace(&mut out, &fitted, x, y);
+    out
+}
+
+/// Averages every `factor` by `factor` block into a pixel, weighting colors
+/// by alpha so transparent pixels don't darken edges
+fn shrink(image: &RgbaImage, factor: u32) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    RgbaImage::from_fn(width / factor, height / factor, |x, y| {
+        let mut sum = [0u32; 4];
+        for dy in 0..factor {
+            for dx in 0..factor {
+                let [r, g, b, a] =
+                    image.get_pixel(x * factor + dx, y * factor + dy).0;
+                let a32 = a as u32;
+                sum[0] += r as u32 * a32;
+                sum[1] += g as u32 * a32;
+                sum[2] += b as u32 * a32;
+                sum[3] += a32;
+            }
+        }
+        if sum[3] == 0 {
+            return Rgba([0; 4]);
+        }
+        let channel = |c: u32| ((c + sum[3] / 2) / sum[3]) as u8;
+        let alpha = (sum[3] + factor * factor / 2) / (factor * factor);
+        Rgba([
+            channel(sum[0]),
+            channel(sum[1]),
+            channel(sum[2]),
+            alpha as u8,
+        ])
+    })
+}
+
+fn scale2x(image: &RgbaImage) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let at = |x: i64, y: i64| {
+        let x = x.clamp(0, width as i64 - 1) as u32;
+        let y = y.clamp(0, height as i64 - 1) as u32;
+        *image.get_pixel(x, y)
+    };
+    let mut out = RgbaImage::new(width * 2, height * 2);
+    for y in 0..height {
+        for x in 0..width {
+            let (xi, yi) = (x as i64, y as i64);
+            let p = at(xi, yi);
+            let a = at(xi, yi - 1);
+            let b = at(xi + 1, yi);
+            let c = at(xi - 1, yi);
+            let d = at(xi, yi + 1);
+            let pick = |cond: bool, q| if cond { q } else { p };
+            let (x, y) = (x * 2, y * 2);
+            out.put_pixel(x, y, pick(c == a && c != d && a != b, a));
+            out.put_pixel(x + 1, y, pick(a == b && a != c && b != d, b));
+            out.put_pixel(x, y + 1, pick(d == c && d != b && c != a, c));
+            out.put_pixel(x + 1, y + 1, pick(b == d && b != a && d != c, d));
+        }
+    }
+    out
+}
@@ -0,0 +1,620 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt, fs,
+    path::Path,
+};
+
+use image::GenericImageView;
+use serde::Serialize;
+
+use crate::{
+    atlas::lint, decode_image, emissive_name, model_textures, plan_extraction,
+    ArchiveSet, Atlas, AtlasInfo, AtlasKind, AtlasMap, Config, Namespace,
+    OutputDirs, OutputFormat, Writer, ARCHIVE_EXTENSIONS, MAX_CELLS,
+};
+
+/// Asset referenced by config, but not present in any archive
+#[derive(Debug, Serialize)]
+pub struct MissingAsset {
+    /// Mod id of the namespace referencing it, unless it's the main one
+    pub namespace: Option<String>,
+    /// Config section the reference came from, e.g. `gui` or `blocks.terrain`
+    pub section: String,
+    pub name: String,
+}
+
+impl fmt::Display for MissingAsset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(namespace) = &self.namespace {
+            write!(f, "{}: ", namespace)?;
+        }
+        write!(f, "[{}] {}", self.section, self.name)
+    }
+}
+
+fn check_atlas_map(
+    section: &str,
+    atlas: &AtlasMap,
+    zips: &mut ArchiveSet,
+    missing: &mut Vec<(String, String)>,
+) {
+    for atlas in atlas.keys() {
+        let name = zips.sheet(atlas);
+        if zips.locate(&name).is_none() {
+            missing.push((format!("{}.{}", section, atlas), name));
+        }
+    }
+}
+
+/// Sections and names of assets referenced by `ns` that couldn't be found
+fn check_namespace(
+    ns: &Namespace,
+    zips: &mut ArchiveSet,
+) -> Vec<(String, String)> {
+    let mut missing = Vec::new();
+    let copies = [
+        ("models", &ns.models[..]),
+        ("gui", &ns.gui[..]),
+        ("blocks_copy", &ns.blocks_copy[..]),
+        ("entity", &ns.entity[..]),
+        ("particle", &ns.particle[..]),
+        ("environment", &ns.environment[..]),
+        ("sounds", &ns.sounds.files[..]),
+        ("lang", &ns.lang.files[..]),
+    ];
+    for (section, files) in copies.iter() {
+        for file in files.iter() {
+            if zips.locate(file).is_none() {
+                missing.push((section.to_string(), file.clone()));
+            }
+        }
+    }
+    for file in ns.misc.keys() {
+        if zips.locate(file).is_none() {
+            missing.push(("misc".to_owned(), file.clone()));
+        }
+    }
+    for (dir, copy) in &ns.copy_dirs {
+        let files = zips.list_dir(dir).unwrap_or_default();
+        if !files.iter().any(|file| copy.accepts(file)) {
+            missing.push(("copy_dirs".to_owned(), format!("{}/", dir)));
+        }
+    }
+    for sheet in ns.gui_crops.keys() {
+        if zips.locate(sheet).is_none() {
+            missing.push((format!("gui_crops.{}", sheet), sheet.clone()));
+        }
+    }
+    for sheet in ns.sprite_sheets.keys() {
+        if zips.locate(sheet).is_none() {
+            missing.push((format!("sprite_sheets.{}", sheet), sheet.clone()));
+        }
+    }
+    for (name, set) in &ns.ctm {
+        let sheet = zips.sheet(&set.atlas);
+        if zips.locate(&sheet).is_none() {
+            missing.push((format!("ctm.{}", name), sheet));
+        }
+    }
+    for (name, font) in &ns.fonts {
+        let sheet = zips.sheet(&font.sheet);
+        if zips.locate(&sheet).is_none() {
+            missing.push((format!("fonts.{}", name), sheet));
+        }
+    }
+    check_atlas_map("blocks", &ns.blocks, zips, &mut missing);
+    check_atlas_map("items", &ns.items, zips, &mut missing);
+    missing
+}
+
+/// Checks every asset referenced by config against archives, collecting all
+/// the ones that couldn't be found
+pub fn find_missing(
+    config: &Config,
+    zips: &mut ArchiveSet,
+) -> Vec<MissingAsset> {
+    let mut missing = Vec::new();
+    if let Some(banner) = &config.banner {
+        if zips.locate(banner).is_none() {
+            missing.push(MissingAsset {
+                namespace: None,
+                section: "banner".to_owned(),
+                name: banner.clone(),
+            });
+        }
+    }
+    for (i, ns) in config.all_namespaces().enumerate() {
+        let namespace = (i > 0).then(|| ns.modid.clone());
+        for (section, name) in check_namespace(ns, zips) {
+            missing.push(MissingAsset {
+                namespace: namespace.clone(),
+                section,
+                name,
+            });
+        }
+    }
+    missing
+}
+
+/// Decodes copied `gui` and `blocks_copy` pngs listed in `texture_sizes`,
+/// or all of them with `check_textures`, linting ones that aren't really
+/// png, don't decode, or don't have the size they should. Without a size
+/// given, gui textures should be powers of two, and blocks too, or strips
+/// of square frames. Also lints `animations` of files that aren't copied.
+pub fn check_copied_textures(
+    config: &Config,
+    ns: &Namespace,
+    zips: &mut ArchiveSet,
+    strict: bool,
+) -> anyhow::Result<()> {
+    for file in ns.texture_sizes.keys() {
+        if !ns.gui.contains(file) && !ns.blocks_copy.contains(file) {
+            lint(
+                strict,
+                format_args!(
+                    "texture_sizes: {} isn't in gui or blocks_copy",
+                    file
+                ),
+            )?;
+        }
+    }
+    for file in ns.animations.keys() {
+        let lists = [
+            &ns.gui,
+            &ns.blocks_copy,
+            &ns.entity,
+            &ns.particle,
+            &ns.environment,
+        ];
+        let copied = lists.iter().any(|files| files.contains(file));
+        if !copied && !ns.misc.contains_key(file) {
+            lint(
+                strict,
+                format_args!("animations: {} isn't a copied texture", file),
+            )?;
+        }
+    }
+    let gui = ns.gui.iter().map(|file| ("gui", file));
+    let blocks = ns.blocks_copy.iter().map(|file| ("blocks_copy", file));
+    for (section, file) in gui.chain(blocks) {
+        let expected = ns.texture_sizes.get(file);
+        if !file.ends_with(".png")
+            || (!config.check_textures && expected.is_none())
+        {
+            continue;
+        }
+        // Missing ones are reported on their own
+        let asset = match zips.find(file)? {
+            Some(asset) => asset,
+            None => continue,
+        };
+        if !asset.data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            let format = image::guess_format(&asset.data)
+                .map_or("unknown format".to_owned(), |f| format!("{:?}", f));
+            lint(
+                strict,
+                format_args!(
+                    "[{}] {}: isn't a png, but {}",
+                    section, file, format
+                ),
+            )?;
+        }
+        let (width, height) = match decode_image(&asset.data, file) {
+            Ok(image) => image.dimensions(),
+            Err(e) => {
+                lint(
+                    strict,
+                    format_args!(
+                        "[{}] {}: can't be decoded: {}",
+                        section, file, e
+                    ),
+                )?;
+                continue;
+            }
+        };
+        log::debug!("[{}] {}: {}x{}", section, file, width, height);
+        let problem = match expected {
+            Some(&(w, h)) if (w, h) != (width, height) => {
+                format!("expected {}x{}", w, h)
+            }
+            Some(_) => continue,
+            None if !width.is_power_of_two() => {
+                "width isn't a power of two".into()
+            }
+            None if section == "gui" && !height.is_power_of_two() => {
+                "height isn't a power of two".into()
+            }
+            None if section == "blocks_copy" && height % width != 0 => {
+                "height isn't a whole number of square frames".into()
+            }
+            None => continue,
+        };
+        lint(
+            strict,
+            format_args!(
+                "[{}] {}: is {}x{}, {}",
+                section, file, width, height, problem
+            ),
+        )?;
+    }
+    Ok(())
+}
+
+/// Drops every reference to `missing` assets from config, so the rest can
+/// still be extracted
+pub fn remove_missing(config: &mut Config, missing: &[MissingAsset]) {
+    for asset in missing {
+        if asset.section == "banner" {
+            config.banner = None;
+            continue;
+        }
+        let ns = match &asset.namespace {
+            Some(modid) => {
+                match config.namespaces.iter_mut().find(|ns| ns.modid == *modid)
+                {
+                    Some(ns) => ns,
+                    None => continue,
+                }
+            }
+            None => &mut config.main,
+        };
+        remove_from(ns, asset);
+    }
+}
+
+fn remove_from(ns: &mut Namespace, asset: &MissingAsset) {
+    let name = &asset.name;
+    match asset.section.split_once('.') {
+        Some(("blocks", atlas)) => {
+            ns.blocks.remove(atlas);
+        }
+        Some(("items", atlas)) => {
+            ns.items.remove(atlas);
+        }
+        Some(("gui_crops", sheet)) => {
+            ns.gui_crops.remove(sheet);
+        }
+        Some(("sprite_sheets", sheet)) => {
+            ns.sprite_sheets.remove(sheet);
+        }
+        Some(("ctm", set)) => {
+            ns.ctm.remove(set);
+        }
+        Some(("fonts", font)) => {
+            ns.fonts.remove(font);
+        }
+        _ => match &asset.section[..] {
+            "models" => ns.models.retain(|file| file != name),
+            "gui" => ns.gui.retain(|file| file != name),
+            "blocks_copy" => ns.blocks_copy.retain(|file| file != name),
+            "entity" => ns.entity.retain(|file| file != name),
+            "particle" => ns.particle.retain(|file| file != name),
+            "environment" => ns.environment.retain(|file| file != name),
+            "misc" => {
+                ns.misc.remove(name);
+            }
+            "sounds" => ns.sounds.files.retain(|file| file != name),
+            "lang" => ns.lang.files.retain(|file| file != name),
+            "copy_dirs" => {
+                ns.copy_dirs.remove(name.trim_end_matches('/'));
+            }
+            _ => {}
+        },
+    }
+}
+
+/// Checks textures referenced by models `writer` wrote against the output
+/// tree of each namespace in `dirs`, counting both files written by the run
+/// and ones already there. Lints references to textures that don't exist,
+/// which show up as purple and black checkers in game, then block and item
+/// textures the run wrote that no model references, for kinds of which it
+/// wrote any models. References to other namespaces, like vanilla ones,
+/// aren't checked.
+pub fn check_texture_usage(
+    dirs: &[OutputDirs],
+    writer: &Writer,
+    strict: bool,
+) -> anyhow::Result<()> {
+    let textures: HashMap<_, _> = dirs
+        .iter()
+        .filter_map(|dirs| {
+            let modid = dirs.namespace.file_name()?.to_string_lossy();
+            Some((modid.into_owned(), dirs.namespace.join("textures")))
+        })
+        .collect();
+    let records = writer.records();
+    let written: BTreeSet<&Path> =
+        records.iter().map(|record| record.path.as_path()).collect();
+    let is_json = |path: &Path| path.extension().is_some_and(|e| e == "json");
+    let mut referenced = HashSet::new();
+    for model in written.iter().filter(|path| is_json(path)) {
+        let is_model = dirs.iter().any(|dirs| {
+            model.starts_with(&dirs.models)
+                || model.starts_with(&dirs.item_models)
+        });
+        if !is_model {
+            continue;
+        }
+        for texture in model_textures(&writer.read(model)?) {
+            let (modid, name) =
+                texture.split_once(':').unwrap_or(("minecraft", &texture));
+            let dir = match textures.get(modid) {
+                Some(dir) => dir,
+                None => continue,
+            };
+            let path =
+                writer.output_path(&dir.join(name).with_extension("png"));
+            if !written.contains(path.as_path()) && writer.read(&path).is_err()
+            {
+                lint(
+                    strict,
+                    format_args!(
+                        "{}: texture {} doesn't exist, expected {}",
+                        model.display(),
+                        texture,
+                        path.display()
+                    ),
+                )?;
+            }
+            referenced.insert(path);
+        }
+    }
+    // Emissive overlays are found by name, rather than referenced
+    let emissive: HashSet<_> = referenced
+        .iter()
+        .map(|path| {
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            let ext = path.extension().unwrap_or_default().to_string_lossy();
+            path.with_file_name(format!("{}.{}", emissive_name(&stem), ext))
+        })
+        .collect();
+    let mut unused = BTreeSet::<&Path>::new();
+    for dirs in dirs {
+        for (textures, models) in [
+            (&dirs.blocks, &dirs.models),
+            (&dirs.items, &dirs.item_models),
+        ] {
+            let has_models = written
+                .iter()
+                .any(|path| path.starts_with(models) && is_json(path));
+            if !has_models {
+                continue;
+            }
+            unused.extend(written.iter().filter(|path| {
+                // Textures in output format, not mcmeta or other files
+                let png = path.with_extension("png");
+                path.starts_with(textures)
+                    && writer.output_path(&png) == **path
+                    && !referenced.contains(**path)
+                    && !emissive.contains(**path)
+            }));
+        }
+    }
+    if !unused.is_empty() {
+        let list: Vec<_> = unused
+            .iter()
+            .map(|path| format!("  {}", path.display()))
+            .collect();
+        lint(
+            strict,
+            format_args!(
+                "{} textures no model references:\n{}",
+                unused.len(),
+                list.join("\n")
+            ),
+        )?;
+    }
+    Ok(())
+}
+
+/// Whether `name` only has characters resource locations allow in paths
+fn is_resource_path(name: &str) -> bool {
+    !name.is_empty()
+        && name.bytes().all(|b| {
+            matches!(b, b'a'..=b'z' | b'0'..=b'9' | b'_' | b'-' | b'.' | b'/')
+        })
+}
+
+/// Tiles of `atlas` covering cells of another one, e.g. with `span` or
+/// `frames`, as pairs of names. A strip's `animation` overlaps its frames
+/// on purpose and isn't counted. Three digit indices are placed on `grid`,
+/// the sheet's columns and rows, failing if they land on a cell already
+/// mapped. Without it the widest grid is assumed, as any fits other keys.
+fn overlapping_tiles(
+    atlas: &Atlas,
+    grid: Option<(u32, u32)>,
+) -> anyhow::Result<BTreeSet<(String, String)>> {
+    let (columns, rows) = grid.unwrap_or((MAX_CELLS, MAX_CELLS));
+    let atlas = match atlas.kind {
+        AtlasKind::Grid => atlas.with_grid(columns, rows)?,
+        AtlasKind::Strip => Cow::Borrowed(atlas),
+    };
+    let mut owners = HashMap::new();
+    let mut overlaps = BTreeSet::new();
+    for (pos, tile) in &atlas.tiles {
+        let (w, h) = tile.footprint();
+        let (x, y) = (pos.x() as u32, pos.y() as u32);
+        for y in y..(y + h).min(MAX_CELLS) {
+            for x in x..(x + w).min(MAX_CELLS) {
+                if let Some(owner) = owners.insert((x, y), &tile.name) {
+                    overlaps.insert((owner.clone(), tile.name.clone()));
+                }
+            }
+        }
+    }
+    Ok(overlaps)
+}
+
+/// Columns and rows of the sheet of atlas `name`, read from its header, if
+/// it's found and they can be told
+fn sheet_grid(
+    zips: &ArchiveSet,
+    name: &str,
+    atlas: &Atlas,
+    tile_size: u32,
+) -> anyhow::Result<Option<(u32, u32)>> {
+    let dimensions = zips.image_dimensions(&zips.sheet(name))?;
+    Ok(dimensions
+        .map(|(width, height)| AtlasInfo::of(atlas, width, height, tile_size))
+        .filter(|info| info.tile_size > 0)
+        .map(|info| (info.columns(), info.rows())))
+}
+
+/// Archives in `dir`, recognized by `ARCHIVE_EXTENSIONS`, relative to
+/// `base`. Directories that are sources themselves aren't looked into.
+fn find_archives(
+    base: &Path,
+    dir: &Path,
+    sources: &HashSet<&str>,
+    found: &mut Vec<String>,
+) -> anyhow::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let relative = path.strip_prefix(base)?.to_string_lossy();
+        let relative = relative.replace('\\', "/");
+        if sources.contains(relative.as_str()) {
+            continue;
+        }
+        if path.is_dir() {
+            find_archives(base, &path, sources, found)?;
+        } else if path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ARCHIVE_EXTENSIONS.contains(&ext))
+        {
+            found.push(relative);
+        }
+    }
+    Ok(())
+}
+
+/// Looks for mistakes in config that don't stop extraction, reading only
+/// headers of sheets: tiles overlapping others, names that aren't valid
+/// resource locations, sources in `folders` nothing is read from, and
+/// archives in `input_dir` missing from `folders`. Returns every finding.
+pub fn lint_config(
+    config: &Config,
+    zips: &mut ArchiveSet,
+    input_dir: &Path,
+) -> anyhow::Result<Vec<String>> {
+    let mut findings = vec![];
+    for ns in config.all_namespaces() {
+        let modid = &ns.modid;
+        if !is_resource_path(modid) || modid.contains('/') {
+            findings.push(format!(
+                "mod id `{}` isn't a valid namespace: use lowercase \
+                 letters, digits, `_`, `-` and `.`",
+                modid
+            ));
+        }
+        // Sections and names that end up in resource locations
+        let mut names: Vec<(String, String)> = vec![];
+        for (section, atlas) in [("blocks", &ns.blocks), ("items", &ns.items)] {
+            for (name, map) in atlas {
+                let section = format!("{}.{}", section, name);
+                let grid = sheet_grid(zips, name, map, config.tile_size)?;
+                match overlapping_tiles(map, grid) {
+                    Ok(overlaps) => {
+                        for (first, second) in overlaps {
+                            findings.push(format!(
+                                "[{}] `{}` overlaps cells of `{}`",
+                                section, second, first
+                            ));
+                        }
+                    }
+                    Err(e) => findings.push(format!("[{}] {}", section, e)),
+                }
+                for tile in map.tiles.values() {
+                    let outputs = tile.output_names(&config.palettes);
+                    let models = tile.model_aliases.iter().cloned();
+                    for output in outputs.into_iter().chain(models) {
+                        names.push((section.clone(), output));
+                    }
+                }
+            }
+        }
+        let copies = [
+            ("models", &ns.models),
+            ("gui", &ns.gui),
+            ("blocks_copy", &ns.blocks_copy),
+            ("entity", &ns.entity),
+            ("particle", &ns.particle),
+            ("environment", &ns.environment),
+            ("sounds", &ns.sounds.files),
+        ];
+        for (section, files) in copies {
+            for file in files {
+                names.push((section.to_owned(), file.clone()));
+            }
+        }
+        for to in ns.misc.values() {
+            names.push(("misc".to_owned(), to.clone()));
+        }
+        for (sheet, crops) in &ns.gui_crops {
+            for crop in crops {
+                let section = format!("gui_crops.{}", sheet);
+                names.push((section, crop.name.clone()));
+            }
+        }
+        for (sheet, sprites) in &ns.sprite_sheets {
+            for crop in &sprites.crops {
+                let section = format!("sprite_sheets.{}", sheet);
+                names.push((section, crop.name.clone()));
+            }
+        }
+        for name in ns.ctm.keys() {
+            names.push(("ctm".to_owned(), name.clone()));
+        }
+        for name in ns.fonts.keys() {
+            names.push(("fonts".to_owned(), name.clone()));
+        }
+        for (section, name) in names {
+            if !is_resource_path(&name) {
+                findings.push(format!(
+                    "[{}] `{}` isn't a valid resource location: use \
+                     lowercase letters, digits, `_`, `-`, `.` and `/`",
+                    section, name
+                ));
+            }
+        }
+    }
+
+    // Sources every planned file comes from, as `<source>:<entry>`
+    let plan = plan_extraction(
+        config,
+        &config.layout,
+        Path::new(""),
+        zips,
+        OutputFormat::Png,
+    );
+    let read: Vec<_> = plan
+        .files
+        .iter()
+        .filter_map(|file| file.source.as_ref())
+        .collect();
+    for layer in zips.layers() {
+        let prefix = format!("{}:", layer.name);
+        if !read.iter().any(|source| source.starts_with(&prefix)) {
+            findings
+                .push(format!("[folders] nothing is read from {}", layer.name));
+        }
+    }
+
+    let mut sources: HashSet<_> = config
+        .folders
+        .keys()
+        .map(|name| name.split('!').next().unwrap())
+        .collect();
+    // Jars there are picked by modid, the rest are other mods
+    if let Some(dir) = &config.mods_dir {
+        sources.insert(dir.trim_end_matches('/'));
+    }
+    let mut archives = vec![];
+    find_archives(input_dir, input_dir, &sources, &mut archives)?;
+    archives.sort();
+    for archive in archives {
+        findings.push(format!("{} isn't in [folders]", archive));
+    }
+    Ok(findings)
+}
@@ -0,0 +1,182 @@
+use std::{collections::BTreeMap, fmt};
+
+use image::RgbaImage;
+use serde::Serialize;
+
+use crate::{decode_image, AssetSource};
+
+/// How a file differs between output and reference pack
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Mismatch {
+    /// Only in the reference pack
+    Missing,
+    /// Only in the output
+    Extra,
+    /// Images of different sizes, as `[width, height]`
+    Size {
+        output: (u32, u32),
+        reference: (u32, u32),
+    },
+    /// Images with pixels differing by more than the tolerance
+    Pixels { count: u32, max_delta: u8 },
+    /// Other files with different content
+    Content,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing => f.write_str("missing from output"),
+            Self::Extra => f.write_str("not in reference"),
+            Self::Size { output, reference } => write!(
+                f,
+                "{}x{}, reference is {}x{}",
+                output.0, output.1, reference.0, reference.1
+            ),
+            Self::Pixels { count, max_delta } => {
+                write!(f, "{} pixels differ, by up to {}", count, max_delta)
+            }
+            Self::Content => f.write_str("content differs"),
+        }
+    }
+}
+
+/// File that differs, by its path from `assets/` on
+#[derive(Debug, Clone, Serialize)]
+pub struct Difference {
+    pub path: String,
+    #[serde(flatten)]
+    pub mismatch: Mismatch,
+}
+
+impl Difference {
+    /// Whether it's something the output got wrong, rather than has on top
+    pub fn is_regression(&self) -> bool {
+        self.mismatch != Mismatch::Extra
+    }
+}
+
+/// Part of `path` from `assets/` on, wherever the pack root is
+fn asset_path(path: &str) -> Option<&str> {
+    if path.starts_with("assets/") {
+        return Some(path);
+    }
+    path.find("/assets/").map(|i| &path[i + 1..])
+}
+
+/// Files of `source` under `assets/`, by asset path
+fn assets(
+    source: &mut dyn AssetSource,
+) -> anyhow::Result<BTreeMap<String, String>> {
+    Ok(source
+        .list()?
+        .into_iter()
+        .filter_map(|path| Some((asset_path(&path)?.to_owned(), path)))
+        .collect())
+}
+
+/// Number of pixels differing in some channel by more than `tolerance`, and
+/// the largest difference. Pixels transparent in both count as the same.
+fn compare_pixels(
+    output: &RgbaImage,
+    reference: &RgbaImage,
+    tolerance: u8,
+) -> (u32, u8) {
+    let mut count = 0;
+    let mut max_delta = 0;
+    for (a, b) in output.pixels().zip(reference.pixels()) {
+        if a.0[3] == 0 && b.0[3] == 0 {
+            continue;
+        }
+        let delta = (0..4).map(|i| a.0[i].abs_diff(b.0[i])).max().unwrap();
+        if delta > tolerance {
+            count += 1;
+            max_delta = max_delta.max(delta);
+        }
+    }
+    (count, max_delta)
+}
+
+/// How `output` differs from `reference`, both contents of a file at
+/// `path`. Images are compared by pixels, json by value, anything else
+/// byte for byte.
+fn compare(
+    path: &str,
+    output: &[u8],
+    reference: &[u8],
+    tolerance: u8,
+) -> Option<Mismatch> {
+    if output == reference {
+        return None;
+    }
+    if path.ends_with(".png") {
+        let decode = |data| Some(decode_image(data, path).ok()?.into_rgba8());
+        if let (Some(output), Some(reference)) =
+            (decode(output), decode(reference))
+        {
+            if output.dimensions() != reference.dimensions() {
+                return Some(Mismatch::Size {
+                    output: output.dimensions(),
+                    reference: reference.dimensions(),
+                });
+            }
+            let (count, max_delta) =
+                compare_pixels(&output, &reference, tolerance);
+            return (count > 0)
+                .then_some(Mismatch::Pixels { count, max_delta });
+        }
+    }
+    if path.ends_with(".json") || path.ends_with(".mcmeta") {
+        let parse = |data| serde_json::from_slice::<serde_json::Value>(data);
+        if let (Ok(output), Ok(reference)) = (parse(output), parse(reference)) {
+            return (output != reference).then_some(Mismatch::Content);
+        }
+    }
+    Some(Mismatch::Content)
+}
+
+/// Compares every file under `assets/` of `output` with the same file in
+/// `reference`, a known-good pack, wherever their roots are. Image pixels
+/// can differ in each channel by up to `tolerance`, e.g. for packs saved by
+/// another encoder with slight color shifts.
+pub fn verify_pack(
+    output: &mut dyn AssetSource,
+    reference: &mut dyn AssetSource,
+    tolerance: u8,
+) -> anyhow::Result<Vec<Difference>> {
+    let outputs = assets(output)?;
+    let references = assets(reference)?;
+    let mut differences = vec![];
+    let mut add = |path: &str, mismatch| {
+        differences.push(Difference {
+            path: path.to_owned(),
+            mismatch,
+        })
+    };
+    for (path, entry) in &references {
+        let output_entry = match outputs.get(path) {
+            Some(output_entry) => output_entry,
+            None => {
+                add(path, Mismatch::Missing);
+                continue;
+            }
+        };
+        let read = |source: &mut dyn AssetSource, entry: &str| {
+            source.read(entry)?.ok_or_else(|| {
+                anyhow::anyhow!("{} was listed, but can't be read", entry)
+            })
+        };
+        let data = read(output, output_entry)?;
+        let expected = read(reference, entry)?;
+        if let Some(mismatch) = compare(path, &data, &expected, tolerance) {
+            add(path, mismatch);
+        }
+    }
+    for path in outputs.keys() {
+        if !references.contains_key(path) {
+            add(path, Mismatch::Extra);
+        }
+    }
+    Ok(differences)
+}
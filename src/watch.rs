@@ -0,0 +1,95 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver, RecvTimeoutError},
+    time::Duration,
+};
+
+use notify::{
+    Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher as _,
+};
+
+/// Quiet time after the last change before a burst of them counts as one,
+/// so saving several files at once only re-runs once
+const SETTLE: Duration = Duration::from_millis(200);
+
+/// Waits for changes to config and input files between runs
+pub struct Watch {
+    // Stops watching when dropped
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    ignored: Vec<PathBuf>,
+}
+
+impl Watch {
+    /// Watches directories recursively, and files through their directory,
+    /// so that also files next to them, like `ids` or templates, count, and
+    /// editors replacing a file instead of writing into it are still seen
+    pub fn new(paths: &[PathBuf]) -> anyhow::Result<Self> {
+        let (sender, events) = channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        for path in paths {
+            let path = std::path::absolute(path)?;
+            let (dir, mode) = if path.is_dir() {
+                (path.as_path(), RecursiveMode::Recursive)
+            } else {
+                let dir = path.parent().unwrap_or(&path);
+                (dir, RecursiveMode::NonRecursive)
+            };
+            watcher.watch(dir, mode).map_err(|e| {
+                anyhow::anyhow!("can't watch {}: {}", dir.display(), e)
+            })?;
+        }
+        Ok(Self {
+            _watcher: watcher,
+            events,
+            ignored: vec![],
+        })
+    }
+
+    /// Skips changes within `paths` from now on, e.g. outputs written by the
+    /// run the previous change started
+    pub fn ignore(&mut self, paths: &[PathBuf]) -> anyhow::Result<()> {
+        self.ignored = paths
+            .iter()
+            .map(std::path::absolute)
+            .collect::<Result<_, _>>()?;
+        Ok(())
+    }
+
+    fn counts(&self, path: &Path) -> bool {
+        !self.ignored.iter().any(|ignored| path.starts_with(ignored))
+    }
+
+    /// Changed paths of `event`, unless it's ignored or only a read
+    fn changed(&self, event: Event) -> Vec<PathBuf> {
+        if matches!(event.kind, EventKind::Access(_)) {
+            return vec![];
+        }
+        event
+            .paths
+            .into_iter()
+            .filter(|path| self.counts(path))
+            .collect()
+    }
+
+    /// Blocks until something changes, returning every changed path once
+    /// they settle
+    pub fn wait(&self) -> anyhow::Result<Vec<PathBuf>> {
+        let mut changed = vec![];
+        while changed.is_empty() {
+            changed = self.changed(self.events.recv()??);
+        }
+        loop {
+            match self.events.recv_timeout(SETTLE) {
+                Ok(event) => changed.extend(self.changed(event?)),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => {
+                    anyhow::bail!("file watcher stopped")
+                }
+            }
+        }
+        changed.sort();
+        changed.dedup();
+        Ok(changed)
+    }
+}
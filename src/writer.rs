@@ -0,0 +1,647 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    fs::{self, File},
+    io::{self, Cursor, Seek, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use anyhow::Context;
+use image::{DynamicImage, RgbaImage};
+use serde::Serialize;
+use zip::{write::FileOptions, DateTime, ZipWriter};
+
+use crate::{
+    color_chunks, insert_color_chunks, insert_text_chunk, optimize_png,
+    staging::Staging, strip_color_chunks, ColorChunks, OutputFormat, Plan,
+    Progress, Stage, Timings, PROVENANCE_KEYWORD,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WritePolicy {
+    Error,
+    Skip,
+    Overwrite,
+}
+
+impl FromStr for WritePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(Self::Error),
+            "skip" => Ok(Self::Skip),
+            "overwrite" => Ok(Self::Overwrite),
+            _ => anyhow::bail!("expected one of: error, skip, overwrite"),
+        }
+    }
+}
+
+/// Anything a zip can be written into, like a file or `Cursor<Vec<u8>>`
+pub trait ZipOutput: Write + Seek + Send {}
+
+impl<T: Write + Seek + Send> ZipOutput for T {}
+
+/// Where buffered entries go on `finish`
+enum Sink {
+    /// Zip file, written aside and renamed over it
+    ZipFile(PathBuf),
+    /// Zip written into output handed over by the caller
+    Zip(Mutex<Option<Box<dyn ZipOutput>>>),
+    /// Zip streamed to stdout
+    Stdout,
+    /// Kept as they are, for `take_files`
+    Memory(Mutex<BTreeMap<String, Vec<u8>>>),
+}
+
+/// Where output files end up
+enum Target {
+    /// Files are staged next to their place and moved there on `finish`
+    Dir(Mutex<Staging>),
+    /// Entries are buffered and handed to the sink, sorted, on `finish`
+    Buffered(Sink, Mutex<BTreeMap<String, Vec<u8>>>),
+}
+
+/// Writes output files, merging into an existing tree according to policy.
+/// Files with identical content are always left alone. Nothing is changed
+/// until `finish`, so a failed run leaves outputs as they were.
+///
+/// Can be shared between threads, writes to the same path aren't ordered.
+pub struct Writer {
+    policy: WritePolicy,
+    target: Target,
+    created: AtomicUsize,
+    identical: AtomicUsize,
+    skipped: AtomicUsize,
+    overwritten: AtomicUsize,
+    log: Mutex<Vec<(PathBuf, Outcome)>>,
+    sources: Mutex<HashMap<PathBuf, String>>,
+    trims: Mutex<HashMap<PathBuf, Trim>>,
+    progress: Progress,
+    /// Recompress every png before writing it
+    optimize: bool,
+    color_chunks: ColorChunks,
+    /// Color chunks of the sheet each re-encoded png was cut from
+    source_chunks: Mutex<HashMap<PathBuf, Arc<[u8]>>>,
+    format: OutputFormat,
+    /// Same bytes for the same inputs: no timestamps in zips, and records
+    /// in path order rather than the order threads got to them
+    deterministic: bool,
+    /// Where each planned path goes, with ones not in it skipped, if
+    /// following a plan
+    plan: Option<HashMap<PathBuf, PathBuf>>,
+    /// Copy of every image written, by planned path, if asked to keep them
+    images: Option<Mutex<HashMap<PathBuf, RgbaImage>>>,
+    /// Time spent slicing each atlas, if asked to keep track
+    timings: Option<Timings>,
+    /// Name the noted source of every png in a `tEXt` chunk
+    provenance: bool,
+}
+
+/// What happened to a single write
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Created,
+    Identical,
+    Skipped,
+    Overwritten,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Created => "created",
+            Self::Identical => "identical",
+            Self::Skipped => "skipped",
+            Self::Overwritten => "overwritten",
+        };
+        f.write_str(name)
+    }
+}
+
+impl Writer {
+    pub fn new(policy: WritePolicy) -> Self {
+        Self::with_target(policy, Target::Dir(Mutex::default()))
+    }
+
+    /// Writes into a zip archive at `path` instead, using relative output
+    /// paths as entry names
+    pub fn zip(policy: WritePolicy, path: PathBuf) -> Self {
+        let sink = Sink::ZipFile(path);
+        Self::with_target(policy, Target::Buffered(sink, Mutex::default()))
+    }
+
+    /// Like `zip`, but writes the archive into `output`, e.g. one in memory
+    pub fn zip_to(policy: WritePolicy, output: Box<dyn ZipOutput>) -> Self {
+        let sink = Sink::Zip(Mutex::new(Some(output)));
+        Self::with_target(policy, Target::Buffered(sink, Mutex::default()))
+    }
+
+    /// Like `zip`, but streams the archive to stdout, for piping into other
+    /// tools
+    pub fn zip_stdout(policy: WritePolicy) -> Self {
+        let sink = Sink::Stdout;
+        Self::with_target(policy, Target::Buffered(sink, Mutex::default()))
+    }
+
+    /// Keeps files in memory instead, by relative output path, to be taken
+    /// with `take_files`, e.g. where there's no filesystem like on wasm
+    pub fn memory(policy: WritePolicy) -> Self {
+        let sink = Sink::Memory(Mutex::default());
+        Self::with_target(policy, Target::Buffered(sink, Mutex::default()))
+    }
+
+    fn with_target(policy: WritePolicy, target: Target) -> Self {
+        Self {
+            policy,
+            target,
+            created: AtomicUsize::new(0),
+            identical: AtomicUsize::new(0),
+            skipped: AtomicUsize::new(0),
+            overwritten: AtomicUsize::new(0),
+            log: Mutex::default(),
+            sources: Mutex::default(),
+            trims: Mutex::default(),
+            progress: Progress::hidden(),
+            optimize: false,
+            color_chunks: ColorChunks::default(),
+            source_chunks: Mutex::default(),
+            format: OutputFormat::default(),
+            deterministic: false,
+            plan: None,
+            images: None,
+            timings: None,
+            provenance: false,
+        }
+    }
+
+    /// Ticks `progress` on every write
+    pub fn with_progress(self, progress: Progress) -> Self {
+        Self { progress, ..self }
+    }
+
+    /// Keeps track of time spent slicing each atlas, see `timings`
+    pub fn with_timings(self, enabled: bool) -> Self {
+        Self {
+            timings: enabled.then(Timings::default),
+            ..self
+        }
+    }
+
+    pub fn timings(&self) -> Option<&Timings> {
+        self.timings.as_ref()
+    }
+
+    /// Runs `f`, counting its time towards `stage` of `atlas` if timings
+    /// are kept
+    pub fn timed<T>(
+        &self,
+        atlas: &str,
+        stage: Stage,
+        f: impl FnOnce() -> T,
+    ) -> T {
+        match &self.timings {
+            Some(timings) => timings.time(atlas, stage, f),
+            None => f(),
+        }
+    }
+
+    pub fn with_optimize(self, optimize: bool) -> Self {
+        Self { optimize, ..self }
+    }
+
+    pub fn optimizes(&self) -> bool {
+        self.optimize
+    }
+
+    pub fn with_color_chunks(self, color_chunks: ColorChunks) -> Self {
+        Self {
+            color_chunks,
+            ..self
+        }
+    }
+
+    pub fn color_chunks(&self) -> ColorChunks {
+        self.color_chunks
+    }
+
+    /// Stamps pngs with where they come from, as noted with `note_source`
+    /// before writing them, in a `tEXt` chunk keyed `PROVENANCE_KEYWORD`
+    pub fn with_provenance(self, provenance: bool) -> Self {
+        Self { provenance, ..self }
+    }
+
+    /// Writes textures in `format` instead of png
+    pub fn with_format(self, format: OutputFormat) -> Self {
+        Self { format, ..self }
+    }
+
+    pub fn with_deterministic(self, deterministic: bool) -> Self {
+        Self {
+            deterministic,
+            ..self
+        }
+    }
+
+    /// Only writes files listed in `plan`, each to its `output`, counting
+    /// the rest as skipped
+    pub fn with_plan(self, plan: &Plan) -> Self {
+        Self {
+            plan: Some(plan.outputs()),
+            ..self
+        }
+    }
+
+    /// Keeps a copy of every image written, e.g. for `write_gallery`
+    pub fn with_kept_images(self) -> Self {
+        Self {
+            images: Some(Mutex::default()),
+            ..self
+        }
+    }
+
+    /// Image written as planned `path`, if images are kept
+    pub fn kept_image(&self, path: &Path) -> Option<RgbaImage> {
+        let images = self.images.as_ref()?.lock().unwrap();
+        images.get(path).cloned()
+    }
+
+    /// Where output planned as `path` is actually written, with texture
+    /// extension changed to that of output format
+    pub fn output_path(&self, path: &Path) -> PathBuf {
+        self.format.output_path(path)
+    }
+
+    /// Remembers color chunks of the image output `path` is made from, so
+    /// they can be kept even though it's re-encoded
+    pub fn note_chunks(&self, path: &Path, chunks: Arc<[u8]>) {
+        if self.color_chunks == ColorChunks::Keep {
+            let mut source_chunks = self.source_chunks.lock().unwrap();
+            source_chunks.insert(path.to_owned(), chunks);
+        }
+    }
+
+    /// Recompresses a png if asked to, then keeps or strips its color
+    /// chunks according to policy
+    fn prepare_png(&self, path: &Path, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let chunks = match self.color_chunks {
+            ColorChunks::Keep => {
+                let noted =
+                    self.source_chunks.lock().unwrap().get(path).cloned();
+                Some(noted.unwrap_or_else(|| color_chunks(data).into()))
+            }
+            ColorChunks::Strip => None,
+        };
+        let data = if self.optimize {
+            optimize_png(data)
+                .with_context(|| format!("optimizing {}", path.display()))?
+        } else {
+            data.to_owned()
+        };
+        let data = match chunks {
+            Some(chunks) => insert_color_chunks(&data, &chunks),
+            None => strip_color_chunks(&data),
+        };
+        let sources = self.sources.lock().unwrap();
+        Ok(match sources.get(&self.output_path(path)) {
+            Some(source) if self.provenance => {
+                insert_text_chunk(&data, PROVENANCE_KEYWORD, source)
+            }
+            _ => data,
+        })
+    }
+
+    pub fn progress(&self) -> &Progress {
+        &self.progress
+    }
+
+    /// Remembers where output `path` comes from, for `records`
+    pub fn note_source(&self, path: &Path, source: impl Into<String>) {
+        let mut sources = self.sources.lock().unwrap();
+        sources.insert(self.output_path(path), source.into());
+    }
+
+    /// Remembers that output `path` was trimmed, for `records`
+    pub fn note_trim(&self, path: &Path, trim: Trim) {
+        let mut trims = self.trims.lock().unwrap();
+        trims.insert(self.output_path(path), trim);
+    }
+
+    /// Every write so far, in order, with its source if noted
+    pub fn records(&self) -> Vec<WriteRecord> {
+        let sources = self.sources.lock().unwrap();
+        let trims = self.trims.lock().unwrap();
+        let log = self.log.lock().unwrap();
+        let mut records: Vec<_> = log
+            .iter()
+            .map(|(path, outcome)| WriteRecord {
+                path: path.clone(),
+                outcome: *outcome,
+                source: sources.get(path).cloned(),
+                trim: trims.get(path).copied(),
+            })
+            .collect();
+        if self.deterministic {
+            records.sort_by(|a, b| a.path.cmp(&b.path));
+        }
+        records
+    }
+
+    /// Creates output directory, if writing to a directory at all. Ones
+    /// that are new get removed again if the run fails.
+    pub fn create_dir_all(&self, path: &Path) -> anyhow::Result<()> {
+        if let Target::Dir(staging) = &self.target {
+            let mut staging = staging.lock().unwrap();
+            staging.create_dir_all(path).with_context(|| {
+                format!("can't create directory {}", path.display())
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Content of output `path` as of the last write to it, even if not
+    /// moved into place yet
+    pub fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        match &self.target {
+            Target::Dir(staging) => staging.lock().unwrap().read(path),
+            Target::Buffered(_, entries) => {
+                let entries = entries.lock().unwrap();
+                entries
+                    .get(&entry_name(path))
+                    .cloned()
+                    .ok_or_else(|| io::ErrorKind::NotFound.into())
+            }
+        }
+    }
+
+    /// Decides what to do about `path`, given its current content
+    fn outcome(
+        &self,
+        path: &Path,
+        existing: io::Result<Vec<u8>>,
+        data: &[u8],
+    ) -> anyhow::Result<Outcome> {
+        Ok(match existing {
+            Ok(existing) if existing == data => Outcome::Identical,
+            Ok(_) => match self.policy {
+                WritePolicy::Error => anyhow::bail!(
+                    "{} already exists with different content",
+                    path.display()
+                ),
+                WritePolicy::Skip => {
+                    log::info!("skipped existing: {}", path.display());
+                    Outcome::Skipped
+                }
+                WritePolicy::Overwrite => Outcome::Overwritten,
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Outcome::Created,
+            Err(e) => return Err(e.into()),
+        })
+    }
+
+    /// Writes a file, converting pngs to output format
+    pub fn write(&self, path: &Path, data: &[u8]) -> anyhow::Result<()> {
+        let is_png = path.extension().is_some_and(|ext| ext == "png");
+        if !is_png {
+            return self.put(path, data);
+        }
+        let data = match self.format {
+            OutputFormat::Png => self.prepare_png(path, data)?,
+            format => {
+                let image =
+                    image::load_from_memory(data).with_context(|| {
+                        format!("converting {}", path.display())
+                    })?;
+                format.encode(&image)?
+            }
+        };
+        self.put(&self.output_path(path), &data)
+    }
+
+    /// Writes `data` to `path` as it is, or where the plan moved it
+    fn put(&self, path: &Path, data: &[u8]) -> anyhow::Result<()> {
+        let path = match self.plan.as_ref().map(|plan| plan.get(path)) {
+            Some(Some(output)) => {
+                self.follow_move(path, output);
+                output.as_path()
+            }
+            Some(None) => {
+                log::info!("not in plan: {}", path.display());
+                self.record(path, Outcome::Skipped);
+                return Ok(());
+            }
+            None => path,
+        };
+        let outcome = match &self.target {
+            Target::Dir(staging) => {
+                let outcome = self.outcome(path, self.read(path), data)?;
+                if let Outcome::Created | Outcome::Overwritten = outcome {
+                    // Names like `machine/top` land in subdirectories
+                    if let Some(parent) = path.parent() {
+                        self.create_dir_all(parent)?;
+                    }
+                    let staged = staging.lock().unwrap().reserve(path);
+                    fs::write(&staged, data).with_context(|| {
+                        format!("can't stage {}", path.display())
+                    })?;
+                    staging.lock().unwrap().insert(path, staged);
+                }
+                outcome
+            }
+            Target::Buffered(_, entries) => {
+                let outcome = self.outcome(path, self.read(path), data)?;
+                if let Outcome::Created | Outcome::Overwritten = outcome {
+                    let mut entries = entries.lock().unwrap();
+                    entries.insert(entry_name(path), data.to_owned());
+                }
+                outcome
+            }
+        };
+        self.record(path, outcome);
+        Ok(())
+    }
+
+    /// Carries noted source and trim of `path` over to `output` it's moved
+    /// to by the plan
+    fn follow_move(&self, path: &Path, output: &Path) {
+        if path == output {
+            return;
+        }
+        let mut sources = self.sources.lock().unwrap();
+        if let Some(source) = sources.get(path).cloned() {
+            sources.insert(output.to_owned(), source);
+        }
+        let mut trims = self.trims.lock().unwrap();
+        if let Some(trim) = trims.get(path).copied() {
+            trims.insert(output.to_owned(), trim);
+        }
+    }
+
+    fn record(&self, path: &Path, outcome: Outcome) {
+        let counter = match outcome {
+            Outcome::Created => &self.created,
+            Outcome::Identical => &self.identical,
+            Outcome::Skipped => &self.skipped,
+            Outcome::Overwritten => &self.overwritten,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        if log::log_enabled!(log::Level::Debug) {
+            let sources = self.sources.lock().unwrap();
+            let source = sources.get(path).map_or("?", String::as_str);
+            log::debug!("{} {} <- {}", outcome, path.display(), source);
+        }
+        self.log.lock().unwrap().push((path.to_owned(), outcome));
+        self.progress.inc(outcome);
+    }
+
+    /// Moves staged files into place, or writes the whole zip, must be
+    /// called once everything is written. Without it, nothing is.
+    pub fn finish(&self) -> anyhow::Result<()> {
+        let (sink, entries) = match &self.target {
+            Target::Dir(staging) => return staging.lock().unwrap().commit(),
+            Target::Buffered(sink, entries) => {
+                (sink, std::mem::take(&mut *entries.lock().unwrap()))
+            }
+        };
+        let path = match sink {
+            Sink::ZipFile(path) => path,
+            Sink::Memory(files) => {
+                files.lock().unwrap().extend(entries);
+                return Ok(());
+            }
+            Sink::Zip(output) => {
+                let output = output.lock().unwrap().take();
+                let output = output.ok_or_else(|| {
+                    anyhow::anyhow!("zip was already written")
+                })?;
+                return self
+                    .write_zip(output, entries)
+                    .context("can't write zip");
+            }
+            Sink::Stdout => {
+                // Zips need seeking, which pipes can't do
+                let mut zip = Cursor::new(vec![]);
+                self.write_zip(&mut zip, entries)?;
+                let mut stdout = io::stdout().lock();
+                return stdout
+                    .write_all(zip.get_ref())
+                    .and_then(|()| stdout.flush())
+                    .context("can't write zip to stdout");
+            }
+        };
+        // Written aside and renamed, so an old pack is never half replaced
+        let staged = path.with_extension("zip.tex-slasher-new");
+        let result = File::create(&staged)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| self.write_zip(file, entries))
+            .and_then(|()| Ok(fs::rename(&staged, path)?));
+        if result.is_err() {
+            let _ = fs::remove_file(&staged);
+        }
+        result.with_context(|| format!("can't write {}", path.display()))
+    }
+
+    /// Files written to memory by finished runs, by `/`-separated path,
+    /// leaving none behind. Other writers have none.
+    pub fn take_files(&self) -> BTreeMap<String, Vec<u8>> {
+        match &self.target {
+            Target::Buffered(Sink::Memory(files), _) => {
+                std::mem::take(&mut *files.lock().unwrap())
+            }
+            _ => BTreeMap::new(),
+        }
+    }
+
+    fn write_zip(
+        &self,
+        output: impl Write + Seek,
+        entries: BTreeMap<String, Vec<u8>>,
+    ) -> anyhow::Result<()> {
+        let mut zip = ZipWriter::new(output);
+        let mut options = FileOptions::default();
+        if self.deterministic {
+            // Earliest time zip can store, like other reproducible build
+            // tools use
+            options = options
+                .last_modified_time(DateTime::default())
+                .unix_permissions(0o644);
+        }
+        for (name, data) in entries {
+            zip.start_file(name, options)?;
+            zip.write_all(&data)?;
+        }
+        zip.finish()?;
+        Ok(())
+    }
+
+    /// Encodes a texture planned as `path` in output format and writes it
+    pub fn write_image(
+        &self,
+        path: &Path,
+        image: &DynamicImage,
+    ) -> anyhow::Result<()> {
+        if let Some(images) = &self.images {
+            let mut images = images.lock().unwrap();
+            images.insert(path.to_owned(), image.to_rgba8());
+        }
+        let data = self.format.encode(image)?;
+        match self.format {
+            // Still has to be optimized and have color chunks sorted out
+            OutputFormat::Png => self.write(path, &data),
+            _ => self.put(&self.output_path(path), &data),
+        }
+    }
+}
+
+/// Single write, as listed in run report
+#[derive(Debug, Clone, Serialize)]
+pub struct WriteRecord {
+    pub path: PathBuf,
+    pub outcome: Outcome,
+    /// `archive:entry` the file was made from, or `(generated)`
+    pub source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trim: Option<Trim>,
+}
+
+/// Where a texture trimmed of transparent borders was in its frame, in
+/// pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Trim {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// Size of the frame before trimming
+    pub frame_width: u32,
+    pub frame_height: u32,
+}
+
+/// Zip or in-memory entry name for a relative output path, always
+/// `/`-separated
+fn entry_name(path: &Path) -> String {
+    let parts: Vec<_> = path
+        .components()
+        .map(|part| part.as_os_str().to_string_lossy())
+        .collect();
+    parts.join("/")
+}
+
+impl fmt::Display for Writer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "created: {}, identical: {}, skipped: {}, overwritten: {}",
+            self.created.load(Ordering::Relaxed),
+            self.identical.load(Ordering::Relaxed),
+            self.skipped.load(Ordering::Relaxed),
+            self.overwritten.load(Ordering::Relaxed),
+        )
+    }
+}
@@ -0,0 +1,722 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    io::{BufRead, Cursor, Read, Seek},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use glob::{MatchOptions, Pattern};
+use image::{
+    codecs::png::PngDecoder, DynamicImage, GenericImageView, ImageFormat,
+    ImageResult, RgbaImage,
+};
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::{open_source, read_png_head, AssetSource, Folders, Priorities};
+
+/// Extensions sheets are looked for with, in order. Sliced tiles are always
+/// written as png.
+pub const SHEET_EXTENSIONS: [&str; 4] = ["png", "bmp", "gif", "tga"];
+
+/// Decodes an image in any supported format, recognized by its magic
+/// bytes, or by extension of `file` for formats without any, like tga
+pub fn decode_image(data: &[u8], file: &str) -> ImageResult<DynamicImage> {
+    let format = image::guess_format(data)
+        .or_else(|err| ImageFormat::from_path(file).map_err(|_| err))?;
+    image::load_from_memory_with_format(data, format)
+}
+
+/// Like `decode_image`, but straight from `input`, so the encoded file
+/// doesn't have to be read into memory first
+pub fn decode_image_from<R: BufRead + Seek>(
+    input: R,
+    file: &str,
+) -> ImageResult<DynamicImage> {
+    let mut reader = image::io::Reader::new(input).with_guessed_format()?;
+    if reader.format().is_none() {
+        if let Ok(format) = ImageFormat::from_path(file) {
+            reader.set_format(format);
+        }
+    }
+    reader.decode()
+}
+
+/// Like `decode_image`, but reading `input` as it goes, so an encoded png is
+/// never in memory whole. Other formats are read whole first. Returns color
+/// chunks of the png too.
+pub fn decode_image_stream(
+    mut input: impl Read,
+    file: &str,
+) -> ImageResult<(DynamicImage, Vec<u8>)> {
+    let (head, chunks) = read_png_head(&mut input)?;
+    if let Some(chunks) = chunks {
+        let decoder = PngDecoder::new(Cursor::new(head).chain(input))?;
+        return Ok((DynamicImage::from_decoder(decoder)?, chunks));
+    }
+    let mut data = head;
+    input.read_to_end(&mut data)?;
+    Ok((decode_image(&data, file)?, vec![]))
+}
+
+/// Bytes of decoded sheets kept in memory at a time while slicing, a
+/// handful of 4096x4096 HD ones. A single sheet is decoded even if larger.
+pub const DECODE_BUDGET: u64 = 256 << 20;
+
+/// Bytes an image of `width` by `height` takes decoded as RGBA
+pub fn decoded_size(width: u32, height: u32) -> u64 {
+    4 * u64::from(width) * u64::from(height)
+}
+
+/// `*` doesn't cross `/`, only `**` does
+const GLOB_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+fn is_pattern(name: &str) -> bool {
+    name.contains(['*', '?', '['])
+}
+
+/// Every directory containing at least one of `files`, directly or not
+fn directories(files: &[String]) -> BTreeSet<String> {
+    let mut dirs = BTreeSet::new();
+    for file in files {
+        let mut end = 0;
+        while let Some(slash) = file[end..].find('/') {
+            end += slash;
+            dirs.insert(file[..end].to_owned());
+            end += 1;
+        }
+    }
+    dirs
+}
+
+/// Lowercase, `/`-separated form of an entry path, so `Textures\Blocks.PNG`
+/// and `textures/blocks.png` are the same entry
+fn normalize(entry: &str) -> String {
+    entry
+        .replace('\\', "/")
+        .trim_start_matches('/')
+        .to_lowercase()
+}
+
+/// Single input archive or directory
+struct Archive {
+    name: String,
+    priority: i32,
+    /// Locked on its own, so different archives can be read at once
+    source: Mutex<Box<dyn AssetSource>>,
+    /// Folders searched, in order
+    paths: Vec<String>,
+    /// Normalized entry path to actual one, unless paths have to match
+    /// exactly
+    index: Option<HashMap<String, String>>,
+}
+
+impl Archive {
+    /// Entry `file` would be read from, if it's in any folder. Exact matches
+    /// win over ones found through the index.
+    fn entry(&self, file: &str) -> Option<String> {
+        for path in &self.paths {
+            let entry = format!("{}/{}", path, file);
+            if self.source.lock().unwrap().contains(&entry) {
+                return Some(entry);
+            }
+            let index = self.index.as_ref();
+            if let Some(found) = index.and_then(|i| i.get(&normalize(&entry))) {
+                log::trace!("{} matched {} in {}", entry, found, self.name);
+                return Some(found.clone());
+            }
+        }
+        None
+    }
+}
+
+/// File found in more than one archive
+#[derive(Debug, Clone, Serialize)]
+pub struct Shadowed {
+    /// Where the file was read from, as `archive:entry`
+    pub source: String,
+    /// Every other place it was found, in priority order
+    pub shadowed: Vec<String>,
+}
+
+/// Source searched by an `ArchiveSet`
+#[derive(Debug, Clone, Serialize)]
+pub struct Layer {
+    pub name: String,
+    pub priority: i32,
+}
+
+/// Entry found by `ArchiveSet::search`
+#[derive(Debug, Clone, Serialize)]
+pub struct Found {
+    pub archive: String,
+    pub entry: String,
+    /// Name config can refer to it by, if it's in one of the archive's
+    /// folders
+    pub name: Option<String>,
+}
+
+/// Input archives or directories, each searched under its own list of
+/// folders, in order of priority
+///
+/// Can be shared between threads: each archive is read by one thread at a
+/// time, while different archives are read at once, see `prefetch_images`.
+pub struct ArchiveSet {
+    archives: Vec<Archive>,
+    /// Decoded images, so sheets used by several sections are decoded once
+    images: Mutex<HashMap<String, Arc<RgbaImage>>>,
+    /// Color chunks of each decoded image, for outputs cut out of it
+    chunks: Mutex<HashMap<String, Arc<[u8]>>>,
+    /// How long reading and decoding each image took, if kept track of
+    load_times: Option<Mutex<HashMap<String, (Duration, Duration)>>>,
+    /// Whether to look for files in more than one archive
+    track_shadowed: bool,
+    /// Fail on files found in several archives of the same priority
+    error_on_ambiguous: bool,
+    shadowed: Mutex<BTreeMap<String, Shadowed>>,
+    /// Files whose entries failed to read, to why
+    unreadable: Mutex<BTreeMap<String, String>>,
+}
+
+/// File read out of one of the archives
+#[derive(Debug)]
+pub struct Asset {
+    /// Where the file was found, as `archive:entry`
+    pub source: String,
+    pub data: Vec<u8>,
+}
+
+impl ArchiveSet {
+    /// Opens every source in `folders`, looking into archives inside them up
+    /// to `nested_depth` levels down. Folders can be glob patterns, matched
+    /// against directories in the source. Sources with higher priority are
+    /// searched first, ones without any have priority 0. Unless `exact`,
+    /// entries are matched ignoring case and `\` vs `/`.
+    pub fn new(
+        folders: &Folders,
+        priorities: &Priorities,
+        input_dir: &Path,
+        nested_depth: u32,
+        exact: bool,
+    ) -> anyhow::Result<Self> {
+        Self::with_sources(folders, priorities, exact, |file| {
+            open_source(input_dir, file, nested_depth)
+        })
+    }
+
+    /// Like `new`, but every source in `folders` is opened by `open`, given
+    /// its name, e.g. to read some from memory
+    pub fn with_sources(
+        folders: &Folders,
+        priorities: &Priorities,
+        exact: bool,
+        mut open: impl FnMut(&str) -> anyhow::Result<Box<dyn AssetSource>>,
+    ) -> anyhow::Result<Self> {
+        for name in priorities.keys() {
+            anyhow::ensure!(
+                folders.contains_key(name),
+                "priority given for {}, which isn't in folders",
+                name
+            );
+        }
+        let mut archives = folders
+            .iter()
+            .map(|(file, paths)| -> anyhow::Result<_> {
+                let mut source = open(file)?;
+                let mut expanded = vec![];
+                for path in paths {
+                    if !is_pattern(path) {
+                        expanded.push(path.clone());
+                        continue;
+                    }
+                    let pattern = Pattern::new(path)?;
+                    let before = expanded.len();
+                    expanded.extend(
+                        directories(&source.list()?).into_iter().filter(
+                            |dir| pattern.matches_with(dir, GLOB_OPTIONS),
+                        ),
+                    );
+                    if expanded.len() == before {
+                        log::warn!(
+                            "folder pattern {} matches nothing in {}",
+                            path,
+                            file
+                        );
+                    }
+                }
+                let mut index = None;
+                if !exact {
+                    let mut entries = HashMap::new();
+                    // Reversed, so of entries differing only in case the
+                    // first one listed wins
+                    for entry in source.list()?.into_iter().rev() {
+                        entries.insert(normalize(&entry), entry);
+                    }
+                    index = Some(entries);
+                }
+                Ok(Archive {
+                    name: file.clone(),
+                    priority: priorities.get(file).copied().unwrap_or(0),
+                    source: Mutex::new(source),
+                    paths: expanded,
+                    index,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        // Stable, so sources of the same priority stay in config order
+        archives.sort_by_key(|archive| std::cmp::Reverse(archive.priority));
+        Ok(Self {
+            archives,
+            images: Mutex::default(),
+            chunks: Mutex::default(),
+            load_times: None,
+            track_shadowed: false,
+            error_on_ambiguous: false,
+            shadowed: Mutex::default(),
+            unreadable: Mutex::default(),
+        })
+    }
+
+    /// Makes `find` look for every file in the remaining archives too,
+    /// recording ones it shadows, or failing if `error_on_ambiguous` and one
+    /// of them has the same priority
+    pub fn track_shadowed(&mut self, error_on_ambiguous: bool) {
+        self.track_shadowed = true;
+        self.error_on_ambiguous = error_on_ambiguous;
+    }
+
+    /// Makes `find_image` time reading and decoding, see `load_time`
+    pub fn track_load_times(&mut self) {
+        self.load_times = Some(Mutex::default());
+    }
+
+    /// How long reading and decoding `file` took, the last time it was
+    /// decoded with `find_image`, if `track_load_times` was called
+    pub fn load_time(&self, file: &str) -> Option<(Duration, Duration)> {
+        let load_times = self.load_times.as_ref()?;
+        load_times.lock().unwrap().get(file).copied()
+    }
+
+    /// Files read so far that were also found in other archives
+    pub fn shadowed(&self) -> BTreeMap<String, Shadowed> {
+        self.shadowed.lock().unwrap().clone()
+    }
+
+    /// Records other archives after `winner` containing `file`
+    fn check_shadowed(
+        &self,
+        file: &str,
+        winner: usize,
+        source: &str,
+    ) -> anyhow::Result<()> {
+        if !self.track_shadowed
+            || self.shadowed.lock().unwrap().contains_key(file)
+        {
+            return Ok(());
+        }
+        let priority = self.archives[winner].priority;
+        let mut shadowed = vec![];
+        for archive in &self.archives[winner + 1..] {
+            let entry = match archive.entry(file) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            let other = format!("{}:{}", archive.name, entry);
+            anyhow::ensure!(
+                !self.error_on_ambiguous || archive.priority != priority,
+                "{} is ambiguous: found in {} and {}, both with priority {}",
+                file,
+                source,
+                other,
+                priority
+            );
+            shadowed.push(other);
+        }
+        if !shadowed.is_empty() {
+            log::debug!("{} shadows {}", source, shadowed.join(", "));
+            let source = source.to_owned();
+            let entry = Shadowed { source, shadowed };
+            self.shadowed.lock().unwrap().insert(file.to_owned(), entry);
+        }
+        Ok(())
+    }
+
+    /// Files found so far whose entries couldn't be read, like ones with
+    /// corrupted data, with why. Other archives are searched for them.
+    pub fn unreadable(&self) -> BTreeMap<String, String> {
+        self.unreadable.lock().unwrap().clone()
+    }
+
+    /// Every entry of every archive that `matches` accepts, in priority
+    /// order, whether it's in one of the archive's folders or not
+    pub fn search(
+        &mut self,
+        mut matches: impl FnMut(&str) -> bool,
+    ) -> anyhow::Result<Vec<Found>> {
+        let mut found = vec![];
+        for archive in self.archives.iter_mut() {
+            let exact = archive.index.is_none();
+            let key = |path: &str| match exact {
+                true => path.to_owned(),
+                false => normalize(path),
+            };
+            let entries = archive.source.get_mut().unwrap().list()?;
+            for entry in entries {
+                if !matches(&entry) {
+                    continue;
+                }
+                let path = key(&entry);
+                // Shortest, when it's in several nested folders
+                let name = archive
+                    .paths
+                    .iter()
+                    .filter_map(|folder| {
+                        let prefix = format!("{}/", key(folder));
+                        path.strip_prefix(&prefix).map(str::to_owned)
+                    })
+                    .min_by_key(String::len);
+                found.push(Found {
+                    archive: archive.name.clone(),
+                    entry,
+                    name,
+                });
+            }
+        }
+        Ok(found)
+    }
+
+    /// Reads the first match for `file` into memory. Entries failing to read
+    /// are skipped with a warning, and recorded in `unreadable`.
+    pub fn find(&self, file: &str) -> anyhow::Result<Option<Asset>> {
+        for (i, archive) in self.archives.iter().enumerate() {
+            log::trace!("looking for {} in {}", file, archive.name);
+            let entry = match archive.entry(file) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            let read = archive.source.lock().unwrap().read(&entry);
+            let read = match read {
+                Ok(read) => read,
+                Err(e) => {
+                    let reason = format!("{}: {}", archive.name, e);
+                    log::warn!("skipping {}, can't read {}", file, reason);
+                    let mut unreadable = self.unreadable.lock().unwrap();
+                    unreadable.insert(file.to_owned(), reason);
+                    continue;
+                }
+            };
+            if let Some(data) = read {
+                let source = format!("{}:{}", archive.name, entry);
+                log::trace!("found {} at {}", file, source);
+                self.check_shadowed(file, i, &source)?;
+                return Ok(Some(Asset { source, data }));
+            }
+        }
+        log::trace!("{} not found in any archive", file);
+        Ok(None)
+    }
+
+    /// Like `find`, but passes the first match for `file` to `with` as a
+    /// stream, along with where it was found, instead of reading it into
+    /// memory. The archive is locked meanwhile. Entries failing to open are
+    /// skipped as with `find`.
+    pub fn find_streamed<T>(
+        &self,
+        file: &str,
+        mut with: impl FnMut(&mut dyn Read, &str) -> anyhow::Result<T>,
+    ) -> anyhow::Result<Option<T>> {
+        for (i, archive) in self.archives.iter().enumerate() {
+            log::trace!("looking for {} in {}", file, archive.name);
+            let entry = match archive.entry(file) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            let mut source = archive.source.lock().unwrap();
+            let mut stream = match source.stream(&entry) {
+                Ok(Some(stream)) => stream,
+                Ok(None) => continue,
+                Err(e) => {
+                    let reason = format!("{}: {}", archive.name, e);
+                    log::warn!("skipping {}, can't read {}", file, reason);
+                    let mut unreadable = self.unreadable.lock().unwrap();
+                    unreadable.insert(file.to_owned(), reason);
+                    continue;
+                }
+            };
+            let source = format!("{}:{}", archive.name, entry);
+            log::trace!("found {} at {}", file, source);
+            self.check_shadowed(file, i, &source)?;
+            return with(&mut stream, &source).map(Some);
+        }
+        log::trace!("{} not found in any archive", file);
+        Ok(None)
+    }
+
+    /// Like `find`, but missing files are an error
+    pub fn read(&self, file: &str) -> anyhow::Result<Vec<u8>> {
+        match self.find(file)? {
+            Some(asset) => Ok(asset.data),
+            None => self.not_found(file),
+        }
+    }
+
+    fn not_found<T>(&self, file: &str) -> anyhow::Result<T> {
+        match self.unreadable.lock().unwrap().get(file) {
+            Some(reason) => anyhow::bail!("{} can't be read: {}", file, reason),
+            None => anyhow::bail!("{} not found in archives", file),
+        }
+    }
+
+    /// Every source, in the order they're searched: top layer first
+    pub fn layers(&self) -> Vec<Layer> {
+        self.archives
+            .iter()
+            .map(|archive| Layer {
+                name: archive.name.clone(),
+                priority: archive.priority,
+            })
+            .collect()
+    }
+
+    /// Like `find`, but only describes where the file would be read from,
+    /// as `archive:entry`
+    pub fn locate(&self, file: &str) -> Option<String> {
+        self.archives.iter().find_map(|archive| {
+            let entry = archive.entry(file)?;
+            Some(format!("{}:{}", archive.name, entry))
+        })
+    }
+
+    /// Files matching `pattern` in any folder, relative to that folder
+    fn matches(&self, pattern: &Pattern) -> anyhow::Result<BTreeSet<String>> {
+        let mut matches = BTreeSet::new();
+        for archive in &self.archives {
+            let files = archive.source.lock().unwrap().list()?;
+            for path in archive.paths.iter() {
+                let prefix = format!("{}/", path);
+                matches.extend(
+                    files
+                        .iter()
+                        .filter_map(|file| file.strip_prefix(&prefix))
+                        .filter(|file| pattern.matches_with(file, GLOB_OPTIONS))
+                        .map(str::to_owned),
+                );
+            }
+        }
+        Ok(matches)
+    }
+
+    /// Expands glob patterns in `names` against files in every folder, like
+    /// `machine_*.json` or `gui/**/*.png`. Plain names, and patterns matching
+    /// nothing, are kept as they are, so they are reported as missing later.
+    pub fn expand(&self, names: &[String]) -> anyhow::Result<Vec<String>> {
+        let mut expanded = vec![];
+        for name in names {
+            if !is_pattern(name) {
+                expanded.push(name.clone());
+                continue;
+            }
+            let matches = self.matches(&Pattern::new(name)?)?;
+            if matches.is_empty() {
+                expanded.push(name.clone());
+            }
+            for file in matches {
+                if !expanded.contains(&file) {
+                    expanded.push(file);
+                }
+            }
+        }
+        Ok(expanded)
+    }
+
+    /// Every file under directory `dir`, at any depth, relative to `dir`
+    pub fn list_dir(&self, dir: &str) -> anyhow::Result<Vec<String>> {
+        let dir = dir.trim_end_matches('/');
+        let pattern = Pattern::new(&format!("{}/**/*", Pattern::escape(dir)))?;
+        let prefix = format!("{}/", dir);
+        Ok(self
+            .matches(&pattern)?
+            .iter()
+            .filter_map(|file| file.strip_prefix(&prefix))
+            .map(str::to_owned)
+            .collect())
+    }
+
+    /// Reads and decodes an image, caching the result for the whole run.
+    /// Pngs are decoded as they're read, without a copy of the encoded file.
+    pub fn find_image(
+        &self,
+        file: &str,
+    ) -> anyhow::Result<Option<Arc<RgbaImage>>> {
+        if let Some(image) = self.images.lock().unwrap().get(file) {
+            return Ok(Some(image.clone()));
+        }
+        // No clock on wasm, so only used when asked for
+        let start = self.load_times.as_ref().map(|_| Instant::now());
+        let mut read = None;
+        let decoded = self.find_streamed(file, |input, source| {
+            read = start.map(|start| start.elapsed());
+            let (image, chunks) = decode_image_stream(input, file)?;
+            let (width, height) = image.dimensions();
+            log::trace!(
+                "decoded {}: {}x{} {:?}",
+                source,
+                width,
+                height,
+                image.color()
+            );
+            Ok((image, chunks))
+        })?;
+        let (image, chunks) = match decoded {
+            Some(decoded) => decoded,
+            None => return Ok(None),
+        };
+        // Converting in place keeps a single copy of the pixels in memory
+        let image = Arc::new(image.into_rgba8());
+        if let (Some(load_times), Some(start), Some(read)) =
+            (&self.load_times, start, read)
+        {
+            let decode = start.elapsed() - read;
+            let mut load_times = load_times.lock().unwrap();
+            load_times.insert(file.to_owned(), (read, decode));
+        }
+        let mut images = self.images.lock().unwrap();
+        images.insert(file.to_owned(), image.clone());
+        self.chunks.lock().unwrap().insert(file.to_owned(), chunks.into());
+        Ok(Some(image))
+    }
+
+    /// Width and height of an image, read from its header without decoding
+    /// it, if it's found and the header makes sense
+    pub fn image_dimensions(
+        &self,
+        file: &str,
+    ) -> anyhow::Result<Option<(u32, u32)>> {
+        if let Some(image) = self.images.lock().unwrap().get(file) {
+            return Ok(Some(image.dimensions()));
+        }
+        let dimensions = self.find_streamed(file, |input, _| {
+            // Enough for headers of every format sheets can be in
+            let mut head = vec![];
+            input.take(1024).read_to_end(&mut head)?;
+            let mut reader = image::io::Reader::new(Cursor::new(head))
+                .with_guessed_format()?;
+            if reader.format().is_none() {
+                if let Ok(format) = ImageFormat::from_path(file) {
+                    reader.set_format(format);
+                }
+            }
+            Ok(reader.into_dimensions().ok())
+        })?;
+        Ok(dimensions.flatten())
+    }
+
+    /// Reads and decodes `files` with `find_image` on all threads, so later
+    /// calls only hit the cache. Files in different archives are read and
+    /// decoded at once, ones in the same archive one by one, as they're
+    /// streamed out of it. Missing ones are left for those calls to report.
+    pub fn prefetch_images(&self, files: &[String]) -> anyhow::Result<()> {
+        let files: BTreeSet<_> = files.iter().collect();
+        files
+            .into_par_iter()
+            .try_for_each(|file| self.find_image(file).map(drop))
+    }
+
+    /// Drops `file` from the image cache, so sheets already sliced don't
+    /// add up in memory. It's decoded again if needed later.
+    pub fn release_image(&self, file: &str) {
+        self.images.lock().unwrap().remove(file);
+    }
+
+    /// Color chunks of an image decoded with `find_image`, empty if it had
+    /// none or wasn't decoded yet
+    pub fn color_chunks(&self, file: &str) -> Arc<[u8]> {
+        self.chunks
+            .lock()
+            .unwrap()
+            .get(file)
+            .cloned()
+            .unwrap_or_else(|| Arc::from(&[][..]))
+    }
+
+    /// File of sheet `atlas`, as `<atlas>.png`, or with another of
+    /// `SHEET_EXTENSIONS` if only that one exists
+    pub fn sheet(&self, atlas: &str) -> String {
+        let file = |ext: &str| {
+            let path = Path::new(atlas).with_extension(ext);
+            path.to_str().unwrap().to_owned()
+        };
+        SHEET_EXTENSIONS
+            .iter()
+            .map(|ext| file(ext))
+            .find(|file| self.locate(file).is_some())
+            .unwrap_or_else(|| file("png"))
+    }
+
+    /// Like `find_image`, but missing files are an error
+    pub fn image(&self, file: &str) -> anyhow::Result<Arc<RgbaImage>> {
+        match self.find_image(file)? {
+            Some(image) => Ok(image),
+            None => self.not_found(file),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemorySource, OutputFormat};
+
+    fn sheet() -> RgbaImage {
+        RgbaImage::from_fn(48, 32, |x, y| {
+            image::Rgba([x as u8, y as u8, 7, 255])
+        })
+    }
+
+    fn archives(files: Vec<(&str, Vec<u8>)>) -> ArchiveSet {
+        let folders: Folders =
+            [("mod.jar".to_owned(), vec!["textures".to_owned()])].into();
+        let files: BTreeMap<_, _> = files
+            .into_iter()
+            .map(|(path, data)| (format!("textures/{}", path), data))
+            .collect();
+        ArchiveSet::with_sources(&folders, &Priorities::new(), true, |_| {
+            Ok(Box::new(MemorySource::new(files.clone())))
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn streamed_images_match_decoded_ones() {
+        let image = DynamicImage::ImageRgba8(sheet());
+        let png = OutputFormat::Png.encode(&image).unwrap();
+        let (streamed, chunks) =
+            decode_image_stream(&png[..], "a.png").unwrap();
+        assert_eq!(streamed.into_rgba8(), sheet());
+        assert!(chunks.is_empty());
+
+        let mut bmp = vec![];
+        image.write_to(&mut bmp, ImageFormat::Bmp).unwrap();
+        let (streamed, _) = decode_image_stream(&bmp[..], "a.bmp").unwrap();
+        assert_eq!(streamed.into_rgba8(), sheet());
+    }
+
+    #[test]
+    fn dimensions_without_decoding() {
+        let image = DynamicImage::ImageRgba8(sheet());
+        let png = OutputFormat::Png.encode(&image).unwrap();
+        let zips = archives(vec![("a.png", png), ("b.png", b"junk".to_vec())]);
+        assert_eq!(zips.image_dimensions("a.png").unwrap(), Some((48, 32)));
+        assert_eq!(zips.image_dimensions("b.png").unwrap(), None);
+        assert_eq!(zips.image_dimensions("c.png").unwrap(), None);
+        assert_eq!(*zips.image("a.png").unwrap(), sheet());
+        zips.release_image("a.png");
+        assert_eq!(zips.image_dimensions("a.png").unwrap(), Some((48, 32)));
+        assert_eq!(decoded_size(48, 32), 48 * 32 * 4);
+    }
+}